@@ -9,6 +9,7 @@
 //! Run this example with
 //!    cargo run --example provide-bytes collection
 //! To provide a collection (multiple blobs)
+
 use anyhow::Result;
 use tokio_util::task::LocalPoolHandle;
 use tracing_subscriber::{prelude::*, EnvFilter};
@@ -92,7 +93,23 @@ async fn main() -> Result<()> {
 
             // spawn a task to handle the connection
             tokio::spawn(async move {
-                iroh_bytes::provider::handle_connection(conn, db, MockEventSender, lp).await
+                let conn = match conn.await {
+                    Ok(conn) => conn,
+                    Err(err) => {
+                        println!("error connecting: {err}");
+                        return;
+                    }
+                };
+                // the example endpoint does not authenticate node ids
+                iroh_bytes::provider::handle_connection(
+                    conn,
+                    None,
+                    db,
+                    MockEventSender,
+                    Default::default(),
+                    lp,
+                )
+                .await
             });
         }
     });
@@ -25,18 +25,27 @@
 //!   strictly needed since it's likely they will be useful soon again.
 //! - *Requests per node*: to avoid overwhelming nodes with requests, the number of concurrent
 //!   requests to a single node is also limited.
+//!
+//! Large blobs can be downloaded from several providers at once, see [`SwarmConfig`]. Every
+//! additional provider used for a download counts as an active request for that node.
 
 use std::{
-    collections::{hash_map::Entry, HashMap, VecDeque},
+    collections::{HashMap, VecDeque},
     num::NonZeroUsize,
+    ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
 };
 
-use crate::{get::Stats, protocol::RangeSpecSeq, store::Store, Hash, HashAndFormat};
-use bao_tree::ChunkRanges;
+use crate::{
+    get::Stats,
+    protocol::{RangeSpec, RangeSpecSeq},
+    store::Store,
+    BlobFormat, Hash, HashAndFormat,
+};
+use bao_tree::{ByteNum, ChunkRanges};
 use futures::{future::LocalBoxFuture, FutureExt, StreamExt};
 use iroh_net::{MagicEndpoint, NodeId};
 use tokio::{
@@ -48,8 +57,11 @@ use tracing::{debug, error_span, trace, warn, Instrument};
 
 mod get;
 mod invariants;
+mod swarm;
 mod test;
 
+pub use swarm::SwarmConfig;
+
 /// Delay added to a request when it's first received.
 const INITIAL_REQUEST_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
 /// Number of retries initially assigned to a request.
@@ -97,6 +109,11 @@ pub trait Getter {
     type Connection;
     /// Return a future that performs the download using the given connection.
     fn get(&mut self, kind: DownloadKind, conn: Self::Connection) -> GetFut;
+    /// Return a future that performs the download using several connections concurrently.
+    ///
+    /// The first connection is the one to the node the request was assigned to, the failure
+    /// action of the returned future applies to it.
+    fn get_swarm(&mut self, kind: DownloadKind, conns: Vec<Self::Connection>) -> GetFut;
 }
 
 /// Concurrency limits for the [`Downloader`].
@@ -151,33 +168,137 @@ pub enum DownloadKind {
         /// Hash sequence to be downloaded.
         hash: Hash,
     },
+    /// Download some chunk ranges of a single blob.
+    BlobRanges {
+        /// Blob to be downloaded.
+        hash: Hash,
+        /// Chunk ranges of the blob to download.
+        ranges: RangeSpec,
+    },
+    /// Download a selection of a sequence of hashes.
+    ///
+    /// The first [`RangeSpec`] of `ranges` applies to the hash sequence itself, the following
+    /// ones to its children. The hash sequence is always downloaded entirely, since it is
+    /// needed to know the hashes of the children.
+    HashSeqRanges {
+        /// Hash sequence to be downloaded.
+        hash: Hash,
+        /// Chunk ranges of the hash sequence and its children to download.
+        ranges: RangeSpecSeq,
+    },
 }
 
 impl DownloadKind {
+    /// Create a [`DownloadKind`] for the chunks of a blob covering the given byte range.
+    pub fn blob_bytes(hash: Hash, range: Range<u64>) -> Self {
+        let start = ByteNum(range.start).full_chunks();
+        let end = ByteNum(range.end).chunks();
+        DownloadKind::BlobRanges {
+            hash,
+            ranges: RangeSpec::new(ChunkRanges::from(start..end)),
+        }
+    }
+
     /// Get the requested hash.
     const fn hash(&self) -> &Hash {
         match self {
-            DownloadKind::Blob { hash } | DownloadKind::HashSeq { hash } => hash,
+            DownloadKind::Blob { hash }
+            | DownloadKind::HashSeq { hash }
+            | DownloadKind::BlobRanges { hash, .. }
+            | DownloadKind::HashSeqRanges { hash, .. } => hash,
         }
     }
 
     /// Get the requested hash and format.
     fn hash_and_format(&self) -> HashAndFormat {
         match self {
-            DownloadKind::Blob { hash } => HashAndFormat::raw(*hash),
-            DownloadKind::HashSeq { hash } => HashAndFormat::hash_seq(*hash),
+            DownloadKind::Blob { hash } | DownloadKind::BlobRanges { hash, .. } => {
+                HashAndFormat::raw(*hash)
+            }
+            DownloadKind::HashSeq { hash } | DownloadKind::HashSeqRanges { hash, .. } => {
+                HashAndFormat::hash_seq(*hash)
+            }
         }
     }
 
     /// Get the ranges this download is requesting.
-    // NOTE: necessary to extend downloads to support ranges of blobs ranges of collections.
-    #[allow(dead_code)]
     fn ranges(&self) -> RangeSpecSeq {
         match self {
             DownloadKind::Blob { .. } => RangeSpecSeq::from_ranges([ChunkRanges::all()]),
             DownloadKind::HashSeq { .. } => RangeSpecSeq::all(),
+            DownloadKind::BlobRanges { ranges, .. } => {
+                RangeSpecSeq::new([ranges.clone(), RangeSpec::EMPTY])
+            }
+            DownloadKind::HashSeqRanges { ranges, .. } => ranges.clone(),
+        }
+    }
+
+    /// Whether this download requests everything of its hash.
+    fn is_full(&self) -> bool {
+        matches!(
+            self,
+            DownloadKind::Blob { .. } | DownloadKind::HashSeq { .. }
+        )
+    }
+
+    /// Create the kind requesting exactly `ranges` of `hash_and_format`.
+    ///
+    /// This uses [`DownloadKind::Blob`] or [`DownloadKind::HashSeq`] if everything is
+    /// requested, so that equal requests map to equal kinds.
+    fn from_ranges(hash_and_format: HashAndFormat, ranges: RangeSpecSeq) -> Self {
+        let HashAndFormat { hash, format } = hash_and_format;
+        match format {
+            BlobFormat::Raw => {
+                let ranges = ranges.iter().next().cloned().unwrap_or(RangeSpec::EMPTY);
+                if ranges.is_all() {
+                    DownloadKind::Blob { hash }
+                } else {
+                    DownloadKind::BlobRanges { hash, ranges }
+                }
+            }
+            BlobFormat::HashSeq => {
+                if ranges == RangeSpecSeq::all() {
+                    DownloadKind::HashSeq { hash }
+                } else {
+                    DownloadKind::HashSeqRanges { hash, ranges }
+                }
+            }
         }
     }
+
+    /// Normalize this kind, so that equal requests map to equal kinds.
+    fn normalize(self) -> Self {
+        match self {
+            DownloadKind::Blob { .. } | DownloadKind::HashSeq { .. } => self,
+            _ => Self::from_ranges(self.hash_and_format(), self.ranges()),
+        }
+    }
+
+    /// Checks if a download of this kind also downloads everything requested by `other`.
+    fn contains(&self, other: &DownloadKind) -> bool {
+        if self.hash_and_format() != other.hash_and_format() {
+            return false;
+        }
+        self.is_full() || self.ranges().is_superset(&other.ranges())
+    }
+
+    /// Combine two downloads of the same hash and format into a single one.
+    ///
+    /// Returns `None` if the downloads are for different hashes or formats.
+    fn union(&self, other: &DownloadKind) -> Option<DownloadKind> {
+        let hash_and_format = self.hash_and_format();
+        if hash_and_format != other.hash_and_format() {
+            return None;
+        }
+        if self.contains(other) {
+            return Some(self.clone());
+        }
+        if other.contains(self) {
+            return Some(other.clone());
+        }
+        let ranges = self.ranges().union(&other.ranges());
+        Some(Self::from_ranges(hash_and_format, ranges))
+    }
 }
 
 // For readability. In the future we might care about some data reporting on a successful download
@@ -223,8 +344,28 @@ pub struct Downloader {
 }
 
 impl Downloader {
-    /// Create a new Downloader.
+    /// Create a new Downloader with the default [`ConcurrencyLimits`] and [`SwarmConfig`].
     pub fn new<S>(store: S, endpoint: MagicEndpoint, rt: LocalPoolHandle) -> Self
+    where
+        S: Store,
+    {
+        Self::with_config(
+            store,
+            endpoint,
+            rt,
+            ConcurrencyLimits::default(),
+            SwarmConfig::default(),
+        )
+    }
+
+    /// Create a new Downloader with custom [`ConcurrencyLimits`] and [`SwarmConfig`].
+    pub fn with_config<S>(
+        store: S,
+        endpoint: MagicEndpoint,
+        rt: LocalPoolHandle,
+        concurrency_limits: ConcurrencyLimits,
+        swarm_config: SwarmConfig,
+    ) -> Self
     where
         S: Store,
     {
@@ -233,10 +374,19 @@ impl Downloader {
         let dialer = iroh_net::dialer::Dialer::new(endpoint);
 
         let create_future = move || {
-            let concurrency_limits = ConcurrencyLimits::default();
-            let getter = get::IoGetter { store };
+            let max_swarm_providers = swarm_config.max_providers;
+            let getter = get::IoGetter {
+                store,
+                swarm_config,
+            };
 
-            let service = Service::new(getter, dialer, concurrency_limits, msg_rx);
+            let service = Service::new(
+                getter,
+                dialer,
+                concurrency_limits,
+                max_swarm_providers,
+                msg_rx,
+            );
 
             service.run().instrument(error_span!("downloader", %me))
         };
@@ -248,8 +398,13 @@ impl Downloader {
     }
 
     /// Queue a download.
+    ///
+    /// If a download that covers everything requested by `kind` is already queued, the
+    /// returned handle will complete with it. Queued range downloads of the same hash are
+    /// merged into a single request, as long as they have not started yet.
     pub async fn queue(&mut self, kind: DownloadKind, nodes: Vec<NodeInfo>) -> DownloadHandle {
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let kind = kind.normalize();
 
         let (sender, receiver) = oneshot::channel();
         let handle = DownloadHandle {
@@ -373,6 +528,8 @@ struct ActiveRequestInfo {
     cancellation: CancellationToken,
     /// Peer doing this request attempt.
     node: NodeId,
+    /// Additional nodes this request attempt is downloading from concurrently.
+    swarm_nodes: Vec<NodeId>,
 }
 
 /// Information about a request that has not started.
@@ -452,6 +609,8 @@ struct Service<G: Getter, D: Dialer> {
     dialer: D,
     /// Limits to concurrent tasks handled by the service.
     concurrency_limits: ConcurrencyLimits,
+    /// Maximum number of nodes a single blob is downloaded from concurrently.
+    max_swarm_providers: usize,
     /// Channel to receive messages from the service's handle.
     msg_rx: mpsc::Receiver<Message>,
     /// Peers available to use and their relevant information.
@@ -474,6 +633,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         getter: G,
         dialer: D,
         concurrency_limits: ConcurrencyLimits,
+        max_swarm_providers: usize,
         msg_rx: mpsc::Receiver<Message>,
     ) -> Self {
         Service {
@@ -481,6 +641,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             providers: ProviderMap::default(),
             dialer,
             concurrency_limits,
+            max_swarm_providers,
             msg_rx,
             nodes: HashMap::default(),
             goodbye_nodes_queue: delay_queue::DelayQueue::default(),
@@ -565,15 +726,30 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         nodes: Vec<NodeInfo>,
     ) {
         self.providers.add_nodes(*kind.hash(), &nodes);
-        if let Some(info) = self.current_requests.get_mut(&kind) {
+        if let Some((active_kind, info)) = self
+            .current_requests
+            .iter_mut()
+            .find(|(active_kind, _)| active_kind.contains(&kind))
+        {
             // this intent maps to a download that already exists, simply register it
             info.intents.insert(id, sender);
             // increasing the retries by one accounts for multiple intents for the same request in
             // a conservative way
             info.remaining_retries += 1;
-            return trace!(?kind, ?info, "intent registered with active request");
+            return trace!(
+                ?kind,
+                ?active_kind,
+                ?info,
+                "intent registered with active request"
+            );
         }
 
+        // a scheduled request for the same hash is extended to also cover this intent
+        let kind = match self.coalesce_scheduled(&kind) {
+            Some(scheduled_kind) => scheduled_kind,
+            None => kind,
+        };
+
         let needs_node = self
             .scheduled_requests
             .get(&kind)
@@ -612,6 +788,31 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         }
     }
 
+    /// Merges `kind` with a scheduled request for the same hash and format, if there is one.
+    ///
+    /// The scheduled request keeps its intents, retries and position in the schedule, but is
+    /// extended to also download everything requested by `kind`. Returns the kind of the
+    /// merged scheduled request.
+    fn coalesce_scheduled(&mut self, kind: &DownloadKind) -> Option<DownloadKind> {
+        let (scheduled_kind, merged_kind) = self
+            .scheduled_requests
+            .keys()
+            .find_map(|scheduled| Some((scheduled.clone(), scheduled.union(kind)?)))?;
+        if scheduled_kind != merged_kind {
+            let mut info = self
+                .scheduled_requests
+                .remove(&scheduled_kind)
+                .expect("is registered");
+            let expired = self.scheduled_request_queue.remove(&info.delay_key);
+            info.delay_key = self
+                .scheduled_request_queue
+                .insert_at(merged_kind.clone(), expired.deadline());
+            trace!(?scheduled_kind, ?merged_kind, "scheduled request extended");
+            self.scheduled_requests.insert(merged_kind.clone(), info);
+        }
+        Some(merged_kind)
+    }
+
     /// Gets the best candidate for a download.
     ///
     /// Peers are selected prioritizing those with an open connection and with capacity for another
@@ -707,26 +908,51 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
     ///
     /// This removes the registered download intent and, depending on its state, it will either
     /// remove it from the scheduled requests, or cancel the future.
+    ///
+    /// Intents can be registered with a request of a different kind than the one they were
+    /// queued with, if that request covers the intent. The request is thus found by the intent's
+    /// id among the requests for the same hash.
     fn handle_cancel_download(&mut self, id: Id, kind: DownloadKind) {
         let hash = *kind.hash();
         let mut download_removed = false;
-        if let Entry::Occupied(mut occupied_entry) = self.current_requests.entry(kind.clone()) {
+        let active_kind = self
+            .current_requests
+            .iter()
+            .find(|(k, info)| k.hash() == &hash && info.intents.contains_key(&id))
+            .map(|(k, _)| k.clone());
+        let scheduled_kind = self
+            .scheduled_requests
+            .iter()
+            .find(|(k, info)| k.hash() == &hash && info.intents.contains_key(&id))
+            .map(|(k, _)| k.clone());
+        if let Some(active_kind) = active_kind {
             // remove the intent from the associated request
-            let intents = &mut occupied_entry.get_mut().intents;
-            intents.remove(&id);
+            let info = self
+                .current_requests
+                .get_mut(&active_kind)
+                .expect("just found");
+            info.intents.remove(&id);
             // if this was the last intent associated with the request cancel it
-            if intents.is_empty() {
+            if info.intents.is_empty() {
                 download_removed = true;
-                occupied_entry.remove().cancellation.cancel();
+                info.cancellation.cancel();
+                self.current_requests.remove(&active_kind);
             }
-        } else if let Entry::Occupied(mut occupied_entry) = self.scheduled_requests.entry(kind) {
+        } else if let Some(scheduled_kind) = scheduled_kind {
             // remove the intent from the associated request
-            let intents = &mut occupied_entry.get_mut().intents;
-            intents.remove(&id);
+            let info = self
+                .scheduled_requests
+                .get_mut(&scheduled_kind)
+                .expect("just found");
+            info.intents.remove(&id);
             // if this was the last intent associated with the request remove it from the schedule
             // queue
-            if intents.is_empty() {
-                let delay_key = occupied_entry.remove().delay_key;
+            if info.intents.is_empty() {
+                let delay_key = self
+                    .scheduled_requests
+                    .remove(&scheduled_kind)
+                    .expect("just found")
+                    .delay_key;
                 self.scheduled_request_queue.remove(&delay_key);
                 download_removed = true;
             }
@@ -747,37 +973,31 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
 
     /// Checks if this hash is needed.
     fn is_needed(&self, hash: Hash) -> bool {
-        let as_blob = DownloadKind::Blob { hash };
-        let as_hash_seq = DownloadKind::HashSeq { hash };
-        self.current_requests.contains_key(&as_blob)
-            || self.scheduled_requests.contains_key(&as_blob)
-            || self.current_requests.contains_key(&as_hash_seq)
-            || self.scheduled_requests.contains_key(&as_hash_seq)
+        self.is_current_request(hash)
+            || self
+                .scheduled_requests
+                .keys()
+                .any(|kind| kind.hash() == &hash)
     }
 
     /// Check if this hash is currently being downloaded.
     fn is_current_request(&self, hash: Hash) -> bool {
-        let as_blob = DownloadKind::Blob { hash };
-        let as_hash_seq = DownloadKind::HashSeq { hash };
-        self.current_requests.contains_key(&as_blob)
-            || self.current_requests.contains_key(&as_hash_seq)
+        self.current_requests
+            .keys()
+            .any(|kind| kind.hash() == &hash)
     }
 
     /// Remove a hash from the scheduled queue.
     fn unschedule(&mut self, hash: Hash) -> Option<(DownloadKind, PendingRequestInfo)> {
-        let as_blob = DownloadKind::Blob { hash };
-        let as_hash_seq = DownloadKind::HashSeq { hash };
-        let info = match self.scheduled_requests.remove(&as_blob) {
-            Some(req) => Some(req),
-            None => self.scheduled_requests.remove(&as_hash_seq),
-        };
-        if let Some(info) = info {
-            let kind = self.scheduled_request_queue.remove(&info.delay_key);
-            let kind = kind.into_inner();
-            Some((kind, info))
-        } else {
-            None
-        }
+        let kind = self
+            .scheduled_requests
+            .keys()
+            .find(|kind| kind.hash() == &hash)
+            .cloned()?;
+        let info = self.scheduled_requests.remove(&kind)?;
+        let kind = self.scheduled_request_queue.remove(&info.delay_key);
+        let kind = kind.into_inner();
+        Some((kind, info))
     }
 
     /// Handle receiving a new connection.
@@ -841,27 +1061,15 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         let ActiveRequestInfo {
             intents,
             node,
+            swarm_nodes,
             mut remaining_retries,
             ..
         } = info;
 
-        let node_info = self
-            .nodes
-            .get_mut(&node)
-            .expect("node exists in the mapping");
-        node_info.state = match &node_info.state {
-            PeerState::Busy { active_requests } => {
-                match NonZeroUsize::new(active_requests.get() - 1) {
-                    Some(active_requests) => PeerState::Busy { active_requests },
-                    None => {
-                        // last request of the node was this one
-                        let drop_key = self.goodbye_nodes_queue.insert(node, IDLE_PEER_TIMEOUT);
-                        PeerState::Idle { drop_key }
-                    }
-                }
-            }
-            PeerState::Idle { .. } => unreachable!("node was busy"),
-        };
+        self.release_node(node);
+        for swarm_node in &swarm_nodes {
+            self.release_node(*swarm_node);
+        }
 
         let hash = *kind.hash();
 
@@ -882,6 +1090,10 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             }
             Err(FailureAction::DropPeer(reason)) => {
                 debug!(%node, ?kind, %reason, "node will be dropped");
+                let node_info = self
+                    .nodes
+                    .get_mut(&node)
+                    .expect("node exists in the mapping");
                 if let Some(_connection) = node_info.conn.take() {
                     // TODO(@divma): this will fail open streams, do we want this?
                     // connection.close(..)
@@ -911,6 +1123,60 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         if node_ready {
             self.on_node_ready(node);
         }
+        // the nodes that helped with this download are ready in any case
+        for swarm_node in swarm_nodes {
+            self.on_node_ready(swarm_node);
+        }
+    }
+
+    /// Decrements the count of active requests of a node after a request finished.
+    fn release_node(&mut self, node: NodeId) {
+        let node_info = self
+            .nodes
+            .get_mut(&node)
+            .expect("node exists in the mapping");
+        node_info.state = match &node_info.state {
+            PeerState::Busy { active_requests } => {
+                match NonZeroUsize::new(active_requests.get() - 1) {
+                    Some(active_requests) => PeerState::Busy { active_requests },
+                    None => {
+                        // last request of the node was this one
+                        let drop_key = self.goodbye_nodes_queue.insert(node, IDLE_PEER_TIMEOUT);
+                        PeerState::Idle { drop_key }
+                    }
+                }
+            }
+            PeerState::Idle { .. } => unreachable!("node was busy"),
+        };
+    }
+
+    /// Reserves connections to additional providers to download `kind` from concurrently.
+    ///
+    /// Only raw blobs are downloaded from several providers. Nodes are only used if they are
+    /// known to be providers, are connected, and have capacity for another request.
+    fn get_swarm_connections(
+        &mut self,
+        kind: &DownloadKind,
+        node: NodeId,
+    ) -> Vec<(NodeId, D::Connection)> {
+        if self.max_swarm_providers <= 1 || kind.hash_and_format().format != BlobFormat::Raw {
+            return Vec::new();
+        }
+        let max_swarm_nodes = self.max_swarm_providers - 1;
+        let swarm_nodes = self
+            .providers
+            .get_candidates(kind.hash())
+            .filter(|(node_id, role)| **node_id != node && **role == Role::Provider)
+            .map(|(node_id, _role)| *node_id)
+            .collect::<Vec<_>>();
+        swarm_nodes
+            .into_iter()
+            .filter_map(|node_id| {
+                let conn = self.get_node_connection_for_download(&node_id)?;
+                Some((node_id, conn))
+            })
+            .take(max_swarm_nodes)
+            .collect()
     }
 
     /// A scheduled request is ready to be processed.
@@ -976,18 +1242,26 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         remaining_retries: u8,
         intents: HashMap<Id, oneshot::Sender<DownloadResult>>,
     ) {
-        debug!(%node, ?kind, "starting download");
+        let (swarm_nodes, swarm_conns): (Vec<_>, Vec<_>) =
+            self.get_swarm_connections(&kind, node).into_iter().unzip();
+        debug!(%node, ?kind, ?swarm_nodes, "starting download");
         let cancellation = CancellationToken::new();
         let info = ActiveRequestInfo {
             intents,
             remaining_retries,
             cancellation,
             node,
+            swarm_nodes,
         };
         let cancellation = info.cancellation.clone();
         self.current_requests.insert(kind.clone(), info);
 
-        let get = self.getter.get(kind.clone(), conn);
+        let get = if swarm_conns.is_empty() {
+            self.getter.get(kind.clone(), conn)
+        } else {
+            let conns = std::iter::once(conn).chain(swarm_conns).collect();
+            self.getter.get_swarm(kind.clone(), conns)
+        };
         let fut = async move {
             // NOTE: it's an open question if we should do timeouts at this point. Considerations from @Frando:
             // > at this stage we do not know the size of the download, so the timeout would have
@@ -1,23 +1,31 @@
 //! [`Getter`] implementation that performs requests over [`quinn::Connection`]s.
 
 use crate::{
-    get::{db::get_to_db, error::GetError},
+    get::{
+        db::{get_ranges_to_db, get_to_db},
+        error::GetError,
+        Stats,
+    },
+    protocol::RangeSpec,
     store::Store,
     util::progress::IgnoreProgressSender,
 };
+use bao_tree::ChunkRanges;
 use futures::FutureExt;
 #[cfg(feature = "metrics")]
 use iroh_metrics::{inc, inc_by};
+use tracing::debug;
 
 #[cfg(feature = "metrics")]
 use crate::metrics::Metrics;
 
-use super::{DownloadKind, FailureAction, GetFut, Getter};
+use super::{swarm, DownloadKind, FailureAction, GetFut, Getter, SwarmConfig};
 
 impl From<GetError> for FailureAction {
     fn from(e: GetError) -> Self {
         match e {
             e @ GetError::NotFound(_) => FailureAction::AbortRequest(e.into()),
+            e @ GetError::PermissionDenied(_) => FailureAction::AbortRequest(e.into()),
             e @ GetError::RemoteReset(_) => FailureAction::RetryLater(e.into()),
             e @ GetError::NoncompliantNode(_) => FailureAction::DropPeer(e.into()),
             e @ GetError::Io(_) => FailureAction::RetryLater(e.into()),
@@ -31,6 +39,7 @@ impl From<GetError> for FailureAction {
 /// [`Getter`] implementation that performs requests over [`quinn::Connection`]s.
 pub(crate) struct IoGetter<S: Store> {
     pub store: S,
+    pub swarm_config: SwarmConfig,
 }
 
 impl<S: Store> Getter for IoGetter<S> {
@@ -38,39 +47,85 @@ impl<S: Store> Getter for IoGetter<S> {
 
     fn get(&mut self, kind: DownloadKind, conn: Self::Connection) -> GetFut {
         let store = self.store.clone();
-        let progress_sender = IgnoreProgressSender::default();
-        let fut = async move {
-            let get_conn = || async move { Ok(conn) };
-            let res = get_to_db(&store, get_conn, &kind.hash_and_format(), progress_sender).await;
-            match res {
-                Ok(stats) => {
-                    #[cfg(feature = "metrics")]
-                    {
-                        let crate::get::Stats {
-                            bytes_written,
-                            bytes_read: _,
-                            elapsed,
-                        } = stats;
+        get_to_store(store, kind, conn, Stats::default()).boxed_local()
+    }
 
-                        inc!(Metrics, downloads_success);
-                        inc_by!(Metrics, download_bytes_total, bytes_written);
-                        inc_by!(Metrics, download_time_total, elapsed.as_millis() as u64);
-                    }
-                    Ok(stats)
-                }
-                Err(e) => {
-                    // record metrics according to the error
-                    #[cfg(feature = "metrics")]
-                    {
-                        match &e {
-                            GetError::NotFound(_) => inc!(Metrics, downloads_notfound),
-                            _ => inc!(Metrics, downloads_error),
-                        }
+    fn get_swarm(&mut self, kind: DownloadKind, conns: Vec<Self::Connection>) -> GetFut {
+        let store = self.store.clone();
+        let swarm_config = self.swarm_config.clone();
+        let fut = async move {
+            let Some(conn) = conns.first().cloned() else {
+                return Err(FailureAction::AbortRequest(anyhow::anyhow!(
+                    "no connections to swarm"
+                )));
+            };
+            // the swarm is only an optimization, the following regular download gets whatever
+            // is still missing and marks the blob as complete.
+            let ranges = kind
+                .ranges()
+                .iter()
+                .next()
+                .map(RangeSpec::to_chunk_ranges)
+                .unwrap_or_else(ChunkRanges::empty);
+            let stats =
+                match swarm::prefetch(&store, *kind.hash(), &ranges, conns, &swarm_config).await {
+                    Ok(stats) => stats,
+                    Err(err) => {
+                        debug!(?kind, %err, "swarm download failed");
+                        Stats::default()
                     }
-                    Err(e.into())
-                }
-            }
+                };
+            get_to_store(store, kind, conn, stats).await
         };
         fut.boxed_local()
     }
 }
+
+/// Performs the download of `kind` over `conn`, adding `prior_stats` to the reported stats.
+async fn get_to_store<S: Store>(
+    store: S,
+    kind: DownloadKind,
+    conn: quinn::Connection,
+    prior_stats: Stats,
+) -> Result<Stats, FailureAction> {
+    let progress_sender = IgnoreProgressSender::default();
+    let hash_and_format = kind.hash_and_format();
+    let res = if kind.is_full() {
+        let get_conn = || async move { Ok(conn) };
+        get_to_db(&store, get_conn, &hash_and_format, progress_sender).await
+    } else {
+        let ranges = kind.ranges();
+        get_ranges_to_db(&store, conn, &hash_and_format, &ranges, progress_sender).await
+    };
+    match res {
+        Ok(mut stats) => {
+            stats.bytes_written += prior_stats.bytes_written;
+            stats.bytes_read += prior_stats.bytes_read;
+            stats.elapsed += prior_stats.elapsed;
+            #[cfg(feature = "metrics")]
+            {
+                let Stats {
+                    bytes_written,
+                    bytes_read: _,
+                    elapsed,
+                } = stats;
+
+                inc!(Metrics, downloads_success);
+                inc_by!(Metrics, download_bytes_total, bytes_written);
+                inc_by!(Metrics, download_time_total, elapsed.as_millis() as u64);
+            }
+            Ok(stats)
+        }
+        Err(e) => {
+            // record metrics according to the error
+            #[cfg(feature = "metrics")]
+            {
+                match &e {
+                    GetError::NotFound(_) => inc!(Metrics, downloads_notfound),
+                    _ => inc!(Metrics, downloads_error),
+                }
+            }
+            Err(e.into())
+        }
+    }
+}
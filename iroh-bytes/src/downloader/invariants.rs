@@ -63,6 +63,9 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         for req_info in self.current_requests.values() {
             // nothing like some classic word count
             *real_count.entry(req_info.node).or_default() += 1;
+            for node in &req_info.swarm_nodes {
+                *real_count.entry(*node).or_default() += 1;
+            }
         }
         for (peer, info) in self.nodes.iter() {
             assert_eq!(
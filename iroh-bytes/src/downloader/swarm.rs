@@ -0,0 +1,356 @@
+//! Downloading a single blob from multiple providers concurrently.
+//!
+//! The chunks of the blob that are missing locally are split into segments of
+//! [`SwarmConfig::segment_size`]. Every provider fetches one segment at a time, so providers
+//! that are faster end up fetching more segments. Once every segment has been assigned, idle
+//! providers also fetch segments that are still in flight at other providers, and whichever
+//! finishes first wins. This way a single slow provider can not stall the download. The
+//! providers that lost are cancelled, but only between writes, so the shared entry never
+//! contains partially written data.
+//!
+//! Every segment is verified against the blob's hash while it is written to the store.
+
+use std::collections::VecDeque;
+
+use bao_tree::{ByteNum, ChunkNum, ChunkRanges};
+use futures::{stream::FuturesUnordered, Future, StreamExt};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace};
+
+use crate::{
+    get::{
+        db::{get_blob_segment, valid_ranges},
+        request::get_verified_size,
+        Stats,
+    },
+    store::{MapEntry, Store},
+    Hash,
+};
+
+/// Configuration for downloading a single blob from multiple providers.
+#[derive(Debug, Clone)]
+pub struct SwarmConfig {
+    /// Maximum number of providers to download a single blob from concurrently.
+    ///
+    /// Setting this to `1` disables swarming.
+    pub max_providers: usize,
+    /// Size of the segments, in bytes, the blob is split into.
+    pub segment_size: u64,
+    /// Blobs smaller than this, in bytes, are always downloaded from a single provider.
+    pub min_blob_size: u64,
+}
+
+impl Default for SwarmConfig {
+    fn default() -> Self {
+        SwarmConfig {
+            max_providers: 4,
+            segment_size: 1024 * 1024 * 4,
+            min_blob_size: 1024 * 1024 * 16,
+        }
+    }
+}
+
+impl SwarmConfig {
+    /// Segment size in chunks.
+    fn segment_chunks(&self) -> u64 {
+        ByteNum(self.segment_size).chunks().0.max(1)
+    }
+}
+
+/// Fetches the `ranges` of a blob that are missing locally, using all `conns` concurrently.
+///
+/// This is best effort: segments that could not be fetched from any provider are left to a
+/// following regular download. The entry is never marked as complete.
+pub(super) async fn prefetch<S: Store>(
+    store: &S,
+    hash: Hash,
+    ranges: &ChunkRanges,
+    conns: Vec<quinn::Connection>,
+    config: &SwarmConfig,
+) -> anyhow::Result<Stats> {
+    let Some(conn) = conns.first() else {
+        return Ok(Stats::default());
+    };
+    let (size, mut stats) = get_verified_size(conn, &hash).await?;
+    if size < config.min_blob_size {
+        trace!(%hash, size, "blob too small to swarm");
+        return Ok(stats);
+    }
+    let entry = match store.get_mut(&hash).await? {
+        Some(entry) if entry.is_complete() => return Ok(stats),
+        Some(entry) => entry,
+        None => store.get_or_create(hash, size).await?,
+    };
+    let local = valid_ranges::<S>(&entry)
+        .await
+        .unwrap_or_else(|_| ChunkRanges::empty());
+    let blob_ranges = ChunkRanges::from(..ByteNum(size).chunks());
+    let wanted: ChunkRanges = blob_ranges.intersection(ranges);
+    let wanted: ChunkRanges = wanted.difference(&local);
+    let segments = split_ranges(&wanted, config.segment_chunks());
+    debug!(%hash, size, segments = segments.len(), providers = conns.len(), "swarming download");
+
+    let (segment_stats, remaining) = fetch_segments(conns, segments, |conn, segment, cancel| {
+        let entry = entry.clone();
+        async move {
+            let stats = get_blob_segment(conn, &hash, entry, &segment, &cancel).await?;
+            anyhow::Ok(stats)
+        }
+    })
+    .await;
+    if !remaining.is_empty() {
+        debug!(%hash, remaining = remaining.len(), "some segments could not be fetched");
+    }
+    stats.bytes_written += segment_stats.bytes_written;
+    stats.bytes_read += segment_stats.bytes_read;
+    // the segments were fetched after the size, so the durations add up
+    stats.elapsed += segment_stats.elapsed;
+    Ok(stats)
+}
+
+/// Splits `ranges` into segments aligned to multiples of `segment_chunks`.
+///
+/// `ranges` must be bounded.
+fn split_ranges(ranges: &ChunkRanges, segment_chunks: u64) -> Vec<ChunkRanges> {
+    let Some(end) = ranges.boundaries().last() else {
+        return Vec::new();
+    };
+    (0..end.0.div_ceil(segment_chunks))
+        .map(|i| {
+            let cell =
+                ChunkRanges::from(ChunkNum(i * segment_chunks)..ChunkNum((i + 1) * segment_chunks));
+            cell.intersection::<[ChunkNum; 2]>(ranges)
+        })
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// A segment being fetched from a provider.
+#[derive(Debug)]
+struct InFlight {
+    /// Index of the segment.
+    segment: usize,
+    /// Index of the provider.
+    provider: usize,
+    /// Cancels the fetch if another provider was faster.
+    cancel: CancellationToken,
+}
+
+/// Fetches `segments` from `providers`, one segment per provider at a time.
+///
+/// A provider for which `fetch` fails is not used again, and its segment is assigned to
+/// another provider. Once a segment is done, the token passed to the other fetches of the
+/// same segment is cancelled, and their futures are polled until they return.
+///
+/// Returns the accumulated [`Stats`] and the segments that could not be fetched from any
+/// provider. Since segments are fetched concurrently, the elapsed time of the stats is the
+/// wall clock time of the whole call rather than the sum of the individual fetches.
+pub(super) async fn fetch_segments<P, F, Fut>(
+    providers: Vec<P>,
+    segments: Vec<ChunkRanges>,
+    mut fetch: F,
+) -> (Stats, Vec<ChunkRanges>)
+where
+    P: Clone,
+    F: FnMut(P, ChunkRanges, CancellationToken) -> Fut,
+    Fut: Future<Output = anyhow::Result<Stats>>,
+{
+    let start = Instant::now();
+    let mut stats = Stats::default();
+    let mut queue: VecDeque<usize> = (0..segments.len()).collect();
+    let mut done = vec![false; segments.len()];
+    let mut idle: VecDeque<usize> = (0..providers.len()).collect();
+    // ordered by the time the fetch was started
+    let mut in_flight: Vec<InFlight> = Vec::new();
+    let mut futs = FuturesUnordered::new();
+    loop {
+        while let Some(provider) = idle.pop_front() {
+            let segment = match queue.pop_front() {
+                Some(segment) => segment,
+                None => {
+                    // help with the oldest segment that is only fetched by a single provider
+                    let helpable = in_flight
+                        .iter()
+                        .find(|a| in_flight.iter().filter(|b| b.segment == a.segment).count() == 1);
+                    match helpable {
+                        Some(other) => other.segment,
+                        None => {
+                            idle.push_front(provider);
+                            break;
+                        }
+                    }
+                }
+            };
+            trace!(segment, provider, "fetching segment");
+            let cancel = CancellationToken::new();
+            let fut = fetch(
+                providers[provider].clone(),
+                segments[segment].clone(),
+                cancel.clone(),
+            );
+            futs.push(async move { (provider, segment, fut.await) });
+            in_flight.push(InFlight {
+                segment,
+                provider,
+                cancel,
+            });
+        }
+
+        let Some((provider, segment, res)) = futs.next().await else {
+            break;
+        };
+        let index = in_flight
+            .iter()
+            .position(|f| f.provider == provider && f.segment == segment)
+            .expect("fetch is in flight");
+        let cancelled = in_flight.remove(index).cancel.is_cancelled();
+        match res {
+            Ok(segment_stats) => {
+                done[segment] = true;
+                stats.bytes_written += segment_stats.bytes_written;
+                stats.bytes_read += segment_stats.bytes_read;
+                // the segment is done, stop other providers fetching it
+                for other in in_flight.iter().filter(|f| f.segment == segment) {
+                    other.cancel.cancel();
+                }
+                idle.push_back(provider);
+            }
+            Err(_) if cancelled => {
+                // another provider was faster
+                idle.push_back(provider);
+            }
+            Err(err) => {
+                debug!(segment, provider, %err, "fetching segment failed, dropping provider");
+                let pending = in_flight.iter().any(|f| f.segment == segment);
+                if !done[segment] && !pending {
+                    queue.push_front(segment);
+                }
+            }
+        }
+    }
+
+    let remaining = segments
+        .into_iter()
+        .zip(done)
+        .filter_map(|(segment, done)| (!done).then_some(segment))
+        .collect();
+    stats.elapsed = start.elapsed();
+    (stats, remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+
+    fn segments(n: u64) -> Vec<ChunkRanges> {
+        (0..n)
+            .map(|i| ChunkRanges::from(ChunkNum(i * 16)..ChunkNum((i + 1) * 16)))
+            .collect()
+    }
+
+    #[test]
+    fn split_ranges_aligned() {
+        let ranges = &ChunkRanges::from(ChunkNum(3)..ChunkNum(10))
+            | &ChunkRanges::from(ChunkNum(14)..ChunkNum(17));
+        let split = split_ranges(&ranges, 8);
+        assert_eq!(
+            split,
+            vec![
+                ChunkRanges::from(ChunkNum(3)..ChunkNum(8)),
+                &ChunkRanges::from(ChunkNum(8)..ChunkNum(10))
+                    | &ChunkRanges::from(ChunkNum(14)..ChunkNum(16)),
+                ChunkRanges::from(ChunkNum(16)..ChunkNum(17)),
+            ]
+        );
+        assert!(split_ranges(&ChunkRanges::empty(), 8).is_empty());
+    }
+
+    /// All segments are fetched, and every provider participates.
+    #[tokio::test(start_paused = true)]
+    async fn fetch_segments_all_providers() {
+        let history = Arc::new(Mutex::new(Vec::new()));
+        let (_stats, remaining) = fetch_segments(vec![0, 1, 2], segments(12), |provider, _, _| {
+            let history = history.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                history.lock().unwrap().push(provider);
+                anyhow::Ok(Stats::default())
+            }
+        })
+        .await;
+        assert!(remaining.is_empty());
+        let history = history.lock().unwrap();
+        assert_eq!(history.len(), 12);
+        for provider in 0..3 {
+            assert_eq!(history.iter().filter(|p| **p == provider).count(), 4);
+        }
+    }
+
+    /// Segments of a failing provider are fetched from the others.
+    #[tokio::test(start_paused = true)]
+    async fn fetch_segments_failing_provider() {
+        let (_stats, remaining) =
+            fetch_segments(vec![0, 1], segments(8), |provider, _, _| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                anyhow::ensure!(provider != 0, "provider 0 fails");
+                Ok(Stats::default())
+            })
+            .await;
+        assert!(remaining.is_empty());
+
+        let (_stats, remaining) = fetch_segments(vec![0], segments(8), |_, _, _| async move {
+            Err::<Stats, _>(anyhow::anyhow!("always fails"))
+        })
+        .await;
+        assert_eq!(remaining, segments(8));
+    }
+
+    /// A slow provider does not stall the download.
+    #[tokio::test(start_paused = true)]
+    async fn fetch_segments_slow_provider() {
+        let start = tokio::time::Instant::now();
+        let (stats, remaining) =
+            fetch_segments(vec![0, 1], segments(4), |provider, _, cancel| async move {
+                let delay = if provider == 0 { 3600 } else { 1 };
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(delay)) => {}
+                    _ = cancel.cancelled() => anyhow::bail!("cancelled"),
+                }
+                anyhow::Ok(Stats::default())
+            })
+            .await;
+        assert!(remaining.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(60));
+        assert_eq!(stats.elapsed, start.elapsed());
+    }
+
+    /// Fetches of a segment that is done elsewhere are cancelled, but not dropped.
+    #[tokio::test(start_paused = true)]
+    async fn fetch_segments_cancelled_fetch_completes() {
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let (_stats, remaining) = fetch_segments(vec![0, 1], segments(1), |provider, _, cancel| {
+            let finished = finished.clone();
+            async move {
+                if provider == 0 {
+                    // a slow provider that is still writing when it is cancelled
+                    cancel.cancelled().await;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    finished.lock().unwrap().push(provider);
+                    anyhow::bail!("cancelled");
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                finished.lock().unwrap().push(provider);
+                anyhow::Ok(Stats::default())
+            }
+        })
+        .await;
+        assert!(remaining.is_empty());
+        assert_eq!(*finished.lock().unwrap(), vec![1, 0]);
+    }
+}
@@ -1,6 +1,7 @@
 #![cfg(test)]
 use std::time::Duration;
 
+use bao_tree::ChunkNum;
 use iroh_net::key::SecretKey;
 
 use super::*;
@@ -20,7 +21,14 @@ impl Downloader {
             // we want to see the logs of the service
             let _guard = iroh_test::logging::setup();
 
-            let service = Service::new(getter, dialer, concurrency_limits, msg_rx);
+            let max_swarm_providers = SwarmConfig::default().max_providers;
+            let service = Service::new(
+                getter,
+                dialer,
+                concurrency_limits,
+                max_swarm_providers,
+                msg_rx,
+            );
             service.run().await
         });
 
@@ -252,3 +260,183 @@ async fn peer_role_provider() {
     getter.assert_history(&[(kind, peer_provider)]);
     dialer.assert_history(&[peer_provider]);
 }
+
+/// Tests that scheduled range requests of the same blob are merged into a single request.
+#[tokio::test]
+async fn coalesce_ranges() {
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    let concurrency_limits = ConcurrencyLimits::default();
+
+    let mut downloader =
+        Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let peer = SecretKey::generate().public();
+    let hash = Hash::new([0u8; 32]);
+    let kind_1 = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::new(ChunkRanges::from(ChunkNum(0)..ChunkNum(4))),
+    };
+    let kind_2 = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::new(ChunkRanges::from(ChunkNum(8)..ChunkNum(12))),
+    };
+    let handle_1 = downloader
+        .queue(kind_1, vec![(peer, Role::Candidate).into()])
+        .await;
+    let handle_2 = downloader
+        .queue(kind_2, vec![(peer, Role::Candidate).into()])
+        .await;
+    handle_1.await.expect("should report success");
+    handle_2.await.expect("should report success");
+
+    // verify that a single request for both ranges was sent
+    let merged = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::new(
+            &ChunkRanges::from(ChunkNum(0)..ChunkNum(4))
+                | &ChunkRanges::from(ChunkNum(8)..ChunkNum(12)),
+        ),
+    };
+    getter.assert_history(&[(merged, peer)]);
+}
+
+/// Tests that range requests covered by an active request are registered with it.
+#[tokio::test]
+async fn ranges_covered_by_active_request() {
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    // make request take some time to ensure the second intent is received before completion
+    getter.set_request_duration(Duration::from_secs(1));
+    let concurrency_limits = ConcurrencyLimits::default();
+
+    let mut downloader =
+        Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let peer = SecretKey::generate().public();
+    let hash = Hash::new([0u8; 32]);
+    let kind = DownloadKind::Blob { hash };
+    // a provider is used right away, so the request becomes active without initial delay
+    let handle_full = downloader
+        .queue(kind.clone(), vec![(peer, Role::Provider).into()])
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let handle_range = downloader
+        .queue(
+            DownloadKind::blob_bytes(hash, 1024..4096),
+            vec![(peer, Role::Provider).into()],
+        )
+        .await;
+    handle_full.await.expect("should report success");
+    handle_range.await.expect("should report success");
+
+    // verify that the request was sent just once
+    getter.assert_history(&[(kind, peer)]);
+}
+
+/// Tests that cancelling an intent merged into another request does not cancel the request.
+#[tokio::test]
+async fn cancel_coalesced_ranges() {
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    let concurrency_limits = ConcurrencyLimits::default();
+
+    let mut downloader =
+        Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let peer = SecretKey::generate().public();
+    let hash = Hash::new([0u8; 32]);
+    let kind_1 = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::new(ChunkRanges::from(ChunkNum(0)..ChunkNum(4))),
+    };
+    let kind_2 = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::new(ChunkRanges::from(ChunkNum(2)..ChunkNum(3))),
+    };
+    let handle_1 = downloader
+        .queue(kind_1.clone(), vec![(peer, Role::Candidate).into()])
+        .await;
+    let handle_2 = downloader
+        .queue(kind_2, vec![(peer, Role::Candidate).into()])
+        .await;
+    downloader.cancel(handle_2).await;
+    handle_1.await.expect("should report success");
+
+    // the second range is contained in the first, so just the first request was sent
+    getter.assert_history(&[(kind_1, peer)]);
+}
+
+/// Tests that range requests covering everything are treated like full requests.
+#[test]
+fn normalize_full_ranges() {
+    let hash = Hash::new([0u8; 32]);
+    let kind = DownloadKind::BlobRanges {
+        hash,
+        ranges: RangeSpec::all(),
+    };
+    assert_eq!(kind.normalize(), DownloadKind::Blob { hash });
+    let kind = DownloadKind::HashSeqRanges {
+        hash,
+        ranges: RangeSpecSeq::all(),
+    };
+    assert_eq!(kind.normalize(), DownloadKind::HashSeq { hash });
+    // a blob and a hash seq of the same hash are never merged
+    assert!(DownloadKind::Blob { hash }
+        .union(&DownloadKind::HashSeq { hash })
+        .is_none());
+}
+
+/// Tests that a blob is downloaded from all connected providers concurrently.
+#[tokio::test]
+async fn swarm_connected_providers() {
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    let concurrency_limits = ConcurrencyLimits::default();
+
+    let mut downloader =
+        Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    // connect to both peers by downloading something from each of them
+    let peer_1 = SecretKey::from_bytes(&[0u8; 32]).public();
+    let peer_2 = SecretKey::from_bytes(&[1u8; 32]).public();
+    let kind_1 = DownloadKind::Blob {
+        hash: Hash::new([1u8; 32]),
+    };
+    let kind_2 = DownloadKind::Blob {
+        hash: Hash::new([2u8; 32]),
+    };
+    let handle_1 = downloader
+        .queue(kind_1.clone(), vec![(peer_1, Role::Provider).into()])
+        .await;
+    let handle_2 = downloader
+        .queue(kind_2.clone(), vec![(peer_2, Role::Provider).into()])
+        .await;
+    handle_1.await.expect("should report success");
+    handle_2.await.expect("should report success");
+
+    // both peers are connected and idle now, so both are used for this download
+    let kind = DownloadKind::Blob {
+        hash: Hash::new([0u8; 32]),
+    };
+    let handle = downloader
+        .queue(
+            kind.clone(),
+            vec![
+                (peer_1, Role::Provider).into(),
+                (peer_2, Role::Provider).into(),
+            ],
+        )
+        .await;
+    handle.await.expect("should report success");
+
+    getter.assert_history(&[(kind_1, peer_1), (kind_2, peer_2)]);
+    let swarm_history = getter.swarm_history();
+    assert_eq!(swarm_history.len(), 1);
+    let (swarm_kind, mut swarm_peers) = swarm_history[0].clone();
+    assert_eq!(swarm_kind, kind);
+    swarm_peers.sort();
+    let mut expected_peers = vec![peer_1, peer_2];
+    expected_peers.sort();
+    assert_eq!(swarm_peers, expected_peers);
+}
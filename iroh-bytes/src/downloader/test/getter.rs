@@ -15,6 +15,8 @@ struct TestingGetterInner {
     request_duration: Duration,
     /// History of requests performed by the [`Getter`] and if they were successful.
     request_history: Vec<(DownloadKind, NodeId)>,
+    /// History of requests performed by the [`Getter`] from multiple nodes.
+    swarm_history: Vec<(DownloadKind, Vec<NodeId>)>,
 }
 
 impl Getter for TestingGetter {
@@ -32,6 +34,17 @@ impl Getter for TestingGetter {
         }
         .boxed_local()
     }
+
+    fn get_swarm(&mut self, kind: DownloadKind, peers: Vec<NodeId>) -> GetFut {
+        let mut inner = self.0.write();
+        inner.swarm_history.push((kind, peers));
+        let request_duration = inner.request_duration;
+        async move {
+            tokio::time::sleep(request_duration).await;
+            Ok(Stats::default())
+        }
+        .boxed_local()
+    }
 }
 
 impl TestingGetter {
@@ -43,4 +56,9 @@ impl TestingGetter {
     pub(super) fn assert_history(&self, history: &[(DownloadKind, NodeId)]) {
         assert_eq!(self.0.read().request_history, history);
     }
+
+    /// Get the history of requests performed from multiple nodes.
+    pub(super) fn swarm_history(&self) -> Vec<(DownloadKind, Vec<NodeId>)> {
+        self.0.read().swarm_history.clone()
+    }
 }
@@ -28,6 +28,8 @@ use crate::{
 use anyhow::anyhow;
 use bao_tree::{ByteNum, ChunkRanges};
 use iroh_io::AsyncSliceReader;
+#[cfg(feature = "downloader")]
+use tokio_util::sync::CancellationToken;
 use tracing::trace;
 
 /// Get a blob or collection into a store.
@@ -140,6 +142,356 @@ async fn get_blob<
     Ok(stats)
 }
 
+/// Get some chunk ranges of a blob or hash sequence into a store.
+///
+/// Like [`get_to_db`], this considers data that is already in the store, and will only request
+/// the missing parts of `ranges`. Entries are only marked as complete once all their data is
+/// available.
+///
+/// For hash sequences the first [`RangeSpec`] of `ranges` is for the hash sequence itself,
+/// the following ones for its children. The hash sequence is always downloaded entirely, since
+/// it is needed to know the hashes of the children.
+pub async fn get_ranges_to_db<D: BaoStore>(
+    db: &D,
+    conn: quinn::Connection,
+    hash_and_format: &HashAndFormat,
+    ranges: &RangeSpecSeq,
+    sender: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<Stats, GetError> {
+    let HashAndFormat { hash, format } = hash_and_format;
+    match format {
+        BlobFormat::Raw => {
+            // the iterator is infinite, so there is always a first element
+            let ranges = ranges
+                .iter()
+                .next()
+                .map(RangeSpec::to_chunk_ranges)
+                .unwrap_or_else(ChunkRanges::empty);
+            get_blob_ranges(db, conn, hash, &ranges, sender).await
+        }
+        BlobFormat::HashSeq => get_hash_seq_ranges(db, conn, hash, ranges, sender).await,
+    }
+}
+
+/// Get some chunk ranges of a single blob.
+async fn get_blob_ranges<D: BaoStore>(
+    db: &D,
+    conn: quinn::Connection,
+    hash: &Hash,
+    ranges: &ChunkRanges,
+    progress: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<Stats, GetError> {
+    let (entry, local_ranges) = match db.get_mut(hash).await? {
+        Some(entry) if entry.is_complete() => {
+            tracing::info!("already got entire blob");
+            progress
+                .send(DownloadProgress::FoundLocal {
+                    child: 0,
+                    hash: *hash,
+                    size: entry.size(),
+                    valid_ranges: RangeSpec::all(),
+                })
+                .await?;
+            return Ok(Stats::default());
+        }
+        Some(entry) => {
+            trace!("got partial data for {}", hash);
+            let valid_ranges = valid_ranges::<D>(&entry)
+                .await
+                .ok()
+                .unwrap_or_else(ChunkRanges::empty);
+            progress
+                .send(DownloadProgress::FoundLocal {
+                    child: 0,
+                    hash: *hash,
+                    size: entry.size(),
+                    valid_ranges: RangeSpec::new(&valid_ranges),
+                })
+                .await?;
+            (Some(entry), valid_ranges)
+        }
+        None => (None, ChunkRanges::empty()),
+    };
+    let required_ranges: ChunkRanges = ranges.difference(&local_ranges);
+    if required_ranges.is_empty() {
+        trace!("already got requested ranges of {}", hash);
+        return Ok(Stats::default());
+    }
+
+    let request = GetRequest::new(*hash, RangeSpecSeq::from_ranges([required_ranges.clone()]));
+    let request = get::fsm::start(conn, request);
+    // create a new bidi stream
+    let connected = request.next().await?;
+    // next step. we have requested a single hash, so this must be StartRoot
+    let ConnectedNext::StartRoot(start) = connected.next().await? else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartRoot")));
+    };
+    // move to the header
+    let header = start.next();
+    let (end, entry, size) = get_blob_ranges_inner(db, header, entry, progress).await?;
+    if covers_blob(size, &local_ranges, &required_ranges) {
+        db.insert_complete(entry).await?;
+    }
+    // we have requested a single hash, so we must be at closing
+    let EndBlobNext::Closing(end) = end.next() else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartRoot")));
+    };
+    // this closes the bidi stream. Do something with the stats?
+    let stats = end.next().await?;
+    Ok(stats)
+}
+
+/// Get some chunk ranges of a hash sequence's children.
+async fn get_hash_seq_ranges<D: BaoStore>(
+    db: &D,
+    conn: quinn::Connection,
+    root_hash: &Hash,
+    ranges: &RangeSpecSeq,
+    sender: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<Stats, GetError> {
+    use tracing::info as log;
+    // we need the entire hash seq to know the children
+    let mut stats = get_blob_ranges(
+        db,
+        conn.clone(),
+        root_hash,
+        &ChunkRanges::all(),
+        sender.clone(),
+    )
+    .await?;
+    let entry = db
+        .get(root_hash)
+        .await?
+        .ok_or_else(|| GetError::LocalFailure(anyhow!("just downloaded but not in db")))?;
+    let reader = entry.data_reader().await?;
+    let (mut hash_seq, count) = parse_hash_seq(reader).await.map_err(|err| {
+        GetError::NoncompliantNode(anyhow!("Failed to parse downloaded HashSeq: {err}"))
+    })?;
+    sender
+        .send(DownloadProgress::FoundHashSeq {
+            hash: *root_hash,
+            children: count,
+        })
+        .await?;
+    let mut children: Vec<Hash> = vec![];
+    while let Some(hash) = hash_seq.next().await? {
+        children.push(hash);
+    }
+    let infos = blob_infos(db, &children).await?;
+    // send the info about what we have
+    for (i, info) in infos.iter().enumerate() {
+        if let Some(size) = info.size() {
+            sender
+                .send(DownloadProgress::FoundLocal {
+                    child: (i as u64) + 1,
+                    hash: children[i],
+                    size,
+                    valid_ranges: RangeSpec::new(&info.valid_ranges()),
+                })
+                .await?;
+        }
+    }
+    // the first range spec is for the hash seq itself, which we already have
+    let required = ranges
+        .iter()
+        .skip(1)
+        .zip(infos.iter())
+        .map(|(spec, info)| spec.to_chunk_ranges().difference(&info.valid_ranges()))
+        .collect::<Vec<ChunkRanges>>();
+    if required.iter().all(|ranges| ranges.is_empty()) {
+        log!("nothing to do");
+        return Ok(stats);
+    }
+
+    log!("requesting chunks {:?}", required);
+    let request = GetRequest::new(
+        *root_hash,
+        RangeSpecSeq::from_ranges(
+            std::iter::once(ChunkRanges::empty()).chain(required.iter().cloned()),
+        ),
+    );
+    let request = get::fsm::start(conn, request);
+    // create a new bidi stream
+    let connected = request.next().await?;
+    // we have not requested the root, so this must be StartChild
+    let ConnectedNext::StartChild(start) = connected.next().await? else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartChild")));
+    };
+    let mut next = EndBlobNext::MoreChildren(start);
+    // read all the children
+    let finishing = loop {
+        let start = match next {
+            EndBlobNext::MoreChildren(start) => start,
+            EndBlobNext::Closing(finish) => break finish,
+        };
+        let child_offset = usize::try_from(start.child_offset())
+            .map_err(|_| GetError::NoncompliantNode(anyhow!("child offset too large")))?;
+        let (child_hash, info, required_ranges) = match (
+            children.get(child_offset),
+            infos.get(child_offset),
+            required.get(child_offset),
+        ) {
+            (Some(blob), Some(info), Some(required_ranges)) => (*blob, info, required_ranges),
+            _ => break start.finish(),
+        };
+        let (entry, local_ranges) = match info {
+            BlobInfo::Missing => (None, ChunkRanges::empty()),
+            BlobInfo::Partial {
+                entry,
+                valid_ranges,
+            } => (Some(entry.clone()), valid_ranges.clone()),
+            BlobInfo::Complete { .. } => {
+                return Err(GetError::NoncompliantNode(anyhow!(
+                    "got data we have not requested"
+                )));
+            }
+        };
+        let header = start.next(child_hash);
+        let (end_blob, entry, size) =
+            get_blob_ranges_inner(db, header, entry, sender.clone()).await?;
+        if covers_blob(size, &local_ranges, required_ranges) {
+            db.insert_complete(entry).await?;
+        }
+        next = end_blob.next();
+    };
+    // this closes the bidi stream
+    let children_stats = finishing.next().await?;
+    stats.bytes_written += children_stats.bytes_written;
+    stats.bytes_read += children_stats.bytes_read;
+    stats.elapsed += children_stats.elapsed;
+    Ok(stats)
+}
+
+/// Download some chunk ranges of a blob into an existing entry.
+///
+/// Unlike [`get_ranges_to_db`] this neither checks which data is available locally nor marks
+/// the entry as complete. This allows downloading different ranges of the same blob
+/// concurrently.
+///
+/// The download stops early once `cancel` is triggered. Cancellation only takes effect while
+/// waiting for the remote, a batch that is being written to `entry` is always completed.
+#[cfg(feature = "downloader")]
+pub(crate) async fn get_blob_segment<E: MapEntryMut>(
+    conn: quinn::Connection,
+    hash: &Hash,
+    entry: E,
+    ranges: &ChunkRanges,
+    cancel: &CancellationToken,
+) -> Result<Stats, GetError> {
+    let request = GetRequest::new(*hash, RangeSpecSeq::from_ranges([ranges]));
+    let request = get::fsm::start(conn, request);
+    // create a new bidi stream
+    let connected = or_cancelled(cancel, request.next()).await??;
+    // next step. we have requested a single hash, so this must be StartRoot
+    let ConnectedNext::StartRoot(start) = or_cancelled(cancel, connected.next()).await?? else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartRoot")));
+    };
+    // move to the header
+    let header = start.next();
+    let (mut content, size) = or_cancelled(cancel, header.next()).await??;
+    let mut bw = entry.batch_writer().await?;
+    let mut batch = Vec::new();
+    let end = loop {
+        match or_cancelled(cancel, content.next()).await? {
+            get::fsm::BlobContentNext::More((next, item)) => {
+                let item = item?;
+                let is_leaf = matches!(item, bao_tree::io::fsm::BaoContentItem::Leaf(_));
+                batch.push(item);
+                // write complete batches, without racing against the cancellation
+                if is_leaf {
+                    bw.write_batch(size, std::mem::take(&mut batch)).await?;
+                }
+                content = next;
+            }
+            get::fsm::BlobContentNext::Done(end) => break end,
+        }
+    };
+    bw.sync().await?;
+    drop(bw);
+    // we have requested a single hash, so we must be at closing
+    let EndBlobNext::Closing(end) = end.next() else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartRoot")));
+    };
+    // this closes the bidi stream
+    let stats = end.next().await?;
+    Ok(stats)
+}
+
+/// Runs `fut` to completion, unless `cancel` is triggered first.
+#[cfg(feature = "downloader")]
+async fn or_cancelled<T>(
+    cancel: &CancellationToken,
+    fut: impl Future<Output = T>,
+) -> Result<T, GetError> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(GetError::Io(anyhow!("download cancelled"))),
+        res = fut => Ok(res),
+    }
+}
+
+/// Checks if `local_ranges` together with the downloaded `requested_ranges` cover an entire
+/// blob of the given size.
+fn covers_blob(size: u64, local_ranges: &ChunkRanges, requested_ranges: &ChunkRanges) -> bool {
+    let all_ranges = ChunkRanges::from(..ByteNum(size).chunks());
+    let missing: ChunkRanges = all_ranges.difference(&(local_ranges | requested_ranges));
+    missing.is_empty()
+}
+
+/// Get some chunk ranges of a blob, writing them to the given entry or a new one.
+///
+/// Returns the entry the data was written to and the size of the blob.
+async fn get_blob_ranges_inner<D: BaoStore>(
+    db: &D,
+    at_header: AtBlobHeader,
+    entry: Option<D::EntryMut>,
+    sender: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<(AtEndBlob, D::EntryMut, u64), GetError> {
+    // read the size. The size we get here is not verified, but since we use
+    // it for the tree traversal we are guaranteed not to get more than size.
+    let (at_content, size) = at_header.next().await?;
+    let hash = at_content.hash();
+    let child_offset = at_content.offset();
+    // get or create the partial entry
+    let entry = match entry {
+        Some(entry) => entry,
+        None => db.get_or_create(hash, size).await?,
+    };
+    // create a batch writer for the bao file
+    let bw = entry.batch_writer().await?;
+    // allocate a new id for progress reports for this transfer
+    let id = sender.new_id();
+    sender
+        .send(DownloadProgress::Found {
+            id,
+            hash,
+            size,
+            child: child_offset,
+        })
+        .await?;
+    let sender2 = sender.clone();
+    let on_write = move |offset: u64, _length: usize| {
+        // if try send fails it means that the receiver has been dropped.
+        // in that case we want to abort the write_all_with_outboard.
+        sender2
+            .try_send(DownloadProgress::Progress { id, offset })
+            .map_err(|e| {
+                tracing::info!("aborting download of {}", hash);
+                e
+            })?;
+        Ok(())
+    };
+    let mut bw = FallibleProgressBatchWriter::new(bw, on_write);
+    // use the convenience method to write all to the batch writer
+    let at_end = at_content.write_all_batch(&mut bw).await?;
+    // sync the underlying storage, if needed
+    bw.sync().await?;
+    drop(bw);
+    // notify that we are done
+    sender.send(DownloadProgress::Done { id }).await?;
+    Ok((at_end, entry, size))
+}
+
 /// Given a partial entry, get the valid ranges.
 pub async fn valid_ranges<D: MapMut>(entry: &D::EntryMut) -> anyhow::Result<ChunkRanges> {
     use tracing::trace as log;
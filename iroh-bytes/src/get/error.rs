@@ -1,6 +1,6 @@
 //! Error returned from get operations
 
-use crate::util::progress::ProgressSendError;
+use crate::{protocol::Closed, util::progress::ProgressSendError};
 
 /// Failures for a get operation
 #[derive(Debug, thiserror::Error)]
@@ -8,6 +8,9 @@ pub enum GetError {
     /// Hash not found.
     #[error("Hash not found")]
     NotFound(#[source] anyhow::Error),
+    /// Remote refused to serve the request.
+    #[error("Permission denied")]
+    PermissionDenied(#[source] anyhow::Error),
     /// Remote has reset the connection.
     #[error("Remote has reset the connection")]
     RemoteReset(#[source] anyhow::Error),
@@ -77,6 +80,9 @@ impl From<quinn::ConnectionError> for GetError {
 impl From<quinn::ReadError> for GetError {
     fn from(value: quinn::ReadError) -> Self {
         match value {
+            quinn::ReadError::Reset(code) if is_permission_denied(code) => {
+                GetError::PermissionDenied(value.into())
+            }
             e @ quinn::ReadError::Reset(_) => GetError::RemoteReset(e.into()),
             quinn::ReadError::ConnectionLost(conn_error) => conn_error.into(),
             quinn::ReadError::UnknownStream
@@ -89,6 +95,11 @@ impl From<quinn::ReadError> for GetError {
     }
 }
 
+/// Whether the provider reset the stream because it rejected the request.
+fn is_permission_denied(code: quinn::VarInt) -> bool {
+    matches!(Closed::try_from(code), Ok(Closed::PermissionDenied))
+}
+
 impl From<quinn::WriteError> for GetError {
     fn from(value: quinn::WriteError) -> Self {
         match value {
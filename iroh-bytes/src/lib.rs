@@ -13,6 +13,7 @@ pub mod hashseq;
 pub mod metrics;
 pub mod protocol;
 pub mod provider;
+pub mod push;
 pub mod store;
 pub mod util;
 
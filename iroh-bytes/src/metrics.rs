@@ -14,6 +14,9 @@ pub struct Metrics {
     pub downloads_success: Counter,
     pub downloads_error: Counter,
     pub downloads_notfound: Counter,
+    pub uploads_throttled: Counter,
+    pub upload_throttled_time_total: Counter,
+    pub requests_throttled: Counter,
 }
 
 impl Default for Metrics {
@@ -24,6 +27,13 @@ impl Default for Metrics {
             downloads_success: Counter::new("Total number of successful downloads"),
             downloads_error: Counter::new("Total number of downloads failed with error"),
             downloads_notfound: Counter::new("Total number of downloads failed with not found"),
+            uploads_throttled: Counter::new("Total number of writes delayed by upload rate limits"),
+            upload_throttled_time_total: Counter::new(
+                "Total time in ms writes were delayed by upload rate limits",
+            ),
+            requests_throttled: Counter::new(
+                "Total number of requests delayed by the concurrent request limit",
+            ),
         }
     }
 }
@@ -87,6 +87,18 @@
 //! the same format as the getter defined requests, followed by the bao encoded
 //! data. From then on the protocol is the same as for getter defined requests.
 //!
+//! ## Pushed data
+//!
+//! In this case the getter offers data to the provider using a [`PushRequest`].
+//! Unlike for other requests, the getter does not finish its side of the stream
+//! after the request, but immediately follows it with the bao encoded data for
+//! the complete blob or collection, in the same format as the provider would
+//! respond to a request for all of it.
+//!
+//! The provider decides whether to accept the data. If it does, it validates and
+//! stores the data and closes its side of the stream once everything is stored.
+//! Otherwise it stops the stream with [`Closed::PermissionDenied`].
+//!
 //! ## Specifying the required data
 //!
 //! A [`GetRequest`] contains a hash and a specification of what data related to
@@ -345,7 +357,7 @@ use serde::{Deserialize, Serialize};
 mod range_spec;
 pub use range_spec::{NonEmptyRequestRangeSpecIter, RangeSpec, RangeSpecSeq};
 
-use crate::Hash;
+use crate::{Hash, HashAndFormat};
 
 /// Maximum message size is limited to 100MiB for now.
 pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024 * 100;
@@ -358,6 +370,8 @@ pub const ALPN: &[u8] = b"/iroh-bytes/4";
 pub enum Request {
     /// A get request for a blob or collection
     Get(GetRequest),
+    /// A request to push a blob or collection to the provider
+    Push(PushRequest),
 }
 
 /// A request
@@ -417,6 +431,22 @@ impl GetRequest {
     }
 }
 
+/// A request to push data to the provider
+///
+/// The request is followed by the bao encoded data on the same stream.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PushRequest {
+    /// The blob or collection that is pushed
+    pub content: HashAndFormat,
+}
+
+impl PushRequest {
+    /// Push a blob or collection
+    pub fn new(content: HashAndFormat) -> Self {
+        Self { content }
+    }
+}
+
 /// Reasons to close connections or stop streams.
 ///
 /// A QUIC **connection** can be *closed* and a **stream** can request the other side to
@@ -442,6 +472,16 @@ pub enum Closed {
     /// Only a single request is allowed on a stream, if more data is received after this a
     /// provider may send this error code in a STOP_STREAM frame.
     RequestReceived = 2,
+    /// The provider refused to serve the request.
+    ///
+    /// Sent by the provider when resetting the response stream of a request that was
+    /// rejected by its [`AuthorizationHandler`](crate::provider::AuthorizationHandler).
+    PermissionDenied = 3,
+    /// The provider failed to store pushed data.
+    ///
+    /// Sent by the provider when resetting the response stream of a [`PushRequest`] whose
+    /// data was invalid or could not be written to its store.
+    PushFailed = 4,
 }
 
 impl Closed {
@@ -451,6 +491,8 @@ impl Closed {
             Closed::StreamDropped => b"stream dropped",
             Closed::ProviderTerminating => b"provider terminating",
             Closed::RequestReceived => b"request received",
+            Closed::PermissionDenied => b"permission denied",
+            Closed::PushFailed => b"push failed",
         }
     }
 }
@@ -474,6 +516,8 @@ impl TryFrom<VarInt> for Closed {
             0 => Ok(Self::StreamDropped),
             1 => Ok(Self::ProviderTerminating),
             2 => Ok(Self::RequestReceived),
+            3 => Ok(Self::PermissionDenied),
+            4 => Ok(Self::PushFailed),
             val => Err(UnknownErrorCode(val)),
         }
     }
@@ -483,7 +527,7 @@ impl TryFrom<VarInt> for Closed {
 mod tests {
     use iroh_test::{assert_eq_hex, hexdump::parse_hexdump};
 
-    use super::{GetRequest, Request};
+    use super::{GetRequest, HashAndFormat, PushRequest, Request};
 
     #[test]
     fn request_wire_format() {
@@ -505,6 +549,14 @@ mod tests {
                     01000100 # the RangeSpecSeq
             ",
             ),
+            (
+                Request::from(PushRequest::new(HashAndFormat::hash_seq(hash))),
+                r"
+                    01 # enum variant for PushRequest
+                    dadadadadadadadadadadadadadadadadadadadadadadadadadadadadadadada # the hash
+                    01 # the format
+            ",
+            ),
         ];
         for (case, expected_hex) in cases {
             let expected = parse_hexdump(expected_hex).unwrap();
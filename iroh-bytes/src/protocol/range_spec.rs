@@ -98,6 +98,17 @@ impl RangeSpec {
         }
         ranges
     }
+
+    /// Creates a [`RangeSpec`] selecting all chunks selected by either `self` or `other`.
+    pub fn union(&self, other: &RangeSpec) -> Self {
+        Self::new(&self.to_chunk_ranges() | &other.to_chunk_ranges())
+    }
+
+    /// Checks if this [`RangeSpec`] selects all chunks that are selected by `other`.
+    pub fn is_superset(&self, other: &RangeSpec) -> bool {
+        let missing: ChunkRanges = other.to_chunk_ranges().difference(&self.to_chunk_ranges());
+        missing.is_empty()
+    }
 }
 
 impl fmt::Debug for RangeSpec {
@@ -248,6 +259,62 @@ impl RangeSpecSeq {
     pub fn iter_non_empty(&self) -> NonEmptyRequestRangeSpecIter<'_> {
         NonEmptyRequestRangeSpecIter::new(self.iter())
     }
+
+    /// Creates a [`RangeSpecSeq`] selecting, for every blob in the sequence, all chunks that
+    /// are selected by either `self` or `other`.
+    pub fn union(&self, other: &RangeSpecSeq) -> Self {
+        let mut res = SmallVec::new();
+        let mut prev_offset = 0;
+        let mut prev = RangeSpec::EMPTY;
+        for offset in self.boundaries(other) {
+            let spec = self.at(offset).union(other.at(offset));
+            // only record the offsets at which the selection actually changes
+            if spec != prev {
+                res.push((offset - prev_offset, spec.clone()));
+                prev_offset = offset;
+                prev = spec;
+            }
+        }
+        Self(res)
+    }
+
+    /// Checks if, for every blob in the sequence, this [`RangeSpecSeq`] selects all chunks
+    /// that are selected by `other`.
+    pub fn is_superset(&self, other: &RangeSpecSeq) -> bool {
+        self.boundaries(other)
+            .into_iter()
+            .all(|offset| self.at(offset).is_superset(other.at(offset)))
+    }
+
+    /// Iterator over the blob offsets at which the [`RangeSpec`] changes, together with the
+    /// new [`RangeSpec`].
+    fn runs(&self) -> impl Iterator<Item = (u64, &RangeSpec)> {
+        self.0.iter().scan(0u64, |offset, (count, spec)| {
+            *offset += count;
+            Some((*offset, spec))
+        })
+    }
+
+    /// The [`RangeSpec`] for the blob at `offset` in the sequence.
+    fn at(&self, offset: u64) -> &RangeSpec {
+        self.runs()
+            .take_while(|(start, _)| *start <= offset)
+            .last()
+            .map(|(_, spec)| spec)
+            .unwrap_or(&EMPTY_RANGE_SPEC)
+    }
+
+    /// Sorted offsets at which the [`RangeSpec`] of `self` or `other` changes.
+    fn boundaries(&self, other: &RangeSpecSeq) -> Vec<u64> {
+        let mut offsets = self
+            .runs()
+            .chain(other.runs())
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
 }
 
 static EMPTY_RANGE_SPEC: RangeSpec = RangeSpec::EMPTY;
@@ -535,7 +602,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn range_spec_seq_union() {
+        let a = RangeSpecSeq::from_ranges(mk_case(vec![0..1, 0..0, 4..8]));
+        let b = RangeSpecSeq::from_ranges(mk_case(vec![0..1, 2..3]));
+        let union = a.union(&b);
+        assert_eq!(
+            union,
+            RangeSpecSeq::from_ranges(mk_case(vec![0..1, 2..3, 4..8]))
+        );
+        assert!(union.is_superset(&a));
+        assert!(union.is_superset(&b));
+        assert!(!a.is_superset(&b));
+        assert!(RangeSpecSeq::all().is_superset(&union));
+        assert_eq!(union.union(&RangeSpecSeq::all()), RangeSpecSeq::all());
+        assert_eq!(a.union(&RangeSpecSeq::empty()), a);
+    }
+
     proptest! {
+        #[test]
+        fn range_spec_seq_union_prop(
+            a in proptest::collection::vec(ranges(0..100), 0..10),
+            b in proptest::collection::vec(ranges(0..100), 0..10),
+        ) {
+            let spec_a = RangeSpecSeq::from_ranges(a.iter().cloned());
+            let spec_b = RangeSpecSeq::from_ranges(b.iter().cloned());
+            let union = spec_a.union(&spec_b);
+            prop_assert!(union.is_superset(&spec_a));
+            prop_assert!(union.is_superset(&spec_b));
+            let n = a.len().max(b.len()) + 1;
+            let expected = spec_a
+                .iter()
+                .zip(spec_b.iter())
+                .map(|(a, b)| &a.to_chunk_ranges() | &b.to_chunk_ranges())
+                .take(n)
+                .collect::<Vec<_>>();
+            let actual = union
+                .iter()
+                .map(|x| x.to_chunk_ranges())
+                .take(n)
+                .collect::<Vec<_>>();
+            prop_assert_eq!(expected, actual);
+        }
+
         #[test]
         fn range_spec_roundtrip(ranges in ranges(0..1000)) {
             let spec = RangeSpec::new(&ranges);
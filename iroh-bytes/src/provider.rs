@@ -1,25 +1,46 @@
 //! The server side API
 use std::fmt::Debug;
+use std::io::Cursor;
+use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{Context, Result};
-use bao_tree::io::fsm::{encode_ranges_validated, Outboard};
+use anyhow::{bail, Context, Result};
+use bao_tree::io::fsm::{
+    encode_ranges_validated, BaoContentItem, Outboard, ResponseDecoderReadingNext,
+    ResponseDecoderStart,
+};
+use bao_tree::ChunkRanges;
+use bytes::{Bytes, BytesMut};
 use futures::future::BoxFuture;
+use iroh_base::key::NodeId;
 use iroh_base::rpc::RpcError;
 use iroh_io::stats::{
     SliceReaderStats, StreamWriterStats, TrackingSliceReader, TrackingStreamWriter,
 };
 use iroh_io::{AsyncSliceReader, AsyncStreamWriter, TokioStreamWriter};
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_util::task::LocalPoolHandle;
 use tracing::{debug, debug_span, info, trace, warn};
 use tracing_futures::Instrument;
 
 use crate::hashseq::parse_hash_seq;
-use crate::protocol::{GetRequest, RangeSpec, Request};
+use crate::protocol::{
+    Closed, GetRequest, PushRequest, RangeSpec, RangeSpecSeq, Request, MAX_MESSAGE_SIZE,
+};
 use crate::store::*;
 use crate::util::Tag;
-use crate::{BlobFormat, Hash};
+use crate::{BlobFormat, Hash, HashAndFormat, IROH_BLOCK_SIZE};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(feature = "metrics")]
+use iroh_metrics::inc;
+
+mod limits;
+pub use limits::{ProviderLimits, Throttle};
+use limits::{ThrottledWriter, UploadLimits};
 
 /// Events emitted by the provider informing about the current status.
 #[derive(Debug, Clone)]
@@ -47,6 +68,15 @@ pub enum Event {
         /// The hash for which the client wants to receive data.
         hash: Hash,
     },
+    /// A request was rejected by the [`AuthorizationHandler`] or the [`PushPolicy`].
+    RequestRejected {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+        /// The hash for which the client wanted to receive or push data.
+        hash: Hash,
+    },
     /// A request was received from a client.
     CustomGetRequestReceived {
         /// An unique connection id.
@@ -165,6 +195,31 @@ pub async fn read_request(mut reader: quinn::RecvStream) -> Result<Request> {
     Ok(request)
 }
 
+/// Read the request from the getter, without reading past its end.
+///
+/// Returns the request and the bytes that were received after it. For a
+/// [`PushRequest`] these are the start of the pushed data.
+async fn read_request_prefix(reader: &mut quinn::RecvStream) -> Result<(Request, Bytes)> {
+    let mut buf = BytesMut::new();
+    loop {
+        match postcard::take_from_bytes::<Request>(&buf) {
+            Ok((request, rest)) => return Ok((request, Bytes::copy_from_slice(rest))),
+            Err(postcard::Error::DeserializeUnexpectedEnd) => {}
+            Err(err) => return Err(err.into()),
+        }
+        if buf.len() >= MAX_MESSAGE_SIZE {
+            bail!("request too large");
+        }
+        let Some(chunk) = reader
+            .read_chunk(MAX_MESSAGE_SIZE - buf.len(), true)
+            .await?
+        else {
+            bail!("stream finished before the request was complete");
+        };
+        buf.extend_from_slice(&chunk.bytes);
+    }
+}
+
 /// Transfers the collection & blob data.
 ///
 /// First, it transfers the collection data & its associated outboard encoding data. Then it sequentially transfers each individual blob data & its associated outboard
@@ -275,23 +330,113 @@ pub trait EventSender: Clone + Sync + Send + 'static {
     fn send(&self, event: Event) -> BoxFuture<()>;
 }
 
+/// Decides whether a remote node may fetch the requested data.
+///
+/// The handler is consulted for every request before any data is sent.  A rejected request
+/// is answered by resetting the stream with [`Closed::PermissionDenied`], which the getter
+/// reports as [`GetError::PermissionDenied`](crate::get::error::GetError::PermissionDenied).
+pub trait AuthorizationHandler: Send + Sync + Debug + 'static {
+    /// Returns whether the request is allowed.
+    ///
+    /// `node_id` is `None` if the remote did not authenticate with an iroh node certificate.
+    /// The format of `content` is [`BlobFormat::HashSeq`] if the request includes any
+    /// children, and [`BlobFormat::Raw`] otherwise.
+    fn authorize(
+        &self,
+        node_id: Option<NodeId>,
+        content: HashAndFormat,
+        ranges: &RangeSpecSeq,
+    ) -> BoxFuture<'static, bool>;
+}
+
+/// Decides whether to accept data pushed by a remote node.
+///
+/// The policy is consulted for every [`PushRequest`] before any data is read.  A rejected
+/// push is answered by stopping the stream with [`Closed::PermissionDenied`].
+pub trait PushPolicy: Send + Sync + Debug + 'static {
+    /// Returns whether `content` pushed by `node_id` should be stored.
+    ///
+    /// `node_id` is `None` if the remote did not authenticate with an iroh node certificate.
+    fn allow_push(
+        &self,
+        node_id: Option<NodeId>,
+        content: HashAndFormat,
+    ) -> BoxFuture<'static, bool>;
+}
+
+/// A [`PushPolicy`] that rejects all pushes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RejectAll;
+
+impl PushPolicy for RejectAll {
+    fn allow_push(
+        &self,
+        _node_id: Option<NodeId>,
+        _content: HashAndFormat,
+    ) -> BoxFuture<'static, bool> {
+        Box::pin(async { false })
+    }
+}
+
+/// An [`AuthorizationHandler`] that allows all requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllowAll;
+
+impl AuthorizationHandler for AllowAll {
+    fn authorize(
+        &self,
+        _node_id: Option<NodeId>,
+        _content: HashAndFormat,
+        _ranges: &RangeSpecSeq,
+    ) -> BoxFuture<'static, bool> {
+        Box::pin(async { true })
+    }
+}
+
+/// The policies and limits which apply to all connections of a provider.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    /// Decides which get requests are served.
+    pub authorization: Arc<dyn AuthorizationHandler>,
+    /// Decides which pushed data is accepted.
+    pub push_policy: Arc<dyn PushPolicy>,
+    /// The [`ProviderLimits`] within which data is served, shared between all connections.
+    pub throttle: Arc<Throttle>,
+}
+
+impl Default for ProviderConfig {
+    /// Serves all requests without limits and rejects all pushes.
+    fn default() -> Self {
+        Self {
+            authorization: Arc::new(AllowAll),
+            push_policy: Arc::new(RejectAll),
+            throttle: Default::default(),
+        }
+    }
+}
+
 /// Handle a single connection.
-pub async fn handle_connection<D: Map, E: EventSender>(
-    connecting: quinn::Connecting,
+///
+/// `node_id` is the authenticated id of the remote node, if known. It is passed to the
+/// [`AuthorizationHandler`] and the [`PushPolicy`] of `config`, and selects the per node upload
+/// limits.
+pub async fn handle_connection<D: Store, E: EventSender>(
+    connection: quinn::Connection,
+    node_id: Option<NodeId>,
     db: D,
     events: E,
+    config: ProviderConfig,
     rt: LocalPoolHandle,
 ) {
-    let remote_addr = connecting.remote_address();
-    let connection = match connecting.await {
-        Ok(conn) => conn,
-        Err(err) => {
-            warn!(%remote_addr, "Error connecting: {err:#}");
-            return;
-        }
-    };
+    let remote_addr = connection.remote_address();
     let connection_id = connection.stable_id() as u64;
     let span = debug_span!("connection", connection_id, %remote_addr);
+    let upload_limits = config.throttle.upload_limits(node_id);
+    let requests = config
+        .throttle
+        .limits()
+        .max_concurrent_requests_per_connection
+        .map(|max| Arc::new(Semaphore::new(max.get())));
     async move {
         while let Ok((writer, reader)) = connection.accept_bi().await {
             // The stream ID index is used to identify this request.  Requests only arrive in
@@ -302,12 +447,17 @@ pub async fn handle_connection<D: Map, E: EventSender>(
                 connection_id,
                 events: events.clone(),
                 inner: writer,
+                limits: upload_limits.clone(),
             };
             events.send(Event::ClientConnected { connection_id }).await;
             let db = db.clone();
-            rt.spawn_pinned(|| {
+            let config = config.clone();
+            let requests = requests.clone();
+            rt.spawn_pinned(move || {
                 async move {
-                    if let Err(err) = handle_stream(db, reader, writer).await {
+                    if let Err(err) =
+                        handle_stream(db, reader, writer, node_id, config, requests).await
+                    {
                         warn!("error: {err:#?}",);
                     }
                 }
@@ -319,14 +469,17 @@ pub async fn handle_connection<D: Map, E: EventSender>(
     .await
 }
 
-async fn handle_stream<D: Map, E: EventSender>(
+async fn handle_stream<D: Store, E: EventSender>(
     db: D,
-    reader: quinn::RecvStream,
-    writer: ResponseWriter<E>,
+    mut reader: quinn::RecvStream,
+    mut writer: ResponseWriter<E>,
+    node_id: Option<NodeId>,
+    config: ProviderConfig,
+    requests: Option<Arc<Semaphore>>,
 ) -> Result<()> {
     // 1. Decode the request.
     debug!("reading request");
-    let request = match read_request(reader).await {
+    let (request, rest) = match read_request_prefix(&mut reader).await {
         Ok(r) => r,
         Err(e) => {
             writer.notify_transfer_aborted(None).await;
@@ -335,7 +488,68 @@ async fn handle_stream<D: Map, E: EventSender>(
     };
 
     match request {
-        Request::Get(request) => handle_get(db, request, writer).await,
+        Request::Get(request) => {
+            // 2. Check that the remote is allowed to fetch the data.
+            let content = requested_content(&request);
+            if !config
+                .authorization
+                .authorize(node_id, content, &request.ranges)
+                .await
+            {
+                debug!(?node_id, hash = %request.hash, "request rejected");
+                writer.notify_request_rejected(request.hash).await;
+                writer.inner.reset(Closed::PermissionDenied.into()).ok();
+                return Ok(());
+            }
+            // 3. Wait for one of the running get requests to finish if there are too many.
+            let _permit = match requests {
+                Some(requests) => Some(acquire_request_permit(requests).await?),
+                None => None,
+            };
+            handle_get(db, request, writer).await
+        }
+        Request::Push(request) => {
+            // 2. Check that we want the data.
+            if !config
+                .push_policy
+                .allow_push(node_id, request.content)
+                .await
+            {
+                debug!(?node_id, hash = %request.content.hash, "push rejected");
+                writer.notify_request_rejected(request.content.hash).await;
+                reader.stop(Closed::PermissionDenied.into()).ok();
+                writer.inner.reset(Closed::PermissionDenied.into()).ok();
+                return Ok(());
+            }
+            handle_push(db, request, rest, reader, writer).await
+        }
+    }
+}
+
+/// Waits until another get request may be served on the connection.
+async fn acquire_request_permit(requests: Arc<Semaphore>) -> Result<OwnedSemaphorePermit> {
+    match requests.clone().try_acquire_owned() {
+        Ok(permit) => Ok(permit),
+        Err(_) => {
+            debug!("too many concurrent requests, waiting");
+            #[cfg(feature = "metrics")]
+            inc!(Metrics, requests_throttled);
+            Ok(requests.acquire_owned().await?)
+        }
+    }
+}
+
+/// The content requested by `request`.
+///
+/// Requests for just the root are considered requests for a raw blob.
+fn requested_content(request: &GetRequest) -> HashAndFormat {
+    let format = match request.ranges.as_single() {
+        Some((0, _)) => BlobFormat::Raw,
+        _ => BlobFormat::HashSeq,
+    };
+    HashAndFormat {
+        hash: request.hash,
+        format,
     }
 }
 
@@ -398,19 +612,133 @@ pub async fn handle_get<D: Map, E: EventSender>(
     Ok(())
 }
 
+/// Handle a single push request.
+///
+/// `prefix` are the bytes of the pushed data that were already read together with the
+/// request. The pushed data is validated while it is written to the store. Once everything
+/// is stored, the content is tagged and the response stream is finished. Content that is
+/// already tagged keeps its existing tag, so repeated pushes do not pile up tags.
+pub async fn handle_push<D: Store, E: EventSender>(
+    db: D,
+    request: PushRequest,
+    prefix: Bytes,
+    reader: quinn::RecvStream,
+    mut writer: ResponseWriter<E>,
+) -> Result<()> {
+    let HashAndFormat { hash, format } = request.content;
+    debug!(%hash, ?format, "receiving push");
+    // protect the partially written data from gc
+    let _temp_tag = db.temp_tag(request.content);
+    let mut reader = Cursor::new(prefix).chain(reader);
+    if let Err(err) = receive_push(&db, request.content, &mut reader).await {
+        reader.get_mut().1.stop(Closed::PushFailed.into()).ok();
+        writer.inner.reset(Closed::PushFailed.into()).ok();
+        return Err(err);
+    }
+    let tag = match find_tag(&db, request.content).await? {
+        Some(tag) => tag,
+        None => db.create_tag(request.content).await?,
+    };
+    writer
+        .events
+        .send(Event::TaggedBlobAdded { hash, format, tag })
+        .await;
+    writer.inner.finish().await?;
+    debug!(%hash, "push completed");
+    Ok(())
+}
+
+/// Returns an existing tag pointing to `content`, if any.
+async fn find_tag<D: Store>(db: &D, content: HashAndFormat) -> Result<Option<Tag>> {
+    for item in db.tags().await? {
+        let (tag, value) = item?;
+        if value == content {
+            return Ok(Some(tag));
+        }
+    }
+    Ok(None)
+}
+
+/// Receive the pushed data of `content` into the store.
+async fn receive_push<D: Store>(
+    db: &D,
+    content: HashAndFormat,
+    reader: impl AsyncRead + Unpin,
+) -> Result<()> {
+    let mut reader = receive_blob(db, content.hash, reader).await?;
+    if content.format == BlobFormat::HashSeq {
+        let entry = db
+            .get(&content.hash)
+            .await?
+            .context("pushed hash seq not found in store")?;
+        let (mut children, _num_blobs) = parse_hash_seq(entry.data_reader().await?).await?;
+        while let Some(hash) = children.next().await? {
+            reader = receive_blob(db, hash, reader).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Receive a single pushed blob into the store, returning the reader for the remaining data.
+///
+/// If the blob is already complete in the store, the data is validated but not written.
+async fn receive_blob<D: Store, R: AsyncRead + Unpin>(db: &D, hash: Hash, reader: R) -> Result<R> {
+    let decoder =
+        ResponseDecoderStart::new(hash.into(), ChunkRanges::all(), IROH_BLOCK_SIZE, reader);
+    let (mut decoder, size) = decoder.next().await?;
+    let entry = match db.get_mut(&hash).await? {
+        Some(entry) if entry.is_complete() => None,
+        Some(entry) => Some(entry),
+        None => Some(db.get_or_create(hash, size).await?),
+    };
+    let mut bw = match &entry {
+        Some(entry) => Some(entry.batch_writer().await?),
+        None => None,
+    };
+    let mut batch = Vec::new();
+    let reader = loop {
+        match decoder.next().await {
+            ResponseDecoderReadingNext::More((next, item)) => {
+                let item = item?;
+                let is_leaf = matches!(item, BaoContentItem::Leaf(_));
+                batch.push(item);
+                if is_leaf {
+                    let batch = std::mem::take(&mut batch);
+                    if let Some(bw) = bw.as_mut() {
+                        bw.write_batch(size, batch).await?;
+                    }
+                }
+                decoder = next;
+            }
+            ResponseDecoderReadingNext::Done(reader) => break reader,
+        }
+    };
+    if let Some(bw) = bw.as_mut() {
+        bw.sync().await?;
+    }
+    // the writer borrows the entry, release it before completing the entry
+    drop(bw);
+    if let Some(entry) = entry {
+        db.insert_complete(entry).await?;
+    }
+    Ok(reader)
+}
+
 /// A helper struct that combines a quinn::SendStream with auxiliary information
 #[derive(Debug)]
 pub struct ResponseWriter<E> {
     inner: quinn::SendStream,
     events: E,
     connection_id: u64,
+    limits: UploadLimits,
 }
 
 impl<E: EventSender> ResponseWriter<E> {
     fn tracking_writer(
         &mut self,
-    ) -> TrackingStreamWriter<TokioStreamWriter<&mut quinn::SendStream>> {
-        TrackingStreamWriter::new(TokioStreamWriter(&mut self.inner))
+    ) -> TrackingStreamWriter<ThrottledWriter<TokioStreamWriter<&mut quinn::SendStream>>> {
+        let writer = TokioStreamWriter(&mut self.inner);
+        TrackingStreamWriter::new(ThrottledWriter::new(writer, self.limits.clone()))
     }
 
     fn connection_id(&self) -> u64 {
@@ -462,6 +790,16 @@ impl<E: EventSender> ResponseWriter<E> {
             .await;
     }
 
+    async fn notify_request_rejected(&self, hash: Hash) {
+        self.events
+            .send(Event::RequestRejected {
+                connection_id: self.connection_id(),
+                request_id: self.request_id(),
+                hash,
+            })
+            .await;
+    }
+
     async fn notify_transfer_aborted(&self, stats: Option<Box<TransferStats>>) {
         if let Some(stats) = &stats {
             Self::print_stats(stats);
@@ -0,0 +1,279 @@
+//! Limits for serving data to remote nodes.
+use std::collections::HashMap;
+use std::io;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use iroh_base::key::NodeId;
+use iroh_io::AsyncStreamWriter;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::trace;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Metrics;
+#[cfg(feature = "metrics")]
+use iroh_metrics::{inc, inc_by};
+
+/// Limits for serving data to remote nodes.
+///
+/// All limits are disabled by default. A limit of zero is rejected, leave a limit unset to not
+/// limit at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderLimits {
+    /// Maximum upload rate in bytes per second, over all connections.
+    pub max_upload_rate: Option<NonZeroU64>,
+    /// Maximum upload rate in bytes per second, to a single node.
+    ///
+    /// This applies to all connections of the node together.
+    pub max_upload_rate_per_node: Option<NonZeroU64>,
+    /// Maximum number of get requests served concurrently on a single connection.
+    ///
+    /// Further get requests wait until one of the running ones is done. Push requests are
+    /// not limited.
+    pub max_concurrent_requests_per_connection: Option<NonZeroUsize>,
+}
+
+/// Shared state to enforce [`ProviderLimits`] across all connections.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    limits: ProviderLimits,
+    global: Option<Arc<RateLimiter>>,
+    per_node: Mutex<HashMap<NodeId, Arc<RateLimiter>>>,
+}
+
+impl Throttle {
+    /// Create a new throttle enforcing `limits`.
+    pub fn new(limits: ProviderLimits) -> Self {
+        Self {
+            global: limits
+                .max_upload_rate
+                .map(|rate| Arc::new(RateLimiter::new(rate))),
+            limits,
+            per_node: Default::default(),
+        }
+    }
+
+    /// The limits enforced by this throttle.
+    pub fn limits(&self) -> &ProviderLimits {
+        &self.limits
+    }
+
+    /// The upload rate limits for a connection to `node_id`.
+    ///
+    /// Connections of remotes without a node id are only subject to the global limit.
+    pub(super) fn upload_limits(&self, node_id: Option<NodeId>) -> UploadLimits {
+        let node = match (node_id, self.limits.max_upload_rate_per_node) {
+            (Some(node_id), Some(rate)) => {
+                let mut per_node = self.per_node.lock().unwrap();
+                // forget the limiters of nodes without connections
+                per_node.retain(|_, limiter| Arc::strong_count(limiter) > 1);
+                let limiter = per_node
+                    .entry(node_id)
+                    .or_insert_with(|| Arc::new(RateLimiter::new(rate)));
+                Some(limiter.clone())
+            }
+            _ => None,
+        };
+        UploadLimits {
+            global: self.global.clone(),
+            node,
+        }
+    }
+}
+
+/// The upload rate limiters that apply to a single connection.
+#[derive(Debug, Clone, Default)]
+pub(super) struct UploadLimits {
+    global: Option<Arc<RateLimiter>>,
+    node: Option<Arc<RateLimiter>>,
+}
+
+impl UploadLimits {
+    /// Wait until `len` bytes may be sent.
+    async fn wait(&self, len: usize) {
+        let len = len as u64;
+        let global = self.global.as_ref().map(|l| l.take(len));
+        let node = self.node.as_ref().map(|l| l.take(len));
+        let delay = global.max(node).unwrap_or_default();
+        if !delay.is_zero() {
+            trace!(len, ?delay, "upload throttled");
+            #[cfg(feature = "metrics")]
+            {
+                inc!(Metrics, uploads_throttled);
+                inc_by!(
+                    Metrics,
+                    upload_throttled_time_total,
+                    delay.as_millis() as u64
+                );
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// A token bucket limiting a rate in bytes per second.
+///
+/// The bucket holds at most one second worth of tokens. Taking more tokens than available
+/// puts the bucket into debt, so callers are served in the order they took tokens.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: NonZeroU64) -> Self {
+        let rate = rate.get();
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate as f64,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `len` tokens, returning how long to wait until they are available.
+    fn take(&self, len: u64) -> Duration {
+        let rate = self.rate as f64;
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.updated = now;
+        bucket.tokens -= len as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+/// A writer that delays writes to stay within the [`UploadLimits`].
+#[derive(Debug)]
+pub(super) struct ThrottledWriter<W> {
+    inner: W,
+    limits: UploadLimits,
+}
+
+impl<W> ThrottledWriter<W> {
+    pub(super) fn new(inner: W, limits: UploadLimits) -> Self {
+        Self { inner, limits }
+    }
+}
+
+impl<W: AsyncStreamWriter> AsyncStreamWriter for ThrottledWriter<W> {
+    async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.limits.wait(data.len()).await;
+        self.inner.write(data).await
+    }
+
+    async fn write_bytes(&mut self, data: Bytes) -> io::Result<()> {
+        self.limits.wait(data.len()).await;
+        self.inner.write_bytes(data).await
+    }
+
+    async fn sync(&mut self) -> io::Result<()> {
+        self.inner.sync().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_base::key::SecretKey;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter() {
+        let limiter = RateLimiter::new(NonZeroU64::new(1000).unwrap());
+        // the bucket starts full
+        assert_eq!(limiter.take(1000), Duration::ZERO);
+        assert_eq!(limiter.take(500), Duration::from_millis(500));
+        // the debt needs to be paid back first
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert_eq!(limiter.take(500), Duration::from_millis(500));
+        // the bucket never holds more than one second worth of tokens
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(limiter.take(1500), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn per_node_limits() {
+        let throttle = Throttle::new(ProviderLimits {
+            max_upload_rate_per_node: NonZeroU64::new(1000),
+            ..Default::default()
+        });
+        let a = SecretKey::generate().public();
+        let b = SecretKey::generate().public();
+
+        // connections to the same node share the limiter
+        let a1 = throttle.upload_limits(Some(a));
+        let a2 = throttle.upload_limits(Some(a));
+        let b1 = throttle.upload_limits(Some(b));
+        assert!(a1.global.is_none());
+        assert_eq!(a1.node.as_ref().unwrap().take(1000), Duration::ZERO);
+        assert_eq!(a2.node.as_ref().unwrap().take(1000), Duration::from_secs(1));
+        assert_eq!(b1.node.as_ref().unwrap().take(1000), Duration::ZERO);
+
+        // unknown nodes are not limited
+        assert!(throttle.upload_limits(None).node.is_none());
+
+        // the limiter is kept as long as a connection uses it
+        drop(a1);
+        let _b2 = throttle.upload_limits(Some(b));
+        assert!(throttle.per_node.lock().unwrap().contains_key(&a));
+        drop(a2);
+        let _b3 = throttle.upload_limits(Some(b));
+        assert!(!throttle.per_node.lock().unwrap().contains_key(&a));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn global_limit() {
+        let throttle = Throttle::new(ProviderLimits {
+            max_upload_rate: NonZeroU64::new(1000),
+            ..Default::default()
+        });
+        let limits = throttle.upload_limits(None);
+        let start = Instant::now();
+        for _ in 0..4 {
+            limits.wait(1000).await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn zero_upload_rate_rejected() {
+        let limits: ProviderLimits = serde_json::from_str(r#"{"max_upload_rate": 1000}"#).unwrap();
+        assert_eq!(limits.max_upload_rate, NonZeroU64::new(1000));
+        for key in ["max_upload_rate", "max_upload_rate_per_node"] {
+            let json = format!(r#"{{"{key}": 0}}"#);
+            assert!(serde_json::from_str::<ProviderLimits>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn zero_concurrent_requests_rejected() {
+        let limits: ProviderLimits =
+            serde_json::from_str(r#"{"max_concurrent_requests_per_connection": 2}"#).unwrap();
+        assert_eq!(
+            limits.max_concurrent_requests_per_connection,
+            NonZeroUsize::new(2)
+        );
+        assert!(serde_json::from_str::<ProviderLimits>(
+            r#"{"max_concurrent_requests_per_connection": 0}"#
+        )
+        .is_err());
+    }
+}
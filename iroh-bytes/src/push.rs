@@ -0,0 +1,157 @@
+//! The client side API for pushing data to a provider
+//!
+//! Pushing is the reverse of getting: the client offers a blob or collection from its
+//! own store, and the provider decides whether it wants to store it. This allows to
+//! upload data to a node that can not dial back.
+//!
+//! The data is sent in the same format as a provider would send it in response to a
+//! request for the complete blob or collection, so it is validated by the receiver.
+use std::time::Instant;
+
+use anyhow::anyhow;
+use iroh_io::stats::TrackingStreamWriter;
+use iroh_io::TokioStreamWriter;
+use tracing::debug;
+
+use crate::{
+    get::Stats,
+    hashseq::parse_hash_seq,
+    protocol::{Closed, PushRequest, RangeSpec, Request, MAX_MESSAGE_SIZE},
+    provider::{send_blob, SentStatus},
+    store::{Map, MapEntry},
+    BlobFormat, Hash, HashAndFormat,
+};
+
+/// Failures for a push operation
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    /// The content is not complete in the local store.
+    #[error("Content not found")]
+    NotFound(Hash),
+    /// The remote refused to accept the content.
+    #[error("Permission denied")]
+    PermissionDenied,
+    /// The remote failed to validate or store the content.
+    #[error("Remote failed to store the content")]
+    RemoteFailure,
+    /// Network or IO operation failed.
+    #[error("A network or IO operation failed")]
+    Io(#[source] anyhow::Error),
+}
+
+impl PushError {
+    /// Map the error code of a stopped or reset stream.
+    fn from_code(code: quinn::VarInt) -> Self {
+        match Closed::try_from(code) {
+            Ok(Closed::PermissionDenied) => PushError::PermissionDenied,
+            Ok(Closed::PushFailed) => PushError::RemoteFailure,
+            _ => PushError::Io(anyhow!("stream closed by remote with code {code}")),
+        }
+    }
+}
+
+impl From<quinn::WriteError> for PushError {
+    fn from(value: quinn::WriteError) -> Self {
+        match value {
+            quinn::WriteError::Stopped(code) => PushError::from_code(code),
+            e => PushError::Io(e.into()),
+        }
+    }
+}
+
+/// Push `content` from `db` to the provider at the other end of `connection`.
+///
+/// For a [`BlobFormat::HashSeq`] all children are pushed as well, so they must be
+/// complete in `db`. Returns once the provider has stored the content.
+pub async fn push<D: Map>(
+    connection: &quinn::Connection,
+    db: &D,
+    content: HashAndFormat,
+) -> Result<Stats, PushError> {
+    let start = Instant::now();
+    let request = postcard::to_stdvec(&Request::Push(PushRequest::new(content)))
+        .map_err(|e| PushError::Io(e.into()))?;
+    debug_assert!(request.len() <= MAX_MESSAGE_SIZE);
+    let (mut writer, mut reader) = connection
+        .open_bi()
+        .await
+        .map_err(|e| PushError::Io(e.into()))?;
+    let sent = async {
+        writer.write_all(&request).await?;
+        let bytes_written = send_content(db, content, &mut writer).await?;
+        writer.finish().await?;
+        Ok(request.len() as u64 + bytes_written)
+    }
+    .await;
+    let bytes_written = match sent {
+        Ok(bytes_written) => bytes_written,
+        Err(err) => {
+            // make sure the remote does not wait for more data
+            writer.reset(Closed::StreamDropped.into()).ok();
+            return Err(err);
+        }
+    };
+
+    // the remote finishes its side of the stream once everything is stored
+    match reader.read_to_end(0).await {
+        Ok(_) => {}
+        Err(quinn::ReadToEndError::Read(quinn::ReadError::Reset(code))) => {
+            return Err(PushError::from_code(code));
+        }
+        Err(e) => return Err(PushError::Io(e.into())),
+    }
+    debug!(hash = %content.hash, bytes_written, "push completed");
+    Ok(Stats {
+        bytes_written,
+        bytes_read: 0,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Write the bao encoding of `content` to `writer`, returning the number of bytes written.
+async fn send_content<D: Map>(
+    db: &D,
+    content: HashAndFormat,
+    writer: &mut quinn::SendStream,
+) -> Result<u64, PushError> {
+    let mut bytes_written = send_complete_blob(db, content.hash, writer).await?;
+    if content.format == BlobFormat::HashSeq {
+        let entry = db
+            .get(&content.hash)
+            .await
+            .map_err(|e| PushError::Io(e.into()))?
+            .ok_or(PushError::NotFound(content.hash))?;
+        let reader = entry
+            .data_reader()
+            .await
+            .map_err(|e| PushError::Io(e.into()))?;
+        let (mut children, _num_blobs) = parse_hash_seq(reader)
+            .await
+            .map_err(|e| PushError::Io(e.into()))?;
+        while let Some(hash) = children.next().await.map_err(|e| PushError::Io(e.into()))? {
+            bytes_written += send_complete_blob(db, hash, writer).await?;
+        }
+    }
+    Ok(bytes_written)
+}
+
+/// Write the bao encoding of a complete blob to `writer`.
+async fn send_complete_blob<D: Map>(
+    db: &D,
+    hash: Hash,
+    writer: &mut quinn::SendStream,
+) -> Result<u64, PushError> {
+    match db.get(&hash).await {
+        Ok(Some(entry)) if entry.is_complete() => {}
+        Ok(_) => return Err(PushError::NotFound(hash)),
+        Err(e) => return Err(PushError::Io(e.into())),
+    }
+    let mut tw = TrackingStreamWriter::new(TokioStreamWriter(&mut *writer));
+    let (status, _size, _read_stats) = send_blob(db, hash, &RangeSpec::all(), &mut tw)
+        .await
+        .map_err(|e| PushError::Io(e.into()))?;
+    if status == SentStatus::NotFound {
+        return Err(PushError::NotFound(hash));
+    }
+    Ok(tw.stats().total().size)
+}
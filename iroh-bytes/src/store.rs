@@ -1,11 +1,13 @@
 //! Implementations of blob stores
 use crate::{BlobFormat, Hash, HashAndFormat};
 
-#[cfg(feature = "fs-store")]
+#[cfg(any(feature = "fs-store", feature = "s3-store"))]
 mod bao_file;
 pub mod mem;
 mod mutable_mem_storage;
 pub mod readonly_mem;
+#[cfg(feature = "s3-store")]
+pub mod s3;
 
 #[cfg(feature = "fs-store")]
 pub mod fs;
@@ -90,3 +92,75 @@ impl TempCounterMap {
         res.into_iter()
     }
 }
+
+/// Times at which blobs were last accessed.
+///
+/// Accesses are recorded in memory, so reading a blob does not need a database write.
+/// Stores that persist their blobs take the pending accesses with
+/// [`AccessTimes::take_pending`] and persist them from time to time.
+#[derive(Debug, Clone)]
+struct AccessTimes(std::sync::Arc<AccessTimesInner>);
+
+#[derive(Debug)]
+struct AccessTimesInner {
+    /// When the store was loaded.
+    loaded: std::time::SystemTime,
+    /// Accesses that were not taken yet.
+    pending: std::sync::Mutex<std::collections::HashMap<Hash, std::time::SystemTime>>,
+}
+
+impl Default for AccessTimes {
+    fn default() -> Self {
+        Self(std::sync::Arc::new(AccessTimesInner {
+            loaded: std::time::SystemTime::now(),
+            pending: Default::default(),
+        }))
+    }
+}
+
+impl AccessTimes {
+    /// Record an access to `hash` now.
+    fn touch(&self, hash: Hash) {
+        let now = std::time::SystemTime::now();
+        self.0.pending.lock().unwrap().insert(hash, now);
+    }
+
+    /// The last access to `hash` that was not taken yet.
+    fn get(&self, hash: &Hash) -> Option<std::time::SystemTime> {
+        self.0.pending.lock().unwrap().get(hash).copied()
+    }
+
+    /// When the store was loaded.
+    ///
+    /// Blobs without a recorded access count as accessed at this time.
+    fn loaded(&self) -> std::time::SystemTime {
+        self.0.loaded
+    }
+
+    /// Take the accesses recorded since the last call, to persist them.
+    #[cfg(any(feature = "fs-store", feature = "s3-store"))]
+    fn take_pending(&self) -> Vec<(Hash, std::time::SystemTime)> {
+        let mut pending = self.0.pending.lock().unwrap();
+        std::mem::take(&mut *pending).into_iter().collect()
+    }
+
+    /// Forget about a deleted blob.
+    fn remove(&self, hash: &Hash) {
+        self.0.pending.lock().unwrap().remove(hash);
+    }
+}
+
+/// Encode an access time as milliseconds since the unix epoch, for storing it in a database.
+#[cfg(any(feature = "fs-store", feature = "s3-store"))]
+fn to_unix_millis(time: std::time::SystemTime) -> u64 {
+    let since_epoch = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_millis() as u64
+}
+
+/// Decode an access time stored with [`to_unix_millis`].
+#[cfg(any(feature = "fs-store", feature = "s3-store"))]
+fn from_unix_millis(millis: u64) -> std::time::SystemTime {
+    std::time::UNIX_EPOCH + std::time::Duration::from_millis(millis)
+}
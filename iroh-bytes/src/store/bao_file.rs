@@ -37,6 +37,8 @@ use crate::{
 use iroh_base::hash::Hash;
 
 use super::mutable_mem_storage::{MutableMemStorage, SizeInfo};
+#[cfg(feature = "fs-store")]
+use super::AccessTimes;
 
 /// Data files are stored in 3 files. The data file, the outboard file,
 /// and a sizes file. The sizes file contains the size that the remote side told us
@@ -354,6 +356,9 @@ pub struct BaoFileConfig {
     /// Todo: make this async.
     #[debug("{:?}", on_file_create.as_ref().map(|_| ()))]
     on_file_create: Option<CreateCb>,
+    /// Where to record reads of the data, if they are tracked.
+    #[cfg(feature = "fs-store")]
+    access: Option<AccessTimes>,
 }
 
 impl BaoFileConfig {
@@ -363,9 +368,18 @@ impl BaoFileConfig {
             dir,
             max_mem,
             on_file_create,
+            #[cfg(feature = "fs-store")]
+            access: None,
         }
     }
 
+    /// Record reads of the data of handles with this configuration in `access`.
+    #[cfg(feature = "fs-store")]
+    pub(super) fn with_access_times(mut self, access: AccessTimes) -> Self {
+        self.access = Some(access);
+        self
+    }
+
     /// Get the paths for a hash.
     fn paths(&self, hash: &Hash) -> DataPaths {
         DataPaths {
@@ -374,6 +388,39 @@ impl BaoFileConfig {
             sizes: self.dir.join(format!("{}.sizes4", hash.to_hex())),
         }
     }
+
+    /// The hashes of all partial files in the directory.
+    #[cfg(feature = "s3-store")]
+    pub(crate) fn file_hashes(&self) -> io::Result<Vec<Hash>> {
+        let mut hashes = Vec::new();
+        for entry in std::fs::read_dir(self.dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("data") {
+                continue;
+            }
+            if let Some(hash) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<Hash>().ok())
+            {
+                hashes.push(hash);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Delete the files for a hash, if there are any.
+    #[cfg(feature = "s3-store")]
+    pub(crate) fn delete_files(&self, hash: &Hash) -> io::Result<()> {
+        let paths = self.paths(hash);
+        for path in [paths.data, paths.outboard, paths.sizes] {
+            match std::fs::remove_file(path) {
+                Err(cause) if cause.kind() != io::ErrorKind::NotFound => return Err(cause),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A reader for a bao file, reading just the data.
@@ -586,6 +633,14 @@ impl BaoFileHandle {
         self.hash
     }
 
+    /// Record a read of the data, if reads are tracked.
+    #[cfg(feature = "fs-store")]
+    pub(super) fn touch(&self) {
+        if let Some(access) = &self.config.access {
+            access.touch(self.hash);
+        }
+    }
+
     /// Create a new writer from the handle.
     pub fn writer(&self) -> BaoFileWriter {
         BaoFileWriter(Some(self.clone()))
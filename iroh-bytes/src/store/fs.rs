@@ -66,7 +66,7 @@
 //! errors when communicating with the actor.
 use std::{
     collections::{BTreeMap, BTreeSet},
-    io::{self, BufReader, Read},
+    io,
     path::{Path, PathBuf},
     sync::{Arc, RwLock},
     time::{Duration, SystemTime},
@@ -74,7 +74,6 @@ use std::{
 
 use bao_tree::io::{
     fsm::Outboard,
-    outboard::PostOrderMemOutboard,
     sync::{ReadAt, Size},
 };
 use bytes::Bytes;
@@ -102,17 +101,18 @@ use crate::{
         bao_file::{BaoFileStorage, CompleteStorage},
         fs::{
             tables::BaoFilePart,
-            util::{overwrite_and_sync, read_and_remove, ProgressReader},
+            util::{overwrite_and_sync, read_and_remove},
         },
     },
     util::{
+        compute_outboard,
         progress::{
             BoxedProgressSender, IdGenerator, IgnoreProgressSender, ProgressSendError,
             ProgressSender,
         },
         raw_outboard_size, LivenessTracker, MemOrFile,
     },
-    Tag, TempTag, IROH_BLOCK_SIZE,
+    Tag, TempTag,
 };
 use tables::{ReadOnlyTables, ReadableTables, Tables};
 
@@ -122,8 +122,9 @@ use self::test_support::EntryData;
 
 use super::{
     bao_file::{BaoFileConfig, BaoFileHandle, BaoFileHandleWeak, CreateCb},
-    temp_name, BaoBatchWriter, BaoBlobSize, ConsistencyCheckProgress, EntryStatus, ExportMode,
-    ExportProgressCb, ImportMode, ImportProgress, Map, TempCounterMap,
+    from_unix_millis, temp_name, to_unix_millis, AccessTimes, BaoBatchWriter, BaoBlobSize,
+    BlobUsage, ConsistencyCheckProgress, EntryStatus, ExportMode, ExportProgressCb, ImportMode,
+    ImportProgress, Map, TempCounterMap,
 };
 
 /// Location of the data.
@@ -482,10 +483,12 @@ impl super::MapEntry for Entry {
     }
 
     async fn outboard(&self) -> io::Result<impl Outboard> {
+        self.touch();
         self.outboard()
     }
 
     async fn data_reader(&self) -> io::Result<impl AsyncSliceReader> {
+        self.touch();
         Ok(self.data_reader())
     }
 }
@@ -628,6 +631,15 @@ pub(crate) enum ActorMessage {
         hash: HashAndFormat,
         tx: oneshot::Sender<ActorResult<Tag>>,
     },
+    /// Modification method: persist the last access times of blobs.
+    RecordAccess {
+        times: Vec<(Hash, SystemTime)>,
+        tx: oneshot::Sender<ActorResult<()>>,
+    },
+    /// Bulk query method: get the last access times of all blobs.
+    AccessTimes {
+        tx: oneshot::Sender<ActorResult<BTreeMap<Hash, SystemTime>>>,
+    },
     /// Modification method: unconditional delete the data for a number of hashes
     Delete {
         hashes: Vec<Hash>,
@@ -666,6 +678,7 @@ impl ActorMessage {
             | Self::EntryStatus { .. }
             | Self::Blobs { .. }
             | Self::Tags { .. }
+            | Self::AccessTimes { .. }
             | Self::GcStart { .. }
             | Self::GetFullEntryState { .. }
             | Self::Dump => MessageCategory::ReadOnly,
@@ -676,6 +689,7 @@ impl ActorMessage {
             | Self::SetTag { .. }
             | Self::CreateTag { .. }
             | Self::SetFullEntryState { .. }
+            | Self::RecordAccess { .. }
             | Self::Delete { .. } => MessageCategory::ReadWrite,
             Self::UpdateInlineOptions { .. }
             | Self::Sync { .. }
@@ -776,6 +790,7 @@ struct StoreInner {
     temp: Arc<RwLock<TempCounterMap>>,
     handle: Option<std::thread::JoinHandle<()>>,
     path_options: Arc<PathOptions>,
+    access: AccessTimes,
 }
 
 impl LivenessTracker for RwLock<TempCounterMap> {
@@ -806,7 +821,8 @@ impl StoreInner {
         );
         std::fs::create_dir_all(path.parent().unwrap())?;
         let temp: Arc<RwLock<TempCounterMap>> = Default::default();
-        let (actor, tx) = Actor::new(&path, options.clone(), temp.clone(), rt)?;
+        let access = AccessTimes::default();
+        let (actor, tx) = Actor::new(&path, options.clone(), temp.clone(), access.clone(), rt)?;
         let handle = std::thread::Builder::new()
             .name("redb-actor".to_string())
             .spawn(move || {
@@ -820,6 +836,7 @@ impl StoreInner {
             temp,
             handle: Some(handle),
             path_options: Arc::new(options.path),
+            access,
         })
     }
 
@@ -925,6 +942,25 @@ impl StoreInner {
         Ok(rx.await??)
     }
 
+    /// Persist the accesses recorded since the last call.
+    async fn record_access(&self) -> OuterResult<()> {
+        let times = self.access.take_pending();
+        if times.is_empty() {
+            return Ok(());
+        }
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send_async(ActorMessage::RecordAccess { times, tx })
+            .await?;
+        Ok(rx.await??)
+    }
+
+    async fn access_times(&self) -> OuterResult<BTreeMap<Hash, SystemTime>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send_async(ActorMessage::AccessTimes { tx }).await?;
+        Ok(rx.await??)
+    }
+
     async fn gc_start(&self) -> OuterResult<()> {
         let (tx, rx) = oneshot::channel();
         self.tx.send_async(ActorMessage::GcStart { tx }).await?;
@@ -1161,6 +1197,7 @@ impl StoreInner {
     }
 
     async fn shutdown(&self) {
+        self.record_access().await.ok();
         let (tx, rx) = oneshot::channel();
         self.tx
             .send_async(ActorMessage::Shutdown { tx: Some(tx) })
@@ -1173,6 +1210,12 @@ impl StoreInner {
 impl Drop for StoreInner {
     fn drop(&mut self) {
         if let Some(handle) = self.handle.take() {
+            let times = self.access.take_pending();
+            if !times.is_empty() {
+                // messages are handled in order, so this is persisted before the shutdown
+                let (tx, _rx) = oneshot::channel();
+                self.tx.send(ActorMessage::RecordAccess { times, tx }).ok();
+            }
             self.tx.send(ActorMessage::Shutdown { tx: None }).ok();
             handle.join().ok();
         }
@@ -1298,6 +1341,7 @@ impl super::MapMut for Store {
     }
 
     async fn insert_complete(&self, entry: Self::EntryMut) -> io::Result<()> {
+        self.0.access.touch(entry.hash());
         Ok(self.0.complete(entry).await?)
     }
 
@@ -1315,6 +1359,24 @@ impl super::ReadableStore for Store {
         Ok(Box::new(self.0.partial_blobs().await?.into_iter()))
     }
 
+    async fn blob_usage(&self) -> io::Result<super::DbIter<BlobUsage>> {
+        self.0.record_access().await?;
+        let times = self.0.access_times().await?;
+        let mut usage = Vec::new();
+        for hash in self.0.blobs().await? {
+            let hash = hash?;
+            let Some(entry) = self.0.get(hash).await? else {
+                continue;
+            };
+            usage.push(Ok(BlobUsage {
+                hash,
+                size: entry.current_size()?,
+                last_access: times.get(&hash).copied().unwrap_or(self.0.access.loaded()),
+            }));
+        }
+        Ok(Box::new(usage.into_iter()))
+    }
+
     async fn tags(&self) -> io::Result<super::DbIter<(Tag, HashAndFormat)>> {
         Ok(Box::new(self.0.tags().await?.into_iter()))
     }
@@ -1352,12 +1414,12 @@ impl super::Store for Store {
         progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
     ) -> io::Result<(crate::TempTag, u64)> {
         let this = self.0.clone();
-        Ok(
-            tokio::task::spawn_blocking(move || {
-                this.import_file_sync(path, mode, format, progress)
-            })
-            .await??,
-        )
+        let (tag, size) = tokio::task::spawn_blocking(move || {
+            this.import_file_sync(path, mode, format, progress)
+        })
+        .await??;
+        self.0.access.touch(*tag.hash());
+        Ok((tag, size))
     }
 
     async fn import_bytes(
@@ -1366,7 +1428,10 @@ impl super::Store for Store {
         format: iroh_base::hash::BlobFormat,
     ) -> io::Result<crate::TempTag> {
         let this = self.0.clone();
-        Ok(tokio::task::spawn_blocking(move || this.import_bytes_sync(data, format)).await??)
+        let tag =
+            tokio::task::spawn_blocking(move || this.import_bytes_sync(data, format)).await??;
+        self.0.access.touch(*tag.hash());
+        Ok(tag)
     }
 
     async fn import_stream(
@@ -1396,10 +1461,12 @@ impl super::Store for Store {
         writer.flush().await?;
         drop(writer);
         let file = ImportSource::TempFile(temp_data_path);
-        Ok(tokio::task::spawn_blocking(move || {
+        let (tag, size) = tokio::task::spawn_blocking(move || {
             this.0.finalize_import_sync(file, format, id, progress)
         })
-        .await??)
+        .await??;
+        self.0.access.touch(*tag.hash());
+        Ok((tag, size))
     }
 
     async fn set_tag(&self, name: Tag, hash: Option<HashAndFormat>) -> io::Result<()> {
@@ -1411,7 +1478,12 @@ impl super::Store for Store {
     }
 
     async fn delete(&self, hashes: Vec<Hash>) -> io::Result<()> {
-        Ok(self.0.delete(hashes).await?)
+        self.0.delete(hashes.clone()).await?;
+        let temp = self.0.temp.read().unwrap();
+        for hash in hashes.iter().filter(|hash| !temp.contains(hash)) {
+            self.0.access.remove(hash);
+        }
+        Ok(())
     }
 
     async fn gc_start(&self) -> io::Result<()> {
@@ -1433,6 +1505,7 @@ impl Actor {
         path: &Path,
         options: Options,
         temp: Arc<RwLock<TempCounterMap>>,
+        access: AccessTimes,
         rt: tokio::runtime::Handle,
     ) -> ActorResult<(Self, flume::Sender<ActorMessage>)> {
         let db = redb::Database::create(path)?;
@@ -1456,7 +1529,8 @@ impl Actor {
             Arc::new(options.path.data_path.clone()),
             16 * 1024,
             Some(on_file_create),
-        );
+        )
+        .with_access_times(access);
         Ok((
             Self {
                 db,
@@ -2017,6 +2091,7 @@ impl ActorState {
             }
             tracing::info!("deleting {}", &hash.to_hex()[..8]);
             self.handles.remove(&hash);
+            tables.access_times.remove(hash)?;
             if let Some(entry) = tables.blobs.remove(hash)? {
                 match entry.value() {
                     EntryState::Complete {
@@ -2059,6 +2134,32 @@ impl ActorState {
         Ok(())
     }
 
+    fn record_access(
+        &mut self,
+        tables: &mut Tables,
+        times: Vec<(Hash, SystemTime)>,
+    ) -> ActorResult<()> {
+        for (hash, time) in times {
+            // the blob might have been deleted since it was accessed
+            if tables.blobs.get(hash)?.is_some() {
+                tables.access_times.insert(hash, to_unix_millis(time))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn access_times(
+        &mut self,
+        tables: &impl ReadableTables,
+    ) -> ActorResult<BTreeMap<Hash, SystemTime>> {
+        let mut res = BTreeMap::new();
+        for item in tables.access_times().iter()? {
+            let (hash, millis) = item?;
+            res.insert(hash.value(), from_unix_millis(millis.value()));
+        }
+        Ok(res)
+    }
+
     fn on_complete(&mut self, tables: &mut Tables, entry: BaoFileHandle) -> ActorResult<()> {
         let hash = entry.hash();
         let mut info = None;
@@ -2190,6 +2291,10 @@ impl ActorState {
                 let res = self.tags(tables, filter);
                 tx.send(res).ok();
             }
+            ActorMessage::AccessTimes { tx } => {
+                let res = self.access_times(tables);
+                tx.send(res).ok();
+            }
             ActorMessage::GcStart { tx } => {
                 self.protected.clear();
                 self.handles.retain(|_, weak| weak.is_live());
@@ -2233,6 +2338,10 @@ impl ActorState {
                 let res = self.delete(tables, hashes);
                 tx.send(res).ok();
             }
+            ActorMessage::RecordAccess { times, tx } => {
+                let res = self.record_access(tables, times);
+                tx.send(res).ok();
+            }
             ActorMessage::OnComplete { handle } => {
                 let res = self.on_complete(tables, handle);
                 res.ok();
@@ -2279,42 +2388,6 @@ fn export_file_copy(
     Ok(())
 }
 
-/// Synchronously compute the outboard of a file, and return hash and outboard.
-///
-/// It is assumed that the file is not modified while this is running.
-///
-/// If it is modified while or after this is running, the outboard will be
-/// invalid, so any attempt to compute a slice from it will fail.
-///
-/// If the size of the file is changed while this is running, an error will be
-/// returned.
-///
-/// The computed outboard is without length prefix.
-fn compute_outboard(
-    read: impl Read,
-    size: u64,
-    progress: impl Fn(u64) -> io::Result<()> + Send + Sync + 'static,
-) -> io::Result<(Hash, Option<Vec<u8>>)> {
-    // compute outboard size so we can pre-allocate the buffer.
-    let outboard_size = usize::try_from(raw_outboard_size(size))
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "size too large"))?;
-    let mut outboard = Vec::with_capacity(outboard_size);
-
-    // wrap the reader in a progress reader, so we can report progress.
-    let reader = ProgressReader::new(read, progress);
-    // wrap the reader in a buffered reader, so we read in large chunks
-    // this reduces the number of io ops and also the number of progress reports
-    let mut reader = BufReader::with_capacity(1024 * 1024, reader);
-
-    let hash =
-        bao_tree::io::sync::outboard_post_order(&mut reader, size, IROH_BLOCK_SIZE, &mut outboard)?;
-    let ob = PostOrderMemOutboard::load(hash, &outboard, IROH_BLOCK_SIZE)?.flip();
-    tracing::trace!(%hash, "done");
-    let ob = ob.into_inner();
-    let ob = if !ob.is_empty() { Some(ob) } else { None };
-    Ok((hash.into(), ob))
-}
-
 fn dump(tables: &impl ReadableTables) -> ActorResult<()> {
     for e in tables.blobs().iter()? {
         let (k, v) = e?;
@@ -18,6 +18,10 @@ pub(super) const INLINE_DATA_TABLE: TableDefinition<Hash, &[u8]> =
 pub(super) const INLINE_OUTBOARD_TABLE: TableDefinition<Hash, &[u8]> =
     TableDefinition::new("inline-outboard-0");
 
+/// Last access times of blobs, in milliseconds since the unix epoch.
+pub(super) const ACCESS_TIMES_TABLE: TableDefinition<Hash, u64> =
+    TableDefinition::new("access-times-0");
+
 /// A trait similar to [`redb::ReadableTable`] but for all tables that make up
 /// the blob store. This can be used in places where either a readonly or
 /// mutable table is needed.
@@ -26,6 +30,7 @@ pub(super) trait ReadableTables {
     fn tags(&self) -> &impl ReadableTable<Tag, HashAndFormat>;
     fn inline_data(&self) -> &impl ReadableTable<Hash, &'static [u8]>;
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]>;
+    fn access_times(&self) -> &impl ReadableTable<Hash, u64>;
 }
 
 /// A struct similar to [`redb::Table`] but for all tables that make up the
@@ -35,6 +40,7 @@ pub(super) struct Tables<'a, 'b> {
     pub tags: redb::Table<'a, 'b, Tag, HashAndFormat>,
     pub inline_data: redb::Table<'a, 'b, Hash, &'static [u8]>,
     pub inline_outboard: redb::Table<'a, 'b, Hash, &'static [u8]>,
+    pub access_times: redb::Table<'a, 'b, Hash, u64>,
     pub delete_after_commit: &'b mut DeleteSet,
 }
 
@@ -55,6 +61,7 @@ impl<'db, 'txn> Tables<'db, 'txn> {
             tags: tx.open_table(TAGS_TABLE)?,
             inline_data: tx.open_table(INLINE_DATA_TABLE)?,
             inline_outboard: tx.open_table(INLINE_OUTBOARD_TABLE)?,
+            access_times: tx.open_table(ACCESS_TIMES_TABLE)?,
             delete_after_commit,
         })
     }
@@ -73,6 +80,9 @@ impl ReadableTables for Tables<'_, '_> {
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_outboard
     }
+    fn access_times(&self) -> &impl ReadableTable<Hash, u64> {
+        &self.access_times
+    }
 }
 
 /// A struct similar to [`redb::ReadOnlyTable`] but for all tables that make up
@@ -82,6 +92,7 @@ pub(super) struct ReadOnlyTables<'txn> {
     pub tags: redb::ReadOnlyTable<'txn, Tag, HashAndFormat>,
     pub inline_data: redb::ReadOnlyTable<'txn, Hash, &'static [u8]>,
     pub inline_outboard: redb::ReadOnlyTable<'txn, Hash, &'static [u8]>,
+    pub access_times: redb::ReadOnlyTable<'txn, Hash, u64>,
 }
 
 impl<'txn> ReadOnlyTables<'txn> {
@@ -91,6 +102,7 @@ impl<'txn> ReadOnlyTables<'txn> {
             tags: tx.open_table(TAGS_TABLE)?,
             inline_data: tx.open_table(INLINE_DATA_TABLE)?,
             inline_outboard: tx.open_table(INLINE_OUTBOARD_TABLE)?,
+            access_times: tx.open_table(ACCESS_TIMES_TABLE)?,
         })
     }
 }
@@ -108,6 +120,9 @@ impl ReadableTables for ReadOnlyTables<'_> {
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_outboard
     }
+    fn access_times(&self) -> &impl ReadableTable<Hash, u64> {
+        &self.access_times
+    }
 }
 
 /// Helper to keep track of files to delete after a transaction is committed.
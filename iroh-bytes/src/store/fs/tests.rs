@@ -8,6 +8,7 @@ use crate::store::bao_file::test_support::{
 };
 use crate::store::{Map as _, MapEntryMut, MapMut, ReadableStore, Store as _};
 use crate::util::raw_outboard;
+use crate::IROH_BLOCK_SIZE;
 
 macro_rules! assert_matches {
         ($expression:expr, $pattern:pat) => {
@@ -804,3 +805,37 @@ async fn actor_store_smoke() {
     db.sync().await.unwrap();
     db.dump().await.unwrap();
 }
+
+async fn last_access(db: &Store, hash: Hash) -> SystemTime {
+    let usage = db.blob_usage().await.unwrap();
+    let usage = usage.collect::<io::Result<Vec<_>>>().unwrap();
+    usage
+        .into_iter()
+        .find(|u| u.hash == hash)
+        .unwrap()
+        .last_access
+}
+
+#[tokio::test]
+async fn access_times_persist() {
+    let testdir = tempfile::tempdir().unwrap();
+    let data = Bytes::from(random_test_data(1024));
+    let (hash, accessed) = {
+        let db = Store::load(testdir.path()).await.unwrap();
+        let tt = db.import_bytes(data, BlobFormat::Raw).await.unwrap();
+        let hash = *tt.hash();
+        let imported = last_access(&db, hash).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // reading the data counts as an access
+        let entry = db.get(&hash).await.unwrap().unwrap();
+        let mut reader = crate::store::MapEntry::data_reader(&entry).await.unwrap();
+        reader.read_to_end().await.unwrap();
+        let accessed = last_access(&db, hash).await;
+        assert!(accessed > imported);
+        db.shutdown().await;
+        (hash, accessed)
+    };
+    // access times are persisted with the store
+    let db = Store::load(testdir.path()).await.unwrap();
+    assert_eq!(last_access(&db, hash).await, accessed);
+}
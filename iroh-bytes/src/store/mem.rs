@@ -29,8 +29,8 @@ use crate::{
 };
 
 use super::{
-    temp_name, BaoBatchWriter, ConsistencyCheckProgress, ExportMode, ExportProgressCb, ImportMode,
-    ImportProgress, Map, TempCounterMap,
+    temp_name, AccessTimes, BaoBatchWriter, BlobUsage, ConsistencyCheckProgress, ExportMode,
+    ExportProgressCb, ImportMode, ImportProgress, Map, TempCounterMap,
 };
 
 /// A fully featured in memory database for iroh-bytes, including support for
@@ -85,14 +85,17 @@ impl Store {
         progress.blocking_send(ImportProgress::OutboardDone { id, hash })?;
         use super::Store;
         let tag = self.temp_tag(HashAndFormat { hash, format });
+        let mut state = self.write_lock();
         let entry = Entry {
             inner: Arc::new(EntryInner {
                 hash,
                 data: RwLock::new(storage),
+                access: state.access.clone(),
             }),
             complete: true,
         };
-        self.write_lock().entries.insert(hash, entry);
+        state.access.touch(hash);
+        state.entries.insert(hash, entry);
         Ok(tag)
     }
 
@@ -229,6 +232,7 @@ impl super::Store for Store {
         for hash in hashes {
             if !state.temp.contains(&hash) {
                 state.entries.remove(&hash);
+                state.access.remove(&hash);
             }
         }
         Ok(())
@@ -242,6 +246,7 @@ struct StateInner {
     entries: BTreeMap<Hash, Entry>,
     tags: BTreeMap<Tag, HashAndFormat>,
     temp: TempCounterMap,
+    access: AccessTimes,
 }
 
 /// An in memory entry
@@ -255,6 +260,8 @@ pub struct Entry {
 struct EntryInner {
     hash: Hash,
     data: RwLock<MutableMemStorage>,
+    /// Where to record reads of the data.
+    access: AccessTimes,
 }
 
 impl MapEntry for Entry {
@@ -272,6 +279,7 @@ impl MapEntry for Entry {
     }
 
     async fn outboard(&self) -> io::Result<impl Outboard> {
+        self.inner.access.touch(self.hash());
         let size = self.inner.data.read().unwrap().current_size();
         Ok(PreOrderOutboard {
             root: self.hash().into(),
@@ -281,6 +289,7 @@ impl MapEntry for Entry {
     }
 
     async fn data_reader(&self) -> io::Result<impl AsyncSliceReader> {
+        self.inner.access.touch(self.hash());
         Ok(DataReader(self.inner.clone()))
     }
 }
@@ -335,7 +344,7 @@ impl super::Map for Store {
     type Entry = Entry;
 
     async fn get(&self, hash: &Hash) -> std::io::Result<Option<Self::Entry>> {
-        Ok(self.inner.0.read().unwrap().entries.get(hash).cloned())
+        Ok(self.read_lock().entries.get(hash).cloned())
     }
 }
 
@@ -351,6 +360,7 @@ impl super::MapMut for Store {
             inner: Arc::new(EntryInner {
                 hash,
                 data: RwLock::new(MutableMemStorage::default()),
+                access: self.read_lock().access.clone(),
             }),
             complete: false,
         };
@@ -384,6 +394,7 @@ impl super::MapMut for Store {
             .unwrap_or_default();
         if !complete {
             entry.complete = true;
+            inner.access.touch(hash);
             inner.entries.insert(hash, entry);
         }
         Ok(())
@@ -411,6 +422,23 @@ impl ReadableStore for Store {
         ))
     }
 
+    async fn blob_usage(&self) -> io::Result<crate::store::DbIter<BlobUsage>> {
+        let state = self.read_lock();
+        let usage = state
+            .entries
+            .values()
+            .filter(|x| x.complete)
+            .map(|x| {
+                Ok(BlobUsage {
+                    hash: x.hash(),
+                    size: x.inner.data.read().unwrap().current_size(),
+                    last_access: state.access.get(&x.hash()).unwrap_or(state.access.loaded()),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(usage.into_iter()))
+    }
+
     async fn tags(
         &self,
     ) -> io::Result<crate::store::DbIter<(crate::Tag, iroh_base::hash::HashAndFormat)>> {
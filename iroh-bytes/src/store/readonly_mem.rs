@@ -6,6 +6,7 @@ use std::{
     io,
     path::PathBuf,
     sync::Arc,
+    time::SystemTime,
 };
 
 use crate::{
@@ -28,7 +29,9 @@ use futures::Stream;
 use iroh_io::AsyncSliceReader;
 use tokio::io::AsyncWriteExt;
 
-use super::{BaoBatchWriter, BaoBlobSize, ConsistencyCheckProgress, DbIter, ExportProgressCb};
+use super::{
+    BaoBatchWriter, BaoBlobSize, BlobUsage, ConsistencyCheckProgress, DbIter, ExportProgressCb,
+};
 
 /// A readonly in memory database for iroh-bytes.
 ///
@@ -264,6 +267,23 @@ impl ReadableStore for Store {
     async fn partial_blobs(&self) -> io::Result<DbIter<Hash>> {
         Ok(Box::new(std::iter::empty()))
     }
+
+    async fn blob_usage(&self) -> io::Result<DbIter<BlobUsage>> {
+        // accesses are not tracked, since blobs can not be deleted anyway
+        Ok(Box::new(
+            self.0
+                .iter()
+                .map(|(hash, (_, data))| {
+                    Ok(BlobUsage {
+                        hash: *hash,
+                        size: data.len() as u64,
+                        last_access: SystemTime::UNIX_EPOCH,
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter(),
+        ))
+    }
 }
 
 impl MapEntryMut for Entry {
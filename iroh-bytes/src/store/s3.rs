@@ -0,0 +1,1330 @@
+//! A blob store that keeps blob data in an S3 compatible object store.
+//!
+//! Main entry point is [Store].
+//!
+//! Complete blobs are stored as two objects named after the hash of the blob: the data and
+//! the outboard. Data is read with ranged requests, so serving a small range of a large
+//! blob only transfers that range from the object store. The outboard is small compared to
+//! the data and is fetched as a whole. Large objects are uploaded in parts, so the size of
+//! a blob is not limited by the maximum size of a single upload.
+//!
+//! Objects can not be modified once written, so partial blobs are kept in files in a local
+//! directory until they are complete and then uploaded. Partial blobs survive a restart.
+//! Imported data is staged in local files as well, so it never has to fit into memory.
+//!
+//! The sizes of complete blobs and the tags are stored in a local redb database. Database
+//! transactions block, so they are run on the blocking thread pool.
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::SystemTime,
+};
+
+use bao_tree::{
+    io::{fsm::Outboard, outboard::PreOrderOutboard},
+    BaoTree, ByteNum,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use iroh_base::hash::{BlobFormat, Hash, HashAndFormat};
+use iroh_io::AsyncSliceReader;
+use redb::{ReadableTable, TableDefinition};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    store::{
+        bao_file::{self, BaoFileConfig, BaoFileHandle, BaoFileWriter},
+        BaoBlobSize, EntryStatus, MapEntry, MapEntryMut, ReadableStore,
+    },
+    util::{
+        compute_outboard,
+        progress::{BoxedProgressSender, IdGenerator, IgnoreProgressSender, ProgressSender},
+        raw_outboard, raw_outboard_size, LivenessTracker, MemOrFile,
+    },
+    Tag, TempTag, IROH_BLOCK_SIZE,
+};
+
+use super::{
+    from_unix_millis, temp_name, to_unix_millis, AccessTimes, BaoBatchWriter, BlobUsage,
+    ConsistencyCheckProgress, DbIter, ExportMode, ExportProgressCb, ImportMode, ImportProgress,
+    Map, ReportLevel, TempCounterMap,
+};
+
+mod client;
+pub use client::{ObjectStore, S3Client, S3Config};
+
+const BLOBS_TABLE: TableDefinition<Hash, u64> = TableDefinition::new("blobs-0");
+
+const TAGS_TABLE: TableDefinition<Tag, HashAndFormat> = TableDefinition::new("tags-0");
+
+/// Last access times of complete blobs, in milliseconds since the unix epoch.
+const ACCESS_TIMES_TABLE: TableDefinition<Hash, u64> = TableDefinition::new("access-times-0");
+
+/// Size of the pieces in which blobs are read from the object store when exporting.
+const EXPORT_READ_SIZE: usize = 1024 * 1024 * 4;
+
+/// Size of the parts of a multipart upload.
+///
+/// Objects up to this size are uploaded with a single request.
+const PART_SIZE: u64 = 1024 * 1024 * 16;
+
+/// Maximum number of parts of a multipart upload.
+///
+/// The part size is increased for objects that would need more parts.
+const MAX_PARTS: u64 = 10_000;
+
+fn data_key(hash: &Hash) -> String {
+    format!("{}.data", hash.to_hex())
+}
+
+fn outboard_key(hash: &Hash) -> String {
+    format!("{}.obao4", hash.to_hex())
+}
+
+fn to_io_err(e: impl Into<redb::Error>) -> io::Error {
+    io::Error::other(e.into())
+}
+
+/// A blob store that keeps blob data in an S3 compatible object store.
+#[derive(Debug, Clone)]
+pub struct Store {
+    inner: Arc<StoreInner>,
+}
+
+struct StoreInner {
+    /// Sizes of complete blobs and tags.
+    db: redb::Database,
+    objects: Arc<dyn ObjectStore>,
+    /// Location of the files of partial blobs.
+    partial: Arc<BaoFileConfig>,
+    /// Directory for staging imported data.
+    temp_dir: PathBuf,
+    state: RwLock<State>,
+    access: AccessTimes,
+}
+
+impl fmt::Debug for StoreInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreInner")
+            .field("objects", &self.objects)
+            .field("partial", &self.partial)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    partial: BTreeMap<Hash, BaoFileHandle>,
+    temp: TempCounterMap,
+}
+
+impl LivenessTracker for StoreInner {
+    fn on_clone(&self, inner: &HashAndFormat) {
+        tracing::trace!("temp tagging: {:?}", inner);
+        let mut state = self.state.write().unwrap();
+        state.temp.inc(inner);
+    }
+
+    fn on_drop(&self, inner: &HashAndFormat) {
+        tracing::trace!("temp tag drop: {:?}", inner);
+        let mut state = self.state.write().unwrap();
+        state.temp.dec(inner);
+    }
+}
+
+impl StoreInner {
+    /// The size of the complete blob `hash`, if it is in the store.
+    fn complete_size(&self, hash: &Hash) -> io::Result<Option<u64>> {
+        let tx = self.db.begin_read().map_err(to_io_err)?;
+        let blobs = tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+        let size = blobs.get(hash).map_err(to_io_err)?.map(|x| x.value());
+        Ok(size)
+    }
+
+    /// All complete blobs and their sizes.
+    fn complete_blobs(&self) -> io::Result<Vec<(Hash, u64)>> {
+        let tx = self.db.begin_read().map_err(to_io_err)?;
+        let blobs = tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+        let mut res = Vec::new();
+        for item in blobs.iter().map_err(to_io_err)? {
+            let (hash, size) = item.map_err(to_io_err)?;
+            res.push((hash.value(), size.value()));
+        }
+        Ok(res)
+    }
+
+    /// Persist the accesses recorded since the last call.
+    fn record_access(&self) -> io::Result<()> {
+        let times = self.access.take_pending();
+        if times.is_empty() {
+            return Ok(());
+        }
+        let tx = self.db.begin_write().map_err(to_io_err)?;
+        {
+            let blobs = tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+            let mut access_times = tx.open_table(ACCESS_TIMES_TABLE).map_err(to_io_err)?;
+            for (hash, time) in times {
+                // the blob might have been deleted since it was accessed
+                if blobs.get(hash).map_err(to_io_err)?.is_some() {
+                    access_times
+                        .insert(hash, to_unix_millis(time))
+                        .map_err(to_io_err)?;
+                }
+            }
+        }
+        tx.commit().map_err(to_io_err)
+    }
+
+    /// The persisted last access times of complete blobs.
+    fn access_times(&self) -> io::Result<BTreeMap<Hash, SystemTime>> {
+        let tx = self.db.begin_read().map_err(to_io_err)?;
+        let table = tx.open_table(ACCESS_TIMES_TABLE).map_err(to_io_err)?;
+        let mut res = BTreeMap::new();
+        for item in table.iter().map_err(to_io_err)? {
+            let (hash, millis) = item.map_err(to_io_err)?;
+            res.insert(hash.value(), from_unix_millis(millis.value()));
+        }
+        Ok(res)
+    }
+}
+
+impl Drop for StoreInner {
+    fn drop(&mut self) {
+        if let Err(cause) = self.record_access() {
+            tracing::warn!("failed to persist access times: {}", cause);
+        }
+    }
+}
+
+impl Store {
+    /// Load or create a store in the directory at `path`, keeping the blob data in
+    /// `objects`.
+    ///
+    /// The directory holds the database with the sizes of complete blobs and the tags, and
+    /// the files of partial blobs.
+    pub fn load(path: impl AsRef<Path>, objects: Arc<dyn ObjectStore>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let partial_dir = path.join("partial");
+        let temp_dir = path.join("temp");
+        std::fs::create_dir_all(&partial_dir)?;
+        // imports that were interrupted are not resumed
+        if temp_dir.exists() {
+            std::fs::remove_dir_all(&temp_dir)?;
+        }
+        std::fs::create_dir_all(&temp_dir)?;
+        let db = redb::Database::create(path.join("blobs.db")).map_err(to_io_err)?;
+        // create the tables, so read transactions can always open them
+        let tx = db.begin_write().map_err(to_io_err)?;
+        tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+        tx.open_table(TAGS_TABLE).map_err(to_io_err)?;
+        tx.open_table(ACCESS_TIMES_TABLE).map_err(to_io_err)?;
+        tx.commit().map_err(to_io_err)?;
+        let partial = Arc::new(BaoFileConfig::new(Arc::new(partial_dir), 0, None));
+        let mut state = State::default();
+        for hash in partial.file_hashes()? {
+            let handle = BaoFileHandle::incomplete_file(partial.clone(), hash)?;
+            state.partial.insert(hash, handle);
+        }
+        Ok(Self {
+            inner: Arc::new(StoreInner {
+                db,
+                objects,
+                partial,
+                temp_dir,
+                state: RwLock::new(state),
+                access: Default::default(),
+            }),
+        })
+    }
+
+    /// Load or create a store in the directory at `path`, keeping the blob data in the S3
+    /// bucket described by `config`.
+    pub fn load_s3(path: impl AsRef<Path>, config: S3Config) -> io::Result<Self> {
+        let client = S3Client::new(config).map_err(io::Error::other)?;
+        Self::load(path, Arc::new(client))
+    }
+
+    /// Take a write lock on the in memory state
+    fn write_lock(&self) -> RwLockWriteGuard<'_, State> {
+        self.inner.state.write().unwrap()
+    }
+
+    /// Take a read lock on the in memory state
+    fn read_lock(&self) -> RwLockReadGuard<'_, State> {
+        self.inner.state.read().unwrap()
+    }
+
+    /// Run `f` on the blocking thread pool, since database transactions block.
+    async fn with_db<T, F>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&StoreInner) -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&inner)).await?
+    }
+
+    async fn complete_size(&self, hash: Hash) -> io::Result<Option<u64>> {
+        self.with_db(move |inner| inner.complete_size(&hash)).await
+    }
+
+    fn complete_entry(&self, hash: Hash, size: u64) -> Entry {
+        Entry {
+            hash,
+            inner: EntryInner::Complete {
+                size,
+                objects: self.inner.objects.clone(),
+            },
+            access: self.inner.access.clone(),
+        }
+    }
+
+    fn partial_entry(&self, hash: Hash, handle: BaoFileHandle) -> Entry {
+        Entry {
+            hash,
+            inner: EntryInner::Partial(handle),
+            access: self.inner.access.clone(),
+        }
+    }
+
+    /// Upload the data and outboard of a complete blob, and record it in the database.
+    async fn upload_complete(
+        &self,
+        hash: Hash,
+        size: u64,
+        data: impl AsyncSliceReader,
+        outboard: impl AsyncSliceReader,
+    ) -> io::Result<()> {
+        if self.complete_size(hash).await?.is_some() {
+            return Ok(());
+        }
+        let objects = self.inner.objects.as_ref();
+        let outboard_size = raw_outboard_size(size);
+        if outboard_size > 0 {
+            upload_object(
+                objects,
+                &outboard_key(&hash),
+                outboard,
+                outboard_size,
+                PART_SIZE,
+            )
+            .await?;
+        }
+        upload_object(objects, &data_key(&hash), data, size, PART_SIZE).await?;
+        // only record the blob once both objects are in place
+        self.with_db(move |inner| {
+            let tx = inner.db.begin_write().map_err(to_io_err)?;
+            {
+                let mut blobs = tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+                blobs.insert(hash, size).map_err(to_io_err)?;
+            }
+            tx.commit().map_err(to_io_err)
+        })
+        .await?;
+        self.inner.access.touch(hash);
+        Ok(())
+    }
+
+    async fn import_bytes_impl(
+        &self,
+        id: u64,
+        bytes: Bytes,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<TempTag> {
+        progress
+            .send(ImportProgress::OutboardProgress { id, offset: 0 })
+            .await?;
+        let data = bytes.clone();
+        let (outboard, hash) = tokio::task::spawn_blocking(move || raw_outboard(&data)).await?;
+        progress
+            .send(ImportProgress::OutboardDone { id, hash })
+            .await?;
+        let tag = TempTag::new(HashAndFormat { hash, format }, Some(self.inner.clone()));
+        let size = bytes.len() as u64;
+        self.upload_complete(hash, size, bytes, Bytes::from(outboard))
+            .await?;
+        Ok(tag)
+    }
+
+    /// Import the file at `path`, which must not change until the import is done.
+    async fn import_file_impl(
+        &self,
+        id: u64,
+        path: PathBuf,
+        size: u64,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<TempTag> {
+        progress
+            .send(ImportProgress::OutboardProgress { id, offset: 0 })
+            .await?;
+        let file = std::fs::File::open(&path)?;
+        let progress2 = progress.clone();
+        let (file, (hash, outboard)) = tokio::task::spawn_blocking(move || {
+            let res = compute_outboard(&file, size, move |offset| {
+                Ok(progress2.try_send(ImportProgress::OutboardProgress { id, offset })?)
+            })?;
+            io::Result::Ok((file, res))
+        })
+        .await??;
+        progress
+            .send(ImportProgress::OutboardDone { id, hash })
+            .await?;
+        let tag = TempTag::new(HashAndFormat { hash, format }, Some(self.inner.clone()));
+        // read the parts to upload from the file instead of loading it into memory
+        let handle = BaoFileHandle::new_complete(
+            self.inner.partial.clone(),
+            hash,
+            MemOrFile::File((file, size)),
+            MemOrFile::Mem(outboard.unwrap_or_default().into()),
+        );
+        self.upload_complete(hash, size, handle.data_reader(), handle.outboard_reader())
+            .await?;
+        Ok(tag)
+    }
+}
+
+/// Upload `size` bytes read from `reader` to the object at `key`.
+///
+/// Objects larger than `part_size` are uploaded in parts, so only a single part is in
+/// memory at a time.
+async fn upload_object(
+    objects: &dyn ObjectStore,
+    key: &str,
+    mut reader: impl AsyncSliceReader,
+    size: u64,
+    part_size: u64,
+) -> io::Result<()> {
+    if size <= part_size {
+        let data = read_exact_at(&mut reader, 0, size as usize).await?;
+        return objects.put(key, data).await;
+    }
+    let part_size = part_size.max(size.div_ceil(MAX_PARTS));
+    let upload_id = objects.create_multipart(key).await?;
+    let res = async {
+        let mut etags = Vec::new();
+        let mut offset = 0;
+        while offset < size {
+            let len = part_size.min(size - offset);
+            let data = read_exact_at(&mut reader, offset, len as usize).await?;
+            let part_number = etags.len() as u32 + 1;
+            etags.push(
+                objects
+                    .upload_part(key, &upload_id, part_number, data)
+                    .await?,
+            );
+            offset += len;
+        }
+        objects.complete_multipart(key, &upload_id, etags).await
+    }
+    .await;
+    if res.is_err() {
+        // do not leave the uploaded parts behind
+        objects.abort_multipart(key, &upload_id).await.ok();
+    }
+    res
+}
+
+async fn read_exact_at(
+    reader: &mut impl AsyncSliceReader,
+    offset: u64,
+    len: usize,
+) -> io::Result<Bytes> {
+    let data = reader.read_at(offset, len).await?;
+    if data.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+impl super::Store for Store {
+    async fn import_file(
+        &self,
+        path: PathBuf,
+        mode: ImportMode,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)> {
+        let id = progress.new_id();
+        progress
+            .send(ImportProgress::Found {
+                id,
+                name: path.to_string_lossy().to_string(),
+            })
+            .await?;
+        let (path, temp) = match mode {
+            // the caller promises not to change the file while it is imported
+            ImportMode::TryReference => (path, false),
+            ImportMode::Copy => {
+                progress.try_send(ImportProgress::CopyProgress { id, offset: 0 })?;
+                let temp = self.inner.temp_dir.join(temp_name());
+                tokio::fs::copy(&path, &temp).await?;
+                (temp, true)
+            }
+        };
+        let res = async {
+            let size = tokio::fs::metadata(&path).await?.len();
+            progress.send(ImportProgress::Size { id, size }).await?;
+            let tag = self
+                .import_file_impl(id, path.clone(), size, format, progress)
+                .await?;
+            Ok((tag, size))
+        }
+        .await;
+        if temp {
+            tokio::fs::remove_file(&path).await.ok();
+        }
+        res
+    }
+
+    async fn import_stream(
+        &self,
+        mut data: impl Stream<Item = io::Result<Bytes>> + Unpin + Send + 'static,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)> {
+        let id = progress.new_id();
+        let name = temp_name();
+        progress
+            .send(ImportProgress::Found {
+                id,
+                name: name.clone(),
+            })
+            .await?;
+        let path = self.inner.temp_dir.join(name);
+        let res = async {
+            let mut file = tokio::fs::File::create(&path).await?;
+            let mut size = 0;
+            while let Some(chunk) = data.next().await {
+                let chunk = chunk?;
+                file.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+                progress
+                    .try_send(ImportProgress::CopyProgress { id, offset: size })
+                    .ok();
+            }
+            file.flush().await?;
+            drop(file);
+            progress.send(ImportProgress::Size { id, size }).await?;
+            let tag = self
+                .import_file_impl(id, path.clone(), size, format, progress)
+                .await?;
+            Ok((tag, size))
+        }
+        .await;
+        tokio::fs::remove_file(&path).await.ok();
+        res
+    }
+
+    async fn import_bytes(&self, bytes: Bytes, format: BlobFormat) -> io::Result<TempTag> {
+        self.import_bytes_impl(0, bytes, format, IgnoreProgressSender::default())
+            .await
+    }
+
+    async fn set_tag(&self, name: Tag, value: Option<HashAndFormat>) -> io::Result<()> {
+        self.with_db(move |inner| {
+            let tx = inner.db.begin_write().map_err(to_io_err)?;
+            {
+                let mut tags = tx.open_table(TAGS_TABLE).map_err(to_io_err)?;
+                if let Some(value) = value {
+                    tags.insert(name, value).map_err(to_io_err)?;
+                } else {
+                    tags.remove(name).map_err(to_io_err)?;
+                }
+            }
+            tx.commit().map_err(to_io_err)
+        })
+        .await
+    }
+
+    async fn create_tag(&self, hash: HashAndFormat) -> io::Result<Tag> {
+        self.with_db(move |inner| {
+            let tx = inner.db.begin_write().map_err(to_io_err)?;
+            let tag = {
+                let mut tags = tx.open_table(TAGS_TABLE).map_err(to_io_err)?;
+                let tag = Tag::auto(SystemTime::now(), |x| {
+                    matches!(tags.get(Tag(Bytes::copy_from_slice(x))), Ok(Some(_)))
+                });
+                tags.insert(tag.clone(), hash).map_err(to_io_err)?;
+                tag
+            };
+            tx.commit().map_err(to_io_err)?;
+            Ok(tag)
+        })
+        .await
+    }
+
+    fn temp_tag(&self, tag: HashAndFormat) -> TempTag {
+        TempTag::new(tag, Some(self.inner.clone()))
+    }
+
+    async fn gc_start(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn delete(&self, hashes: Vec<Hash>) -> io::Result<()> {
+        let deleted = self
+            .with_db(move |inner| {
+                let mut deleted = Vec::new();
+                let mut state = inner.state.write().unwrap();
+                let tx = inner.db.begin_write().map_err(to_io_err)?;
+                {
+                    let mut blobs = tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+                    let mut access_times = tx.open_table(ACCESS_TIMES_TABLE).map_err(to_io_err)?;
+                    for hash in hashes {
+                        if state.temp.contains(&hash) {
+                            continue;
+                        }
+                        if state.partial.remove(&hash).is_some() {
+                            inner.partial.delete_files(&hash)?;
+                        }
+                        inner.access.remove(&hash);
+                        access_times.remove(hash).map_err(to_io_err)?;
+                        if blobs.remove(hash).map_err(to_io_err)?.is_some() {
+                            deleted.push(hash);
+                        }
+                    }
+                }
+                tx.commit().map_err(to_io_err)?;
+                Ok(deleted)
+            })
+            .await?;
+        // the blobs are no longer in the database, so failing here only leaks objects
+        for hash in deleted {
+            self.inner.objects.delete(&data_key(&hash)).await?;
+            self.inner.objects.delete(&outboard_key(&hash)).await?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&self) {
+        if let Err(cause) = self.with_db(StoreInner::record_access).await {
+            tracing::warn!("failed to persist access times: {}", cause);
+        }
+    }
+}
+
+/// An entry in the S3 store
+#[derive(Debug, Clone)]
+pub struct Entry {
+    hash: Hash,
+    inner: EntryInner,
+    /// Where to record reads of the data.
+    access: AccessTimes,
+}
+
+#[derive(Debug, Clone)]
+enum EntryInner {
+    /// A partial entry, kept in local files until it is complete.
+    Partial(BaoFileHandle),
+    /// A complete entry, stored in the object store.
+    Complete {
+        size: u64,
+        objects: Arc<dyn ObjectStore>,
+    },
+}
+
+impl MapEntry for Entry {
+    fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    fn size(&self) -> BaoBlobSize {
+        match &self.inner {
+            EntryInner::Partial(handle) => {
+                BaoBlobSize::new(handle.current_size().unwrap_or_default(), false)
+            }
+            EntryInner::Complete { size, .. } => BaoBlobSize::new(*size, true),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        matches!(self.inner, EntryInner::Complete { .. })
+    }
+
+    async fn outboard(&self) -> io::Result<impl Outboard> {
+        self.access.touch(self.hash);
+        let (size, data) = match &self.inner {
+            EntryInner::Partial(handle) => (
+                handle.current_size()?,
+                OutboardReader::Partial(handle.outboard_reader()),
+            ),
+            EntryInner::Complete { size, objects } => {
+                let data = if raw_outboard_size(*size) == 0 {
+                    Bytes::new()
+                } else {
+                    objects.get(&outboard_key(&self.hash)).await?
+                };
+                (*size, OutboardReader::Complete(data))
+            }
+        };
+        Ok(PreOrderOutboard {
+            root: self.hash.into(),
+            tree: BaoTree::new(ByteNum(size), IROH_BLOCK_SIZE),
+            data,
+        })
+    }
+
+    async fn data_reader(&self) -> io::Result<impl AsyncSliceReader> {
+        Ok(self.read_data())
+    }
+}
+
+impl Entry {
+    /// Reader for the data of this entry.
+    ///
+    /// Unlike the reader returned by [`MapEntry::data_reader`], the futures of this reader are
+    /// known to be `Send`.
+    fn read_data(&self) -> DataReader {
+        self.access.touch(self.hash);
+        match &self.inner {
+            EntryInner::Partial(handle) => DataReader::Partial(handle.data_reader()),
+            EntryInner::Complete { size, objects } => DataReader::Complete {
+                objects: objects.clone(),
+                key: data_key(&self.hash),
+                size: *size,
+            },
+        }
+    }
+}
+
+impl MapEntryMut for Entry {
+    async fn batch_writer(&self) -> io::Result<impl BaoBatchWriter> {
+        Ok(match &self.inner {
+            EntryInner::Partial(handle) => BatchWriter::Partial(handle.writer()),
+            EntryInner::Complete { .. } => BatchWriter::Complete,
+        })
+    }
+}
+
+enum DataReader {
+    Partial(bao_file::DataReader),
+    /// Reads ranges of the data object.
+    Complete {
+        objects: Arc<dyn ObjectStore>,
+        key: String,
+        size: u64,
+    },
+}
+
+impl AsyncSliceReader for DataReader {
+    async fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Bytes> {
+        match self {
+            Self::Partial(reader) => reader.read_at(offset, len).await,
+            Self::Complete { objects, key, size } => {
+                let end = offset.saturating_add(len as u64).min(*size);
+                if offset >= end {
+                    return Ok(Bytes::new());
+                }
+                objects.get_range(key, offset..end).await
+            }
+        }
+    }
+
+    async fn len(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Partial(reader) => reader.len().await,
+            Self::Complete { size, .. } => Ok(*size),
+        }
+    }
+}
+
+enum OutboardReader {
+    Partial(bao_file::OutboardReader),
+    Complete(Bytes),
+}
+
+impl AsyncSliceReader for OutboardReader {
+    async fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Bytes> {
+        match self {
+            Self::Partial(reader) => reader.read_at(offset, len).await,
+            Self::Complete(data) => data.read_at(offset, len).await,
+        }
+    }
+
+    async fn len(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Partial(reader) => reader.len().await,
+            Self::Complete(data) => data.len().await,
+        }
+    }
+}
+
+/// Batch writer for an entry.
+///
+/// Writes to a complete entry are ignored, since the data is already there and verified.
+enum BatchWriter {
+    Partial(BaoFileWriter),
+    Complete,
+}
+
+impl BaoBatchWriter for BatchWriter {
+    async fn write_batch(
+        &mut self,
+        size: u64,
+        batch: Vec<bao_tree::io::fsm::BaoContentItem>,
+    ) -> io::Result<()> {
+        match self {
+            Self::Partial(writer) => writer.write_batch(size, batch).await,
+            Self::Complete => Ok(()),
+        }
+    }
+
+    async fn sync(&mut self) -> io::Result<()> {
+        match self {
+            Self::Partial(writer) => writer.sync().await,
+            Self::Complete => Ok(()),
+        }
+    }
+}
+
+impl Map for Store {
+    type Entry = Entry;
+
+    async fn get(&self, hash: &Hash) -> io::Result<Option<Self::Entry>> {
+        Ok(match self.complete_size(*hash).await? {
+            Some(size) => Some(self.complete_entry(*hash, size)),
+            None => {
+                let handle = self.read_lock().partial.get(hash).cloned();
+                handle.map(|handle| self.partial_entry(*hash, handle))
+            }
+        })
+    }
+}
+
+impl super::MapMut for Store {
+    type EntryMut = Entry;
+
+    async fn get_mut(&self, hash: &Hash) -> io::Result<Option<Self::EntryMut>> {
+        self.get(hash).await
+    }
+
+    async fn get_or_create(&self, hash: Hash, _size: u64) -> io::Result<Entry> {
+        if let Some(size) = self.complete_size(hash).await? {
+            return Ok(self.complete_entry(hash, size));
+        }
+        let handle = {
+            let mut state = self.write_lock();
+            match state.partial.get(&hash) {
+                Some(handle) => handle.clone(),
+                None => {
+                    let handle = BaoFileHandle::incomplete_file(self.inner.partial.clone(), hash)?;
+                    state.partial.insert(hash, handle.clone());
+                    handle
+                }
+            }
+        };
+        Ok(self.partial_entry(hash, handle))
+    }
+
+    async fn entry_status(&self, hash: &Hash) -> io::Result<EntryStatus> {
+        let this = self.clone();
+        let hash = *hash;
+        tokio::task::spawn_blocking(move || this.entry_status_sync(&hash)).await?
+    }
+
+    fn entry_status_sync(&self, hash: &Hash) -> io::Result<EntryStatus> {
+        Ok(if self.inner.complete_size(hash)?.is_some() {
+            EntryStatus::Complete
+        } else if self.read_lock().partial.contains_key(hash) {
+            EntryStatus::Partial
+        } else {
+            EntryStatus::NotFound
+        })
+    }
+
+    async fn insert_complete(&self, entry: Entry) -> io::Result<()> {
+        let EntryInner::Partial(handle) = entry.inner else {
+            return Ok(());
+        };
+        let size = handle.current_size()?;
+        self.upload_complete(
+            entry.hash,
+            size,
+            handle.data_reader(),
+            handle.outboard_reader(),
+        )
+        .await?;
+        // the blob is in the object store now, so the local files are no longer needed
+        self.write_lock().partial.remove(&entry.hash);
+        let hash = entry.hash;
+        self.with_db(move |inner| inner.partial.delete_files(&hash))
+            .await
+    }
+}
+
+impl ReadableStore for Store {
+    async fn blobs(&self) -> io::Result<DbIter<Hash>> {
+        let blobs = self.with_db(StoreInner::complete_blobs).await?;
+        Ok(Box::new(blobs.into_iter().map(|(hash, _)| Ok(hash))))
+    }
+
+    async fn partial_blobs(&self) -> io::Result<DbIter<Hash>> {
+        let hashes = self.read_lock().partial.keys().copied().collect::<Vec<_>>();
+        Ok(Box::new(hashes.into_iter().map(Ok)))
+    }
+
+    async fn blob_usage(&self) -> io::Result<DbIter<BlobUsage>> {
+        let (blobs, times) = self
+            .with_db(|inner| {
+                inner.record_access()?;
+                Ok((inner.complete_blobs()?, inner.access_times()?))
+            })
+            .await?;
+        let loaded = self.inner.access.loaded();
+        let usage = blobs
+            .into_iter()
+            .map(|(hash, size)| {
+                Ok(BlobUsage {
+                    hash,
+                    size,
+                    last_access: times.get(&hash).copied().unwrap_or(loaded),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(usage.into_iter()))
+    }
+
+    async fn tags(&self) -> io::Result<DbIter<(Tag, HashAndFormat)>> {
+        let tags = self
+            .with_db(|inner| {
+                let tx = inner.db.begin_read().map_err(to_io_err)?;
+                let table = tx.open_table(TAGS_TABLE).map_err(to_io_err)?;
+                let mut tags = Vec::new();
+                for item in table.iter().map_err(to_io_err)? {
+                    let (tag, value) = item.map_err(to_io_err)?;
+                    tags.push(Ok((tag.value(), value.value())));
+                }
+                Ok(tags)
+            })
+            .await?;
+        Ok(Box::new(tags.into_iter()))
+    }
+
+    fn temp_tags(&self) -> Box<dyn Iterator<Item = HashAndFormat> + Send + Sync + 'static> {
+        let tags = self.read_lock().temp.keys();
+        Box::new(tags)
+    }
+
+    async fn consistency_check(
+        &self,
+        repair: bool,
+        tx: BoxedProgressSender<ConsistencyCheckProgress>,
+    ) -> io::Result<()> {
+        tx.send(ConsistencyCheckProgress::Start).await?;
+        let mut missing = Vec::new();
+        for (hash, size) in self.with_db(StoreInner::complete_blobs).await? {
+            // reading the last byte checks that the data object exists and is not truncated
+            let last = self
+                .inner
+                .objects
+                .get_range(&data_key(&hash), size.saturating_sub(1)..size)
+                .await;
+            if !matches!(last, Ok(last) if last.len() as u64 == size.min(1)) {
+                tx.send(ConsistencyCheckProgress::Update {
+                    message: "data object is missing or truncated".to_string(),
+                    entry: Some(hash),
+                    level: ReportLevel::Error,
+                })
+                .await?;
+                missing.push(hash);
+            }
+        }
+        if repair && !missing.is_empty() {
+            let count = missing.len();
+            self.with_db(move |inner| {
+                let db_tx = inner.db.begin_write().map_err(to_io_err)?;
+                {
+                    let mut blobs = db_tx.open_table(BLOBS_TABLE).map_err(to_io_err)?;
+                    for hash in &missing {
+                        blobs.remove(hash).map_err(to_io_err)?;
+                    }
+                }
+                db_tx.commit().map_err(to_io_err)
+            })
+            .await?;
+            tx.send(ConsistencyCheckProgress::Update {
+                message: format!("removed {count} broken entries"),
+                entry: None,
+                level: ReportLevel::Info,
+            })
+            .await?;
+        }
+        tx.send(ConsistencyCheckProgress::Done).await?;
+        Ok(())
+    }
+    async fn export(
+        &self,
+        hash: Hash,
+        target: PathBuf,
+        _mode: ExportMode,
+        progress: ExportProgressCb,
+    ) -> io::Result<()> {
+        tracing::trace!("exporting {} to {}", hash, target.display());
+        if !target.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "target path must be absolute",
+            ));
+        }
+        let parent = target.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "target path has no parent directory",
+            )
+        })?;
+        let entry = self
+            .get(&hash)
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "hash not found"))?;
+        // create the directory in which the target file is
+        tokio::fs::create_dir_all(parent).await?;
+        let mut reader = entry.read_data();
+        let mut file = tokio::fs::File::create(target).await?;
+        let mut offset = 0;
+        loop {
+            let bytes = reader.read_at(offset, EXPORT_READ_SIZE).await?;
+            if bytes.is_empty() {
+                break;
+            }
+            file.write_all(&bytes).await?;
+            offset += bytes.len() as u64;
+            progress(offset)?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "fs-store"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use bao_tree::ChunkRanges;
+    use iroh_io::AsyncSliceReaderExt;
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::{
+        get::db::valid_ranges,
+        store::{
+            bao_file::test_support::{
+                decode_response_into_batch, random_test_data, simulate_remote,
+            },
+            MapMut, Store as _,
+        },
+    };
+
+    /// A stand-in for an S3 compatible object store, serving objects from memory.
+    ///
+    /// This only understands the requests made by [`S3Client`] and does not check
+    /// signatures.
+    #[derive(Debug, Clone, Default)]
+    struct MockS3(Arc<Mutex<MockState>>);
+
+    #[derive(Debug, Default)]
+    struct MockState {
+        objects: BTreeMap<String, Bytes>,
+        /// Parts of the multipart uploads in progress, by upload id.
+        uploads: BTreeMap<String, BTreeMap<u32, Bytes>>,
+        next_upload_id: u64,
+    }
+
+    impl MockS3 {
+        /// Spawn the server, returning the config for a client to talk to it.
+        async fn spawn(&self) -> S3Config {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let this = self.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let this = this.clone();
+                    tokio::spawn(async move { this.handle(stream).await });
+                }
+            });
+            S3Config {
+                endpoint: format!("http://{addr}"),
+                bucket: "blobs".to_string(),
+                region: "us-east-1".to_string(),
+                access_key_id: "minio".to_string(),
+                secret_access_key: "minio123".to_string(),
+            }
+        }
+
+        fn keys(&self) -> Vec<String> {
+            self.0.lock().unwrap().objects.keys().cloned().collect()
+        }
+
+        fn object(&self, key: &str) -> Option<Bytes> {
+            self.0.lock().unwrap().objects.get(key).cloned()
+        }
+
+        fn uploads(&self) -> usize {
+            self.0.lock().unwrap().uploads.len()
+        }
+
+        /// Handle a single request, then close the connection.
+        async fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+            let mut buf = Vec::new();
+            let header_end = loop {
+                let mut chunk = [0u8; 4096];
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break pos + 4;
+                }
+            };
+            let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+            let mut lines = head.split("\r\n");
+            let mut request_line = lines.next().unwrap_or_default().split(' ');
+            let method = request_line.next().unwrap_or_default();
+            let target = request_line.next().unwrap_or_default();
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let path = path.to_string();
+            let query: HashMap<&str, &str> =
+                query.split('&').filter_map(|x| x.split_once('=')).collect();
+            let headers: HashMap<String, String> = lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                .collect();
+            let len: usize = headers
+                .get("content-length")
+                .map(|x| x.parse().unwrap())
+                .unwrap_or_default();
+            let mut body = buf[header_end..].to_vec();
+            while body.len() < len {
+                let mut chunk = [0u8; 4096];
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+                body.extend_from_slice(&chunk[..n]);
+            }
+            assert!(headers
+                .get("authorization")
+                .is_some_and(|x| x.starts_with("AWS4-HMAC-SHA256 Credential=minio/")));
+
+            let mut etag = None;
+            let (status, body) = {
+                let mut state = self.0.lock().unwrap();
+                let upload_id = query.get("uploadId").map(|x| x.to_string());
+                match (method, upload_id) {
+                    ("POST", None) if query.contains_key("uploads") => {
+                        state.next_upload_id += 1;
+                        let id = format!("upload-{}", state.next_upload_id);
+                        state.uploads.insert(id.clone(), BTreeMap::new());
+                        let xml = format!(
+                            "<InitiateMultipartUploadResult><UploadId>{id}</UploadId>\
+                             </InitiateMultipartUploadResult>"
+                        );
+                        ("200 OK", Bytes::from(xml))
+                    }
+                    ("PUT", Some(id)) => {
+                        let part: u32 = query["partNumber"].parse().unwrap();
+                        etag = Some(format!("\"{id}-{part}\""));
+                        match state.uploads.get_mut(&id) {
+                            Some(parts) => {
+                                parts.insert(part, body.into());
+                                ("200 OK", Bytes::new())
+                            }
+                            None => ("404 Not Found", Bytes::new()),
+                        }
+                    }
+                    ("POST", Some(id)) => match state.uploads.remove(&id) {
+                        Some(parts) => {
+                            let data = parts.into_values().flatten().collect::<Vec<_>>();
+                            state.objects.insert(path, data.into());
+                            ("200 OK", Bytes::from("<CompleteMultipartUploadResult/>"))
+                        }
+                        None => ("404 Not Found", Bytes::new()),
+                    },
+                    ("DELETE", Some(id)) => {
+                        state.uploads.remove(&id);
+                        ("204 No Content", Bytes::new())
+                    }
+                    ("PUT", None) => {
+                        state.objects.insert(path, body.into());
+                        ("200 OK", Bytes::new())
+                    }
+                    ("DELETE", None) => {
+                        state.objects.remove(&path);
+                        ("204 No Content", Bytes::new())
+                    }
+                    ("GET", None) => match (state.objects.get(&path), headers.get("range")) {
+                        (None, _) => ("404 Not Found", Bytes::new()),
+                        (Some(data), None) => ("200 OK", data.clone()),
+                        (Some(data), Some(range)) => {
+                            let (start, end) = range
+                                .strip_prefix("bytes=")
+                                .and_then(|x| x.split_once('-'))
+                                .unwrap();
+                            let start: usize = start.parse().unwrap();
+                            let end: usize = end.parse::<usize>().unwrap() + 1;
+                            if start >= data.len() {
+                                ("416 Range Not Satisfiable", Bytes::new())
+                            } else {
+                                let end = end.min(data.len());
+                                ("206 Partial Content", data.slice(start..end))
+                            }
+                        }
+                    },
+                    _ => ("405 Method Not Allowed", Bytes::new()),
+                }
+            };
+            let etag = etag.map(|x| format!("etag: {x}\r\n")).unwrap_or_default();
+            let head = format!(
+                "HTTP/1.1 {status}\r\n{etag}content-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await?;
+            stream.write_all(&body).await?;
+            stream.shutdown().await
+        }
+    }
+
+    #[tokio::test]
+    async fn s3_store_import_and_reload() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mock = MockS3::default();
+        let config = mock.spawn().await;
+
+        let data = Bytes::from(random_test_data(100_000));
+        let hash = {
+            let store = Store::load_s3(dir.path(), config.clone())?;
+            let tt = store.import_bytes(data.clone(), BlobFormat::Raw).await?;
+            let hash = *tt.hash();
+            store
+                .set_tag(Tag::from("test"), Some(HashAndFormat::raw(hash)))
+                .await?;
+            hash
+        };
+        let mut keys = vec![data_key(&hash), outboard_key(&hash)];
+        keys.sort();
+        assert_eq!(
+            mock.keys(),
+            keys.iter()
+                .map(|k| format!("/blobs/{k}"))
+                .collect::<Vec<_>>()
+        );
+
+        // blobs and tags survive a restart, the data is read from the object store
+        let store = Store::load_s3(dir.path(), config)?;
+        assert_eq!(store.entry_status(&hash).await?, EntryStatus::Complete);
+        let blobs = store.blobs().await?.collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(blobs, vec![hash]);
+        let tags = store.tags().await?.collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(tags, vec![(Tag::from("test"), HashAndFormat::raw(hash))]);
+
+        let entry = store.get(&hash).await?.unwrap();
+        assert!(entry.is_complete());
+        let mut reader = entry.data_reader().await?;
+        assert_eq!(reader.read_at(1000, 5000).await?, data.slice(1000..6000));
+        assert_eq!(reader.read_at(99_000, 5000).await?, data.slice(99_000..));
+        assert_eq!(reader.read_to_end().await?, data);
+        let valid = valid_ranges::<Store>(&entry).await?;
+        assert_eq!(valid, ChunkRanges::from(..ByteNum(100_000).chunks()));
+
+        // deleting the blob deletes the objects
+        store.set_tag(Tag::from("test"), None).await?;
+        store.delete(vec![hash]).await?;
+        assert_eq!(store.entry_status(&hash).await?, EntryStatus::NotFound);
+        assert!(mock.keys().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn s3_store_partial_entry() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mock = MockS3::default();
+        let config = mock.spawn().await;
+
+        let data = random_test_data(100_000);
+        let (hash, encoded) = simulate_remote(&data);
+        {
+            let store = Store::load_s3(dir.path(), config.clone())?;
+            let entry = store.get_or_create(hash, 0).await?;
+            let writer = entry.batch_writer().await?;
+            decode_response_into_batch(hash, IROH_BLOCK_SIZE, ChunkRanges::all(), encoded, writer)
+                .await?;
+        }
+        // partial entries are kept in local files and survive a restart
+        let store = Store::load_s3(dir.path(), config)?;
+        assert_eq!(store.entry_status(&hash).await?, EntryStatus::Partial);
+        let partial = store
+            .partial_blobs()
+            .await?
+            .collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(partial, vec![hash]);
+        assert!(mock.keys().is_empty());
+
+        let entry = store.get(&hash).await?.unwrap();
+        let valid = valid_ranges::<Store>(&entry).await?;
+        assert_eq!(valid, ChunkRanges::from(..ByteNum(100_000).chunks()));
+        store.insert_complete(entry).await?;
+        assert_eq!(store.entry_status(&hash).await?, EntryStatus::Complete);
+        assert_eq!(mock.keys().len(), 2);
+        assert_eq!(std::fs::read_dir(dir.path().join("partial"))?.count(), 0);
+        let entry = store.get(&hash).await?.unwrap();
+        let mut reader = entry.data_reader().await?;
+        assert_eq!(reader.read_to_end().await?, Bytes::from(data));
+        let valid = valid_ranges::<Store>(&entry).await?;
+        assert_eq!(valid, ChunkRanges::from(..ByteNum(100_000).chunks()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn s3_multipart_upload() -> anyhow::Result<()> {
+        let mock = MockS3::default();
+        let client = S3Client::new(mock.spawn().await)?;
+
+        // 100_000 bytes in parts of 16 KiB is 6 full parts and a short one
+        let data = Bytes::from(random_test_data(100_000));
+        upload_object(&client, "large", data.clone(), 100_000, 1024 * 16).await?;
+        assert_eq!(mock.object("/blobs/large"), Some(data.clone()));
+        assert_eq!(mock.uploads(), 0);
+
+        // a failed upload is aborted
+        let res = upload_object(&client, "short", data.clone(), 200_000, 1024 * 16).await;
+        assert!(res.is_err());
+        assert_eq!(mock.object("/blobs/short"), None);
+        assert_eq!(mock.uploads(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn s3_store_import_stream() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mock = MockS3::default();
+        let store = Store::load_s3(dir.path(), mock.spawn().await)?;
+
+        let data = Bytes::from(random_test_data(100_000));
+        let chunks = data
+            .chunks(1000)
+            .map(|x| io::Result::Ok(Bytes::copy_from_slice(x)))
+            .collect::<Vec<_>>();
+        let (tt, size) = store
+            .import_stream(
+                futures::stream::iter(chunks),
+                BlobFormat::Raw,
+                IgnoreProgressSender::default(),
+            )
+            .await?;
+        assert_eq!(size, 100_000);
+        let entry = store.get(tt.hash()).await?.unwrap();
+        assert_eq!(entry.data_reader().await?.read_to_end().await?, data);
+        // the staged data is removed after the import
+        assert_eq!(std::fs::read_dir(dir.path().join("temp"))?.count(), 0);
+        Ok(())
+    }
+}
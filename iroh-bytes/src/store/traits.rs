@@ -1,5 +1,10 @@
 //! Traits for in-memory or persistent maps of blob with bao encoded outboards.
-use std::{collections::BTreeSet, io, path::PathBuf};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use bao_tree::{
     io::fsm::{BaoContentItem, Outboard},
@@ -279,6 +284,13 @@ pub trait ReadableStore: Map {
     /// list partial blobs in the database
     fn partial_blobs(&self) -> impl Future<Output = io::Result<DbIter<Hash>>> + Send;
 
+    /// Size and last access time of all complete blobs in the database.
+    ///
+    /// A blob counts as accessed when it is added and whenever its data or outboard is
+    /// read. Persistent stores persist the access times with the blobs. Blobs without a
+    /// recorded access count as accessed when the store was loaded.
+    fn blob_usage(&self) -> impl Future<Output = io::Result<DbIter<BlobUsage>>> + Send;
+
     /// This trait method extracts a file to a local path.
     ///
     /// `hash` is the hash of the file
@@ -391,6 +403,23 @@ pub trait Store: ReadableStore + MapMut {
         })
     }
 
+    /// Remove blobs that are not marked as live once they exceed the `limits`.
+    ///
+    /// This is an alternative to [`Store::gc_sweep`] that keeps blobs not marked as live
+    /// as a cache. Least recently accessed blobs are removed first, until the store is
+    /// within the limits again. Partial blobs not marked as live are always removed.
+    fn gc_evict(
+        &self,
+        live: &BTreeSet<Hash>,
+        limits: CacheLimits,
+    ) -> impl Stream<Item = GcSweepEvent> + Unpin {
+        Gen::new(move |co| async move {
+            if let Err(e) = gc_evict_task(self, live, limits, &co).await {
+                co.yield_(GcSweepEvent::Error(e)).await;
+            }
+        })
+    }
+
     /// physically delete the given hashes from the store.
     fn delete(&self, hashes: Vec<Hash>) -> impl Future<Output = io::Result<()>> + Send;
 
@@ -636,6 +665,81 @@ async fn gc_sweep_task<'a>(
     Ok(())
 }
 
+async fn gc_evict_task<'a>(
+    store: &'a impl Store,
+    live: &BTreeSet<Hash>,
+    limits: CacheLimits,
+    co: &Co<GcSweepEvent>,
+) -> anyhow::Result<()> {
+    let usage = store.blob_usage().await?.collect::<io::Result<Vec<_>>>()?;
+    let (mut evict, size) = select_evictions(usage, live, limits, SystemTime::now());
+    let evicted = evict.len();
+    for hash in store.partial_blobs().await? {
+        let hash = hash?;
+        if !live.contains(&hash) {
+            evict.push(hash);
+        }
+    }
+    for batch in evict.chunks(100) {
+        store.delete(batch.to_vec()).await?;
+    }
+    co.yield_(GcSweepEvent::CustomDebug(format!(
+        "evicted {} blobs, {} bytes remaining",
+        evicted, size
+    )))
+    .await;
+    Ok(())
+}
+
+/// Select the blobs to evict to stay within `limits`, least recently accessed first.
+///
+/// Returns the blobs to evict and the total size of the remaining blobs.
+fn select_evictions(
+    mut usage: Vec<BlobUsage>,
+    live: &BTreeSet<Hash>,
+    limits: CacheLimits,
+    now: SystemTime,
+) -> (Vec<Hash>, u64) {
+    let mut size = usage.iter().map(|u| u.size).sum::<u64>();
+    usage.retain(|u| !live.contains(&u.hash));
+    usage.sort_by_key(|u| u.last_access);
+    let mut evict = Vec::new();
+    for u in usage {
+        let idle = now.duration_since(u.last_access).unwrap_or_default();
+        let too_old = limits.max_idle.is_some_and(|max| idle > max);
+        let too_large = limits.max_size.is_some_and(|max| size > max);
+        if !too_old && !too_large {
+            // all following blobs were accessed more recently
+            break;
+        }
+        size -= u.size;
+        evict.push(u.hash);
+    }
+    (evict, size)
+}
+
+/// Size and last access time of a blob.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobUsage {
+    /// The hash of the blob
+    pub hash: Hash,
+    /// The size of the blob in bytes
+    pub size: u64,
+    /// The time the blob was last accessed
+    pub last_access: SystemTime,
+}
+
+/// Limits for keeping blobs that are not reachable from tags as a cache.
+///
+/// See [`Store::gc_evict`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheLimits {
+    /// Evict blobs once all complete blobs together are larger than this many bytes.
+    pub max_size: Option<u64>,
+    /// Evict blobs that were not accessed for this long.
+    pub max_idle: Option<Duration>,
+}
+
 /// An event related to GC
 #[derive(Debug)]
 pub enum GcMarkEvent {
@@ -892,3 +996,59 @@ pub enum Event {
     /// A GC was completed
     GcCompleted,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(i: u8, size: u64, last_access: SystemTime) -> BlobUsage {
+        BlobUsage {
+            hash: Hash::new([i]),
+            size,
+            last_access,
+        }
+    }
+
+    #[test]
+    fn select_evictions_lru() {
+        let now = SystemTime::now();
+        let min = |m: u64| now - Duration::from_secs(60 * m);
+        let blobs = vec![
+            usage(0, 1000, min(1)),
+            usage(1, 2000, min(3)),
+            usage(2, 3000, min(2)),
+            usage(3, 4000, min(10)),
+        ];
+        let live = [Hash::new([3])].into_iter().collect();
+
+        // no limits, nothing is evicted
+        let (evict, size) = select_evictions(blobs.clone(), &live, Default::default(), now);
+        assert!(evict.is_empty());
+        assert_eq!(size, 10_000);
+
+        // least recently accessed first, but never live blobs
+        let limits = CacheLimits {
+            max_size: Some(8000),
+            max_idle: None,
+        };
+        let (evict, size) = select_evictions(blobs.clone(), &live, limits, now);
+        assert_eq!(evict, vec![Hash::new([1])]);
+        assert_eq!(size, 8000);
+        let limits = CacheLimits {
+            max_size: Some(4500),
+            max_idle: None,
+        };
+        let (evict, size) = select_evictions(blobs.clone(), &live, limits, now);
+        assert_eq!(evict, vec![Hash::new([1]), Hash::new([2]), Hash::new([0])]);
+        assert_eq!(size, 4000);
+
+        // blobs that were idle for too long
+        let limits = CacheLimits {
+            max_size: None,
+            max_idle: Some(Duration::from_secs(90)),
+        };
+        let (evict, size) = select_evictions(blobs, &live, limits, now);
+        assert_eq!(evict, vec![Hash::new([1]), Hash::new([2])]);
+        assert_eq!(size, 5000);
+    }
+}
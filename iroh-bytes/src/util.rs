@@ -1,10 +1,16 @@
 //! Utility functions and types.
-use bao_tree::ChunkRanges;
+use bao_tree::{io::outboard::PostOrderMemOutboard, ChunkRanges};
 use bytes::Bytes;
 use derive_more::{Debug, Display, From, Into};
 use range_collections::range_set::RangeSetRange;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Borrow, fmt, sync::Arc, time::SystemTime};
+use std::{
+    borrow::Borrow,
+    fmt,
+    io::{BufReader, Read},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use crate::{BlobFormat, Hash, HashAndFormat, IROH_BLOCK_SIZE};
 
@@ -272,3 +278,66 @@ pub(crate) fn raw_outboard(data: &[u8]) -> (Vec<u8>, Hash) {
     outboard.splice(0..8, []);
     (outboard, hash.into())
 }
+
+/// A reader that calls a callback with the number of bytes read after each read.
+struct ProgressReader<R, F: Fn(u64) -> std::io::Result<()>> {
+    inner: R,
+    offset: u64,
+    cb: F,
+}
+
+impl<R: Read, F: Fn(u64) -> std::io::Result<()>> ProgressReader<R, F> {
+    fn new(inner: R, cb: F) -> Self {
+        Self {
+            inner,
+            offset: 0,
+            cb,
+        }
+    }
+}
+
+impl<R: Read, F: Fn(u64) -> std::io::Result<()>> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.offset += read as u64;
+        (self.cb)(self.offset)?;
+        Ok(read)
+    }
+}
+
+/// Synchronously compute the outboard of a file, and return hash and outboard.
+///
+/// It is assumed that the file is not modified while this is running.
+///
+/// If it is modified while or after this is running, the outboard will be
+/// invalid, so any attempt to compute a slice from it will fail.
+///
+/// If the size of the file is changed while this is running, an error will be
+/// returned.
+///
+/// The computed outboard is without length prefix.
+#[allow(dead_code)]
+pub(crate) fn compute_outboard(
+    read: impl Read,
+    size: u64,
+    progress: impl Fn(u64) -> std::io::Result<()> + Send + Sync + 'static,
+) -> std::io::Result<(Hash, Option<Vec<u8>>)> {
+    // compute outboard size so we can pre-allocate the buffer.
+    let outboard_size = usize::try_from(raw_outboard_size(size))
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "size too large"))?;
+    let mut outboard = Vec::with_capacity(outboard_size);
+
+    // wrap the reader in a progress reader, so we can report progress.
+    let reader = ProgressReader::new(read, progress);
+    // wrap the reader in a buffered reader, so we read in large chunks
+    // this reduces the number of io ops and also the number of progress reports
+    let mut reader = BufReader::with_capacity(1024 * 1024, reader);
+
+    let hash =
+        bao_tree::io::sync::outboard_post_order(&mut reader, size, IROH_BLOCK_SIZE, &mut outboard)?;
+    let ob = PostOrderMemOutboard::load(hash, &outboard, IROH_BLOCK_SIZE)?.flip();
+    tracing::trace!(%hash, "done");
+    let ob = ob.into_inner();
+    let ob = if !ob.is_empty() { Some(ob) } else { None };
+    Ok((hash.into(), ob))
+}
@@ -19,11 +19,12 @@ use tokio::io::AsyncReadExt;
 
 use iroh::bytes::{provider::AddProgress, Hash, Tag};
 use iroh::sync::{
-    store::{DownloadPolicy, FilterKind, Query, SortDirection},
+    store::{AccessPolicy, DownloadPolicy, FilterKind, Query, SortDirection},
     AuthorId, NamespaceId,
 };
 use iroh::{
     client::{Doc, Entry, Iroh, LiveEvent},
+    net::NodeId,
     rpc_protocol::{DocTicket, ProviderService, SetTagOption, WrapOption},
     sync_engine::Origin,
     util::fs::{path_content_info, path_to_key, PathContent},
@@ -84,6 +85,40 @@ pub enum DlPolicyCmd {
     },
 }
 
+/// General access policy for a document.
+#[derive(Debug, Clone, Copy, clap::ValueEnum, derive_more::Display)]
+pub enum AccessKind {
+    /// Allow every node to sync this document.
+    Everyone,
+    /// Allow no node to sync this document.
+    Nobody,
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum AccessPolicyCmd {
+    Set {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Set the general access policy for this document.
+        kind: AccessKind,
+        /// Add a node id as an exception to the access policy.
+        #[clap(short, long, value_name = "node_id")]
+        except: Vec<NodeId>,
+    },
+    Get {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+    },
+}
+
 #[derive(Debug, Clone, Parser)]
 pub enum DocCommands {
     /// Set the active document (only works within the Iroh console).
@@ -93,6 +128,9 @@ pub enum DocCommands {
         /// Switch to the created document (only in the Iroh console).
         #[clap(long)]
         switch: bool,
+        /// Encrypt the keys and content of all entries in the document.
+        #[clap(long)]
+        encrypted: bool,
     },
     /// Join a document from a ticket.
     Join {
@@ -135,6 +173,11 @@ pub enum DocCommands {
     /// Set the download policies for a document.
     #[clap(subcommand)]
     DlPolicy(DlPolicyCmd),
+    /// Set the access policies for a document.
+    ///
+    /// The access policy decides which nodes may sync the document with us.
+    #[clap(subcommand)]
+    AccessPolicy(AccessPolicyCmd),
     /// Get entries in a document.
     ///
     /// Shows the author, content hash and content length for all entries for this key.
@@ -283,6 +326,8 @@ pub enum ShareMode {
     Read,
     /// Write access
     Write,
+    /// Read-only access without the key of an encrypted document
+    Relay,
 }
 
 impl From<ShareMode> for iroh::rpc_protocol::ShareMode {
@@ -290,6 +335,7 @@ impl From<ShareMode> for iroh::rpc_protocol::ShareMode {
         match value {
             ShareMode::Read => iroh::rpc_protocol::ShareMode::Read,
             ShareMode::Write => iroh::rpc_protocol::ShareMode::Write,
+            ShareMode::Relay => iroh::rpc_protocol::ShareMode::Relay,
         }
     }
 }
@@ -322,12 +368,16 @@ impl DocCommands {
                 env.set_doc(doc)?;
                 println!("Active doc is now {}", fmt_short(doc.as_bytes()));
             }
-            Self::New { switch } => {
+            Self::New { switch, encrypted } => {
                 if switch && !env.is_console() {
                     bail!("The --switch flag is only supported within the Iroh console.");
                 }
 
-                let doc = iroh.docs.create().await?;
+                let doc = if encrypted {
+                    iroh.docs.create_encrypted().await?
+                } else {
+                    iroh.docs.create().await?
+                };
                 println!("{}", doc.id());
 
                 if switch {
@@ -666,6 +716,41 @@ impl DocCommands {
                     }
                 }
             }
+            Self::AccessPolicy(AccessPolicyCmd::Set { doc, kind, except }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let access_policy = match kind {
+                    AccessKind::Everyone => AccessPolicy::EveryoneExcept(except),
+                    AccessKind::Nobody => AccessPolicy::NobodyExcept(except),
+                };
+                if let Err(e) = doc.set_access_policy(access_policy).await {
+                    println!("Could not set the document's access policy. {e}")
+                }
+            }
+            Self::AccessPolicy(AccessPolicyCmd::Get { doc }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                match doc.get_access_policy().await {
+                    Ok(access_policy) => {
+                        let (kind, exceptions) = match access_policy {
+                            AccessPolicy::EveryoneExcept(exceptions) => {
+                                (AccessKind::Everyone, exceptions)
+                            }
+                            AccessPolicy::NobodyExcept(exceptions) => {
+                                (AccessKind::Nobody, exceptions)
+                            }
+                        };
+                        println!("Allow {kind} to sync this document.");
+                        if !exceptions.is_empty() {
+                            println!("Exceptions:");
+                            for exception in exceptions {
+                                println!("{exception}")
+                            }
+                        }
+                    }
+                    Err(x) => {
+                        println!("Could not get the document's access policy: {x}")
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -872,19 +957,20 @@ where
             }
         })
         .try_chunks(1024)
-        .map_ok(|chunks| {
-            futures::stream::iter(chunks.into_iter().map(|(key, hash, size)| {
-                let doc = doc.clone();
-                let imp = task_imp.clone();
-                Ok(async move {
-                    doc.set_hash(author_id, key, hash, size).await?;
-                    imp.import_progress();
-                    anyhow::Ok(size)
-                })
-            }))
+        .map_err(|err| err.1)
+        .map_ok(|chunk| {
+            let doc = doc.clone();
+            let imp = task_imp.clone();
+            async move {
+                let count = chunk.len() as u64;
+                let size = chunk.iter().map(|(_key, _hash, size)| size).sum::<u64>();
+                // insert each chunk in a single batch
+                doc.set_hash_batch(author_id, chunk).await?;
+                imp.import_progress(count);
+                anyhow::Ok(size)
+            }
         })
-        .try_flatten()
-        .try_buffer_unordered(64)
+        .try_buffer_unordered(4)
         .try_collect()
         .await?;
 
@@ -932,8 +1018,8 @@ impl ImportProgressBar {
         self.add.inc(size);
     }
 
-    fn import_progress(&self) {
-        self.import.inc(1);
+    fn import_progress(&self, count: u64) {
+        self.import.inc(count);
     }
 
     fn add_done(&self) {
@@ -966,7 +1052,8 @@ mod tests {
 
         let data_dir = tempfile::tempdir()?;
 
-        let node = crate::commands::start::start_node(data_dir.path(), None).await?;
+        let node =
+            crate::commands::start::start_node(data_dir.path(), None, Default::default()).await?;
         let client = node.client();
         let doc = client.docs.create().await.context("doc create")?;
         let author = client.authors.create().await.context("author create")?;
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use clap::Subcommand;
+use iroh::{client::Iroh, gossip::TopicId, net::key::PublicKey, rpc_protocol::ProviderService};
+use quic_rpc::ServiceConnection;
+
+#[derive(Subcommand, Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum GossipCommands {
+    /// Get information about the state of a gossip topic
+    ///
+    /// Shows the peers in the swarm membership and the broadcast tree of the topic, and stats
+    /// about the messages received so far.
+    TopicInfo {
+        /// The topic id
+        topic: TopicId,
+    },
+}
+
+impl GossipCommands {
+    pub async fn run<C>(self, iroh: &Iroh<C>) -> Result<()>
+    where
+        C: ServiceConnection<ProviderService>,
+    {
+        match self {
+            Self::TopicInfo { topic } => match iroh.gossip.topic_info(topic).await? {
+                None => println!("Not joined"),
+                Some(info) => {
+                    println!("Active view: {}", fmt_peers(&info.active_view));
+                    println!("Passive view: {}", fmt_peers(&info.passive_view));
+                    println!("Eager peers: {}", fmt_peers(&info.eager_peers));
+                    println!("Lazy peers: {}", fmt_peers(&info.lazy_peers));
+                    println!("Message cache size: {}", info.message_cache_size);
+                    println!("Messages sent: {}", info.messages_sent);
+                    println!("Messages received: {}", info.messages_received);
+                    println!(
+                        "Payload messages received: {}",
+                        info.payload_messages_received
+                    );
+                    println!(
+                        "Control messages received: {}",
+                        info.control_messages_received
+                    );
+                    println!("Max delivery hop: {}", info.max_last_delivery_hop);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn fmt_peers(peers: &[PublicKey]) -> String {
+    if peers.is_empty() {
+        return "-".to_string();
+    }
+    peers
+        .iter()
+        .map(|peer| peer.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
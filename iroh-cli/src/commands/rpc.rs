@@ -6,8 +6,8 @@ use quic_rpc::ServiceConnection;
 use crate::config::ConsoleEnv;
 
 use super::{
-    author::AuthorCommands, blob::BlobCommands, doc::DocCommands, node::NodeCommands,
-    tag::TagCommands,
+    author::AuthorCommands, blob::BlobCommands, doc::DocCommands, gossip::GossipCommands,
+    node::NodeCommands, tag::TagCommands,
 };
 
 #[derive(Subcommand, Debug, Clone)]
@@ -55,6 +55,13 @@ pub enum RpcCommands {
         #[clap(subcommand)]
         command: TagCommands,
     },
+    /// Inspect gossip topics
+    ///
+    /// Gossip topics are broadcast swarms, used for example to sync documents.
+    Gossip {
+        #[clap(subcommand)]
+        command: GossipCommands,
+    },
 }
 
 impl RpcCommands {
@@ -68,6 +75,7 @@ impl RpcCommands {
             Self::Doc { command } => command.run(iroh, env).await,
             Self::Author { command } => command.run(iroh, env).await,
             Self::Tag { command } => command.run(iroh).await,
+            Self::Gossip { command } => command.run(iroh).await,
         }
     }
 }
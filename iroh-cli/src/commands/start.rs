@@ -7,6 +7,7 @@ use futures::Future;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use iroh::node::Node;
 use iroh::{
+    bytes::provider::ProviderLimits,
     net::relay::{RelayMap, RelayMode},
     node::RpcStatus,
 };
@@ -74,7 +75,7 @@ where
     let relay_map = config.relay_map()?;
 
     let spinner = create_spinner("Iroh booting...");
-    let node = start_node(iroh_data_root, relay_map).await?;
+    let node = start_node(iroh_data_root, relay_map, config.provider_limits.clone()).await?;
     drop(spinner);
 
     eprintln!("{}", welcome_message(&node)?);
@@ -124,6 +125,7 @@ where
 pub(crate) async fn start_node(
     iroh_data_root: &Path,
     relay_map: Option<RelayMap>,
+    provider_limits: ProviderLimits,
 ) -> Result<Node<iroh::bytes::store::fs::Store>> {
     let rpc_status = RpcStatus::load(iroh_data_root).await?;
     match rpc_status {
@@ -143,6 +145,7 @@ pub(crate) async fn start_node(
     Node::persistent(iroh_data_root)
         .await?
         .relay_mode(relay_mode)
+        .provider_limits(provider_limits)
         .enable_rpc()
         .await?
         .spawn()
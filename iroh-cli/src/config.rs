@@ -11,6 +11,7 @@ use std::{
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use config::{Environment, File, Value};
+use iroh::bytes::provider::ProviderLimits;
 use iroh::net::{
     defaults::{default_eu_relay_node, default_na_relay_node},
     relay::{RelayMap, RelayNode},
@@ -91,6 +92,8 @@ pub(crate) struct NodeConfig {
     pub(crate) gc_policy: GcPolicy,
     /// Bind address on which to serve Prometheus metrics
     pub(crate) metrics_addr: Option<SocketAddr>,
+    /// Limits for serving blobs to other nodes.
+    pub(crate) provider_limits: ProviderLimits,
 }
 
 impl Default for NodeConfig {
@@ -100,6 +103,7 @@ impl Default for NodeConfig {
             relay_nodes: [default_na_relay_node(), default_eu_relay_node()].into(),
             gc_policy: GcPolicy::Disabled,
             metrics_addr: Some(([127, 0, 0, 1], 9090).into()),
+            provider_limits: ProviderLimits::default(),
         }
     }
 }
@@ -427,5 +431,6 @@ mod tests {
         let config = NodeConfig::load(&[][..], "__FOO", HashMap::<String, String>::new()).unwrap();
 
         assert_eq!(config.relay_nodes.len(), 2);
+        assert_eq!(config.provider_limits, ProviderLimits::default());
     }
 }
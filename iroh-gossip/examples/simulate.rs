@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use clap::Parser;
+use iroh_gossip::proto::{
+    sim::{NetworkConfig, PartitionConfig, Simulator, SimulatorConfig},
+    Config,
+};
+
+/// Simulate a gossip swarm and report delivery ratio, latency and overhead
+///
+/// The simulation runs on a synthetic clock and is deterministic for a given seed. Use it to
+/// compare protocol configurations, e.g. different graft timeouts or view sizes.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Seed for all random decisions of the simulation.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// Number of peers.
+    #[clap(short, long, default_value_t = 1000)]
+    peers: usize,
+    /// Number of broadcast rounds.
+    #[clap(short, long, default_value_t = 20)]
+    rounds: usize,
+    /// Maximum number of ticks of a round, after which a broadcast counts as not delivered.
+    #[clap(long, default_value_t = 200)]
+    round_ticks: usize,
+    /// Minimum one-way link latency, in milliseconds.
+    #[clap(long, default_value_t = 10)]
+    latency_min: u64,
+    /// Maximum one-way link latency, in milliseconds.
+    #[clap(long, default_value_t = 100)]
+    latency_max: u64,
+    /// Probability that a message is lost.
+    #[clap(long, default_value_t = 0.)]
+    loss: f64,
+    /// Fraction of the peers that leave and rejoin before each round.
+    #[clap(long, default_value_t = 0.)]
+    churn: f64,
+    /// Let leaving peers crash instead of quitting the topic.
+    #[clap(long)]
+    crash: bool,
+    /// Cut off this fraction of the peers from the others for some rounds.
+    #[clap(long)]
+    partition: Option<f64>,
+    /// Round in which the partition starts.
+    #[clap(long, default_value_t = 5)]
+    partition_start: usize,
+    /// Round in which the partition heals.
+    #[clap(long, default_value_t = 10)]
+    partition_end: usize,
+    /// Capacity of the active view.
+    #[clap(long)]
+    active_view: Option<usize>,
+    /// Capacity of the passive view.
+    #[clap(long)]
+    passive_view: Option<usize>,
+    /// First graft timeout, in milliseconds.
+    #[clap(long)]
+    graft_timeout_1: Option<u64>,
+    /// Second graft timeout, in milliseconds.
+    #[clap(long)]
+    graft_timeout_2: Option<u64>,
+    /// Timeout after which IHave messages are dispatched, in milliseconds.
+    #[clap(long)]
+    dispatch_timeout: Option<u64>,
+    /// Print the results of each round.
+    #[clap(short, long)]
+    verbose: bool,
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let mut config = Config::default();
+    if let Some(capacity) = args.active_view {
+        config.membership.active_view_capacity = capacity;
+    }
+    if let Some(capacity) = args.passive_view {
+        config.membership.passive_view_capacity = capacity;
+    }
+    if let Some(timeout) = args.graft_timeout_1 {
+        config.broadcast.graft_timeout_1 = Duration::from_millis(timeout);
+    }
+    if let Some(timeout) = args.graft_timeout_2 {
+        config.broadcast.graft_timeout_2 = Duration::from_millis(timeout);
+    }
+    if let Some(timeout) = args.dispatch_timeout {
+        config.broadcast.dispatch_timeout = Duration::from_millis(timeout);
+    }
+
+    let sim_config = SimulatorConfig {
+        seed: args.seed,
+        peers_count: args.peers,
+        round_max_ticks: args.round_ticks,
+        network: NetworkConfig {
+            seed: args.seed,
+            latency_min: Duration::from_millis(args.latency_min),
+            latency_max: Duration::from_millis(args.latency_max),
+            loss: args.loss,
+            ..Default::default()
+        },
+        churn: args.churn,
+        graceful_leave: !args.crash,
+        partition: args.partition.map(|fraction| PartitionConfig {
+            fraction,
+            start_round: args.partition_start,
+            end_round: args.partition_end,
+        }),
+        ..Default::default()
+    };
+    println!("{sim_config:?}");
+    println!("{config:?}");
+
+    let mut simulator = Simulator::new(sim_config, config);
+    simulator.init();
+    simulator.bootstrap();
+    for i in 0..args.rounds {
+        let round = simulator.round();
+        if args.verbose {
+            println!(
+                "round {i}: sender {} delivered {}/{} max latency {:?} payload {} control {}",
+                round.sender,
+                round.delivered(),
+                round.expected,
+                round.latencies.last(),
+                round.payload_messages,
+                round.control_messages,
+            );
+        }
+    }
+    println!("{}", simulator.report());
+}
@@ -10,16 +10,27 @@ use iroh_net::{
 };
 use rand::rngs::StdRng;
 use rand_core::SeedableRng;
-use std::{collections::HashMap, future::Future, sync::Arc, task::Poll, time::Instant};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
 use tokio::{
     sync::{broadcast, mpsc, oneshot},
     task::JoinHandle,
 };
 use tracing::{debug, error_span, trace, warn, Instrument};
 
-use self::util::{read_message, write_message, Timers};
-use crate::proto::{self, PeerData, Scope, TopicId};
+use self::{
+    peer_cache::{PeerCache, MAX_PEERS_PER_TOPIC},
+    util::{read_message, write_message, Timers},
+};
+use crate::proto::{self, MessageAuth, PeerData, Scope, TopicId};
 
+mod peer_cache;
 pub mod util;
 
 /// ALPN protocol name
@@ -40,11 +51,15 @@ const TO_ACTOR_CAP: usize = 64;
 const IN_EVENT_CAP: usize = 1024;
 /// Channel capacity for endpoint change message queue (single)
 const ON_ENDPOINTS_CAP: usize = 64;
+/// How often to save the peer cache
+const SAVE_PEERS_INTERVAL: Duration = Duration::from_secs(30);
 
 /// Events emitted from the gossip protocol
 pub type Event = proto::Event<PublicKey>;
 /// Commands for the gossip protocol
 pub type Command = proto::Command<PublicKey>;
+/// Snapshot of the protocol state of a topic
+pub type TopicInfo = proto::TopicInfo<PublicKey>;
 
 type InEvent = proto::InEvent<PublicKey>;
 type OutEvent = proto::OutEvent<PublicKey>;
@@ -82,15 +97,77 @@ impl Gossip {
         endpoint: MagicEndpoint,
         config: proto::Config,
         my_addr: &AddrInfo,
+    ) -> Self {
+        Self::spawn(
+            endpoint,
+            config,
+            my_addr,
+            None,
+            PeerCache::default(),
+            PeerCache::default(),
+        )
+    }
+
+    /// Spawn a gossip actor that persists the peers of its topics, and get a handle for it.
+    ///
+    /// The joined topics and the peers we know in each of them are saved to `peers_path`
+    /// periodically. On start, the topics saved in `peers_path` for which `rejoin` returns
+    /// `true` are joined again through the remembered peers, so that no bootstrap peers are
+    /// needed to rejoin them after a restart. The other saved topics are not joined, but their
+    /// remembered peers are added to the bootstrap peers once they are joined with
+    /// [`Self::join`].
+    pub fn from_endpoint_persistent(
+        endpoint: MagicEndpoint,
+        config: proto::Config,
+        my_addr: &AddrInfo,
+        peers_path: PathBuf,
+        rejoin: impl Fn(&TopicId) -> bool,
+    ) -> Self {
+        let mut peer_cache = match peers_path.exists() {
+            true => match PeerCache::load_from_file(&peers_path) {
+                Ok(cache) => {
+                    debug!(topics = cache.topics.len(), "loaded peer cache");
+                    cache
+                }
+                Err(err) => {
+                    warn!("failed to load peer cache: {err:#}");
+                    PeerCache::default()
+                }
+            },
+            false => PeerCache::default(),
+        };
+        let (joined, other) = std::mem::take(&mut peer_cache.topics)
+            .into_iter()
+            .partition(|(topic, _peers)| rejoin(topic));
+        peer_cache.topics = joined;
+        let remembered = PeerCache { topics: other };
+        Self::spawn(
+            endpoint,
+            config,
+            my_addr,
+            Some(peers_path),
+            peer_cache,
+            remembered,
+        )
+    }
+
+    fn spawn(
+        endpoint: MagicEndpoint,
+        config: proto::Config,
+        my_addr: &AddrInfo,
+        peers_path: Option<PathBuf>,
+        peer_cache: PeerCache,
+        remembered: PeerCache,
     ) -> Self {
         let peer_id = endpoint.node_id();
         let dialer = Dialer::new(endpoint.clone());
-        let state = proto::State::new(
+        let mut state = proto::State::new(
             peer_id,
             encode_peer_data(my_addr).unwrap(),
             config,
             rand::rngs::StdRng::from_entropy(),
         );
+        state.set_secret_key(endpoint.secret_key().clone());
         let (to_actor_tx, to_actor_rx) = mpsc::channel(TO_ACTOR_CAP);
         let (in_event_tx, in_event_rx) = mpsc::channel(IN_EVENT_CAP);
         let (on_endpoints_tx, on_endpoints_rx) = mpsc::channel(ON_ENDPOINTS_CAP);
@@ -110,11 +187,13 @@ impl Gossip {
             timers: Timers::new(),
             subscribers_all: None,
             subscribers_topic: Default::default(),
+            peers_path,
+            remembered,
         };
 
         let actor_handle = tokio::spawn(
             async move {
-                if let Err(err) = actor.run().await {
+                if let Err(err) = actor.run(peer_cache).await {
                     warn!("gossip actor closed with error: {err:?}");
                     Err(err)
                 } else {
@@ -155,6 +234,42 @@ impl Gossip {
         Ok(JoinTopicFut(rx))
     }
 
+    /// Set the [`MessageAuth`] for a topic.
+    ///
+    /// In a topic with [`MessageAuth::Signed`], our messages are signed with the secret key of the
+    /// [`MagicEndpoint`], and received messages are only delivered and relayed if they carry a
+    /// valid signature of an allowed publisher. The verified publisher is set as the `origin` of
+    /// the [`Event::Received`].
+    ///
+    /// All peers in a topic must use the same mode. This should be set before joining the topic,
+    /// to not deliver or relay any unauthenticated messages.
+    pub async fn set_message_auth(&self, topic: TopicId, auth: MessageAuth) -> anyhow::Result<()> {
+        self.send(ToActor::SetMessageAuth(topic, auth)).await?;
+        Ok(())
+    }
+
+    /// Get a snapshot of the membership and broadcast state of a topic.
+    ///
+    /// Returns `None` if we did not join the topic.
+    pub async fn topic_info(&self, topic: TopicId) -> anyhow::Result<Option<TopicInfo>> {
+        let (tx, rx) = oneshot::channel();
+        self.send(ToActor::TopicInfo(topic, tx)).await?;
+        let info = rx.await?;
+        Ok(info)
+    }
+
+    /// Save the peer cache and stop updating it.
+    ///
+    /// Call this before quitting topics when shutting down, so that these topics are rejoined on
+    /// the next start. Quitting topics and broadcasting keeps working afterwards. Does nothing
+    /// if the gossip was not created with [`Self::from_endpoint_persistent`].
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.send(ToActor::Shutdown(tx)).await?;
+        rx.await??;
+        Ok(())
+    }
+
     /// Quit a topic.
     ///
     /// This sends a disconnect message to all active peers and then drops the state
@@ -302,6 +417,8 @@ enum ToActor {
         Vec<PublicKey>,
         #[debug(skip)] oneshot::Sender<anyhow::Result<TopicId>>,
     ),
+    /// Set the message authentication for a topic.
+    SetMessageAuth(TopicId, MessageAuth),
     /// Leave a topic, send disconnect messages and drop all state.
     Quit(TopicId),
     /// Broadcast a message on a topic.
@@ -322,6 +439,10 @@ enum ToActor {
     SubscribeAll(
         #[debug(skip)] oneshot::Sender<anyhow::Result<broadcast::Receiver<(TopicId, Event)>>>,
     ),
+    /// Get a snapshot of the protocol state of a topic.
+    TopicInfo(TopicId, #[debug(skip)] oneshot::Sender<Option<TopicInfo>>),
+    /// Save the peer cache and stop updating it.
+    Shutdown(#[debug(skip)] oneshot::Sender<anyhow::Result<()>>),
 }
 
 /// Actor that sends and handles messages between the connection and main state loops
@@ -351,10 +472,20 @@ struct Actor {
     subscribers_topic: HashMap<TopicId, broadcast::Sender<Event>>,
     /// Broadcast senders for wildcard subscriptions from the application
     subscribers_all: Option<broadcast::Sender<(TopicId, Event)>>,
+    /// Path to save the peer cache to, if persistence is enabled
+    peers_path: Option<PathBuf>,
+    /// Peers remembered for saved topics which were not rejoined on start, used once the
+    /// topics are joined
+    remembered: PeerCache,
 }
 
 impl Actor {
-    pub async fn run(mut self) -> anyhow::Result<()> {
+    pub async fn run(mut self, peer_cache: PeerCache) -> anyhow::Result<()> {
+        self.rejoin(peer_cache).await?;
+        let mut save_peers_timer = tokio::time::interval_at(
+            (Instant::now() + SAVE_PEERS_INTERVAL).into(),
+            SAVE_PEERS_INTERVAL,
+        );
         let mut i = 0;
         loop {
             i += 1;
@@ -412,9 +543,71 @@ impl Actor {
                         self.handle_in_event(InEvent::TimerExpired(timer), now).await.context("timers.drain_expired -> handle_in_event")?;
                     }
                 }
+                _ = save_peers_timer.tick(), if self.peers_path.is_some() => {
+                    trace!(?i, "tick: save_peers_timer");
+                    if let Err(err) = self.save_peer_cache().await {
+                        warn!("failed to save peer cache: {err:#}");
+                    }
+                }
+            }
+        }
+        if let Err(err) = self.save_peer_cache().await {
+            warn!("failed to save peer cache: {err:#}");
+        }
+        Ok(())
+    }
 
+    /// Join the topics of a [`PeerCache`] through the peers remembered for them.
+    async fn rejoin(&mut self, peer_cache: PeerCache) -> anyhow::Result<()> {
+        for (topic, addrs) in peer_cache.topics {
+            let peers = self.add_cached_peers(addrs);
+            debug!(%topic, peers = peers.len(), "rejoin topic from peer cache");
+            self.handle_in_event(
+                InEvent::Command(topic, Command::Join(peers)),
+                Instant::now(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Add the addresses of cached peers to the endpoint, and return their node ids.
+    fn add_cached_peers(&self, addrs: Vec<NodeAddr>) -> Vec<PublicKey> {
+        let mut peers = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            peers.push(addr.node_id);
+            if let Err(err) = self.endpoint.add_node_addr(addr) {
+                debug!("failed to add cached peer address: {err:?}");
             }
         }
+        peers
+    }
+
+    /// Save the joined topics and their known peers, if persistence is enabled.
+    async fn save_peer_cache(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.peers_path else {
+            return Ok(());
+        };
+        let mut cache = PeerCache::default();
+        for (topic, state) in self.state.states() {
+            let addrs = state
+                .known_peers()
+                .take(MAX_PEERS_PER_TOPIC)
+                .map(|(node_id, data)| {
+                    let info = data
+                        .and_then(|data| decode_peer_data(data).ok())
+                        .unwrap_or_default();
+                    NodeAddr { node_id, info }
+                })
+                .collect();
+            cache.topics.insert(*topic, addrs);
+        }
+        // keep the peers of topics which were not joined again since the start
+        for (topic, addrs) in &self.remembered.topics {
+            cache.topics.entry(*topic).or_insert_with(|| addrs.clone());
+        }
+        cache.save_to_file(path).await?;
+        trace!(topics = cache.topics.len(), "saved peer cache");
         Ok(())
     }
 
@@ -455,7 +648,11 @@ impl Actor {
                     }
                 }
             }
-            ToActor::Join(topic_id, peers, reply) => {
+            ToActor::Join(topic_id, mut peers, reply) => {
+                if let Some(addrs) = self.remembered.topics.remove(&topic_id) {
+                    debug!(topic = %topic_id, peers = addrs.len(), "join topic with cached peers");
+                    peers.extend(self.add_cached_peers(addrs));
+                }
                 self.handle_in_event(InEvent::Command(topic_id, Command::Join(peers)), now)
                     .await?;
                 if self.state.has_active_peers(&topic_id) {
@@ -471,6 +668,9 @@ impl Actor {
                     });
                 }
             }
+            ToActor::SetMessageAuth(topic_id, auth) => {
+                self.state.set_message_auth(topic_id, auth);
+            }
             ToActor::Quit(topic_id) => {
                 self.handle_in_event(InEvent::Command(topic_id, Command::Quit), now)
                     .await?;
@@ -492,6 +692,15 @@ impl Actor {
                 let rx = self.subscribe_all();
                 reply.send(Ok(rx)).ok();
             }
+            ToActor::TopicInfo(topic_id, reply) => {
+                let info = self.state.state(&topic_id).map(|state| state.info());
+                reply.send(info).ok();
+            }
+            ToActor::Shutdown(reply) => {
+                let res = self.save_peer_cache().await;
+                self.peers_path = None;
+                reply.send(res).ok();
+            }
         };
         Ok(())
     }
@@ -821,6 +1030,108 @@ mod test {
         drop(cleanup);
     }
 
+    #[tokio::test]
+    async fn gossip_rejoin_after_restart() {
+        let _guard = iroh_test::logging::setup();
+        let (relay_map, relay_url, cleanup) = util::run_relay_and_stun([127, 0, 0, 1].into())
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let peers_path = dir.path().join("gossip-peers.postcard");
+        let addr = AddrInfo {
+            relay_url: Some(relay_url.clone()),
+            direct_addresses: Default::default(),
+        };
+
+        let ep1 = create_endpoint(relay_map.clone()).await.unwrap();
+        let ep2 = create_endpoint(relay_map.clone()).await.unwrap();
+        let secret_key2 = ep2.secret_key().clone();
+        let go1 = Gossip::from_endpoint(ep1.clone(), Default::default(), &addr);
+        let go2 = Gossip::from_endpoint_persistent(
+            ep2.clone(),
+            Default::default(),
+            &addr,
+            peers_path.clone(),
+            |_topic| true,
+        );
+        let pi1 = ep1.node_id();
+
+        let cancel1 = CancellationToken::new();
+        let cancel2 = CancellationToken::new();
+        let task1 = spawn(endpoint_loop(ep1.clone(), go1.clone(), cancel1.clone()));
+        let task2 = spawn(endpoint_loop(ep2.clone(), go2.clone(), cancel2.clone()));
+
+        let topic: TopicId = blake3::hash(b"foobar").into();
+        let other: TopicId = blake3::hash(b"other").into();
+        ep2.add_node_addr(NodeAddr::new(pi1).with_relay_url(relay_url))
+            .unwrap();
+        go1.join(topic, vec![]).await.unwrap();
+        go1.join(other, vec![]).await.unwrap();
+        go2.join(topic, vec![pi1]).await.unwrap().await.unwrap();
+        go2.join(other, vec![pi1]).await.unwrap().await.unwrap();
+
+        // save the peer cache and stop the node
+        go2.shutdown().await.unwrap();
+        cancel2.cancel();
+        timeout(Duration::from_secs(10), task2)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        drop(go2);
+        ep2.close(0u32.into(), b"restart").await.unwrap();
+
+        // restart with the same key, without bootstrap peers, and rejoin only one of the topics
+        let ep2 = MagicEndpoint::builder()
+            .secret_key(secret_key2)
+            .alpns(vec![GOSSIP_ALPN.to_vec()])
+            .relay_mode(RelayMode::Custom(relay_map))
+            .bind(0)
+            .await
+            .unwrap();
+        let go2 = Gossip::from_endpoint_persistent(
+            ep2.clone(),
+            Default::default(),
+            &addr,
+            peers_path,
+            |t| *t == topic,
+        );
+        let task2 = spawn(endpoint_loop(ep2.clone(), go2.clone(), cancel1.clone()));
+
+        // the cached peer becomes a neighbor again
+        timeout(Duration::from_secs(10), async {
+            loop {
+                let info = go2.topic_info(topic).await.unwrap();
+                if info.is_some_and(|info| info.active_view.contains(&pi1)) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+        // the topic that was not selected is not joined
+        assert!(go2.topic_info(other).await.unwrap().is_none());
+        // but joining it without bootstrap peers uses the cached peers
+        let joined = go2.join(other, vec![]).await.unwrap();
+        timeout(Duration::from_secs(10), joined)
+            .await
+            .unwrap()
+            .unwrap();
+        let info = go2.topic_info(other).await.unwrap().unwrap();
+        assert!(info.active_view.contains(&pi1));
+
+        cancel1.cancel();
+        for t in [task1, task2] {
+            timeout(Duration::from_secs(10), t)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+        }
+        drop(cleanup);
+    }
+
     // This is copied from iroh-net/src/hp/magicsock/conn.rs
     // TODO: Move into a public test_utils module in iroh-net?
     mod util {
@@ -0,0 +1,65 @@
+//! Persistence of the peers known for each joined topic.
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{ensure, Context};
+use iroh_net::{util::write_atomic, NodeAddr};
+use serde::{Deserialize, Serialize};
+
+use crate::proto::TopicId;
+
+/// Maximum number of peers remembered for each topic.
+///
+/// Peers of the active view are remembered first, followed by peers of the passive view.
+pub(super) const MAX_PEERS_PER_TOPIC: usize = 16;
+
+/// The joined topics and the peers we know in each of them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct PeerCache {
+    pub(super) topics: BTreeMap<TopicId, Vec<NodeAddr>>,
+}
+
+impl PeerCache {
+    /// Load a [`PeerCache`] from a file written with [`Self::save_to_file`].
+    pub(super) fn load_from_file(path: &Path) -> anyhow::Result<Self> {
+        ensure!(path.is_file(), "{} is not a file", path.display());
+        let contents = std::fs::read(path)?;
+        let cache = postcard::from_bytes(&contents).context("failed to load peer cache")?;
+        Ok(cache)
+    }
+
+    /// Save the [`PeerCache`] to `path`, replacing the file atomically.
+    pub(super) async fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let contents = postcard::to_stdvec(self).context("failed to serialize peer cache")?;
+        write_atomic(path, &contents)
+            .await
+            .context("failed to persist peer cache")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_net::key::SecretKey;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn peer_cache_roundtrip() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("gossip-peers.postcard");
+
+        let peer = SecretKey::generate().public();
+        let addr = NodeAddr::new(peer).with_direct_addresses(["127.0.0.1:1234".parse()?]);
+        let mut cache = PeerCache::default();
+        cache.topics.insert([1u8; 32].into(), vec![addr]);
+        cache.topics.insert([2u8; 32].into(), vec![]);
+        cache.save_to_file(&path).await?;
+        assert_eq!(PeerCache::load_from_file(&path)?, cache);
+
+        // saving again replaces the file
+        let cache = PeerCache::default();
+        cache.save_to_file(&path).await?;
+        assert_eq!(PeerCache::load_from_file(&path)?, cache);
+        Ok(())
+    }
+}
@@ -52,16 +52,14 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 mod hyparview;
 mod plumtree;
+pub mod sim;
 pub mod state;
 pub mod topic;
 pub mod util;
 
-#[cfg(test)]
-mod tests;
-
-pub use plumtree::Scope;
+pub use plumtree::{MessageAuth, Scope};
 pub use state::{InEvent, Message, OutEvent, State, Timer, TopicId};
-pub use topic::{Command, Config, Event, IO};
+pub use topic::{Command, Config, Event, TopicInfo, IO};
 
 /// The identifier for a peer.
 ///
@@ -118,12 +116,13 @@ impl<PI> From<(PI, Option<PeerData>)> for PeerInfo<PI> {
 #[cfg(test)]
 mod test {
 
+    use iroh_base::key::SecretKey;
     use rand::SeedableRng;
     use std::{collections::HashSet, env, time::Instant};
 
-    use super::{Command, Config, Event, State};
+    use super::{Command, Config, Event, MessageAuth, State};
     use crate::proto::{
-        tests::{
+        sim::{
             assert_synchronous_active, report_round_distribution, sort, Network, Simulator,
             SimulatorConfig,
         },
@@ -267,6 +266,76 @@ mod test {
         assert_eq!(received.count(), 5);
         assert!(assert_synchronous_active(&network));
         report_round_distribution(&network);
+
+        // the topic info reflects the membership and the received messages
+        let info = network.peer(&5).unwrap().state(&t).unwrap().info();
+        assert_eq!(
+            Some(info.active_view.clone()),
+            network.get_active(&5, &t).unwrap()
+        );
+        let peers = [info.eager_peers, info.lazy_peers].concat();
+        assert_eq!(sort(peers), sort(info.active_view));
+        assert!(info.payload_messages_received >= 1);
+        assert_eq!(info.message_cache_size, 1);
+    }
+
+    #[test]
+    fn plumtree_signed() {
+        let _guard = iroh_test::logging::setup();
+        let config = Config::default();
+        let mut network = Network::new(Instant::now());
+        let t: TopicId = [0u8; 32].into();
+        let keys: Vec<_> = (0..3u8)
+            .map(|i| SecretKey::from_bytes(&[i + 1; 32]))
+            .collect();
+        // only node 0 is an allowed publisher
+        let publishers = Some([keys[0].public()].into_iter().collect());
+        let auth = MessageAuth::Signed { publishers };
+        let rng = rand_chacha::ChaCha12Rng::seed_from_u64(99);
+        for (i, key) in keys.iter().enumerate() {
+            let mut state = State::new(i, Default::default(), config.clone(), rng.clone());
+            state.set_secret_key(key.clone());
+            state.set_message_auth(t, auth.clone());
+            network.push(state);
+        }
+
+        network.command(0, t, Command::Join(vec![]));
+        network.command(1, t, Command::Join(vec![0]));
+        network.command(2, t, Command::Join(vec![1]));
+        network.ticks(12);
+        let _ = network.events();
+
+        // a message from the allowed publisher is received and relayed, with a verified origin
+        network.command(
+            0,
+            t,
+            Command::Broadcast(b"hi1".to_vec().into(), Scope::Swarm),
+        );
+        network.ticks(12);
+        let received: Vec<_> = network
+            .events()
+            .filter_map(|(peer, _topic, event)| match event {
+                Event::Received(event) => Some((peer, event)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(received.len(), 2);
+        for (_peer, event) in received {
+            assert_eq!(&event.content[..], b"hi1");
+            assert_eq!(event.origin, Some(keys[0].public()));
+        }
+
+        // a message from another publisher is neither delivered nor relayed
+        network.command(
+            1,
+            t,
+            Command::Broadcast(b"hi2".to_vec().into(), Scope::Swarm),
+        );
+        network.ticks(12);
+        let received = network
+            .events()
+            .filter(|x| matches!(x, (_, _, Event::Received(_))));
+        assert_eq!(received.count(), 0);
     }
 
     #[test]
@@ -285,7 +354,12 @@ mod test {
         for i in 0..rounds {
             let from = i + 1;
             let message = format!("m{i}").into_bytes().into();
-            simulator.gossip_round(from, message)
+            let round = simulator.gossip_round(from, message);
+            assert_eq!(
+                round.delivered(),
+                round.expected,
+                "all nodes received the broadcast"
+            );
         }
         simulator.report_round_sums();
     }
@@ -306,7 +380,12 @@ mod test {
         for i in 0..rounds {
             let from = 2;
             let message = format!("m{i}").into_bytes().into();
-            simulator.gossip_round(from, message)
+            let round = simulator.gossip_round(from, message);
+            assert_eq!(
+                round.delivered(),
+                round.expected,
+                "all nodes received the broadcast"
+            );
         }
         simulator.report_round_sums();
     }
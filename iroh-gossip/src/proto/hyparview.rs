@@ -449,6 +449,16 @@ where
         }
     }
 
+    /// Get the peers in the active and the passive view, with the [`PeerData`] received for them.
+    ///
+    /// Peers of the active view come first.
+    pub fn known_peers(&self) -> impl Iterator<Item = (PI, Option<&PeerData>)> + '_ {
+        self.active_view
+            .iter()
+            .chain(self.passive_view.iter())
+            .map(|peer| (*peer, self.peer_data.get(peer)))
+    }
+
     /// Get the peer [`PeerInfo`] for a peer.
     fn peer_info(&self, id: &PI) -> PeerInfo<PI> {
         let data = self.peer_data.get(id).cloned();
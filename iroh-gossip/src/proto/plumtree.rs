@@ -7,21 +7,25 @@
 //! [impl]: https://gist.github.com/Horusiath/84fac596101b197da0546d1697580d99
 
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     hash::Hash,
     time::{Duration, Instant},
 };
 
 use bytes::Bytes;
 use derive_more::{Add, From, Sub};
+use iroh_base::key::{PublicKey, SecretKey, Signature};
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use super::{
     util::{idbytes_impls, TimeBoundCache},
-    PeerIdentity, IO,
+    PeerIdentity, TopicId, IO,
 };
 
+/// Prefix of the bytes signed by the publisher of a message in a signed topic.
+const SIGNATURE_CONTEXT: &[u8] = b"iroh-gossip signed message";
+
 /// A message identifier, which is the message content's blake3 hash.
 #[derive(Serialize, Deserialize, Clone, Hash, Copy, PartialEq, Eq)]
 pub struct MessageId([u8; 32]);
@@ -94,18 +98,88 @@ pub struct GossipEvent<PI> {
     pub delivered_from: PI,
     /// The broadcast scope of the message.
     pub scope: DeliveryScope,
+    /// The publisher of the message.
+    ///
+    /// This is only set in topics with [`MessageAuth::Signed`], where the signature of the
+    /// publisher was verified before the message was delivered.
+    pub origin: Option<PublicKey>,
 }
 
 impl<PI> GossipEvent<PI> {
-    fn from_message(message: &Gossip, from: PI) -> Self {
+    fn from_message(message: &Gossip, content: Bytes, origin: Option<PublicKey>, from: PI) -> Self {
         Self {
-            content: message.content.clone(),
+            content,
             scope: message.scope,
             delivered_from: from,
+            origin,
         }
     }
 }
 
+/// Authentication of the gossip messages in a topic.
+///
+/// The mode must be the same for all peers in a topic: peers in a signed topic drop all messages
+/// that are not signed, and peers in an unsigned topic deliver the signed envelope as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum MessageAuth {
+    /// Messages are not authenticated.
+    #[default]
+    Unsigned,
+    /// Each message carries the [`PublicKey`] of its publisher and their signature.
+    ///
+    /// Messages with a missing or invalid signature are neither delivered nor forwarded.
+    Signed {
+        /// The publishers whose messages are delivered and forwarded.
+        ///
+        /// If `None`, messages from all publishers are accepted.
+        publishers: Option<BTreeSet<PublicKey>>,
+    },
+}
+
+/// The content of a [`Gossip`] message in a topic with [`MessageAuth::Signed`].
+#[derive(Serialize, Deserialize, Debug)]
+struct SignedContent {
+    /// The publisher of the message.
+    origin: PublicKey,
+    /// Signature of the publisher over the topic and content.
+    signature: Signature,
+    /// The content of the message.
+    content: Bytes,
+}
+
+impl SignedContent {
+    fn sign(secret_key: &SecretKey, topic: &TopicId, content: Bytes) -> Self {
+        let signature = secret_key.sign(&Self::signed_bytes(topic, &content));
+        Self {
+            origin: secret_key.public(),
+            signature,
+            content,
+        }
+    }
+
+    fn verify(&self, topic: &TopicId) -> bool {
+        self.origin
+            .verify(&Self::signed_bytes(topic, &self.content), &self.signature)
+            .is_ok()
+    }
+
+    fn signed_bytes(topic: &TopicId, content: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIGNATURE_CONTEXT.len() + 32 + content.len());
+        bytes.extend_from_slice(SIGNATURE_CONTEXT);
+        bytes.extend_from_slice(topic.as_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+}
+
+/// The [`MessageAuth`] of a topic, along with what is needed to sign and verify messages.
+#[derive(Debug)]
+struct Auth {
+    mode: MessageAuth,
+    topic: TopicId,
+    secret_key: Option<SecretKey>,
+}
+
 /// Number of delivery hops a message has taken.
 #[derive(
     From, Add, Sub, Serialize, Deserialize, Eq, PartialEq, PartialOrd, Ord, Clone, Copy, Debug, Hash,
@@ -361,6 +435,9 @@ pub struct State<PI> {
     /// Set to false after the first message is received. Used for initial timer scheduling.
     init: bool,
 
+    /// Authentication of messages, if configured with [`Self::set_auth`].
+    auth: Option<Auth>,
+
     /// [`Stats`] of this plumtree.
     pub(crate) stats: Stats,
 }
@@ -380,10 +457,27 @@ impl<PI: PeerIdentity> State<PI> {
             dispatch_timer_scheduled: false,
             cache: Default::default(),
             init: false,
+            auth: None,
             stats: Default::default(),
         }
     }
 
+    /// Set the [`MessageAuth`] for this plumtree.
+    ///
+    /// The `topic` is included in the signed bytes, so that signed messages can not be replayed
+    /// into other topics. The `secret_key` is used to sign the messages we broadcast; without
+    /// it, broadcasting in a signed topic fails.
+    pub fn set_auth(&mut self, topic: TopicId, mode: MessageAuth, secret_key: Option<SecretKey>) {
+        self.auth = match mode {
+            MessageAuth::Unsigned => None,
+            mode => Some(Auth {
+                mode,
+                topic,
+                secret_key,
+            }),
+        };
+    }
+
     /// Handle an [`InEvent`].
     pub fn handle(&mut self, event: InEvent<PI>, now: Instant, io: &mut impl IO<PI>) {
         if !self.init {
@@ -410,6 +504,11 @@ impl<PI: PeerIdentity> State<PI> {
         &self.stats
     }
 
+    /// Number of message payloads currently kept in the message cache.
+    pub fn message_cache_len(&self) -> usize {
+        self.cache.len()
+    }
+
     /// Handle receiving a [`Message`].
     fn handle_message(&mut self, sender: PI, message: Message, now: Instant, io: &mut impl IO<PI>) {
         if matches!(message, Message::Gossip(_)) {
@@ -439,6 +538,19 @@ impl<PI: PeerIdentity> State<PI> {
     /// Will be pushed in full to eager peers.
     /// Pushing the message id to the lazy peers is delayed by a timer.
     fn broadcast(&mut self, content: Bytes, scope: Scope, now: Instant, io: &mut impl IO<PI>) {
+        let content = match &self.auth {
+            None => content,
+            Some(auth) => {
+                let Some(secret_key) = &auth.secret_key else {
+                    warn!("Cannot broadcast in a signed topic without a secret key");
+                    return;
+                };
+                let signed = SignedContent::sign(secret_key, &auth.topic, content);
+                postcard::to_stdvec(&signed)
+                    .expect("failed to serialize signed content")
+                    .into()
+            }
+        };
         let id = MessageId::from_content(&content);
         let scope = match scope {
             Scope::Neighbors => DeliveryScope::Neighbors,
@@ -480,6 +592,15 @@ impl<PI: PeerIdentity> State<PI> {
             io.push(OutEvent::SendMessage(sender, Message::Prune));
         // otherwise store the message, emit to application and forward to peers
         } else {
+            // in signed topics, verify the signature and publisher of the message before it is
+            // delivered or forwarded
+            let (content, origin) = match self.authenticate(&message) {
+                Ok(res) => res,
+                Err(reason) => {
+                    warn!(peer = ?sender, id = %message.id, "Dropping gossip message: {reason}");
+                    return;
+                }
+            };
             if let DeliveryScope::Swarm(prev_round) = message.scope {
                 // insert the message in the list of received messages
                 self.received_messages.insert(
@@ -513,11 +634,35 @@ impl<PI: PeerIdentity> State<PI> {
 
             // emit event to application
             io.push(OutEvent::EmitEvent(Event::Received(
-                GossipEvent::from_message(&message, sender),
+                GossipEvent::from_message(&message, content, origin, sender),
             )));
         }
     }
 
+    /// Check a received [`Gossip`] message against the [`MessageAuth`] of this plumtree.
+    ///
+    /// Returns the content to deliver to the application and the verified publisher, or the
+    /// reason why the message must be dropped.
+    fn authenticate(&self, message: &Gossip) -> Result<(Bytes, Option<PublicKey>), &'static str> {
+        let Some(auth) = &self.auth else {
+            return Ok((message.content.clone(), None));
+        };
+        let signed: SignedContent =
+            postcard::from_bytes(&message.content).map_err(|_| "message is not signed")?;
+        if !signed.verify(&auth.topic) {
+            return Err("invalid signature");
+        }
+        if let MessageAuth::Signed {
+            publishers: Some(publishers),
+        } = &auth.mode
+        {
+            if !publishers.contains(&signed.origin) {
+                return Err("publisher is not allowed");
+            }
+        }
+        Ok((signed.content, Some(signed.origin)))
+    }
+
     /// Optimize the tree by pruning the `sender` of a [`Message::Gossip`] if we previously
     /// received a [`Message::IHave`] for the same message with a much lower number of delivery
     /// hops from the original broadcaster of the message.
@@ -752,6 +897,7 @@ mod test {
                 content,
                 delivered_from: 3,
                 scope: DeliveryScope::Swarm(Round(6)),
+                origin: None,
             })));
             io
         };
@@ -799,6 +945,7 @@ mod test {
                 content,
                 delivered_from: 3,
                 scope: DeliveryScope::Swarm(Round(9)),
+                origin: None,
             })));
             io
         };
@@ -834,6 +981,7 @@ mod test {
                 content,
                 delivered_from: 2,
                 scope: DeliveryScope::Swarm(Round(1)),
+                origin: None,
             })));
             io
         };
@@ -852,6 +1000,98 @@ mod test {
         assert_eq!(io, expected);
     }
 
+    #[test]
+    fn signed_messages() {
+        let config: Config = Default::default();
+        let topic = TopicId::from_bytes([1u8; 32]);
+        let alice = SecretKey::from_bytes(&[2u8; 32]);
+        let mallory = SecretKey::from_bytes(&[3u8; 32]);
+        let publishers = Some([alice.public()].into_iter().collect());
+        let mut state: State<u32> = State::new(1, config.clone());
+        state.set_auth(topic, MessageAuth::Signed { publishers }, None);
+        let now = Instant::now();
+
+        let gossip = |content: Bytes| {
+            Message::Gossip(Gossip {
+                id: MessageId::from_content(&content),
+                content,
+                scope: DeliveryScope::Swarm(Round(1)),
+            })
+        };
+        let sign = |key: &SecretKey, topic: &TopicId, content: &'static [u8]| {
+            let signed = SignedContent::sign(key, topic, Bytes::from_static(content));
+            Bytes::from(postcard::to_stdvec(&signed).unwrap())
+        };
+        let received = |io: &VecDeque<OutEvent<u32>>| {
+            io.iter()
+                .filter_map(|event| match event {
+                    OutEvent::EmitEvent(Event::Received(event)) => Some(event.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // a message signed by an allowed publisher is delivered with its plain content and origin
+        let mut io = VecDeque::new();
+        let message = gossip(sign(&alice, &topic, b"hello"));
+        state.handle(InEvent::RecvMessage(2, message), now, &mut io);
+        let events = received(&io);
+        assert_eq!(events.len(), 1);
+        assert_eq!(&events[0].content[..], b"hello");
+        assert_eq!(events[0].origin, Some(alice.public()));
+        assert_eq!(state.cache.len(), 1);
+
+        // unsigned messages, messages signed by other publishers or for other topics, and
+        // messages with a forged origin are neither delivered nor forwarded
+        let other_topic = TopicId::from_bytes([2u8; 32]);
+        let mut forged: SignedContent =
+            postcard::from_bytes(&sign(&mallory, &topic, b"4")).unwrap();
+        forged.origin = alice.public();
+        let forged = Bytes::from(postcard::to_stdvec(&forged).unwrap());
+        for content in [
+            Bytes::from_static(b"1"),
+            sign(&mallory, &topic, b"2"),
+            sign(&alice, &other_topic, b"3"),
+            forged,
+        ] {
+            let mut io = VecDeque::new();
+            state.handle(InEvent::RecvMessage(2, gossip(content)), now, &mut io);
+            assert!(io.is_empty());
+        }
+        assert_eq!(state.cache.len(), 1);
+
+        // messages broadcast from a signed plumtree are accepted by its peers
+        let mut publisher: State<u32> = State::new(3, config.clone());
+        publisher.set_auth(
+            topic,
+            MessageAuth::Signed { publishers: None },
+            Some(alice.clone()),
+        );
+        let mut io = VecDeque::new();
+        publisher.handle(InEvent::NeighborUp(1), now, &mut io);
+        let content = Bytes::from_static(b"broadcast");
+        publisher.handle(InEvent::Broadcast(content, Scope::Swarm), now, &mut io);
+        let message = io
+            .iter()
+            .find_map(|event| match event {
+                OutEvent::SendMessage(1, message) => Some(message.clone()),
+                _ => None,
+            })
+            .expect("message is pushed to the eager peer");
+        let mut io = VecDeque::new();
+        state.handle(InEvent::RecvMessage(3, message), now, &mut io);
+        let events = received(&io);
+        assert_eq!(events.len(), 1);
+        assert_eq!(&events[0].content[..], b"broadcast");
+        assert_eq!(events[0].origin, Some(alice.public()));
+
+        // without a secret key, nothing is broadcast in a signed topic
+        let mut io = VecDeque::new();
+        let content = Bytes::from_static(b"unsigned");
+        state.handle(InEvent::Broadcast(content, Scope::Swarm), now, &mut io);
+        assert!(io.is_empty());
+    }
+
     #[test]
     fn cache_is_evicted() {
         let config: Config = Default::default();
@@ -0,0 +1,1039 @@
+//! Simulation framework for testing the protocol implementation
+//!
+//! The [`Network`] drives many protocol [`State`]s over a simulated network with a synthetic
+//! clock that advances in ticks. Each link has a latency and may lose messages, peers can go
+//! offline and come back with a fresh state, and the network can be split into two partitions.
+//!
+//! The [`Simulator`] builds a swarm on such a network and runs broadcast rounds. For each round it
+//! records which peers received the message, how long it took, and how many messages were sent.
+//! The results are summarized in a [`Report`] with the delivery ratio, delivery latency
+//! percentiles and the message overhead, which allows to compare different protocol [`Config`]s.
+//!
+//! All randomness is derived from the configured seeds, so the same configuration always yields
+//! the same [`Report`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use rand::{
+    rngs::StdRng,
+    seq::{IteratorRandom, SliceRandom},
+    Rng,
+};
+use rand_core::SeedableRng;
+use tracing::{debug, warn};
+
+use crate::proto::Scope;
+
+use super::{
+    state::MessageKind, util::TimerMap, Command, Config, Event, InEvent, Message, OutEvent,
+    PeerIdentity, State, Timer, TopicId,
+};
+
+/// Duration of a tick of the [`Network`].
+pub const TICK_DURATION: Duration = Duration::from_millis(10);
+const DEFAULT_LATENCY: Duration = TICK_DURATION.saturating_mul(3);
+
+/// Configuration of the links of a [`Network`].
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    /// Seed for the link latencies and the message loss.
+    pub seed: u64,
+    /// Minimum one-way latency of a link.
+    pub latency_min: Duration,
+    /// Maximum one-way latency of a link.
+    ///
+    /// Each link gets a fixed latency chosen uniformly between [`Self::latency_min`] and this.
+    pub latency_max: Duration,
+    /// Probability that a message is lost on its link.
+    pub loss: f64,
+    /// Time after which a peer notices that a connection broke without being closed, because the
+    /// other peer crashed or is in another partition.
+    pub failure_detection: Duration,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            latency_min: DEFAULT_LATENCY,
+            latency_max: DEFAULT_LATENCY,
+            loss: 0.,
+            failure_detection: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Number of messages sent on a [`Network`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    /// Number of payload messages.
+    pub payload_messages: u64,
+    /// Number of control messages.
+    pub control_messages: u64,
+}
+
+/// Test network implementation.
+///
+/// Stores events in VecDeques and processes on ticks.
+/// Timers are checked after each tick. The local time is increased with TICK_DURATION before
+/// each tick.
+///
+/// Messages to peers that are offline or in another partition are not delivered. Instead, the
+/// sender is notified that the connection broke after [`NetworkConfig::failure_detection`].
+///
+/// Note: Panics when sending to an unknown peer.
+#[derive(Debug)]
+pub struct Network<PI, R> {
+    config: NetworkConfig,
+    rng: StdRng,
+    start: Instant,
+    time: Instant,
+    tick_duration: Duration,
+    inqueues: Vec<VecDeque<InEvent<PI>>>,
+    pub(crate) peers: Vec<State<PI, R>>,
+    online: Vec<bool>,
+    /// Increased each time a peer goes offline, to drop events for its previous state.
+    incarnations: Vec<u64>,
+    peers_by_address: HashMap<PI, usize>,
+    conns: BTreeSet<ConnId<PI>>,
+    /// Peers cut off from the others while the network is split.
+    partition: Option<HashSet<PI>>,
+    events: VecDeque<(PI, TopicId, Event<PI>)>,
+    timers: TimerMap<(usize, u64, Timer<PI>)>,
+    transport: TimerMap<(usize, u64, InEvent<PI>)>,
+    latencies: HashMap<ConnId<PI>, Duration>,
+    stats: NetworkStats,
+}
+impl<PI, R> Network<PI, R> {
+    /// Create a network where each link has the same latency and no messages are lost.
+    pub fn new(time: Instant) -> Self {
+        Self::with_config(time, NetworkConfig::default())
+    }
+
+    /// Create a network whose links behave as configured.
+    pub fn with_config(time: Instant, config: NetworkConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            start: time,
+            time,
+            tick_duration: TICK_DURATION,
+            inqueues: Default::default(),
+            peers: Default::default(),
+            online: Default::default(),
+            incarnations: Default::default(),
+            peers_by_address: Default::default(),
+            conns: Default::default(),
+            partition: None,
+            events: Default::default(),
+            timers: TimerMap::new(),
+            transport: TimerMap::new(),
+            latencies: HashMap::new(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Get the current time of the network.
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+
+    /// Get the number of messages sent so far.
+    pub fn stats(&self) -> NetworkStats {
+        self.stats
+    }
+}
+
+fn push_back<PI: Eq + std::hash::Hash>(
+    inqueues: &mut [VecDeque<InEvent<PI>>],
+    peer_pos: usize,
+    event: InEvent<PI>,
+) {
+    inqueues.get_mut(peer_pos).unwrap().push_back(event);
+}
+
+impl<PI: PeerIdentity + Ord, R: Rng + Clone> Network<PI, R> {
+    /// Add a peer to the network.
+    pub fn push(&mut self, peer: State<PI, R>) {
+        let idx = self.inqueues.len();
+        self.inqueues.push(VecDeque::new());
+        self.online.push(true);
+        self.incarnations.push(0);
+        self.peers_by_address.insert(*peer.me(), idx);
+        self.peers.push(peer);
+    }
+
+    /// Drain the events emitted by the peers.
+    pub fn events(&mut self) -> impl Iterator<Item = (PI, TopicId, Event<PI>)> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Drain the events emitted by the peers, sorted.
+    pub fn events_sorted(&mut self) -> Vec<(PI, TopicId, Event<PI>)> {
+        sort(self.events().collect())
+    }
+
+    /// Get the open connections, sorted.
+    pub fn conns(&self) -> Vec<(PI, PI)> {
+        self.conns.iter().cloned().map(Into::into).collect()
+    }
+
+    /// Queue a command for a peer, to be handled in the next tick.
+    pub fn command(&mut self, peer: PI, topic: TopicId, command: Command<PI>) {
+        debug!(?peer, "~~ COMMAND {command:?}");
+        let idx = *self.peers_by_address.get(&peer).unwrap();
+        push_back(&mut self.inqueues, idx, InEvent::Command(topic, command));
+    }
+
+    /// Run `n` ticks.
+    pub fn ticks(&mut self, n: usize) {
+        (0..n).for_each(|_| self.tick())
+    }
+
+    /// Get the number of ticks run so far.
+    pub fn get_tick(&self) -> u32 {
+        ((self.time - self.start) / self.tick_duration.as_millis() as u32).as_millis() as u32
+    }
+
+    /// Advance the time by a tick, and let the peers handle the timers and messages that are due.
+    pub fn tick(&mut self) {
+        self.time += self.tick_duration;
+
+        // process timers
+        for (_time, (idx, incarnation, timer)) in self.timers.drain_until(&self.time) {
+            if self.incarnations[idx] == incarnation {
+                push_back(&mut self.inqueues, idx, InEvent::TimerExpired(timer));
+            }
+        }
+
+        // move messages
+        let arrived: Vec<_> = self.transport.drain_until(&self.time).collect();
+        for (_time, (idx, incarnation, event)) in arrived {
+            if self.incarnations[idx] != incarnation {
+                continue;
+            }
+            // messages in flight are lost when the network is split
+            if let InEvent::RecvMessage(from, _message) = &event {
+                if !self.reachable(from, self.peers[idx].me()) {
+                    continue;
+                }
+            }
+            push_back(&mut self.inqueues, idx, event);
+        }
+
+        // process inqueues: let peer handle all incoming events
+        let sent_before = self.stats;
+        for idx in 0..self.inqueues.len() {
+            while let Some(event) = self.inqueues[idx].pop_front() {
+                self.handle(idx, event);
+            }
+        }
+        let messages_sent = self.stats.payload_messages + self.stats.control_messages
+            - sent_before.payload_messages
+            - sent_before.control_messages;
+        debug!(
+            tick = self.get_tick(),
+            "~~ TICK (messages sent: {messages_sent})"
+        );
+    }
+
+    /// Get the protocol state of a peer.
+    pub fn peer(&self, peer: &PI) -> Option<&State<PI, R>> {
+        self.peers_by_address
+            .get(peer)
+            .cloned()
+            .and_then(|idx| self.peers.get(idx))
+    }
+
+    /// Get the active view of a peer for a topic.
+    ///
+    /// Returns `None` if the peer is unknown, and `Some(None)` if it did not join the topic.
+    pub fn get_active(&self, peer: &PI, topic: &TopicId) -> Option<Option<Vec<PI>>> {
+        let peer = self.peer(peer)?;
+        match peer.state(topic) {
+            Some(state) => Some(Some(
+                state.swarm.active_view.iter().cloned().collect::<Vec<_>>(),
+            )),
+            None => Some(None),
+        }
+    }
+
+    /// Whether a peer is online.
+    pub fn is_online(&self, peer: &PI) -> bool {
+        let idx = *self.peers_by_address.get(peer).unwrap();
+        self.online[idx]
+    }
+
+    /// Take a peer offline.
+    ///
+    /// If `graceful` is true, the peer quits all its topics first, and its remaining neighbors
+    /// notice the closed connections after the link latency. Otherwise the peer crashes, and its
+    /// neighbors notice after [`NetworkConfig::failure_detection`].
+    pub fn leave(&mut self, peer: PI, graceful: bool) {
+        let idx = *self.peers_by_address.get(&peer).unwrap();
+        if graceful {
+            let topics: Vec<TopicId> = sort(self.peers[idx].states().map(|(t, _)| *t).collect());
+            for topic in topics {
+                self.handle(idx, InEvent::Command(topic, Command::Quit));
+            }
+        }
+        debug!(?peer, graceful, "~~ LEAVE");
+        self.online[idx] = false;
+        self.incarnations[idx] += 1;
+        self.inqueues[idx].clear();
+        let conns: Vec<_> = self
+            .conns
+            .iter()
+            .filter(|conn| conn.0.contains(&peer))
+            .cloned()
+            .collect();
+        for conn in conns {
+            self.conns.remove(&conn);
+            let (a, b) = conn.into();
+            let other = if a == peer { b } else { a };
+            let delay = match graceful {
+                true => self.latency(&peer, &other),
+                false => self.config.failure_detection,
+            };
+            self.schedule_disconnect(other, peer, delay);
+        }
+    }
+
+    /// Bring an offline peer back online with a fresh protocol state.
+    ///
+    /// The peer has to join its topics again with [`Self::command`].
+    pub fn rejoin(&mut self, state: State<PI, R>) {
+        let idx = *self.peers_by_address.get(state.me()).unwrap();
+        debug!(peer = ?state.me(), "~~ REJOIN");
+        self.peers[idx] = state;
+        self.online[idx] = true;
+    }
+
+    /// Split the network, so that the peers in `cut` can only reach each other.
+    ///
+    /// Connections between the two partitions break, which both sides notice after
+    /// [`NetworkConfig::failure_detection`].
+    pub fn split(&mut self, cut: HashSet<PI>) {
+        debug!(peers = cut.len(), "~~ SPLIT");
+        let conns: Vec<_> = self
+            .conns
+            .iter()
+            .filter(|conn| cut.contains(&conn.0[0]) != cut.contains(&conn.0[1]))
+            .cloned()
+            .collect();
+        self.partition = Some(cut);
+        for conn in conns {
+            self.conns.remove(&conn);
+            let (a, b) = conn.into();
+            self.schedule_disconnect(a, b, self.config.failure_detection);
+            self.schedule_disconnect(b, a, self.config.failure_detection);
+        }
+    }
+
+    /// Heal a split of the network.
+    pub fn heal(&mut self) {
+        debug!("~~ HEAL");
+        self.partition = None;
+    }
+
+    /// Let a peer handle an event, and process the events it emits.
+    fn handle(&mut self, idx: usize, event: InEvent<PI>) {
+        if !self.online[idx] {
+            return;
+        }
+        let peer = *self.peers[idx].me();
+        if let InEvent::RecvMessage(from, _message) = &event {
+            self.conns.insert((*from, peer).into());
+        }
+        debug!(peer = ?peer, "IN  {event:?}");
+        let out: Vec<_> = self.peers[idx].handle(event, self.time).collect();
+        for event in out {
+            debug!(peer = ?peer, "OUT {event:?}");
+            match event {
+                OutEvent::SendMessage(to, message) => self.send(peer, to, message),
+                OutEvent::ScheduleTimer(latency, timer) => {
+                    let incarnation = self.incarnations[idx];
+                    self.timers
+                        .insert(self.time + latency, (idx, incarnation, timer));
+                }
+                OutEvent::DisconnectPeer(to) => {
+                    debug!(peer = ?peer, other = ?to, "disconnect");
+                    if self.conns.remove(&(peer, to).into()) {
+                        let latency = self.latency(&peer, &to) + Duration::from_nanos(1);
+                        self.schedule_disconnect(to, peer, latency);
+                    }
+                }
+                OutEvent::EmitEvent(topic, event) => {
+                    debug!(peer = ?peer, "emit   {event:?}");
+                    self.events.push_back((peer, topic, event));
+                }
+                OutEvent::PeerData(_peer, _data) => {}
+            }
+        }
+    }
+
+    fn send(&mut self, from: PI, to: PI, message: Message<PI>) {
+        match message.kind() {
+            MessageKind::Data => self.stats.payload_messages += 1,
+            MessageKind::Control => self.stats.control_messages += 1,
+        }
+        let to_idx = *self.peers_by_address.get(&to).unwrap();
+        if !self.online[to_idx] || !self.reachable(&from, &to) {
+            // the connection can not be established or breaks
+            self.conns.remove(&ConnId::new(from, to));
+            self.schedule_disconnect(from, to, self.config.failure_detection);
+            return;
+        }
+        if self.config.loss > 0. && self.rng.gen_bool(self.config.loss.min(1.)) {
+            return;
+        }
+        let latency = self.latency(&from, &to);
+        let incarnation = self.incarnations[to_idx];
+        self.transport.insert(
+            self.time + latency,
+            (to_idx, incarnation, InEvent::RecvMessage(from, message)),
+        );
+    }
+
+    /// Notify `peer` after `delay` that its connection to `other` is gone.
+    fn schedule_disconnect(&mut self, peer: PI, other: PI, delay: Duration) {
+        let idx = *self.peers_by_address.get(&peer).unwrap();
+        if !self.online[idx] {
+            return;
+        }
+        let incarnation = self.incarnations[idx];
+        self.transport.insert(
+            self.time + delay,
+            (idx, incarnation, InEvent::PeerDisconnected(other)),
+        );
+    }
+
+    fn reachable(&self, a: &PI, b: &PI) -> bool {
+        match &self.partition {
+            None => true,
+            Some(cut) => cut.contains(a) == cut.contains(b),
+        }
+    }
+
+    /// Get the fixed one-way latency of the link between two peers.
+    fn latency(&mut self, a: &PI, b: &PI) -> Duration {
+        let min = self.config.latency_min;
+        let max = self.config.latency_max.max(min);
+        if min == max {
+            return min;
+        }
+        let rng = &mut self.rng;
+        *self
+            .latencies
+            .entry((*a, *b).into())
+            .or_insert_with(|| rng.gen_range(min..=max))
+    }
+}
+
+/// Check that the active views and eager push peers of all peers are symmetric.
+pub fn assert_synchronous_active<PI: PeerIdentity, R: Rng + Clone>(
+    network: &Network<PI, R>,
+) -> bool {
+    for state in network.peers.iter() {
+        let peer = *state.me();
+        for (topic, state) in state.states() {
+            for other in state.swarm.active_view.iter() {
+                let other_idx = network.peers_by_address.get(other).unwrap();
+                let other_state = &network
+                    .peers
+                    .get(*other_idx)
+                    .unwrap()
+                    .state(topic)
+                    .unwrap()
+                    .swarm
+                    .active_view;
+                if !other_state.contains(&peer) {
+                    warn!(peer = ?peer, other = ?other, "missing active_view peer in other");
+                    return false;
+                }
+            }
+            for other in state.gossip.eager_push_peers.iter() {
+                let other_idx = network.peers_by_address.get(other).unwrap();
+                let other_state = &network
+                    .peers
+                    .get(*other_idx)
+                    .unwrap()
+                    .state(topic)
+                    .unwrap()
+                    .gossip
+                    .eager_push_peers;
+                if !other_state.contains(&peer) {
+                    warn!(peer = ?peer, other = ?other, "missing eager_push peer in other");
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Identifier of a peer in the [`Simulator`].
+pub type PeerId = usize;
+
+/// A simple simulator for the gossip protocol
+#[derive(Debug)]
+pub struct Simulator {
+    simulator_config: SimulatorConfig,
+    protocol_config: Config,
+    rng: StdRng,
+    network: Network<PeerId, StdRng>,
+    round_stats: Vec<RoundStats>,
+    /// Peers that rejoined before the current round.
+    rejoined: HashSet<PeerId>,
+}
+
+/// Configuration of a [`Simulator`].
+#[derive(Debug, Clone)]
+pub struct SimulatorConfig {
+    /// Seed for the random decisions of the simulator and the peers.
+    pub seed: u64,
+    /// Number of peers.
+    pub peers_count: usize,
+    /// Number of peers that form the swarm initially. All other peers join through one of them.
+    pub bootstrap_count: usize,
+    /// Number of ticks to let the initial swarm settle.
+    pub bootstrap_ticks: usize,
+    /// Number of ticks between two joining peers.
+    pub join_ticks: usize,
+    /// Number of ticks to let the swarm settle after all peers joined.
+    pub warmup_ticks: usize,
+    /// Maximum number of ticks of a broadcast round.
+    ///
+    /// A peer that did not receive the broadcast within this time counts as not delivered.
+    pub round_max_ticks: usize,
+    /// Configuration of the links between the peers.
+    pub network: NetworkConfig,
+    /// Fraction of the online peers that leave before each [`Simulator::round`].
+    ///
+    /// The same number of previously departed peers rejoin the swarm with a fresh state.
+    pub churn: f64,
+    /// Whether leaving peers quit the topic, or crash without notifying their neighbors.
+    pub graceful_leave: bool,
+    /// Split the network into two partitions for some rounds.
+    pub partition: Option<PartitionConfig>,
+}
+
+/// Configuration of a network partition in the [`Simulator`].
+#[derive(Debug, Clone)]
+pub struct PartitionConfig {
+    /// Fraction of the peers that are cut off from the others.
+    pub fraction: f64,
+    /// Round in which the partition starts.
+    pub start_round: usize,
+    /// Round in which the partition heals.
+    pub end_round: usize,
+}
+
+/// Results of a single broadcast round.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoundStats {
+    /// The peer that broadcast the message.
+    pub sender: PeerId,
+    /// Number of peers that should have received the message.
+    ///
+    /// These are the peers that were online for the whole round, except the sender.
+    pub expected: usize,
+    /// Time after which each peer received the message, sorted in ascending order.
+    pub latencies: Vec<Duration>,
+    /// Number of ticks the round took.
+    pub ticks: usize,
+    /// Relative message redundancy: the number of payload messages per delivery, minus one.
+    pub rmr: f32,
+    /// Last delivery hop: the maximum number of hops the message took to reach a peer.
+    pub ldh: u16,
+    /// Number of payload messages sent during the round.
+    pub payload_messages: u64,
+    /// Number of control messages sent during the round.
+    pub control_messages: u64,
+}
+
+impl RoundStats {
+    /// Number of peers that received the message.
+    pub fn delivered(&self) -> usize {
+        self.latencies.len()
+    }
+}
+
+/// Results of the broadcast rounds of a [`Simulator`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// The results of each broadcast round.
+    pub rounds: Vec<RoundStats>,
+}
+
+impl Report {
+    /// Ratio of the peers that received a broadcast to the peers that should have received it.
+    pub fn delivery_ratio(&self) -> f64 {
+        match self.expected() {
+            0 => 1.,
+            expected => self.delivered() as f64 / expected as f64,
+        }
+    }
+
+    /// Get the delivery latency below which `percentile` percent of all deliveries happened.
+    ///
+    /// Returns `None` if no message was delivered.
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut latencies: Vec<_> = self
+            .rounds
+            .iter()
+            .flat_map(|r| r.latencies.iter().copied())
+            .collect();
+        latencies.sort();
+        let rank = (percentile / 100. * latencies.len() as f64).ceil() as usize;
+        latencies.get(rank.saturating_sub(1)).copied()
+    }
+
+    /// Relative message redundancy over all rounds.
+    ///
+    /// A value of 0 means that each peer received the payload exactly once.
+    pub fn relative_message_redundancy(&self) -> f64 {
+        let payload: u64 = self.rounds.iter().map(|r| r.payload_messages).sum();
+        match self.delivered() {
+            0 => 0.,
+            delivered => payload as f64 / delivered as f64 - 1.,
+        }
+    }
+
+    /// Number of control messages sent per delivery.
+    pub fn control_overhead(&self) -> f64 {
+        let control: u64 = self.rounds.iter().map(|r| r.control_messages).sum();
+        match self.delivered() {
+            0 => 0.,
+            delivered => control as f64 / delivered as f64,
+        }
+    }
+
+    fn expected(&self) -> usize {
+        self.rounds.iter().map(|r| r.expected).sum()
+    }
+
+    fn delivered(&self) -> usize {
+        self.rounds.iter().map(|r| r.delivered()).sum()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "rounds: {}, delivery ratio: {:.2}% ({}/{})",
+            self.rounds.len(),
+            self.delivery_ratio() * 100.,
+            self.delivered(),
+            self.expected(),
+        )?;
+        let p = |percentile| {
+            self.latency_percentile(percentile)
+                .map_or("-".to_string(), |d| format!("{d:?}"))
+        };
+        writeln!(
+            f,
+            "latency: p50 {} p90 {} p99 {} max {}",
+            p(50.),
+            p(90.),
+            p(99.),
+            p(100.)
+        )?;
+        write!(
+            f,
+            "overhead: RMR {:.2}, control messages per delivery {:.2}",
+            self.relative_message_redundancy(),
+            self.control_overhead()
+        )
+    }
+}
+
+/// The topic all peers of the [`Simulator`] join.
+pub const TOPIC: TopicId = TopicId::from_bytes([0u8; 32]);
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            peers_count: 100,
+            bootstrap_count: 5,
+            bootstrap_ticks: 50,
+            join_ticks: 1,
+            warmup_ticks: 300,
+            round_max_ticks: 200,
+            network: Default::default(),
+            churn: 0.,
+            graceful_leave: true,
+            partition: None,
+        }
+    }
+}
+impl Simulator {
+    /// Create a new simulator.
+    ///
+    /// Call [`Self::init`] and [`Self::bootstrap`] to build the swarm.
+    pub fn new(simulator_config: SimulatorConfig, protocol_config: Config) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(simulator_config.seed),
+            network: Network::with_config(Instant::now(), simulator_config.network.clone()),
+            protocol_config,
+            simulator_config,
+            round_stats: Default::default(),
+            rejoined: Default::default(),
+        }
+    }
+
+    /// Add the peers to the network.
+    pub fn init(&mut self) {
+        for i in 0..self.simulator_config.peers_count {
+            let state = self.new_state(i);
+            self.network.push(state);
+        }
+    }
+
+    /// Let all peers join the swarm, and let the swarm settle.
+    pub fn bootstrap(&mut self) {
+        self.network.command(0, TOPIC, Command::Join(vec![]));
+        for i in 1..self.simulator_config.bootstrap_count {
+            self.network.command(i, TOPIC, Command::Join(vec![0]));
+        }
+        self.network.ticks(self.simulator_config.bootstrap_ticks);
+        let _ = self.network.events();
+
+        for i in self.simulator_config.bootstrap_count..self.simulator_config.peers_count {
+            let contact = i % self.simulator_config.bootstrap_count;
+            self.network.command(i, TOPIC, Command::Join(vec![contact]));
+            self.network.ticks(self.simulator_config.join_ticks);
+            let _ = self.network.events();
+        }
+        self.network.ticks(self.simulator_config.warmup_ticks);
+        let _ = self.network.events();
+    }
+
+    /// Run a broadcast round from a random online peer.
+    ///
+    /// Before the broadcast, the network is split or healed according to
+    /// [`SimulatorConfig::partition`], and peers leave and rejoin according to
+    /// [`SimulatorConfig::churn`].
+    pub fn round(&mut self) -> &RoundStats {
+        let round = self.round_stats.len();
+        if let Some(partition) = self.simulator_config.partition.clone() {
+            if round == partition.start_round {
+                self.split(partition.fraction);
+            } else if round == partition.end_round {
+                self.network.heal();
+            }
+        }
+        self.churn();
+        let online: Vec<_> = self.online().collect();
+        let sender = *online
+            .choose(&mut self.rng)
+            .expect("churn keeps a peer online");
+        let message = Bytes::from(format!("round {round}"));
+        self.gossip_round(sender, message)
+    }
+
+    /// Broadcast a message from a peer, and run the network until all online peers received it
+    /// or [`SimulatorConfig::round_max_ticks`] passed.
+    pub fn gossip_round(&mut self, from: PeerId, message: Bytes) -> &RoundStats {
+        let sent_before = self.network.stats();
+        let mut expected: HashSet<PeerId> = self
+            .online()
+            .filter(|p| *p != from && !self.rejoined.contains(p))
+            .collect();
+        let expected_len = expected.len();
+        self.network.command(
+            from,
+            TOPIC,
+            Command::Broadcast(message.clone(), Scope::Swarm),
+        );
+
+        let mut latencies = vec![];
+        let mut tick = 0;
+        loop {
+            if expected.is_empty() {
+                break;
+            }
+            if tick > self.simulator_config.round_max_ticks {
+                break;
+            }
+            tick += 1;
+            self.network.tick();
+            let events = self.network.events();
+            let received: HashSet<_> = events
+                .filter(
+                    |(_peer, _topic, event)| matches!(event,  Event::Received(recv) if recv.content == message),
+                )
+                .map(|(peer, _topic, _msg)| peer)
+                .collect();
+            for peer in received.iter() {
+                if expected.remove(peer) {
+                    latencies.push(TICK_DURATION * tick as u32);
+                }
+            }
+        }
+
+        let sent = self.network.stats();
+        let payload_messages = sent.payload_messages - sent_before.payload_messages;
+        let control_messages = sent.control_messages - sent_before.control_messages;
+        let rmr = match latencies.len() {
+            0 => 0.,
+            delivered => payload_messages as f32 / delivered as f32 - 1.,
+        };
+        let stats = RoundStats {
+            sender: from,
+            expected: expected_len,
+            latencies,
+            ticks: tick,
+            rmr,
+            ldh: self.max_ldh(),
+            payload_messages,
+            control_messages,
+        };
+        self.round_stats.push(stats);
+        self.reset_stats();
+        self.round_stats.last().expect("just pushed")
+    }
+
+    /// Get the results of the rounds run so far.
+    pub fn report(&self) -> Report {
+        Report {
+            rounds: self.round_stats.clone(),
+        }
+    }
+
+    /// Print the averages over the rounds run so far.
+    pub fn report_round_sums(&self) {
+        let len = self.round_stats.len();
+        let mut rmr = 0.;
+        let mut ldh = 0.;
+        let mut ticks = 0.;
+        for round in self.round_stats.iter() {
+            rmr += round.rmr;
+            ldh += round.ldh as f32;
+            ticks += round.ticks as f32;
+        }
+        rmr /= len as f32;
+        ldh /= len as f32;
+        ticks /= len as f32;
+        eprintln!(
+            "average over {} rounds with {} peers: RMR {rmr:.2} LDH {ldh:.2} ticks {ticks:.2}",
+            self.round_stats.len(),
+            self.network.peers.len(),
+        );
+        eprintln!("RMR = Relative Message Redundancy, LDH = Last Delivery Hop");
+    }
+
+    fn new_state(&mut self, peer: PeerId) -> State<PeerId, StdRng> {
+        let rng = StdRng::seed_from_u64(self.rng.gen());
+        State::new(peer, Default::default(), self.protocol_config.clone(), rng)
+    }
+
+    fn online(&self) -> impl Iterator<Item = PeerId> + '_ {
+        (0..self.network.peers.len()).filter(|p| self.network.is_online(p))
+    }
+
+    /// Let a fraction of the online peers leave, and the same number of offline peers rejoin.
+    fn churn(&mut self) {
+        self.rejoined.clear();
+        let online: Vec<_> = self.online().collect();
+        let count = (online.len() as f64 * self.simulator_config.churn).round() as usize;
+        let count = count.min(online.len().saturating_sub(1));
+        if count == 0 {
+            return;
+        }
+        let offline: Vec<_> = (0..self.network.peers.len())
+            .filter(|p| !self.network.is_online(p))
+            .choose_multiple(&mut self.rng, count);
+        let leaving = online.iter().copied().choose_multiple(&mut self.rng, count);
+        debug!(leaving = leaving.len(), joining = offline.len(), "churn");
+        for peer in leaving.iter() {
+            self.network
+                .leave(*peer, self.simulator_config.graceful_leave);
+        }
+        let remaining: Vec<_> = online
+            .into_iter()
+            .filter(|p| !leaving.contains(p))
+            .collect();
+        for peer in offline {
+            let contact = *remaining
+                .choose(&mut self.rng)
+                .expect("churn keeps a peer online");
+            let state = self.new_state(peer);
+            self.network.rejoin(state);
+            self.network
+                .command(peer, TOPIC, Command::Join(vec![contact]));
+            self.rejoined.insert(peer);
+        }
+    }
+
+    /// Cut off a random `fraction` of the peers from the others.
+    fn split(&mut self, fraction: f64) {
+        let count = (self.network.peers.len() as f64 * fraction).round() as usize;
+        let cut = (0..self.network.peers.len())
+            .choose_multiple(&mut self.rng, count)
+            .into_iter()
+            .collect();
+        self.network.split(cut);
+    }
+
+    fn reset_stats(&mut self) {
+        for state in self.network.peers.iter_mut() {
+            if let Some(state) = state.state_mut(&TOPIC) {
+                state.gossip.stats = Default::default();
+            }
+        }
+    }
+
+    fn max_ldh(&self) -> u16 {
+        let mut max = 0;
+        for state in self.network.peers.iter() {
+            if let Some(state) = state.state(&TOPIC) {
+                let stats = state.gossip.stats();
+                max = max.max(stats.max_last_delivery_hop);
+            }
+        }
+        max
+    }
+}
+
+/// Helper struct for active connections. A sorted tuple.
+#[derive(Debug, Clone, PartialOrd, Ord, Eq, PartialEq, Hash)]
+pub struct ConnId<PI>([PI; 2]);
+impl<PI: Ord> ConnId<PI> {
+    /// Create the connection identifier for two peers, in any order.
+    pub fn new(a: PI, b: PI) -> Self {
+        let mut conn = [a, b];
+        conn.sort();
+        Self(conn)
+    }
+}
+impl<PI: Ord> From<(PI, PI)> for ConnId<PI> {
+    fn from((a, b): (PI, PI)) -> Self {
+        Self::new(a, b)
+    }
+}
+impl<PI: Copy> From<ConnId<PI>> for (PI, PI) {
+    fn from(conn: ConnId<PI>) -> (PI, PI) {
+        (conn.0[0], conn.0[1])
+    }
+}
+
+/// Sort a list of items.
+pub fn sort<T: Ord + Clone>(items: Vec<T>) -> Vec<T> {
+    let mut sorted = items;
+    sorted.sort();
+    sorted
+}
+
+/// Print the distribution of the view sizes and the received message counts of all peers.
+pub fn report_round_distribution<PI: PeerIdentity, R: Rng + Clone>(network: &Network<PI, R>) {
+    let mut eager_distrib: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut lazy_distrib: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut active_distrib: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut passive_distrib: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut payload_recv = 0;
+    let mut control_recv = 0;
+    for state in network.peers.iter() {
+        for (_topic, state) in state.states() {
+            let stats = state.gossip.stats();
+            *eager_distrib
+                .entry(state.gossip.eager_push_peers.len())
+                .or_default() += 1;
+            *lazy_distrib
+                .entry(state.gossip.lazy_push_peers.len())
+                .or_default() += 1;
+            *active_distrib
+                .entry(state.swarm.active_view.len())
+                .or_default() += 1;
+            *passive_distrib
+                .entry(state.swarm.passive_view.len())
+                .or_default() += 1;
+            payload_recv += stats.payload_messages_received;
+            control_recv += stats.control_messages_received;
+        }
+    }
+    // eprintln!("distributions {round_distrib:?}");
+    eprintln!("payload_recv {payload_recv} control_recv {control_recv}");
+    eprintln!("eager_distrib {eager_distrib:?}");
+    eprintln!("lazy_distrib {lazy_distrib:?}");
+    eprintln!("active_distrib {active_distrib:?}");
+    eprintln!("passive_distrib {passive_distrib:?}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SimulatorConfig {
+        SimulatorConfig {
+            peers_count: 50,
+            network: NetworkConfig {
+                latency_min: Duration::from_millis(10),
+                latency_max: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn run(config: SimulatorConfig, rounds: usize) -> Report {
+        let mut simulator = Simulator::new(config, Config::default());
+        simulator.init();
+        simulator.bootstrap();
+        for _ in 0..rounds {
+            simulator.round();
+        }
+        simulator.report()
+    }
+
+    #[test]
+    fn sim_delivers_to_all() {
+        let _guard = iroh_test::logging::setup();
+        let report = run(config(), 5);
+        assert_eq!(report.rounds.len(), 5);
+        assert_eq!(report.delivery_ratio(), 1.);
+        assert!(report.latency_percentile(50.).unwrap() <= report.latency_percentile(99.).unwrap());
+        assert!(report.relative_message_redundancy() >= 0.);
+    }
+
+    #[test]
+    fn sim_is_deterministic() {
+        let _guard = iroh_test::logging::setup();
+        let config = SimulatorConfig {
+            network: NetworkConfig {
+                loss: 0.05,
+                ..config().network
+            },
+            churn: 0.1,
+            ..config()
+        };
+        let a = run(config.clone(), 5);
+        let b = run(config, 5);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn sim_partition() {
+        let _guard = iroh_test::logging::setup();
+        let config = SimulatorConfig {
+            partition: Some(PartitionConfig {
+                fraction: 0.5,
+                start_round: 1,
+                end_round: 4,
+            }),
+            ..config()
+        };
+        let report = run(config, 5);
+        assert_eq!(report.rounds[0].delivered(), report.rounds[0].expected);
+        for round in &report.rounds[1..4] {
+            assert!(round.delivered() < round.expected);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use iroh_base::key::SecretKey;
 use iroh_metrics::{inc, inc_by};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
@@ -13,6 +14,7 @@ use tracing::trace;
 use crate::{
     metrics::Metrics,
     proto::{
+        plumtree::MessageAuth,
         topic::{self, Command},
         util::idbytes_impls,
         Config, PeerData, PeerIdentity,
@@ -139,6 +141,8 @@ pub struct State<PI, R> {
     me_data: PeerData,
     config: Config,
     rng: R,
+    secret_key: Option<SecretKey>,
+    auth: HashMap<TopicId, MessageAuth>,
     states: HashMap<TopicId, topic::State<PI, R>>,
     outbox: Outbox<PI>,
     peer_topics: ConnsMap<PI>,
@@ -157,12 +161,40 @@ impl<PI: PeerIdentity, R: Rng + Clone> State<PI, R> {
             me_data,
             config,
             rng,
+            secret_key: None,
+            auth: Default::default(),
             states: Default::default(),
             outbox: Default::default(),
             peer_topics: Default::default(),
         }
     }
 
+    /// Set the key used to sign our messages in topics with [`MessageAuth::Signed`].
+    pub fn set_secret_key(&mut self, secret_key: SecretKey) {
+        self.secret_key = Some(secret_key);
+        for (topic, state) in self.states.iter_mut() {
+            let auth = self.auth.get(topic).cloned().unwrap_or_default();
+            state.gossip.set_auth(*topic, auth, self.secret_key.clone());
+        }
+    }
+
+    /// Set the [`MessageAuth`] for a topic.
+    ///
+    /// This can be called before or after joining the topic, and is kept when quitting it.
+    pub fn set_message_auth(&mut self, topic: TopicId, auth: MessageAuth) {
+        if let Some(state) = self.states.get_mut(&topic) {
+            state
+                .gossip
+                .set_auth(topic, auth.clone(), self.secret_key.clone());
+        }
+        self.auth.insert(topic, auth);
+    }
+
+    /// Get the [`MessageAuth`] for a topic.
+    pub fn message_auth(&self, topic: &TopicId) -> MessageAuth {
+        self.auth.get(topic).cloned().unwrap_or_default()
+    }
+
     /// Get a reference to the node's [`PeerIdentity`]
     pub fn me(&self) -> &PI {
         &self.me
@@ -173,9 +205,8 @@ impl<PI: PeerIdentity, R: Rng + Clone> State<PI, R> {
         self.states.get(topic)
     }
 
-    /// Get a reference to the protocol state for a topic.
-    #[cfg(test)]
-    pub fn state_mut(&mut self, topic: &TopicId) -> Option<&mut topic::State<PI, R>> {
+    /// Get a mutable reference to the protocol state for a topic.
+    pub(crate) fn state_mut(&mut self, topic: &TopicId) -> Option<&mut topic::State<PI, R>> {
         self.states.get_mut(topic)
     }
 
@@ -219,12 +250,17 @@ impl<PI: PeerIdentity, R: Rng + Clone> State<PI, R> {
                 // when receiving a join command, initialize state if it doesn't exist
                 if matches!(&event, topic::InEvent::Command(Command::Join(_peers))) {
                     if let hash_map::Entry::Vacant(e) = self.states.entry(topic) {
-                        e.insert(topic::State::with_rng(
+                        let state = e.insert(topic::State::with_rng(
                             self.me,
                             Some(self.me_data.clone()),
                             self.config.clone(),
                             self.rng.clone(),
                         ));
+                        if let Some(auth) = self.auth.get(&topic) {
+                            state
+                                .gossip
+                                .set_auth(topic, auth.clone(), self.secret_key.clone());
+                        }
                     }
                 }
 
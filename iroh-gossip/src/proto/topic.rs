@@ -311,6 +311,56 @@ impl<PI: PeerIdentity, R: Rng> State<PI, R> {
     pub fn has_active_peers(&self) -> bool {
         !self.swarm.active_view.is_empty()
     }
+
+    /// Get the peers we know in this topic, with the [`PeerData`] we received for them.
+    ///
+    /// These are the peers in the active view, followed by the peers in the passive view. They
+    /// can be used to rejoin the topic after a restart.
+    pub fn known_peers(&self) -> impl Iterator<Item = (PI, Option<&PeerData>)> + '_ {
+        self.swarm.known_peers()
+    }
+
+    /// Get a snapshot of the membership and broadcast state of this topic.
+    pub fn info(&self) -> TopicInfo<PI> {
+        let gossip_stats = self.gossip.stats();
+        TopicInfo {
+            active_view: self.swarm.active_view.iter().copied().collect(),
+            passive_view: self.swarm.passive_view.iter().copied().collect(),
+            eager_peers: self.gossip.eager_push_peers.iter().copied().collect(),
+            lazy_peers: self.gossip.lazy_push_peers.iter().copied().collect(),
+            message_cache_size: self.gossip.message_cache_len(),
+            messages_sent: self.stats.messages_sent,
+            messages_received: self.stats.messages_received,
+            payload_messages_received: gossip_stats.payload_messages_received,
+            control_messages_received: gossip_stats.control_messages_received,
+            max_last_delivery_hop: gossip_stats.max_last_delivery_hop,
+        }
+    }
+}
+
+/// Snapshot of the protocol state of a topic, returned from [`State::info`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicInfo<PI> {
+    /// Peers in the active view of the swarm membership.
+    pub active_view: Vec<PI>,
+    /// Peers in the passive view of the swarm membership.
+    pub passive_view: Vec<PI>,
+    /// Peers to which message payloads are pushed eagerly.
+    pub eager_peers: Vec<PI>,
+    /// Peers to which only message ids are pushed lazily.
+    pub lazy_peers: Vec<PI>,
+    /// Number of message payloads in the message cache.
+    pub message_cache_size: usize,
+    /// Number of messages sent.
+    pub messages_sent: usize,
+    /// Number of messages received.
+    pub messages_received: usize,
+    /// Number of payload messages received.
+    pub payload_messages_received: u64,
+    /// Number of control messages received.
+    pub control_messages_received: u64,
+    /// Max number of hops a received message took to reach us.
+    pub max_last_delivery_hop: u16,
 }
 
 /// Statistics for the protocol state of a topic
@@ -8,6 +8,7 @@ use std::{
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context as _, Result};
@@ -22,7 +23,9 @@ use iroh_net::key::SecretKey;
 use iroh_net::relay::http::{
     ServerBuilder as RelayServerBuilder, TlsAcceptor, TlsConfig as RelayTlsConfig,
 };
-use iroh_net::relay::{self};
+use iroh_net::relay::{
+    self, AccessConfig, AccessControl, ClientLimits, MeshConfig, RateLimit, RestartConfig,
+};
 use iroh_net::stun;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
@@ -196,6 +199,17 @@ struct Config {
     tls: Option<TlsConfig>,
     /// Rate limiting configuration
     limits: Option<Limits>,
+    /// Access control configuration: which nodes are admitted. Admits all nodes if not set.
+    ///
+    /// On unix, this is reloaded from the config file when the process receives `SIGHUP`.
+    /// Connected nodes which are no longer admitted are disconnected.
+    access: Option<AccessConfig>,
+    /// Mesh configuration: the other relay servers which clients of this server can reach.
+    /// Not part of a mesh if not set.
+    mesh: Option<MeshConfig>,
+    /// Restart configuration: how a shutdown on `SIGTERM` or `ctrl-c` is announced to the
+    /// clients. The server shuts down right after the announcement if not set.
+    restart: Option<Restart>,
     #[cfg(feature = "metrics")]
     /// Metrics serve address. If not set, metrics are not served.
     metrics_addr: Option<SocketAddr>,
@@ -234,6 +248,66 @@ struct Limits {
     accept_conn_limit: Option<f64>,
     /// Burst limit for accepting new connection. Unlimited if not set.
     accept_conn_burst: Option<usize>,
+    /// Rate limit in bytes per second for the packets each client sends. Unlimited if not set.
+    client_send_bytes_per_second: Option<usize>,
+    /// Burst limit in bytes for the packets each client sends. Defaults to the rate limit.
+    client_send_burst: Option<usize>,
+    /// Rate limit in bytes per second for the packets each client receives. Unlimited if not set.
+    client_recv_bytes_per_second: Option<usize>,
+    /// Burst limit in bytes for the packets each client receives. Defaults to the rate limit.
+    client_recv_burst: Option<usize>,
+    /// Maximum number of packets queued for each client.
+    max_queued_packets: Option<usize>,
+    /// Maximum number of packets queued for each client from a single sender.
+    max_queued_packets_per_sender: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Restart {
+    /// Seconds to keep serving the clients after announcing the restart. Defaults to 0.
+    grace_period_secs: Option<u64>,
+    /// Seconds after the grace period over which the clients spread their reconnects. Defaults
+    /// to 5.
+    reconnect_window_secs: Option<u64>,
+}
+
+impl Restart {
+    /// The [`RestartConfig`] of the relay server.
+    fn restart_config(&self) -> RestartConfig {
+        let default = RestartConfig::default();
+        RestartConfig {
+            grace_period: self
+                .grace_period_secs
+                .map_or(default.grace_period, Duration::from_secs),
+            reconnect_window: self
+                .reconnect_window_secs
+                .map_or(default.reconnect_window, Duration::from_secs),
+        }
+    }
+}
+
+impl Limits {
+    /// The [`ClientLimits`] applied to each client connection.
+    fn client_limits(&self) -> ClientLimits {
+        let rate = |bytes_per_second: Option<usize>, burst: Option<usize>| {
+            bytes_per_second.map(|bytes_per_second| RateLimit {
+                bytes_per_second,
+                burst_bytes: burst.unwrap_or(bytes_per_second),
+            })
+        };
+        let default = ClientLimits::default();
+        ClientLimits {
+            // the server receives what the client sends, and sends what the client receives
+            recv_rate: rate(self.client_send_bytes_per_second, self.client_send_burst),
+            send_rate: rate(self.client_recv_bytes_per_second, self.client_recv_burst),
+            max_queued_packets: self
+                .max_queued_packets
+                .unwrap_or(default.max_queued_packets),
+            max_queued_packets_per_sender: self
+                .max_queued_packets_per_sender
+                .unwrap_or(default.max_queued_packets_per_sender),
+        }
+    }
 }
 
 impl Default for Config {
@@ -247,6 +321,9 @@ impl Default for Config {
             enable_relay: true,
             tls: None,
             limits: None,
+            access: None,
+            mesh: None,
+            restart: None,
             #[cfg(feature = "metrics")]
             metrics_addr: None,
         }
@@ -349,7 +426,7 @@ async fn main() -> Result<()> {
     #[cfg(feature = "metrics")]
     let metrics_fut = init_metrics_collection(cfg.metrics_addr);
 
-    let r = run(cli.dev, cfg, None).await;
+    let r = run(cli.dev, cfg, cli.config_path, None).await;
 
     #[cfg(feature = "metrics")]
     if let Some(metrics_fut) = metrics_fut {
@@ -362,6 +439,7 @@ async fn main() -> Result<()> {
 async fn run(
     dev_mode: bool,
     cfg: Config,
+    config_path: Option<PathBuf>,
     addr_sender: Option<tokio::sync::oneshot::Sender<SocketAddr>>,
 ) -> Result<()> {
     let (addr, tls_config) = if dev_mode {
@@ -433,8 +511,16 @@ async fn run(
         (None, HeaderMap::new(), 0)
     };
 
+    let access = AccessControl::new(cfg.access.unwrap_or_default());
+    let client_limits = cfg
+        .limits
+        .as_ref()
+        .map(Limits::client_limits)
+        .unwrap_or_default();
     let mut builder = RelayServerBuilder::new(addr)
         .secret_key(secret_key.map(Into::into))
+        .access_control(access.clone())
+        .client_limits(client_limits)
         .headers(headers)
         .tls_config(tls_config.clone())
         .relay_override(Box::new(relay_disabled_handler))
@@ -444,6 +530,12 @@ async fn run(
         .request_handler(Method::GET, "/robots.txt", Box::new(robots_handler));
     // if tls is enabled, we need to serve this endpoint from a non-tls connection
     // which we check for below
+    if let Some(mesh) = cfg.mesh {
+        builder = builder.mesh(mesh);
+    }
+    if let Some(restart) = cfg.restart {
+        builder = builder.restart_config(restart.restart_config());
+    }
     if tls_config.is_none() {
         builder = builder.request_handler(
             Method::GET,
@@ -462,13 +554,21 @@ async fn run(
         None
     };
 
+    #[cfg(unix)]
+    let reload_task =
+        config_path.map(|path| tokio::task::spawn(reload_access_config(path, access)));
+    #[cfg(not(unix))]
+    let _ = (config_path, access);
+
     if let Some(addr_sender) = addr_sender {
         if let Err(e) = addr_sender.send(relay_server.addr()) {
             bail!("Unable to send the local SocketAddr, the Sender was dropped - {e:?}");
         }
     }
 
-    tokio::signal::ctrl_c().await?;
+    shutdown_signal().await?;
+    // Announce the restart and drain the clients first, STUN keeps being served meanwhile
+    relay_server.shutdown().await;
     // Shutdown all tasks
     if let Some(task) = stun_task {
         task.abort();
@@ -476,11 +576,56 @@ async fn run(
     if let Some(task) = captive_portal_task {
         task.abort()
     }
-    relay_server.shutdown().await;
+    #[cfg(unix)]
+    if let Some(task) = reload_task {
+        task.abort()
+    }
+
+    Ok(())
+}
 
+/// Waits for `ctrl-c`, or on unix also for `SIGTERM`.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = terminate.recv() => info!("received SIGTERM, shutting down"),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
     Ok(())
 }
 
+/// Reloads the access control configuration from the config file on every `SIGHUP`.
+#[cfg(unix)]
+async fn reload_access_config(path: PathBuf, access: AccessControl) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            warn!("unable to listen for SIGHUP, config reloading is disabled: {err:?}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match Config::read_from_file(&path).await {
+            Ok(cfg) => {
+                access.update(cfg.access.unwrap_or_default());
+                info!("reloaded access control configuration");
+            }
+            Err(err) => {
+                warn!("unable to reload config, keeping the current access control: {err:#}")
+            }
+        }
+    }
+}
+
 const NO_CONTENT_CHALLENGE_HEADER: &str = "X-Tailscale-Challenge";
 const NO_CONTENT_RESPONSE_HEADER: &str = "X-Tailscale-Response";
 
@@ -936,7 +1081,7 @@ mod tests {
         let relay_server_task = tokio::spawn(
             async move {
                 // dev mode will bind to IPv6::UNSPECIFIED, so setting it `false`
-                let res = run(false, cfg, Some(addr_send)).await;
+                let res = run(false, cfg, None, Some(addr_send)).await;
                 if let Err(e) = res {
                     eprintln!("error starting relay server {e}");
                 }
@@ -28,6 +28,7 @@ pub fn default_na_relay_node() -> RelayNode {
         url: url.into(),
         stun_only: false,
         stun_port: DEFAULT_RELAY_STUN_PORT,
+        auth_token: None,
     }
 }
 
@@ -41,5 +42,6 @@ pub fn default_eu_relay_node() -> RelayNode {
         url: url.into(),
         stun_only: false,
         stun_port: DEFAULT_RELAY_STUN_PORT,
+        auth_token: None,
     }
 }
@@ -0,0 +1,232 @@
+//! Node discovery through DNS.
+//!
+//! [`DnsDiscovery`] resolves the addressing information of nodes from the signed TXT records
+//! described in [`crate::dns::node_info`], using the [`DnsResolver`](crate::dns::DnsResolver) of the
+//! [`MagicEndpoint`]. It can also publish the records of the local node to a server like
+//! [`crate::dns::server::Server`].
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::{future::FutureExt, stream::BoxStream, StreamExt};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    discovery::{Discovery, DiscoveryItem},
+    dns::node_info::{lookup_node_info, NodeInfo},
+    key::SecretKey,
+    util::AbortingJoinHandle,
+    AddrInfo, MagicEndpoint, NodeId,
+};
+
+/// The provenance of the [`DiscoveryItem`]s of [`DnsDiscovery`].
+pub const DNS_PROVENANCE: &str = "dns";
+
+/// Interval after which the record of the local node is published again, even if unchanged.
+///
+/// Servers may drop records that are not refreshed.
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+/// Delay before retrying a failed publish.
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Discovery of nodes through DNS TXT records below an origin domain.
+///
+/// Resolving a node queries the TXT record at `_iroh.<node-id>.<origin>` and verifies its
+/// signature. With [`Self::with_publisher`], the record of the local node is published to a
+/// server whenever the addresses of the node change.
+#[derive(Debug)]
+pub struct DnsDiscovery {
+    origin: String,
+    publisher: Option<Publisher>,
+}
+
+impl DnsDiscovery {
+    /// Create a [`DnsDiscovery`] which resolves nodes below the `origin` domain.
+    pub fn new(origin: impl Into<String>) -> Self {
+        Self {
+            origin: origin.into(),
+            publisher: None,
+        }
+    }
+
+    /// Publish the record of the local node with `secret_key` to `publish_url`.
+    ///
+    /// See [`crate::dns::server`] for the publishing protocol.
+    pub fn with_publisher(mut self, secret_key: SecretKey, publish_url: Url) -> Self {
+        self.publisher = Some(Publisher::new(secret_key, publish_url));
+        self
+    }
+}
+
+impl Discovery for DnsDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        if let Some(publisher) = &self.publisher {
+            publisher.update(info.clone());
+        }
+    }
+
+    fn resolve(
+        &self,
+        endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, Result<DiscoveryItem>>> {
+        let origin = self.origin.clone();
+        let fut = async move {
+            let resolver = endpoint.dns_resolver();
+            let info = lookup_node_info(resolver, node_id, &origin).await?;
+            debug!(node = %node_id.fmt_short(), ?info, "resolved node info");
+            Ok(DiscoveryItem {
+                provenance: DNS_PROVENANCE,
+                last_updated: Some(info.timestamp),
+                addr_info: info.into(),
+            })
+        };
+        Some(fut.into_stream().boxed())
+    }
+}
+
+/// Publishes the record of the local node from a background task.
+#[derive(Debug)]
+struct Publisher {
+    secret_key: SecretKey,
+    url: Url,
+    info: watch::Sender<Option<AddrInfo>>,
+    task: parking_lot::Mutex<Option<AbortingJoinHandle<()>>>,
+}
+
+impl Publisher {
+    fn new(secret_key: SecretKey, url: Url) -> Self {
+        Self {
+            secret_key,
+            url,
+            info: watch::channel(None).0,
+            task: Default::default(),
+        }
+    }
+
+    /// Set the latest addressing information, and start the publish task if needed.
+    ///
+    /// Must be called from within a tokio runtime.
+    fn update(&self, info: AddrInfo) {
+        self.info.send_replace(Some(info));
+        let mut task = self.task.lock();
+        if task.is_none() {
+            let fut = publish_loop(
+                self.secret_key.clone(),
+                self.url.clone(),
+                self.info.subscribe(),
+            );
+            *task = Some(tokio::task::spawn(fut).into());
+        }
+    }
+}
+
+async fn publish_loop(
+    secret_key: SecretKey,
+    url: Url,
+    mut info: watch::Receiver<Option<AddrInfo>>,
+) {
+    let client = reqwest::Client::new();
+    loop {
+        let current = info.borrow_and_update().clone();
+        let delay = match current {
+            None => REPUBLISH_INTERVAL,
+            Some(addr_info) => match publish(&client, &secret_key, &url, addr_info).await {
+                Ok(()) => REPUBLISH_INTERVAL,
+                Err(err) => {
+                    warn!(%url, "failed to publish node info: {err:#}");
+                    PUBLISH_RETRY_DELAY
+                }
+            },
+        };
+        tokio::select! {
+            res = info.changed() => {
+                if res.is_err() {
+                    break;
+                }
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+    }
+}
+
+async fn publish(
+    client: &reqwest::Client,
+    secret_key: &SecretKey,
+    url: &Url,
+    addr_info: AddrInfo,
+) -> Result<()> {
+    let signed = NodeInfo::new(secret_key.public(), addr_info).sign(secret_key)?;
+    let body = postcard::to_stdvec(&signed)?;
+    client
+        .put(url.clone())
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()
+        .context("server rejected node info")?;
+    debug!(%url, "published node info");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+
+    use crate::{
+        dns::server::{Server, ServerConfig},
+        relay::RelayMode,
+        NodeAddr,
+    };
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+    const ORIGIN: &str = "dns.iroh.test";
+
+    async fn new_endpoint(server: &Server) -> Result<MagicEndpoint> {
+        let secret_key = SecretKey::generate();
+        let discovery =
+            DnsDiscovery::new(ORIGIN).with_publisher(secret_key.clone(), server.publish_url());
+        MagicEndpoint::builder()
+            .secret_key(secret_key)
+            .discovery(Box::new(discovery))
+            .dns_resolver(server.resolver())
+            .relay_mode(RelayMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind(0)
+            .await
+    }
+
+    /// Connect to a node by its node id only, with the addresses resolved from a local server.
+    #[tokio::test]
+    async fn dns_discovery_connect() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let server = Server::spawn(ServerConfig::local(ORIGIN)).await?;
+        let ep1 = new_endpoint(&server).await?;
+        let ep2 = new_endpoint(&server).await?;
+
+        // wait until the record of ep1 is published
+        let resolver = server.resolver();
+        let mut attempts = 0;
+        let info = loop {
+            match lookup_node_info(&resolver, ep1.node_id(), ORIGIN).await {
+                Ok(info) => break info,
+                Err(_) if attempts < 50 => {
+                    attempts += 1;
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(err) => bail!("record was not published: {err:#}"),
+            }
+        };
+        assert_eq!(info.node_id, ep1.node_id());
+        assert!(!info.direct_addresses.is_empty());
+
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        server.shutdown().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,662 @@
+//! Node discovery on the local network through multicast DNS.
+//!
+//! [`MdnsDiscovery`] announces the direct addresses of the local node with DNS-SD records of
+//! the service `_iroh._udp.local`, and resolves other nodes by querying the multicast group of
+//! mDNS. Every node is a service instance named after the base32 encoded [`NodeId`], with a TXT
+//! record that contains one `addr=<socket address>` string per direct address.
+//!
+//! This works without any relay server or internet access. Records received through mDNS are
+//! not authenticated: any host on the local network can announce addresses for any node id.
+//! The connection to the node is still authenticated by its key.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use futures::{stream::BoxStream, StreamExt};
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, Query},
+    rr::{
+        rdata::{PTR, TXT},
+        Name, RData, Record, RecordType,
+    },
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use iroh_base::base32;
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc, watch},
+};
+use tracing::{debug, error_span, trace, warn, Instrument};
+
+use crate::{
+    discovery::{Discovery, DiscoveryItem},
+    net::ip,
+    util::AbortingJoinHandle,
+    AddrInfo, MagicEndpoint, NodeId,
+};
+
+/// The provenance of the [`DiscoveryItem`]s of [`MdnsDiscovery`].
+pub const MDNS_PROVENANCE: &str = "mdns";
+
+/// The DNS-SD service name under which nodes are announced.
+pub const SERVICE_NAME: &str = "_iroh._udp.local.";
+
+/// The IPv4 multicast group and port of mDNS.
+const MDNS_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+/// TTL of announced records, in seconds.
+const TTL: u32 = 120;
+
+/// Interval in which the local node is announced, even if its addresses did not change.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximum size of an mDNS message.
+const MAX_MESSAGE_SIZE: usize = 9000;
+
+const ATTR_ADDR: &str = "addr";
+
+/// Discovery of nodes on the local network through mDNS.
+///
+/// The discovery service runs in a background task, which is stopped when the
+/// [`MdnsDiscovery`] is dropped.
+#[derive(Debug)]
+pub struct MdnsDiscovery {
+    addr_info: watch::Sender<Option<AddrInfo>>,
+    resolve_tx: mpsc::Sender<(NodeId, mpsc::Sender<Result<DiscoveryItem>>)>,
+    _task: AbortingJoinHandle<()>,
+}
+
+impl MdnsDiscovery {
+    /// Create an [`MdnsDiscovery`] for the local node `node_id`.
+    ///
+    /// This binds the mDNS port and joins the mDNS multicast group on all interfaces which are
+    /// up. Queries and announcements are sent out on the default multicast interface only. Must
+    /// be called from within a tokio runtime.
+    pub fn new(node_id: NodeId) -> Result<Self> {
+        let socket = bind_multicast().context("failed to bind mDNS socket")?;
+        Self::with_socket(node_id, socket, MDNS_ADDR.into())
+    }
+
+    /// Create an [`MdnsDiscovery`] which sends its messages to `group` through `socket`.
+    ///
+    /// `socket` must receive the messages other nodes send to `group`.
+    pub(crate) fn with_socket(
+        node_id: NodeId,
+        socket: UdpSocket,
+        group: SocketAddr,
+    ) -> Result<Self> {
+        let (addr_info, addr_info_rx) = watch::channel(None);
+        let (resolve_tx, resolve_rx) = mpsc::channel(64);
+        let actor = Actor::new(node_id, socket, group, addr_info_rx, resolve_rx)?;
+        let task = tokio::task::spawn(
+            actor
+                .run()
+                .instrument(error_span!("mdns", me = %node_id.fmt_short())),
+        );
+        Ok(Self {
+            addr_info,
+            resolve_tx,
+            _task: task.into(),
+        })
+    }
+}
+
+impl Discovery for MdnsDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        self.addr_info.send_replace(Some(info.clone()));
+    }
+
+    fn resolve(
+        &self,
+        _endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, Result<DiscoveryItem>>> {
+        let (tx, rx) = mpsc::channel(8);
+        self.resolve_tx.try_send((node_id, tx)).ok()?;
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        });
+        Some(stream.boxed())
+    }
+}
+
+/// A node discovered through mDNS.
+#[derive(Debug)]
+struct DiscoveredNode {
+    direct_addresses: BTreeSet<SocketAddr>,
+    /// Microseconds since the unix epoch.
+    last_updated: u64,
+    expires: Instant,
+}
+
+impl DiscoveredNode {
+    fn to_item(&self) -> DiscoveryItem {
+        DiscoveryItem {
+            provenance: MDNS_PROVENANCE,
+            last_updated: Some(self.last_updated),
+            addr_info: AddrInfo {
+                relay_url: None,
+                direct_addresses: self.direct_addresses.clone(),
+            },
+        }
+    }
+}
+
+type Subscriber = mpsc::Sender<Result<DiscoveryItem>>;
+
+#[derive(Debug)]
+struct Actor {
+    node_id: NodeId,
+    instance_name: Name,
+    service_name: Name,
+    socket: UdpSocket,
+    /// Where messages are sent to, the mDNS multicast group outside of tests.
+    group: SocketAddr,
+    addr_info: watch::Receiver<Option<AddrInfo>>,
+    resolve_rx: mpsc::Receiver<(NodeId, Subscriber)>,
+    nodes: HashMap<NodeId, DiscoveredNode>,
+    subscribers: HashMap<NodeId, Vec<Subscriber>>,
+}
+
+impl Actor {
+    fn new(
+        node_id: NodeId,
+        socket: UdpSocket,
+        group: SocketAddr,
+        addr_info: watch::Receiver<Option<AddrInfo>>,
+        resolve_rx: mpsc::Receiver<(NodeId, Subscriber)>,
+    ) -> Result<Self> {
+        Ok(Self {
+            node_id,
+            instance_name: instance_name(&node_id)?,
+            service_name: Name::from_ascii(SERVICE_NAME)?,
+            socket,
+            group,
+            addr_info,
+            resolve_rx,
+            nodes: Default::default(),
+            subscribers: Default::default(),
+        })
+    }
+
+    async fn run(mut self) {
+        let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+        let mut announce_interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        loop {
+            tokio::select! {
+                res = self.addr_info.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                    self.announce().await;
+                }
+                msg = self.resolve_rx.recv() => {
+                    let Some((node_id, subscriber)) = msg else {
+                        break;
+                    };
+                    self.handle_resolve(node_id, subscriber).await;
+                }
+                res = self.socket.recv_from(&mut buf) => match res {
+                    Ok((len, from)) => {
+                        if let Err(err) = self.handle_packet(&buf[..len], from).await {
+                            trace!(%from, "ignoring mDNS packet: {err:#}");
+                        }
+                    }
+                    Err(err) => warn!("failed to receive mDNS packet: {err:?}"),
+                },
+                _ = announce_interval.tick() => {
+                    self.announce().await;
+                    self.prune();
+                }
+            }
+        }
+        debug!("mdns discovery stopped");
+    }
+
+    async fn handle_resolve(&mut self, node_id: NodeId, subscriber: Subscriber) {
+        if let Some(node) = self.nodes.get(&node_id) {
+            if node.expires > Instant::now() {
+                subscriber.try_send(Ok(node.to_item())).ok();
+            }
+        }
+        self.subscribers
+            .entry(node_id)
+            .or_default()
+            .push(subscriber);
+
+        let mut message = new_message(MessageType::Query);
+        match instance_name(&node_id) {
+            Ok(name) => {
+                message.add_query(Query::query(name, RecordType::TXT));
+                self.send(message).await;
+            }
+            Err(err) => warn!("invalid instance name: {err:#}"),
+        }
+    }
+
+    async fn handle_packet(&mut self, packet: &[u8], from: SocketAddr) -> Result<()> {
+        let message = Message::from_bytes(packet)?;
+        match message.message_type() {
+            MessageType::Query => self.handle_query(&message).await,
+            MessageType::Response => {
+                for record in message.answers().iter().chain(message.additionals()) {
+                    self.handle_record(record, from);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer queries for the service or for the local node.
+    async fn handle_query(&mut self, query: &Message) {
+        let Some(txt) = self.txt_record(TTL) else {
+            return;
+        };
+        let mut response = new_message(MessageType::Response);
+        for query in query.queries() {
+            let any = query.query_type() == RecordType::ANY;
+            if names_eq(query.name(), &self.service_name)
+                && (any || query.query_type() == RecordType::PTR)
+            {
+                response.add_answer(self.ptr_record(TTL));
+                response.add_additional(txt.clone());
+            } else if names_eq(query.name(), &self.instance_name)
+                && (any || query.query_type() == RecordType::TXT)
+            {
+                response.add_answer(txt.clone());
+            }
+        }
+        if !response.answers().is_empty() {
+            self.send(response).await;
+        }
+    }
+
+    fn handle_record(&mut self, record: &Record, from: SocketAddr) {
+        let Some(RData::TXT(txt)) = record.data() else {
+            return;
+        };
+        let Some(node_id) = self.parse_instance_name(record.name()) else {
+            return;
+        };
+        if node_id == self.node_id {
+            return;
+        }
+        if record.ttl() == 0 {
+            debug!(node = %node_id.fmt_short(), "node left");
+            self.nodes.remove(&node_id);
+            return;
+        }
+        let direct_addresses = parse_txt(txt);
+        if direct_addresses.is_empty() {
+            return;
+        }
+        trace!(node = %node_id.fmt_short(), %from, ?direct_addresses, "discovered node");
+        let node = DiscoveredNode {
+            direct_addresses,
+            last_updated: now_micros(),
+            expires: Instant::now() + Duration::from_secs(record.ttl() as u64),
+        };
+        if let Some(subscribers) = self.subscribers.get_mut(&node_id) {
+            let item = node.to_item();
+            subscribers.retain(|subscriber| match subscriber.try_send(Ok(item.clone())) {
+                Ok(()) => true,
+                Err(mpsc::error::TrySendError::Full(_)) => true,
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            });
+        }
+        self.nodes.insert(node_id, node);
+    }
+
+    /// Announce the local node with an unsolicited response.
+    async fn announce(&mut self) {
+        let Some(txt) = self.txt_record(TTL) else {
+            return;
+        };
+        let mut message = new_message(MessageType::Response);
+        message.add_answer(self.ptr_record(TTL));
+        message.add_answer(txt);
+        self.send(message).await;
+    }
+
+    /// Remove expired nodes and subscribers which were dropped.
+    fn prune(&mut self) {
+        let now = Instant::now();
+        self.nodes.retain(|_, node| node.expires > now);
+        self.subscribers.retain(|_, subscribers| {
+            subscribers.retain(|subscriber| !subscriber.is_closed());
+            !subscribers.is_empty()
+        });
+    }
+
+    async fn send(&self, message: Message) {
+        let bytes = match message.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!("failed to encode mDNS message: {err:?}");
+                return;
+            }
+        };
+        if let Err(err) = self.socket.send_to(&bytes, self.group).await {
+            debug!("failed to send mDNS message: {err:?}");
+        }
+    }
+
+    fn ptr_record(&self, ttl: u32) -> Record {
+        let rdata = RData::PTR(PTR(self.instance_name.clone()));
+        Record::from_rdata(self.service_name.clone(), ttl, rdata)
+    }
+
+    /// The TXT record of the local node, if any direct addresses are known.
+    fn txt_record(&self, ttl: u32) -> Option<Record> {
+        let addr_info = self.addr_info.borrow();
+        let addr_info = addr_info.as_ref()?;
+        if addr_info.direct_addresses.is_empty() {
+            return None;
+        }
+        let strings = addr_info
+            .direct_addresses
+            .iter()
+            .map(|addr| format!("{ATTR_ADDR}={addr}"))
+            .collect();
+        let rdata = RData::TXT(TXT::new(strings));
+        Some(Record::from_rdata(self.instance_name.clone(), ttl, rdata))
+    }
+
+    fn parse_instance_name(&self, name: &Name) -> Option<NodeId> {
+        if name.num_labels() != self.service_name.num_labels() + 1
+            || !names_eq(&name.base_name(), &self.service_name)
+        {
+            return None;
+        }
+        let label = name.iter().next()?;
+        let label = std::str::from_utf8(label).ok()?.to_ascii_lowercase();
+        let bytes = base32::parse_array(&label).ok()?;
+        NodeId::from_bytes(&bytes).ok()
+    }
+}
+
+/// The direct addresses in the strings of a TXT record, ignoring any other attributes.
+fn parse_txt(txt: &TXT) -> BTreeSet<SocketAddr> {
+    txt.txt_data()
+        .iter()
+        .filter_map(|data| {
+            let data = std::str::from_utf8(data).ok()?;
+            match data.split_once('=')? {
+                (ATTR_ADDR, value) => value.parse().ok(),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn instance_name(node_id: &NodeId) -> Result<Name> {
+    let name = format!("{}.{SERVICE_NAME}", base32::fmt(node_id.as_bytes()));
+    Ok(Name::from_ascii(name)?)
+}
+
+fn new_message(message_type: MessageType) -> Message {
+    let mut message = Message::new();
+    message
+        .set_id(0)
+        .set_message_type(message_type)
+        .set_op_code(OpCode::Query)
+        .set_authoritative(message_type == MessageType::Response);
+    message
+}
+
+fn names_eq(a: &Name, b: &Name) -> bool {
+    a.to_ascii()
+        .trim_end_matches('.')
+        .eq_ignore_ascii_case(b.to_ascii().trim_end_matches('.'))
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+/// Bind the mDNS port, shared with other mDNS responders on this host, and join the mDNS
+/// multicast group on every interface which is up.
+fn bind_multicast() -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    let bind_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_ADDR.port());
+    socket.bind(&bind_addr.into())?;
+    let mut joined = false;
+    for iface in default_net::interface::get_interfaces() {
+        if !ip::is_up(&iface) {
+            continue;
+        }
+        // the group is joined once per interface, with any of its addresses
+        let Some(addr) = iface.ipv4.first() else {
+            continue;
+        };
+        match socket.join_multicast_v4(MDNS_ADDR.ip(), &addr.addr) {
+            Ok(()) => joined = true,
+            Err(err) => debug!(iface = %iface.name, "failed to join mDNS group: {err:?}"),
+        }
+    }
+    if !joined {
+        // let the system pick the interface
+        socket.join_multicast_v4(MDNS_ADDR.ip(), &Ipv4Addr::UNSPECIFIED)?;
+    }
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{key::SecretKey, relay::RelayMode, NodeAddr};
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    async fn new_endpoint(
+        secret_key: SecretKey,
+        discovery: MdnsDiscovery,
+    ) -> Result<MagicEndpoint> {
+        MagicEndpoint::builder()
+            .secret_key(secret_key)
+            .discovery(Box::new(discovery))
+            .relay_mode(RelayMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind(0)
+            .await
+    }
+
+    /// Emulates a multicast group on loopback.
+    ///
+    /// Returns the address of the group and one socket per member. Every packet sent to the
+    /// group is forwarded to all other members.
+    async fn loopback_group(
+        members: usize,
+    ) -> Result<(SocketAddr, Vec<UdpSocket>, AbortingJoinHandle<()>)> {
+        let group = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let group_addr = group.local_addr()?;
+        let mut sockets = Vec::new();
+        for _ in 0..members {
+            sockets.push(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?);
+        }
+        let addrs = sockets
+            .iter()
+            .map(|socket| socket.local_addr())
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let task = tokio::task::spawn(async move {
+            let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+            while let Ok((len, from)) = group.recv_from(&mut buf).await {
+                for addr in addrs.iter().filter(|addr| **addr != from) {
+                    group.send_to(&buf[..len], addr).await.ok();
+                }
+            }
+        });
+        Ok((group_addr, sockets, task.into()))
+    }
+
+    async fn new_actor(node_id: NodeId) -> Result<(Actor, watch::Sender<Option<AddrInfo>>)> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let group = socket.local_addr()?;
+        let (addr_info, addr_info_rx) = watch::channel(None);
+        let (_resolve_tx, resolve_rx) = mpsc::channel(1);
+        let actor = Actor::new(node_id, socket, group, addr_info_rx, resolve_rx)?;
+        Ok((actor, addr_info))
+    }
+
+    #[tokio::test]
+    async fn mdns_parse_instance_name() -> Result<()> {
+        let node_id = SecretKey::generate().public();
+        let (actor, _addr_info) = new_actor(SecretKey::generate().public()).await?;
+
+        let name = instance_name(&node_id)?;
+        assert_eq!(actor.parse_instance_name(&name), Some(node_id));
+        // names are case insensitive
+        let upper = Name::from_ascii(name.to_ascii().to_ascii_uppercase())?;
+        assert_eq!(actor.parse_instance_name(&upper), Some(node_id));
+
+        let label = base32::fmt(node_id.as_bytes());
+        for name in [
+            format!("{label}._other._udp.local."),
+            format!("sub.{label}.{SERVICE_NAME}"),
+            SERVICE_NAME.to_string(),
+            format!("notbase32.{SERVICE_NAME}"),
+            format!("{}.{SERVICE_NAME}", &label[1..]),
+        ] {
+            assert_eq!(
+                actor.parse_instance_name(&Name::from_ascii(&name)?),
+                None,
+                "{name}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mdns_parse_txt() {
+        let txt = TXT::new(vec![
+            "addr=1.2.3.4:5".to_string(),
+            "addr=[::1]:6".to_string(),
+            "addr=invalid".to_string(),
+            "other=1.2.3.4:7".to_string(),
+            "addr".to_string(),
+        ]);
+        let expected: BTreeSet<SocketAddr> =
+            ["1.2.3.4:5".parse().unwrap(), "[::1]:6".parse().unwrap()]
+                .into_iter()
+                .collect();
+        assert_eq!(parse_txt(&txt), expected);
+    }
+
+    #[tokio::test]
+    async fn mdns_handle_record() -> Result<()> {
+        let (mut a, a_addr_info) = new_actor(SecretKey::generate().public()).await?;
+        let (mut b, _b_addr_info) = new_actor(SecretKey::generate().public()).await?;
+        let from = a.socket.local_addr()?;
+
+        // without direct addresses, there is nothing to announce
+        assert!(a.txt_record(TTL).is_none());
+        let direct_addresses: BTreeSet<SocketAddr> = [
+            "192.168.1.2:1234".parse().unwrap(),
+            "[fe80::1]:1234".parse().unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        a_addr_info.send_replace(Some(AddrInfo {
+            relay_url: None,
+            direct_addresses: direct_addresses.clone(),
+        }));
+        let txt = a.txt_record(TTL).unwrap();
+
+        // records of the local node are ignored
+        a.handle_record(&txt, from);
+        assert!(a.nodes.is_empty());
+
+        // records of other nodes are stored and sent to subscribers
+        let (subscriber, mut items) = mpsc::channel(1);
+        b.subscribers.insert(a.node_id, vec![subscriber]);
+        b.handle_record(&txt, from);
+        assert_eq!(b.nodes[&a.node_id].direct_addresses, direct_addresses);
+        let item = items.try_recv()??;
+        assert_eq!(item.provenance, MDNS_PROVENANCE);
+        assert_eq!(item.addr_info.direct_addresses, direct_addresses);
+
+        // records which are not TXT records are ignored
+        b.handle_record(&a.ptr_record(TTL), from);
+        assert_eq!(b.nodes.len(), 1);
+
+        // a record with a TTL of zero removes the node
+        b.handle_record(&a.txt_record(0).unwrap(), from);
+        assert!(b.nodes.is_empty());
+        Ok(())
+    }
+
+    /// Resolve and connect to a node on the same host through a multicast group emulated on
+    /// loopback, without relay or internet.
+    #[tokio::test]
+    async fn mdns_discovery_loopback() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let (group, sockets, _group_task) = loopback_group(2).await?;
+        let mut endpoints = Vec::new();
+        for socket in sockets {
+            let secret_key = SecretKey::generate();
+            let discovery = MdnsDiscovery::with_socket(secret_key.public(), socket, group)?;
+            endpoints.push(new_endpoint(secret_key, discovery).await?);
+        }
+        let (ep1, ep2) = (&endpoints[0], &endpoints[1]);
+
+        let mut stream = ep2
+            .discovery()
+            .unwrap()
+            .resolve(ep2.clone(), ep1.node_id())
+            .unwrap();
+        let item = tokio::time::timeout(Duration::from_secs(10), stream.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(item.provenance, MDNS_PROVENANCE);
+        assert!(!item.addr_info.direct_addresses.is_empty());
+        drop(stream);
+
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        Ok(())
+    }
+
+    /// Resolve and connect to a node on the same host through mDNS.
+    #[tokio::test]
+    #[ignore = "needs multicast on the local network"]
+    async fn mdns_discovery_connect() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let secret_key1 = SecretKey::generate();
+        let discovery1 = MdnsDiscovery::new(secret_key1.public())?;
+        let ep1 = new_endpoint(secret_key1, discovery1).await?;
+        let secret_key2 = SecretKey::generate();
+        let discovery2 = MdnsDiscovery::new(secret_key2.public())?;
+        let ep2 = new_endpoint(secret_key2, discovery2).await?;
+
+        let mut stream = ep2
+            .discovery()
+            .unwrap()
+            .resolve(ep2.clone(), ep1.node_id())
+            .unwrap();
+        let item = tokio::time::timeout(Duration::from_secs(10), stream.next())
+            .await?
+            .unwrap()?;
+        assert_eq!(item.provenance, MDNS_PROVENANCE);
+        assert!(!item.addr_info.direct_addresses.is_empty());
+        drop(stream);
+
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        Ok(())
+    }
+}
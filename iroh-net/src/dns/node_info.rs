@@ -0,0 +1,270 @@
+//! Signed node address records published as DNS TXT records.
+//!
+//! The addressing information of a node is published at the domain
+//! `_iroh.<node-id>.<origin>`, where `<node-id>` is the base32 encoded [`NodeId`] and
+//! `<origin>` is the domain of the DNS server that serves the records. Each attribute is a
+//! separate TXT string of the form `key=value`:
+//!
+//! * `relay=<url>`: the home relay of the node, at most once
+//! * `addr=<socket address>`: a direct address of the node, any number of times
+//! * `ts=<timestamp>`: the time the record was created, in microseconds since the unix epoch
+//! * `sig=<signature>`: the base32 encoded signature of the node over all other attributes
+//!
+//! Records are signed by the node they describe, so resolvers do not need to trust the DNS
+//! server.
+
+use std::{
+    collections::BTreeSet,
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use iroh_base::base32;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dns::DnsResolver,
+    key::{SecretKey, Signature},
+    relay::RelayUrl,
+    AddrInfo, NodeId,
+};
+
+/// The label prepended to the node id in the domain of a node record.
+pub const IROH_TXT_NAME: &str = "_iroh";
+
+/// Timeout for looking up a node record.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+const ATTR_RELAY: &str = "relay";
+const ATTR_ADDR: &str = "addr";
+const ATTR_TIMESTAMP: &str = "ts";
+const ATTR_SIGNATURE: &str = "sig";
+
+/// The addressing information of a node, as published in DNS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    /// The node this information is about.
+    pub node_id: NodeId,
+    /// The home relay of the node.
+    pub relay_url: Option<RelayUrl>,
+    /// The direct addresses of the node.
+    pub direct_addresses: BTreeSet<SocketAddr>,
+    /// The time this information was created, in microseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl NodeInfo {
+    /// Create a [`NodeInfo`] for the current time.
+    pub fn new(node_id: NodeId, addr_info: AddrInfo) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+        Self {
+            node_id,
+            relay_url: addr_info.relay_url,
+            direct_addresses: addr_info.direct_addresses,
+            timestamp,
+        }
+    }
+
+    /// Sign this [`NodeInfo`] with the secret key of the node.
+    pub fn sign(self, secret_key: &SecretKey) -> Result<SignedNodeInfo> {
+        ensure!(
+            secret_key.public() == self.node_id,
+            "secret key does not match the node id"
+        );
+        let signature = secret_key.sign(&self.signed_bytes());
+        Ok(SignedNodeInfo {
+            info: self,
+            signature,
+        })
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).expect("serialization to vec is infallible")
+    }
+}
+
+impl From<NodeInfo> for AddrInfo {
+    fn from(info: NodeInfo) -> Self {
+        AddrInfo {
+            relay_url: info.relay_url,
+            direct_addresses: info.direct_addresses,
+        }
+    }
+}
+
+/// A [`NodeInfo`] with a signature of the node it describes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedNodeInfo {
+    info: NodeInfo,
+    signature: Signature,
+}
+
+impl SignedNodeInfo {
+    /// Get the [`NodeInfo`].
+    ///
+    /// The signature is verified when the [`SignedNodeInfo`] is created from untrusted input
+    /// with [`Self::from_txt_strings`]. Use [`Self::verify`] for values obtained otherwise.
+    pub fn info(&self) -> &NodeInfo {
+        &self.info
+    }
+
+    /// Verify the signature.
+    pub fn verify(&self) -> Result<()> {
+        self.info
+            .node_id
+            .verify(&self.info.signed_bytes(), &self.signature)
+            .context("invalid node info signature")
+    }
+
+    /// Encode as the strings of a TXT record.
+    pub fn to_txt_strings(&self) -> Vec<String> {
+        let mut strings = vec![];
+        if let Some(relay_url) = &self.info.relay_url {
+            strings.push(format!("{ATTR_RELAY}={relay_url}"));
+        }
+        for addr in &self.info.direct_addresses {
+            strings.push(format!("{ATTR_ADDR}={addr}"));
+        }
+        strings.push(format!("{ATTR_TIMESTAMP}={}", self.info.timestamp));
+        let signature = base32::fmt(self.signature.to_bytes());
+        strings.push(format!("{ATTR_SIGNATURE}={signature}"));
+        strings
+    }
+
+    /// Decode from the strings of a TXT record and verify the signature.
+    ///
+    /// Unknown attributes are ignored.
+    pub fn from_txt_strings<S: AsRef<str>>(
+        node_id: NodeId,
+        strings: impl IntoIterator<Item = S>,
+    ) -> Result<Self> {
+        let mut relay_url = None;
+        let mut direct_addresses = BTreeSet::new();
+        let mut timestamp = None;
+        let mut signature = None;
+        for string in strings {
+            let Some((key, value)) = string.as_ref().split_once('=') else {
+                continue;
+            };
+            match key {
+                ATTR_RELAY => {
+                    ensure!(relay_url.is_none(), "duplicate relay attribute");
+                    relay_url = Some(value.parse().context("invalid relay url")?);
+                }
+                ATTR_ADDR => {
+                    direct_addresses.insert(value.parse().context("invalid direct address")?);
+                }
+                ATTR_TIMESTAMP => {
+                    timestamp = Some(value.parse().context("invalid timestamp")?);
+                }
+                ATTR_SIGNATURE => {
+                    let bytes = base32::parse_array(value).context("invalid signature")?;
+                    signature = Some(Signature::from_bytes(&bytes));
+                }
+                _ => {}
+            }
+        }
+        let info = NodeInfo {
+            node_id,
+            relay_url,
+            direct_addresses,
+            timestamp: timestamp.ok_or_else(|| anyhow!("missing timestamp"))?,
+        };
+        let signature = signature.ok_or_else(|| anyhow!("missing signature"))?;
+        let signed = Self { info, signature };
+        signed.verify()?;
+        Ok(signed)
+    }
+}
+
+/// Get the domain at which the record of a node is published below `origin`.
+pub fn node_domain(node_id: &NodeId, origin: &str) -> String {
+    let origin = origin.trim_end_matches('.');
+    format!(
+        "{IROH_TXT_NAME}.{}.{origin}",
+        base32::fmt(node_id.as_bytes())
+    )
+}
+
+/// Parse the node id from a domain created with [`node_domain`].
+///
+/// Returns `None` if the domain is not the domain of a node record below `origin`.
+pub fn parse_node_domain(domain: &str, origin: &str) -> Option<NodeId> {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    let origin = origin.trim_end_matches('.').to_ascii_lowercase();
+    let rest = domain.strip_suffix(&origin)?.strip_suffix('.')?;
+    let node_id = rest.strip_prefix(IROH_TXT_NAME)?.strip_prefix('.')?;
+    let bytes = base32::parse_array(node_id).ok()?;
+    NodeId::from_bytes(&bytes).ok()
+}
+
+/// Look up the [`NodeInfo`] of a node below `origin`.
+///
+/// The signature of the record is verified.
+pub async fn lookup_node_info(
+    resolver: &DnsResolver,
+    node_id: NodeId,
+    origin: &str,
+) -> Result<NodeInfo> {
+    // query the fully qualified name, to not apply search domains
+    let name = format!("{}.", node_domain(&node_id, origin));
+    let lookup = tokio::time::timeout(LOOKUP_TIMEOUT, resolver.txt_lookup(name)).await??;
+    let strings = lookup
+        .iter()
+        .flat_map(|txt| txt.txt_data().iter())
+        .map(|data| String::from_utf8_lossy(data).into_owned());
+    let signed = SignedNodeInfo::from_txt_strings(node_id, strings)?;
+    if signed.info.direct_addresses.is_empty() && signed.info.relay_url.is_none() {
+        bail!("node info has no addresses");
+    }
+    Ok(signed.info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn txt_roundtrip() -> Result<()> {
+        let secret_key = SecretKey::generate();
+        let node_id = secret_key.public();
+        let addr_info = AddrInfo {
+            relay_url: Some("https://relay.example.com".parse()?),
+            direct_addresses: ["127.0.0.1:1234".parse()?, "[::1]:1234".parse()?].into(),
+        };
+        let signed = NodeInfo::new(node_id, addr_info).sign(&secret_key)?;
+        let strings = signed.to_txt_strings();
+        assert_eq!(SignedNodeInfo::from_txt_strings(node_id, &strings)?, signed);
+
+        // a modified record is rejected
+        let mut tampered = strings.clone();
+        tampered[1] = "addr=10.0.0.1:1234".to_string();
+        assert!(SignedNodeInfo::from_txt_strings(node_id, &tampered).is_err());
+
+        // a record of another node is rejected
+        let other = SecretKey::generate().public();
+        assert!(SignedNodeInfo::from_txt_strings(other, &strings).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn node_domain_roundtrip() {
+        let node_id = SecretKey::generate().public();
+        let domain = node_domain(&node_id, "dns.example.com.");
+        assert!(domain.starts_with("_iroh."));
+        assert_eq!(parse_node_domain(&domain, "dns.example.com"), Some(node_id));
+        assert_eq!(
+            parse_node_domain(&domain.to_ascii_uppercase(), "dns.example.com"),
+            Some(node_id)
+        );
+        assert_eq!(parse_node_domain(&domain, "example.com."), None);
+        assert_eq!(
+            parse_node_domain("_iroh.foo.dns.example.com", "dns.example.com"),
+            None
+        );
+    }
+}
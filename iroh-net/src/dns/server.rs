@@ -0,0 +1,290 @@
+//! A small DNS server for node records.
+//!
+//! Nodes publish their signed [`SignedNodeInfo`] to the server with an HTTP `PUT` request to
+//! `/publish`, with the postcard encoded record as body. The server verifies the signature and
+//! serves the latest record of each node as TXT record at its
+//! [`node_domain`](super::node_info::node_domain) over DNS on UDP.
+//!
+//! The server keeps all records in memory. It is meant for tests and small deployments.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, ResponseCode},
+    rr::{rdata::TXT, RData, Record, RecordType},
+    serialize::binary::{BinDecodable, BinEncodable},
+};
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    AsyncResolver,
+};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{body::Incoming, service::service_fn, Method, Request, Response, StatusCode};
+use parking_lot::RwLock;
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    task::JoinSet,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info_span, warn, Instrument};
+use url::Url;
+
+use super::{
+    node_info::{parse_node_domain, SignedNodeInfo},
+    DnsResolver,
+};
+use crate::NodeId;
+
+/// The path to which records are published.
+pub const PUBLISH_PATH: &str = "publish";
+
+/// Maximum size of a published record.
+const MAX_RECORD_SIZE: usize = 1024;
+
+/// Maximum size of a DNS message received over UDP.
+const MAX_DNS_MESSAGE_SIZE: usize = 4096;
+
+/// Configuration for a [`Server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// The domain below which node records are served.
+    pub origin: String,
+    /// The address to serve DNS on.
+    pub dns_bind_addr: SocketAddr,
+    /// The address to accept published records on.
+    pub http_bind_addr: SocketAddr,
+    /// The TTL of served records, in seconds.
+    pub ttl: u32,
+}
+
+impl ServerConfig {
+    /// A configuration for tests, which binds to random ports on localhost.
+    pub fn local(origin: impl Into<String>) -> Self {
+        Self {
+            origin: origin.into(),
+            dns_bind_addr: ([127, 0, 0, 1], 0).into(),
+            http_bind_addr: ([127, 0, 0, 1], 0).into(),
+            ttl: 30,
+        }
+    }
+}
+
+/// The records known to the server.
+type Store = Arc<RwLock<HashMap<NodeId, SignedNodeInfo>>>;
+
+/// A running DNS server for node records.
+///
+/// Created with [`Server::spawn`].
+#[derive(Debug)]
+pub struct Server {
+    dns_addr: SocketAddr,
+    http_addr: SocketAddr,
+    store: Store,
+    cancel: CancellationToken,
+    tasks: JoinSet<()>,
+}
+
+impl Server {
+    /// Bind the sockets and start serving.
+    pub async fn spawn(config: ServerConfig) -> Result<Self> {
+        let udp = UdpSocket::bind(config.dns_bind_addr)
+            .await
+            .context("failed to bind DNS socket")?;
+        let tcp = TcpListener::bind(config.http_bind_addr)
+            .await
+            .context("failed to bind HTTP socket")?;
+        let dns_addr = udp.local_addr()?;
+        let http_addr = tcp.local_addr()?;
+
+        let store = Store::default();
+        let cancel = CancellationToken::new();
+        let mut tasks = JoinSet::new();
+        let dns = DnsHandler {
+            origin: config.origin.trim_end_matches('.').to_string(),
+            ttl: config.ttl,
+            store: store.clone(),
+        };
+        tasks.spawn(serve_dns(udp, dns, cancel.clone()).instrument(info_span!("dns", %dns_addr)));
+        tasks.spawn(
+            serve_http(tcp, store.clone(), cancel.clone())
+                .instrument(info_span!("dns-publish", %http_addr)),
+        );
+        debug!(%dns_addr, %http_addr, origin = %config.origin, "node record server started");
+        Ok(Self {
+            dns_addr,
+            http_addr,
+            store,
+            cancel,
+            tasks,
+        })
+    }
+
+    /// The address on which DNS is served.
+    pub fn dns_addr(&self) -> SocketAddr {
+        self.dns_addr
+    }
+
+    /// The URL to which nodes publish their records.
+    pub fn publish_url(&self) -> Url {
+        let url = format!("http://{}/{PUBLISH_PATH}", self.http_addr);
+        url.parse().expect("valid url")
+    }
+
+    /// Create a [`DnsResolver`] which only queries this server.
+    pub fn resolver(&self) -> DnsResolver {
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig::new(self.dns_addr, Protocol::Udp));
+        AsyncResolver::tokio(config, ResolverOpts::default())
+    }
+
+    /// Get the record of a node, if one was published.
+    pub fn get(&self, node_id: &NodeId) -> Option<SignedNodeInfo> {
+        self.store.read().get(node_id).cloned()
+    }
+
+    /// Stop the server.
+    pub async fn shutdown(mut self) {
+        self.cancel.cancel();
+        while let Some(res) = self.tasks.join_next().await {
+            if let Err(err) = res {
+                warn!("node record server task failed: {err:?}");
+            }
+        }
+    }
+}
+
+/// Answers DNS queries from the store.
+#[derive(Debug, Clone)]
+struct DnsHandler {
+    origin: String,
+    ttl: u32,
+    store: Store,
+}
+
+impl DnsHandler {
+    fn handle(&self, packet: &[u8]) -> Result<Vec<u8>> {
+        let request = Message::from_bytes(packet)?;
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(request.op_code())
+            .set_recursion_desired(request.recursion_desired())
+            .set_authoritative(true)
+            .add_queries(request.queries().iter().cloned());
+
+        if request.message_type() != MessageType::Query || request.op_code() != OpCode::Query {
+            response.set_response_code(ResponseCode::NotImp);
+            return Ok(response.to_bytes()?);
+        }
+        for query in request.queries() {
+            let name = query.name().to_ascii();
+            let Some(node_id) = parse_node_domain(&name, &self.origin) else {
+                response.set_response_code(ResponseCode::NXDomain);
+                continue;
+            };
+            let Some(signed) = self.store.read().get(&node_id).cloned() else {
+                response.set_response_code(ResponseCode::NXDomain);
+                continue;
+            };
+            if query.query_type() != RecordType::TXT {
+                continue;
+            }
+            let rdata = RData::TXT(TXT::new(signed.to_txt_strings()));
+            response.add_answer(Record::from_rdata(query.name().clone(), self.ttl, rdata));
+        }
+        Ok(response.to_bytes()?)
+    }
+}
+
+async fn serve_dns(socket: UdpSocket, handler: DnsHandler, cancel: CancellationToken) {
+    let mut buf = vec![0u8; MAX_DNS_MESSAGE_SIZE];
+    loop {
+        let (len, from) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            res = socket.recv_from(&mut buf) => match res {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("failed to receive DNS query: {err:?}");
+                    continue;
+                }
+            }
+        };
+        match handler.handle(&buf[..len]) {
+            Ok(response) => {
+                if let Err(err) = socket.send_to(&response, from).await {
+                    debug!(%from, "failed to send DNS response: {err:?}");
+                }
+            }
+            Err(err) => debug!(%from, "invalid DNS query: {err:#}"),
+        }
+    }
+}
+
+async fn serve_http(listener: TcpListener, store: Store, cancel: CancellationToken) {
+    let mut conns = JoinSet::new();
+    loop {
+        let (stream, from) = tokio::select! {
+            _ = cancel.cancelled() => break,
+            res = listener.accept() => match res {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("failed to accept HTTP connection: {err:?}");
+                    continue;
+                }
+            }
+        };
+        let store = store.clone();
+        let service = service_fn(move |req| handle_publish(req, store.clone()));
+        conns.spawn(async move {
+            let conn = hyper::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(stream), service);
+            if let Err(err) = conn.await {
+                debug!(%from, "HTTP connection failed: {err:?}");
+            }
+        });
+    }
+}
+
+async fn handle_publish(
+    req: Request<Incoming>,
+    store: Store,
+) -> Result<Response<Full<Bytes>>, http::Error> {
+    if req.method() != Method::PUT || req.uri().path() != format!("/{PUBLISH_PATH}") {
+        return response(StatusCode::NOT_FOUND, "not found");
+    }
+    let body = match Limited::new(req.into_body(), MAX_RECORD_SIZE)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(_) => return response(StatusCode::PAYLOAD_TOO_LARGE, "record too large"),
+    };
+    let signed: SignedNodeInfo = match postcard::from_bytes(&body) {
+        Ok(signed) => signed,
+        Err(_) => return response(StatusCode::BAD_REQUEST, "invalid record"),
+    };
+    if signed.verify().is_err() {
+        return response(StatusCode::BAD_REQUEST, "invalid signature");
+    }
+    let node_id = signed.info().node_id;
+    let mut store = store.write();
+    match store.get(&node_id) {
+        Some(current) if current.info().timestamp >= signed.info().timestamp => {
+            response(StatusCode::CONFLICT, "record is outdated")
+        }
+        _ => {
+            debug!(node = %node_id.fmt_short(), "record published");
+            store.insert(node_id, signed);
+            response(StatusCode::OK, "ok")
+        }
+    }
+}
+
+fn response(status: StatusCode, body: &'static str) -> Result<Response<Full<Bytes>>, http::Error> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from_static(body.as_bytes())))
+}
@@ -16,7 +16,7 @@ use crate::{
     dns::{default_resolver, DnsResolver},
     key::{PublicKey, SecretKey},
     magicsock::{self, MagicSock},
-    relay::{RelayMap, RelayMode, RelayUrl},
+    relay::{http::Protocol, RelayMap, RelayMode, RelayUrl},
     tls, NodeId,
 };
 
@@ -41,6 +41,7 @@ pub struct MagicEndpointBuilder {
     /// Path for known peers. See [`MagicEndpointBuilder::peers_data_path`].
     peers_path: Option<PathBuf>,
     dns_resolver: Option<DnsResolver>,
+    relay_protocol: Protocol,
 }
 
 impl Default for MagicEndpointBuilder {
@@ -55,6 +56,7 @@ impl Default for MagicEndpointBuilder {
             discovery: Default::default(),
             peers_path: None,
             dns_resolver: None,
+            relay_protocol: Protocol::RelayWithWebSocketFallback,
         }
     }
 }
@@ -156,6 +158,16 @@ impl MagicEndpointBuilder {
         self
     }
 
+    /// Set the protocol used to connect to relay servers.
+    ///
+    /// By default the relay protocol is tried first, falling back to WebSocket if the upgrade
+    /// fails. This way connections to relay servers also work through proxies which only pass
+    /// WebSocket upgrades.
+    pub fn relay_protocol(mut self, protocol: Protocol) -> Self {
+        self.relay_protocol = protocol;
+        self
+    }
+
     /// Bind the magic endpoint on the specified socket address.
     ///
     /// The *bind_port* is the port that should be bound locally.
@@ -192,6 +204,7 @@ impl MagicEndpointBuilder {
             nodes_path: self.peers_path,
             discovery: self.discovery,
             dns_resolver,
+            relay_protocol: self.relay_protocol,
         };
         MagicEndpoint::bind(Some(server_config), msock_opts, self.keylog).await
     }
@@ -285,6 +298,11 @@ impl MagicEndpoint {
         self.msock.discovery()
     }
 
+    /// Get the DNS resolver used in this [`MagicEndpoint`].
+    pub fn dns_resolver(&self) -> &DnsResolver {
+        self.msock.dns_resolver()
+    }
+
     /// Get the local endpoint addresses on which the underlying magic socket is bound.
     ///
     /// Returns a tuple of the IPv4 and the optional IPv6 address.
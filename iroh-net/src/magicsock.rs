@@ -60,7 +60,7 @@ use crate::{
     magic_endpoint::NodeAddr,
     net::{interfaces, ip::LocalAddresses, netmon, IpFamily},
     netcheck, portmapper,
-    relay::{RelayMap, RelayUrl},
+    relay::{self, RelayMap, RelayUrl},
     stun, AddrInfo,
 };
 
@@ -119,6 +119,9 @@ pub struct Options {
     /// You can use [`crate::dns::default_resolver`] for a resolver that uses the system's DNS
     /// configuration.
     pub dns_resolver: DnsResolver,
+
+    /// The protocol used to connect to relay servers.
+    pub relay_protocol: relay::http::Protocol,
 }
 
 impl Default for Options {
@@ -130,6 +133,7 @@ impl Default for Options {
             nodes_path: None,
             discovery: None,
             dns_resolver: crate::dns::default_resolver().clone(),
+            relay_protocol: relay::http::Protocol::RelayWithWebSocketFallback,
         }
     }
 }
@@ -171,6 +175,9 @@ struct Inner {
     /// The DNS resolver to be used in this magicsock.
     dns_resolver: DnsResolver,
 
+    /// The protocol used to connect to relay servers.
+    relay_protocol: relay::http::Protocol,
+
     /// Key for this node.
     secret_key: SecretKey,
 
@@ -1147,6 +1154,7 @@ impl MagicSock {
             discovery,
             nodes_path,
             dns_resolver,
+            relay_protocol,
         } = opts;
 
         let nodes_path = match nodes_path {
@@ -1227,6 +1235,7 @@ impl MagicSock {
             pending_call_me_maybes: Default::default(),
             endpoints_update_state: EndpointUpdateState::new(),
             dns_resolver,
+            relay_protocol,
         });
 
         let mut actor_tasks = JoinSet::default();
@@ -1404,6 +1413,11 @@ impl MagicSock {
         self.inner.discovery.as_ref().map(Box::as_ref)
     }
 
+    /// Reference to the DNS resolver used in this [`MagicSock`].
+    pub fn dns_resolver(&self) -> &DnsResolver {
+        &self.inner.dns_resolver
+    }
+
     /// Call to notify the system of potential network changes.
     pub async fn network_change(&self) {
         self.inner
@@ -10,7 +10,6 @@ use anyhow::{ensure, Context};
 use iroh_metrics::inc;
 use parking_lot::Mutex;
 use stun_rs::TransactionId;
-use tokio::io::AsyncWriteExt;
 use tracing::{debug, info, instrument, trace, warn};
 
 use self::endpoint::{Endpoint, Options, PingHandled};
@@ -231,37 +230,16 @@ impl NodeMap {
             return Ok(0);
         }
 
-        let mut ext = path.extension().map(|s| s.to_owned()).unwrap_or_default();
-        ext.push(".tmp");
-        let tmp_path = path.with_extension(ext);
-
-        if tokio::fs::try_exists(&tmp_path).await.unwrap_or(false) {
-            tokio::fs::remove_file(&tmp_path)
-                .await
-                .context("failed deleting existing tmp file")?;
-        }
-        if let Some(parent) = tmp_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-        let mut tmp = tokio::fs::File::create(&tmp_path)
-            .await
-            .context("failed creating tmp file")?;
-
+        let mut contents = Vec::new();
         let mut count = 0;
         for node_addr in known_nodes {
             let ser = postcard::to_stdvec(&node_addr).context("failed to serialize node data")?;
-            tmp.write_all(&ser)
-                .await
-                .context("failed to persist node data")?;
+            contents.extend_from_slice(&ser);
             count += 1;
         }
-        tmp.flush().await.context("failed to flush node data")?;
-        drop(tmp);
-
-        // move the file
-        tokio::fs::rename(tmp_path, path)
+        crate::util::write_atomic(path, &contents)
             .await
-            .context("failed renaming node data file")?;
+            .context("failed to persist node data")?;
         Ok(count)
     }
 
@@ -487,6 +487,13 @@ impl RelayActor {
             })
             .can_ack_pings(true)
             .is_preferred(my_relay.as_ref() == Some(&url1))
+            .protocol(self.conn.relay_protocol)
+            .auth_token(
+                self.conn
+                    .relay_map
+                    .get_node(&url1)
+                    .and_then(|node| node.auth_token.clone()),
+            )
             .build(self.conn.secret_key.clone(), self.conn.dns_resolver.clone());
 
         let (s, r) = mpsc::channel(64);
@@ -845,6 +845,7 @@ mod tests {
             url: url.clone(),
             stun_only: true,
             stun_port: DEFAULT_RELAY_STUN_PORT,
+            auth_token: None,
         }])
         .expect("hardcoded");
 
@@ -10,20 +10,28 @@
 
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
+mod access;
 pub(crate) mod client;
 pub(crate) mod client_conn;
 pub(crate) mod clients;
 mod codec;
+mod fair_queue;
 pub mod http;
 mod map;
+mod mesh;
 mod metrics;
 pub(crate) mod server;
 pub(crate) mod types;
 
+pub use self::access::{AccessConfig, AccessControl};
 pub use self::client::{Client as RelayClient, ReceivedMessage};
+pub use self::client_conn::{ClientLimits, RateLimit};
 pub use self::codec::MAX_PACKET_SIZE;
 pub use self::http::Client as HttpClient;
 pub use self::map::{RelayMap, RelayMode, RelayNode};
+pub use self::mesh::{MeshConfig, MeshKey};
 pub use self::metrics::Metrics;
-pub use self::server::{ClientConnHandler, MaybeTlsStream as MaybeTlsStreamServer, Server};
+pub use self::server::{
+    ClientConnHandler, MaybeTlsStream as MaybeTlsStreamServer, RestartConfig, Server,
+};
 pub use iroh_base::node_addr::RelayUrl;
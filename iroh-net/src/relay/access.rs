@@ -0,0 +1,146 @@
+//! Access control for relay clients.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::key::PublicKey;
+
+/// Which clients a relay [`super::Server`] admits.
+///
+/// The default configuration admits every client.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// If set, only clients with these keys are admitted.
+    pub allowlist: Option<BTreeSet<PublicKey>>,
+    /// Clients with these keys are never admitted.
+    pub denylist: BTreeSet<PublicKey>,
+    /// If not empty, clients must present one of these tokens.
+    pub tokens: BTreeSet<String>,
+}
+
+impl AccessConfig {
+    /// Check whether a client is admitted.
+    ///
+    /// Returns the reason for the rejection if it is not.
+    pub fn check(&self, key: &PublicKey, token: Option<&str>) -> Result<(), &'static str> {
+        if self.denylist.contains(key) {
+            return Err("node is denied access to this relay");
+        }
+        if let Some(allowlist) = &self.allowlist {
+            if !allowlist.contains(key) {
+                return Err("node is not allowed to use this relay");
+            }
+        }
+        if !self.tokens.is_empty() {
+            match token {
+                None => return Err("relay requires an auth token"),
+                Some(token) if !self.has_token(token) => {
+                    return Err("invalid auth token");
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks whether `token` is one of the tokens, comparing each of them in constant time.
+    fn has_token(&self, token: &str) -> bool {
+        self.tokens
+            .iter()
+            .fold(false, |found, valid| found | token_matches(valid, token))
+    }
+}
+
+/// Compares two tokens in constant time, only their length is not secret.
+fn token_matches(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// A shared, updatable [`AccessConfig`].
+///
+/// Clones refer to the same configuration, so the configuration of a running server can be
+/// replaced with [`AccessControl::update`]. Changes apply to new connections, and connected
+/// clients which are no longer admitted are disconnected.
+#[derive(Debug, Clone)]
+pub struct AccessControl(Arc<watch::Sender<AccessConfig>>);
+
+impl Default for AccessControl {
+    fn default() -> Self {
+        Self::new(AccessConfig::default())
+    }
+}
+
+impl AccessControl {
+    /// Create a new [`AccessControl`] with the given configuration.
+    pub fn new(config: AccessConfig) -> Self {
+        let (sender, _receiver) = watch::channel(config);
+        Self(Arc::new(sender))
+    }
+
+    /// Replace the configuration.
+    pub fn update(&self, config: AccessConfig) {
+        self.0.send_replace(config);
+    }
+
+    /// Get a copy of the current configuration.
+    pub fn config(&self) -> AccessConfig {
+        self.0.borrow().clone()
+    }
+
+    /// Check whether a client is admitted with the current configuration.
+    pub(crate) fn check(&self, key: &PublicKey, token: Option<&str>) -> Result<(), &'static str> {
+        self.0.borrow().check(key, token)
+    }
+
+    /// Get notified about updates of the configuration.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<AccessConfig> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::key::SecretKey;
+
+    use super::*;
+
+    #[test]
+    fn test_access_config() {
+        let a = SecretKey::generate().public();
+        let b = SecretKey::generate().public();
+
+        let config = AccessConfig::default();
+        assert!(config.check(&a, None).is_ok());
+
+        let config = AccessConfig {
+            allowlist: Some([a].into()),
+            ..Default::default()
+        };
+        assert!(config.check(&a, None).is_ok());
+        assert!(config.check(&b, None).is_err());
+
+        let config = AccessConfig {
+            denylist: [a].into(),
+            ..Default::default()
+        };
+        assert!(config.check(&a, None).is_err());
+        assert!(config.check(&b, None).is_ok());
+
+        let config = AccessConfig {
+            tokens: ["secret".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(config.check(&a, Some("secret")).is_ok());
+        assert!(config.check(&a, Some("wrong")).is_err());
+        assert!(config.check(&a, Some("secret2")).is_err());
+        assert!(config.check(&a, Some("")).is_err());
+        assert!(config.check(&a, None).is_err());
+    }
+}
@@ -18,6 +18,7 @@ use super::{
         recv_frame, write_frame, DerpCodec, Frame, FrameType, MAX_PACKET_SIZE,
         PER_CLIENT_SEND_QUEUE_DEPTH, PROTOCOL_VERSION,
     },
+    mesh::MeshKey,
     types::{ClientInfo, RateLimiter, ServerInfo},
 };
 
@@ -122,6 +123,36 @@ impl Client {
         Ok(())
     }
 
+    /// Subscribes to the connects and disconnects of the clients of the server.
+    ///
+    /// The server then sends a [`ReceivedMessage::PeerPresent`] for every connected client, and a
+    /// [`ReceivedMessage::PeerGone`] whenever a client disconnects. Only allowed for mesh peers.
+    pub async fn watch_connection_changes(&self) -> Result<()> {
+        self.inner
+            .writer_channel
+            .send(ClientWriterMessage::WatchConns)
+            .await?;
+        Ok(())
+    }
+
+    /// Forwards a packet from `srckey` to the client `dstkey` of the server.
+    ///
+    /// Only allowed for mesh peers. Errors if the packet is larger than [`super::MAX_PACKET_SIZE`]
+    pub async fn forward_packet(
+        &self,
+        srckey: PublicKey,
+        dstkey: PublicKey,
+        packet: Bytes,
+    ) -> Result<()> {
+        trace!(%srckey, %dstkey, len = packet.len(), "[RELAY] forward");
+
+        self.inner
+            .writer_channel
+            .send(ClientWriterMessage::ForwardPacket((srckey, dstkey, packet)))
+            .await?;
+        Ok(())
+    }
+
     /// The local address that the [`Client`] is listening on.
     pub fn local_addr(&self) -> Result<SocketAddr> {
         Ok(self.inner.local_addr)
@@ -165,6 +196,7 @@ fn process_incoming_frame(frame: Frame) -> Result<ReceivedMessage> {
             Ok(ReceivedMessage::KeepAlive)
         }
         Frame::PeerGone { peer } => Ok(ReceivedMessage::PeerGone(peer)),
+        Frame::PeerPresent { peer } => Ok(ReceivedMessage::PeerPresent(peer)),
         Frame::RecvPacket { src_key, content } => {
             let packet = ReceivedMessage::ReceivedPacket {
                 source: src_key,
@@ -205,6 +237,10 @@ enum ClientWriterMessage {
     Ping([u8; 8]),
     /// Tell the server whether or not this client is the user's preferred client
     NotePreferred(bool),
+    /// Subscribe to the connects and disconnects of the clients of the server
+    WatchConns,
+    /// Forward a packet (from the first to the second [`PublicKey`]) to the server
+    ForwardPacket((PublicKey, PublicKey, Bytes)),
     /// Shutdown the writer
     Shutdown,
 }
@@ -239,6 +275,24 @@ impl<W: AsyncWrite + Unpin + Send + 'static> ClientWriter<W> {
                     write_frame(&mut self.writer, Frame::NotePreferred { preferred }, None).await?;
                     self.writer.flush().await?;
                 }
+                ClientWriterMessage::WatchConns => {
+                    write_frame(&mut self.writer, Frame::WatchConns, None).await?;
+                    self.writer.flush().await?;
+                }
+                ClientWriterMessage::ForwardPacket((src_key, dst_key, packet)) => {
+                    ensure!(
+                        packet.len() <= MAX_PACKET_SIZE,
+                        "packet too big: {}",
+                        packet.len()
+                    );
+                    let frame = Frame::ForwardPacket {
+                        src_key,
+                        dst_key,
+                        packet,
+                    };
+                    write_frame(&mut self.writer, frame, None).await?;
+                    self.writer.flush().await?;
+                }
                 ClientWriterMessage::Shutdown => {
                     return Ok(());
                 }
@@ -258,6 +312,8 @@ pub struct ClientBuilder {
     is_prober: bool,
     server_public_key: Option<PublicKey>,
     can_ack_pings: bool,
+    auth_token: Option<String>,
+    mesh_key: Option<MeshKey>,
 }
 
 impl ClientBuilder {
@@ -275,6 +331,8 @@ impl ClientBuilder {
             is_prober: false,
             server_public_key: None,
             can_ack_pings: false,
+            auth_token: None,
+            mesh_key: None,
         }
     }
 
@@ -295,6 +353,18 @@ impl ClientBuilder {
         self
     }
 
+    // Set the token to authenticate with, for servers which require one.
+    pub fn auth_token(mut self, auth_token: Option<String>) -> Self {
+        self.auth_token = auth_token;
+        self
+    }
+
+    // Set the key to authenticate as a mesh peer of the server.
+    pub fn mesh_key(mut self, mesh_key: Option<MeshKey>) -> Self {
+        self.mesh_key = mesh_key;
+        self
+    }
+
     async fn server_handshake(&mut self) -> Result<(PublicKey, Option<RateLimiter>)> {
         debug!("server_handshake: started");
         let server_key = recv_server_key(&mut self.reader)
@@ -312,7 +382,8 @@ impl ClientBuilder {
             version: PROTOCOL_VERSION,
             can_ack_pings: self.can_ack_pings,
             is_prober: self.is_prober,
-            mesh_key: None,
+            mesh_key: self.mesh_key.map(|key| *key.as_bytes()),
+            auth_token: self.auth_token.clone(),
         };
         debug!("server_handshake: sending client_key: {:?}", &client_info);
         let shared_secret = self.secret_key.shared(&server_key);
@@ -324,10 +395,15 @@ impl ClientBuilder {
         )
         .await?;
 
-        let Frame::ServerInfo { encrypted_message } =
-            recv_frame(FrameType::ServerInfo, &mut self.reader).await?
-        else {
-            bail!("expected server info");
+        // the server sends a health frame instead of the server info if it rejects us
+        let encrypted_message = match self.reader.next().await {
+            Some(Ok(Frame::ServerInfo { encrypted_message })) => encrypted_message,
+            Some(Ok(Frame::Health { problem })) => {
+                bail!("rejected by server: {}", String::from_utf8_lossy(&problem));
+            }
+            Some(Ok(frame)) => bail!("expected server info, found {}", frame.typ()),
+            Some(Err(err)) => return Err(err),
+            None => bail!("EOF: unexpected stream end, expected server info"),
         };
         let mut buf = encrypted_message.to_vec();
         shared_secret.open(&mut buf)?;
@@ -447,6 +523,11 @@ pub enum ReceivedMessage {
     /// Indicates that the client identified by the underlying public key had previously sent you a
     /// packet but has now disconnected from the server.
     PeerGone(PublicKey),
+    /// Indicates that the client identified by the underlying public key is connected to the
+    /// server.
+    ///
+    /// Only sent to mesh peers which called [`Client::watch_connection_changes`].
+    PeerPresent(PublicKey),
     /// Sent by the server upon first connect.
     ServerInfo {
         /// How many bytes per second the server says it will accept, including all framing bytes.
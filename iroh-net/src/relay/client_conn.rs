@@ -11,18 +11,79 @@ use tokio_util::sync::CancellationToken;
 use tracing::{trace, Instrument};
 
 use crate::util::AbortingJoinHandle;
-use crate::{disco::looks_like_disco_wrapper, key::PublicKey};
+use crate::{
+    disco::looks_like_disco_wrapper,
+    key::{PublicKey, PUBLIC_KEY_LENGTH},
+};
 
 use iroh_metrics::{inc, inc_by};
 
 use super::codec::{DerpCodec, Frame};
 use super::server::MaybeTlsStream;
 use super::{
-    codec::{write_frame, KEEP_ALIVE},
+    codec::{write_frame, KEEP_ALIVE, MAX_PACKET_SIZE, PER_CLIENT_SEND_QUEUE_DEPTH},
+    fair_queue::{self, FairReceiver, FairSender},
+    mesh::PeerChange,
     metrics::Metrics,
-    types::{Packet, ServerMessage},
+    types::{Packet, RateLimiter, Restarting, ServerMessage},
 };
 
+/// Limits the relay server applies to each client connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientLimits {
+    /// Rate limit for the packets a client sends to the relay server.
+    ///
+    /// Packets exceeding the limit are dropped. The limit is announced to clients, which then
+    /// limit their own sending rate. Unlimited if `None`.
+    pub recv_rate: Option<RateLimit>,
+    /// Rate limit for the packets the relay server sends to a client.
+    ///
+    /// Packets exceeding the limit are dropped. Unlimited if `None`.
+    pub send_rate: Option<RateLimit>,
+    /// Maximum number of packets queued for sending to a client.
+    ///
+    /// Packets to a client with a full queue are dropped.
+    pub max_queued_packets: usize,
+    /// Maximum number of packets from a single sender queued for sending to a client.
+    ///
+    /// The queue of a client is shared fairly between all senders, this keeps a single
+    /// sender from taking up all of the queue.
+    pub max_queued_packets_per_sender: usize,
+}
+
+impl Default for ClientLimits {
+    fn default() -> Self {
+        Self {
+            recv_rate: None,
+            send_rate: None,
+            max_queued_packets: PER_CLIENT_SEND_QUEUE_DEPTH,
+            max_queued_packets_per_sender: PER_CLIENT_SEND_QUEUE_DEPTH / 4,
+        }
+    }
+}
+
+/// A token bucket rate limit, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The sustained rate, in bytes per second.
+    pub bytes_per_second: usize,
+    /// The maximum burst, in bytes.
+    ///
+    /// Raised to the size of the largest packet if smaller, so that every packet can pass.
+    pub burst_bytes: usize,
+}
+
+impl RateLimit {
+    /// The burst, raised to the size of the largest frame if needed.
+    pub(crate) fn burst(&self) -> usize {
+        self.burst_bytes.max(MAX_PACKET_SIZE + PUBLIC_KEY_LENGTH)
+    }
+
+    fn limiter(&self) -> Result<Option<RateLimiter>> {
+        RateLimiter::new(self.bytes_per_second, self.burst())
+    }
+}
+
 /// The [`super::server::Server`] side representation of a [`super::client::Client`]'s connection
 #[derive(Debug)]
 pub(crate) struct ClientConnManager {
@@ -35,6 +96,8 @@ pub(crate) struct ClientConnManager {
     // send updates
     // stats: Stats,
     pub(crate) key: PublicKey,
+    /// Whether the client is a mesh peer of the server
+    pub(crate) mesh_peer: bool,
     /// Sent when connection closes
     // TODO: maybe should be a receiver
     done: CancellationToken,
@@ -59,12 +122,16 @@ pub(crate) struct ClientConnManager {
 ///  - packets sent to this client from another client in the network
 #[derive(Debug)]
 pub(crate) struct ClientChannels {
-    /// Queue of packets intended for the client
-    pub(crate) send_queue: mpsc::Sender<Packet>,
+    /// Queue of packets intended for the client, shared fairly between the senders
+    pub(crate) send_queue: FairSender,
     /// Queue of important packets intended for the client
     pub(crate) disco_send_queue: mpsc::Sender<Packet>,
     /// Notify the client that a previous sender has disconnected
     pub(crate) peer_gone: mpsc::Sender<PublicKey>,
+    /// Notify a watching mesh peer about connects and disconnects of the clients of the server
+    pub(crate) peer_changes: mpsc::UnboundedSender<PeerChange>,
+    /// Notify the client that the server is restarting
+    pub(crate) restarting: mpsc::Sender<Restarting>,
 }
 
 /// A builds a [`ClientConnManager`] from a [`PublicKey`] and an io connection.
@@ -75,6 +142,10 @@ pub struct ClientConnBuilder {
     pub(crate) io: Framed<MaybeTlsStream, DerpCodec>,
     pub(crate) write_timeout: Option<Duration>,
     pub(crate) channel_capacity: usize,
+    pub(crate) limits: ClientLimits,
+    pub(crate) mesh_peer: bool,
+    /// The token the client presented, to check it again when the access control changes
+    pub(crate) auth_token: Option<String>,
     pub(crate) server_channel: mpsc::Sender<ServerMessage>,
 }
 
@@ -88,6 +159,8 @@ impl ClientConnBuilder {
             self.io,
             self.write_timeout,
             self.channel_capacity,
+            self.limits,
+            self.mesh_peer,
             self.server_channel,
         )
     }
@@ -104,14 +177,22 @@ impl ClientConnManager {
         io: Framed<MaybeTlsStream, DerpCodec>,
         write_timeout: Option<Duration>,
         channel_capacity: usize,
+        limits: ClientLimits,
+        mesh_peer: bool,
         server_channel: mpsc::Sender<ServerMessage>,
     ) -> ClientConnManager {
         let done = CancellationToken::new();
         let client_id = (key, conn_num);
-        let (send_queue_s, send_queue_r) = mpsc::channel(channel_capacity);
+        let (send_queue_s, send_queue_r) = fair_queue::channel(
+            limits.max_queued_packets,
+            limits.max_queued_packets_per_sender,
+        );
 
         let (disco_send_queue_s, disco_send_queue_r) = mpsc::channel(channel_capacity);
         let (peer_gone_s, peer_gone_r) = mpsc::channel(channel_capacity);
+        // unbounded, so a watcher is never out of sync with the clients of the server
+        let (peer_changes_s, peer_changes_r) = mpsc::unbounded_channel();
+        let (restarting_s, restarting_r) = mpsc::channel(1);
 
         let preferred = Arc::from(AtomicBool::from(false));
 
@@ -124,6 +205,12 @@ impl ClientConnManager {
             key,
             preferred: Arc::clone(&preferred),
             server_channel: server_channel.clone(),
+            // mesh peers carry the packets of many clients, they are not limited
+            recv_limiter: rate_limiter(limits.recv_rate.filter(|_| !mesh_peer)),
+            send_limiter: rate_limiter(limits.send_rate.filter(|_| !mesh_peer)),
+            mesh_peer,
+            peer_changes: peer_changes_r,
+            restarting: restarting_r,
         };
 
         // start io loop
@@ -156,12 +243,15 @@ impl ClientConnManager {
         ClientConnManager {
             conn_num,
             key,
+            mesh_peer,
             io_handle: io_handle.into(),
             done,
             client_channels: ClientChannels {
                 send_queue: send_queue_s,
                 disco_send_queue: disco_send_queue_s,
                 peer_gone: peer_gone_s,
+                peer_changes: peer_changes_s,
+                restarting: restarting_s,
             },
         }
     }
@@ -194,19 +284,23 @@ impl ClientConnManager {
 ///  - a PEER_GONE frame to inform the client that a peer they have previously sent messages to
 ///  is gone from the network
 ///  - packets from other peers
+///  - PEER_PRESENT and PEER_GONE frames to inform a watching mesh peer about the clients of the
+///  server
+///  - a RESTARTING frame to announce that the server is restarting
 ///
 /// On the "read" side, it can:
 ///     - receive a ping and write a pong back
 ///     - note whether the client is `preferred`, aka this client is the preferred way
 ///     to speak to the node ID associated with that client.
-#[derive(Debug)]
+///     - receive watch requests and forwarded packets from mesh peers
+#[derive(derive_more::Debug)]
 pub(crate) struct ClientConnIo {
     /// Io to talk to the client
     io: Framed<MaybeTlsStream, DerpCodec>,
     /// Max time we wait to complete a write to the client
     timeout: Option<Duration>,
     /// Packets queued to send to the client
-    send_queue: mpsc::Receiver<Packet>,
+    send_queue: FairReceiver,
     /// Important packets queued to send to the client
     disco_send_queue: mpsc::Receiver<Packet>,
     /// Notify the client that a previous sender has disconnected
@@ -226,6 +320,20 @@ pub(crate) struct ClientConnIo {
     // might find that the alternative is better, once I have a better idea of how this is supposed
     // to be read.
     preferred: Arc<AtomicBool>,
+
+    /// Limits the rate of packets received from the client
+    #[debug(skip)]
+    recv_limiter: Option<RateLimiter>,
+    /// Limits the rate of packets sent to the client
+    #[debug(skip)]
+    send_limiter: Option<RateLimiter>,
+
+    /// Whether the client is a mesh peer of the server
+    mesh_peer: bool,
+    /// Notify a watching mesh peer about the clients of the server
+    peer_changes: mpsc::UnboundedReceiver<PeerChange>,
+    /// Notify the client that the server is restarting
+    restarting: mpsc::Receiver<Restarting>,
 }
 
 impl ClientConnIo {
@@ -242,6 +350,11 @@ impl ClientConnIo {
 
                 _ = done.cancelled() => {
                     trace!("cancelled");
+                    // the server shuts down right after announcing a restart, make sure the
+                    // announcement is not lost
+                    if let Ok(restarting) = self.restarting.try_recv() {
+                        self.send_restarting(restarting).await?;
+                    }
                     // final flush
                     self.io.flush().await.context("flush")?;
                     return Ok(());
@@ -266,9 +379,27 @@ impl ClientConnIo {
                     trace!("peer gone: {:?}", peer);
                     self.send_peer_gone(peer).await?;
                 }
+                restarting = self.restarting.recv() => {
+                    let restarting = restarting.context("Server.restarting dropped")?;
+                    trace!("restarting: {:?}", restarting);
+                    self.send_restarting(restarting).await?;
+                }
+                change = self.peer_changes.recv() => {
+                    let change = change.context("Server.peer_changes dropped")?;
+                    trace!("peer change: {:?}", change);
+                    match change {
+                        PeerChange::Present(peer) => self.send_peer_present(peer).await?,
+                        PeerChange::Gone(peer) => self.send_peer_gone(peer).await?,
+                    }
+                }
                 packet = self.send_queue.recv() => {
                     let packet = packet.context("Server.send_queue dropped")?;
                     trace!("send packet");
+                    if !check_rate(&self.send_limiter, &packet) {
+                        trace!("send rate limit reached, dropping packet");
+                        inc!(Metrics, packets_throttled_send);
+                        continue;
+                    }
                     self.send_packet(packet).await.context("send packet")?;
                     // TODO: stats
                     // record `packet.enqueuedAt`
@@ -317,6 +448,25 @@ impl ClientConnIo {
         write_frame(&mut self.io, Frame::PeerGone { peer }, self.timeout).await
     }
 
+    /// Sends a restarting frame, does not flush
+    ///
+    /// Errors if the send does not happen within the `timeout` duration
+    async fn send_restarting(&mut self, restarting: Restarting) -> Result<()> {
+        let millis = |duration: Duration| u32::try_from(duration.as_millis()).unwrap_or(u32::MAX);
+        let frame = Frame::Restarting {
+            reconnect_in: millis(restarting.reconnect_in),
+            try_for: millis(restarting.try_for),
+        };
+        write_frame(&mut self.io, frame, self.timeout).await
+    }
+
+    /// Sends a peer present frame, does not flush
+    ///
+    /// Errors if the send does not happen within the `timeout` duration
+    async fn send_peer_present(&mut self, peer: PublicKey) -> Result<()> {
+        write_frame(&mut self.io, Frame::PeerPresent { peer }, self.timeout).await
+    }
+
     /// Writes contents to the client in a `RECV_PACKET` frame. If `srcKey.is_zero`, it uses the
     /// old DERPv1 framing format, otherwise uses the DERPv2 framing format. The bytes of contents
     /// are only valid until this function returns, do not retain the slices.
@@ -359,6 +509,21 @@ impl ClientConnIo {
             Frame::Health { .. } => {
                 inc!(Metrics, other_packets_recv);
             }
+            Frame::WatchConns if self.mesh_peer => {
+                self.send_server(ServerMessage::AddWatcher(self.key))
+                    .await?;
+                inc!(Metrics, other_packets_recv);
+            }
+            Frame::ForwardPacket {
+                src_key,
+                dst_key,
+                packet,
+            } if self.mesh_peer => {
+                let packet_len = packet.len();
+                self.handle_frame_forward_packet(src_key, dst_key, packet)
+                    .await?;
+                inc_by!(Metrics, bytes_recv, packet_len as u64);
+            }
             _ => {
                 inc!(Metrics, unknown_frames);
             }
@@ -428,9 +593,31 @@ impl ClientConnIo {
             src: self.key,
             bytes: data,
         };
+        if !check_rate(&self.recv_limiter, &packet) {
+            trace!("receive rate limit reached, dropping packet");
+            inc!(Metrics, packets_throttled_recv);
+            return Ok(());
+        }
         self.transfer_packet(dst_key, packet).await
     }
 
+    /// Sends a packet forwarded by a mesh peer to the server, which only delivers it to
+    /// its local clients.
+    async fn handle_frame_forward_packet(
+        &self,
+        src_key: PublicKey,
+        dst_key: PublicKey,
+        data: Bytes,
+    ) -> Result<()> {
+        let packet = Packet {
+            src: src_key,
+            bytes: data,
+        };
+        inc!(Metrics, packets_forwarded_in);
+        self.send_server(ServerMessage::RecvForwardedPacket((dst_key, packet)))
+            .await
+    }
+
     /// Send the given packet to the server. The server will attempt to
     /// send the packet to the destination, dropping the packet if the
     /// destination is not connected, or if the destination client can
@@ -449,6 +636,29 @@ impl ClientConnIo {
     }
 }
 
+fn rate_limiter(rate: Option<RateLimit>) -> Option<RateLimiter> {
+    match rate.map(|rate| rate.limiter()) {
+        Some(Ok(limiter)) => limiter,
+        Some(Err(err)) => {
+            tracing::warn!("invalid rate limit, not limiting: {err:#}");
+            None
+        }
+        None => None,
+    }
+}
+
+/// Returns whether the packet is within the rate limit, and takes it into account if so.
+fn check_rate(limiter: &Option<RateLimiter>, packet: &Packet) -> bool {
+    match limiter {
+        // account for the frame like clients do, see `super::client::send_packet`
+        Some(limiter) => {
+            let len = PUBLIC_KEY_LENGTH + packet.bytes.len();
+            limiter.check_n(len).is_ok()
+        }
+        None => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -462,9 +672,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_client_conn_io_basic() -> Result<()> {
-        let (send_queue_s, send_queue_r) = mpsc::channel(10);
+        let (send_queue_s, send_queue_r) = fair_queue::channel(10, 10);
         let (disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
         let (peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_changes_s, peer_changes_r) = mpsc::unbounded_channel();
+        let (_restarting_s, restarting_r) = mpsc::channel(1);
 
         let preferred = Arc::from(AtomicBool::from(true));
         let key = SecretKey::generate().public();
@@ -482,6 +694,11 @@ mod tests {
             key,
             server_channel: server_channel_s,
             preferred: Arc::clone(&preferred),
+            recv_limiter: None,
+            send_limiter: None,
+            mesh_peer: false,
+            peer_changes: peer_changes_r,
+            restarting: restarting_r,
         };
 
         let done = CancellationToken::new();
@@ -498,7 +715,7 @@ mod tests {
             src: key,
             bytes: Bytes::from(&data[..]),
         };
-        send_queue_s.send(packet.clone()).await?;
+        send_queue_s.try_send(packet.clone()).unwrap();
         let frame = recv_frame(FrameType::RecvPacket, &mut io_rw).await?;
         assert_eq!(
             frame,
@@ -594,11 +811,83 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_client_conn_recv_rate_limit() -> Result<()> {
+        let (_send_queue_s, send_queue_r) = fair_queue::channel(10, 10);
+        let (_disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
+        let (_peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_changes_s, peer_changes_r) = mpsc::unbounded_channel();
+        let (_restarting_s, restarting_r) = mpsc::channel(1);
+
+        let preferred = Arc::from(AtomicBool::from(true));
+        let key = SecretKey::generate().public();
+        let (io, io_rw) = tokio::io::duplex(1024);
+        let mut io_rw = Framed::new(io_rw, DerpCodec);
+        let (server_channel_s, mut server_channel_r) = mpsc::channel(10);
+
+        // the burst only fits a single packet of the maximum size, and refills very slowly
+        let rate = RateLimit {
+            bytes_per_second: 1,
+            burst_bytes: 0,
+        };
+        let conn_io = ClientConnIo {
+            io: Framed::new(MaybeTlsStream::Test(io), DerpCodec),
+            timeout: None,
+            send_queue: send_queue_r,
+            disco_send_queue: disco_send_queue_r,
+            peer_gone: peer_gone_r,
+
+            key,
+            server_channel: server_channel_s,
+            preferred: Arc::clone(&preferred),
+            recv_limiter: rate_limiter(Some(rate)),
+            send_limiter: None,
+            mesh_peer: false,
+            peer_changes: peer_changes_r,
+            restarting: restarting_r,
+        };
+
+        let done = CancellationToken::new();
+        let io_done = done.clone();
+        let io_handle = tokio::task::spawn(async move { conn_io.run(io_done).await });
+
+        let target = SecretKey::generate().public();
+        let data = Bytes::from(vec![1u8; MAX_PACKET_SIZE]);
+
+        // the first packet is within the budget and forwarded
+        crate::relay::client::send_packet(&mut io_rw, &None, target, data.clone()).await?;
+        let msg = server_channel_r.recv().await.unwrap();
+        match msg {
+            ServerMessage::SendPacket((got_target, packet)) => {
+                assert_eq!(target, got_target);
+                assert_eq!(data, packet.bytes);
+            }
+            m => {
+                bail!("expected ServerMessage::SendPacket, got {m:?}");
+            }
+        }
+
+        // the client is over budget, so the second packet is dropped
+        crate::relay::client::send_packet(&mut io_rw, &None, target, data).await?;
+        // frames are handled in order, so once the pong arrives the packet was handled
+        let ping = *b"pingpong";
+        write_frame(&mut io_rw, Frame::Ping { data: ping }, None).await?;
+        let frame = recv_frame(FrameType::Pong, &mut io_rw).await?;
+        assert_eq!(frame, Frame::Pong { data: ping });
+        assert!(server_channel_r.try_recv().is_err());
+
+        done.cancel();
+        io_handle.await??;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_client_conn_read_err() -> Result<()> {
-        let (_send_queue_s, send_queue_r) = mpsc::channel(10);
+        let (_send_queue_s, send_queue_r) = fair_queue::channel(10, 10);
         let (_disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
         let (_peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_changes_s, peer_changes_r) = mpsc::unbounded_channel();
+        let (_restarting_s, restarting_r) = mpsc::channel(1);
 
         let preferred = Arc::from(AtomicBool::from(true));
         let key = SecretKey::generate().public();
@@ -617,6 +906,11 @@ mod tests {
             key,
             server_channel: server_channel_s,
             preferred: Arc::clone(&preferred),
+            recv_limiter: None,
+            send_limiter: None,
+            mesh_peer: false,
+            peer_changes: peer_changes_r,
+            restarting: restarting_r,
         };
 
         let done = CancellationToken::new();
@@ -11,9 +11,12 @@ use iroh_metrics::inc;
 use tracing::{Instrument, Span};
 
 use super::{
+    access::AccessConfig,
     client_conn::{ClientConnBuilder, ClientConnManager},
+    fair_queue::TrySendError,
+    mesh::PeerChange,
     metrics::Metrics,
-    types::Packet,
+    types::{Packet, Restarting},
 };
 
 /// Number of times we try to send to a client connection before dropping the data;
@@ -40,13 +43,16 @@ struct Client {
     conn: ClientConnManager,
     /// list of peers we have sent messages to
     sent_to: HashSet<PublicKey>,
+    /// The auth token the client presented when connecting
+    auth_token: Option<String>,
 }
 
 impl Client {
-    pub fn new(conn: ClientConnManager) -> Self {
+    pub fn new(conn: ClientConnManager, auth_token: Option<String>) -> Self {
         Self {
             conn,
             sent_to: HashSet::default(),
+            auth_token,
         }
     }
 
@@ -70,7 +76,14 @@ impl Client {
     }
 
     pub fn send_packet(&self, packet: Packet) -> Result<(), SendError> {
-        let res = try_send(&self.conn.client_channels.send_queue, packet);
+        let res = match self.conn.client_channels.send_queue.try_send(packet) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full) => {
+                inc!(Metrics, packets_dropped_queue_full);
+                Err(SendError::PacketDropped)
+            }
+            Err(TrySendError::Closed) => Err(SendError::SenderClosed),
+        };
         if res.is_ok() {
             // there is a chance that we have a packet forwarder for
             // this peer, so we must check that route before
@@ -91,6 +104,20 @@ impl Client {
         res
     }
 
+    /// Notify a watching mesh peer about a client of the server.
+    pub fn send_peer_change(&self, change: PeerChange) -> Result<(), SendError> {
+        self.conn
+            .client_channels
+            .peer_changes
+            .send(change)
+            .map_err(|_| SendError::SenderClosed)
+    }
+
+    /// Notify the client that the server is restarting.
+    pub fn send_restarting(&self, restarting: Restarting) -> Result<(), SendError> {
+        try_send(&self.conn.client_channels.restarting, restarting)
+    }
+
     pub fn send_peer_gone(&self, key: PublicKey) -> Result<(), SendError> {
         let res = try_send(&self.conn.client_channels.peer_gone, key);
         match res {
@@ -134,6 +161,8 @@ enum SendError {
 #[derive(Debug)]
 pub(crate) struct Clients {
     inner: HashMap<PublicKey, Client>,
+    /// Mesh peers which are notified about the connects and disconnects of the other clients
+    watchers: HashSet<PublicKey>,
 }
 
 impl Drop for Clients {
@@ -144,6 +173,7 @@ impl Clients {
     pub fn new() -> Self {
         Self {
             inner: HashMap::default(),
+            watchers: HashSet::default(),
         }
     }
 
@@ -179,16 +209,48 @@ impl Clients {
     pub fn register(&mut self, client_builder: ClientConnBuilder) {
         // this builds the client handler & starts the read & write loops to that client connection
         let key = client_builder.key;
+        let auth_token = client_builder.auth_token.clone();
         tracing::trace!("registering client: {:?}", key);
         let client = client_builder.build();
         // TODO: in future, do not remove clients that share a publicKey, instead,
         // expand the `Client` struct to handle multiple connections & a policy for
         // how to handle who we write to when multiple connections exist.
-        let client = Client::new(client);
+        let client = Client::new(client, auth_token);
+        let mesh_peer = client.conn.mesh_peer;
         if let Some(old_client) = self.inner.insert(key, client) {
             tracing::warn!("multiple connections found for {key:?}, pruning old connection",);
+            // the new connection has to watch again
+            self.watchers.remove(&key);
             old_client.shutdown();
         }
+        if !mesh_peer {
+            self.notify_watchers(PeerChange::Present(key));
+        }
+    }
+
+    /// Adds a mesh peer to the watchers, and notifies it about all current clients.
+    pub fn add_watcher(&mut self, key: PublicKey) {
+        let Some(watcher) = self.inner.get(&key) else {
+            return;
+        };
+        if !watcher.conn.mesh_peer || !self.watchers.insert(key) {
+            return;
+        }
+        tracing::trace!("adding watcher: {:?}", key);
+        for (peer, client) in self.inner.iter() {
+            if !client.conn.mesh_peer {
+                watcher.send_peer_change(PeerChange::Present(*peer)).ok();
+            }
+        }
+    }
+
+    fn notify_watchers(&self, change: PeerChange) {
+        for key in self.watchers.iter() {
+            if let Some(watcher) = self.inner.get(key) {
+                // a closed watcher is removed once its connection is gone
+                watcher.send_peer_change(change).ok();
+            }
+        }
     }
 
     /// Removes the client from the map of clients, & sends a notification
@@ -196,15 +258,37 @@ impl Clients {
     /// peer is gone from the network.
     pub fn unregister(&mut self, peer: &PublicKey) {
         tracing::trace!("unregistering client: {:?}", peer);
+        self.watchers.remove(peer);
         if let Some(client) = self.inner.remove(peer) {
             for key in client.sent_to.iter() {
                 self.send_peer_gone(key, *peer);
             }
+            if !client.conn.mesh_peer {
+                self.notify_watchers(PeerChange::Gone(*peer));
+            }
             tracing::warn!("pruning connection {peer:?}");
             client.shutdown();
         }
     }
 
+    /// Removes the clients which are not admitted by `access`.
+    ///
+    /// Mesh peers are not subject to the access control and are kept.
+    pub fn retain_admitted(&mut self, access: &AccessConfig) {
+        let denied: Vec<PublicKey> = self
+            .inner
+            .iter()
+            .filter(|(key, client)| {
+                !client.conn.mesh_peer && access.check(key, client.auth_token.as_deref()).is_err()
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in denied {
+            tracing::info!("client {key:?} is no longer admitted, disconnecting");
+            self.unregister(&key);
+        }
+    }
+
     /// Attempt to send a packet to client with [`PublicKey`] `key`
     pub fn send_packet(&mut self, key: &PublicKey, packet: Packet) -> anyhow::Result<()> {
         if let Some(client) = self.inner.get(key) {
@@ -224,6 +308,21 @@ impl Clients {
         anyhow::bail!("Could not find client for {key:?}, dropped packet");
     }
 
+    /// Announces to the client with [`PublicKey`] `key` that the server is restarting.
+    pub fn send_restarting(&self, key: &PublicKey, restarting: Restarting) {
+        if let Some(client) = self.inner.get(key) {
+            // a closed client is removed once its connection is gone
+            client.send_restarting(restarting).ok();
+        }
+    }
+
+    /// Announces to all clients that the server is restarting.
+    pub fn broadcast_restarting(&self, restarting: Restarting) {
+        for key in self.inner.keys() {
+            self.send_restarting(key, restarting);
+        }
+    }
+
     pub fn send_peer_gone(&mut self, key: &PublicKey, peer: PublicKey) {
         if let Some(client) = self.inner.get(key) {
             let res = client.send_peer_gone(peer);
@@ -259,7 +358,7 @@ mod tests {
     use crate::{
         key::SecretKey,
         relay::{
-            client_conn::ClientConnBuilder,
+            client_conn::{ClientConnBuilder, ClientLimits},
             codec::{recv_frame, DerpCodec, Frame, FrameType},
         },
     };
@@ -282,6 +381,9 @@ mod tests {
                 io: Framed::new(crate::relay::server::MaybeTlsStream::Test(io), DerpCodec),
                 write_timeout: None,
                 channel_capacity: 10,
+                limits: ClientLimits::default(),
+                mesh_peer: false,
+                auth_token: None,
                 server_channel,
             },
             FramedRead::new(test_io, DerpCodec),
@@ -337,4 +439,79 @@ mod tests {
         clients.shutdown().await;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_clients_retain_admitted() -> Result<()> {
+        let a_key = SecretKey::generate().public();
+        let b_key = SecretKey::generate().public();
+        let mesh_key = SecretKey::generate().public();
+
+        let mut clients = Clients::new();
+        let (builder_a, _a_rw) = test_client_builder(a_key, 0);
+        clients.register(builder_a);
+        let (mut builder_b, _b_rw) = test_client_builder(b_key, 1);
+        builder_b.auth_token = Some("secret".to_string());
+        clients.register(builder_b);
+        let (mut builder_mesh, _mesh_rw) = test_client_builder(mesh_key, 2);
+        builder_mesh.mesh_peer = true;
+        clients.register(builder_mesh);
+
+        // a is denied, b presented a valid token, mesh peers are not subject to access control
+        let access = AccessConfig {
+            denylist: [a_key].into(),
+            tokens: ["secret".to_string()].into(),
+            ..Default::default()
+        };
+        clients.retain_admitted(&access);
+        assert!(!clients.contains_key(&a_key));
+        assert!(clients.contains_key(&b_key));
+        assert!(clients.contains_key(&mesh_key));
+
+        // the token of b is revoked
+        let access = AccessConfig {
+            tokens: ["other".to_string()].into(),
+            ..Default::default()
+        };
+        clients.retain_admitted(&access);
+        assert!(!clients.contains_key(&b_key));
+        assert!(clients.contains_key(&mesh_key));
+
+        clients.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clients_watchers() -> Result<()> {
+        let a_key = SecretKey::generate().public();
+        let b_key = SecretKey::generate().public();
+        let mesh_key = SecretKey::generate().public();
+
+        let mut clients = Clients::new();
+        let (builder_a, _a_rw) = test_client_builder(a_key, 0);
+        clients.register(builder_a);
+        let (mut builder_mesh, mut mesh_rw) = test_client_builder(mesh_key, 1);
+        builder_mesh.mesh_peer = true;
+        clients.register(builder_mesh);
+
+        // the watcher learns about the existing clients, but not about itself
+        clients.add_watcher(mesh_key);
+        let frame = recv_frame(FrameType::PeerPresent, &mut mesh_rw).await?;
+        assert_eq!(frame, Frame::PeerPresent { peer: a_key });
+
+        let (builder_b, _b_rw) = test_client_builder(b_key, 2);
+        clients.register(builder_b);
+        let frame = recv_frame(FrameType::PeerPresent, &mut mesh_rw).await?;
+        assert_eq!(frame, Frame::PeerPresent { peer: b_key });
+
+        clients.unregister(&a_key);
+        let frame = recv_frame(FrameType::PeerGone, &mut mesh_rw).await?;
+        assert_eq!(frame, Frame::PeerGone { peer: a_key });
+
+        // clients which are not mesh peers cannot watch
+        clients.add_watcher(b_key);
+        assert!(!clients.watchers.contains(&b_key));
+
+        clients.shutdown().await;
+        Ok(())
+    }
 }
@@ -33,8 +33,8 @@ pub(super) const PER_CLIENT_READ_QUEUE_DEPTH: usize = 512;
 ///  - version 1 (zero on wire): consistent box headers, in use by employee dev nodes a bit
 ///  - version 2: received packets have src addrs in FrameType::RecvPacket at beginning
 /// NOTE: we are techincally running a modified version of the protocol.
-/// `FrameType::PeerPresent`, `FrameType::WatchConn`, `FrameType::ClosePeer`, have been removed.
-/// The server will error on that connection if a client sends one of these frames.
+/// `FrameType::ClosePeer` has been removed. `FrameType::PeerPresent`, `FrameType::WatchConns` and
+/// `FrameType::ForwardPacket` are only accepted from mesh peers, see [`super::mesh`].
 pub(super) const PROTOCOL_VERSION: usize = 2;
 
 ///
@@ -52,6 +52,13 @@ pub(super) const PROTOCOL_VERSION: usize = 2;
 ///  * clients sends FrameType::SendPacket
 ///  * server then sends FrameType::RecvPacket to recipient
 ///
+///  Meshing:
+///  * mesh peers log in with the shared mesh key in their ClientInfo
+///  * mesh peer sends FrameType::WatchConns
+///  * server sends FrameType::PeerPresent for every connected client, now and on connect,
+///    and FrameType::PeerGone on disconnect
+///  * mesh peer sends FrameType::ForwardPacket for the clients of the server
+///
 
 const PREFERRED: u8 = 1u8;
 /// indicates this is NOT the client's home node
@@ -84,8 +91,16 @@ pub(crate) enum FrameType {
     ///
     /// 32B pub key of peer that's gone
     PeerGone = 8,
-    /// Frames 9-11 concern meshing, which we have eliminated from our version of the protocol.
-    /// Messages with these frames will be ignored.
+    /// Sent from server to mesh peers to signal that a client is connected to the server.
+    ///
+    /// 32B pub key of peer that's present
+    PeerPresent = 9,
+    /// Sent from mesh peers to the server to subscribe to `FrameType::PeerPresent` and
+    /// `FrameType::PeerGone` frames for all clients of the server.
+    ///
+    /// No payload
+    WatchConns = 10,
+    /// Frame 11 (ClosePeer) has been eliminated from our version of the protocol.
     /// 8 byte ping payload, to be echoed back in FrameType::Pong
     Ping = 12,
     /// 8 byte payload, the contents of ping being replied to
@@ -101,8 +116,10 @@ pub(crate) enum FrameType {
     /// Payload is two big endian u32 durations in milliseconds: when to reconnect,
     /// and how long to try total.
     ///
-    /// Handled on the `[relay::Client]`, but currently never sent on the `[relay::Server]`
+    /// Sent by the `[relay::Server]` when it is closed, see `[relay::RestartConfig]`.
     Restarting = 15,
+    /// Sent from mesh peers to the server to deliver a packet to a client of the server.
+    ///
     /// 32B src pub key + 32B dst pub key + packet bytes
     ForwardPacket = 16,
     #[num_enum(default)]
@@ -183,8 +200,7 @@ pub(super) async fn recv_client_key<S: Stream<Item = anyhow::Result<Frame>> + Un
         shared_secret
             .open(&mut encrypted_message)
             .context("shared secret")?;
-        let info: ClientInfo =
-            postcard::from_bytes(&encrypted_message).context("deserialization")?;
+        let info = ClientInfo::from_bytes(&encrypted_message).context("deserialization")?;
         Ok((client_public_key, info, shared_secret))
     } else {
         anyhow::bail!("expected FrameType::ClientInfo");
@@ -221,6 +237,10 @@ pub(crate) enum Frame {
     PeerGone {
         peer: PublicKey,
     },
+    PeerPresent {
+        peer: PublicKey,
+    },
+    WatchConns,
     Ping {
         data: [u8; 8],
     },
@@ -234,6 +254,11 @@ pub(crate) enum Frame {
         reconnect_in: u32,
         try_for: u32,
     },
+    ForwardPacket {
+        src_key: PublicKey,
+        dst_key: PublicKey,
+        packet: Bytes,
+    },
 }
 
 impl Frame {
@@ -247,10 +272,13 @@ impl Frame {
             Frame::KeepAlive => FrameType::KeepAlive,
             Frame::NotePreferred { .. } => FrameType::NotePreferred,
             Frame::PeerGone { .. } => FrameType::PeerGone,
+            Frame::PeerPresent { .. } => FrameType::PeerPresent,
+            Frame::WatchConns => FrameType::WatchConns,
             Frame::Ping { .. } => FrameType::Ping,
             Frame::Pong { .. } => FrameType::Pong,
             Frame::Health { .. } => FrameType::Health,
             Frame::Restarting { .. } => FrameType::Restarting,
+            Frame::ForwardPacket { .. } => FrameType::ForwardPacket,
         }
     }
 
@@ -271,10 +299,17 @@ impl Frame {
             Frame::KeepAlive => 0,
             Frame::NotePreferred { .. } => 1,
             Frame::PeerGone { .. } => PUBLIC_KEY_LENGTH,
+            Frame::PeerPresent { .. } => PUBLIC_KEY_LENGTH,
+            Frame::WatchConns => 0,
             Frame::Ping { .. } => 8,
             Frame::Pong { .. } => 8,
             Frame::Health { problem } => problem.len(),
             Frame::Restarting { .. } => 4 + 4,
+            Frame::ForwardPacket {
+                src_key: _,
+                dst_key: _,
+                packet,
+            } => PUBLIC_KEY_LENGTH * 2 + packet.len(),
         }
     }
 
@@ -314,6 +349,10 @@ impl Frame {
             Frame::PeerGone { peer } => {
                 dst.put(peer.as_ref());
             }
+            Frame::PeerPresent { peer } => {
+                dst.put(peer.as_ref());
+            }
+            Frame::WatchConns => {}
             Frame::Ping { data } => {
                 dst.put(&data[..]);
             }
@@ -330,6 +369,15 @@ impl Frame {
                 dst.put_u32(*reconnect_in);
                 dst.put_u32(*try_for);
             }
+            Frame::ForwardPacket {
+                src_key,
+                dst_key,
+                packet,
+            } => {
+                dst.put(src_key.as_ref());
+                dst.put(dst_key.as_ref());
+                dst.put(packet.as_ref());
+            }
         }
     }
 
@@ -414,6 +462,18 @@ impl Frame {
                 let peer = PublicKey::try_from(&content[..32])?;
                 Self::PeerGone { peer }
             }
+            FrameType::PeerPresent => {
+                anyhow::ensure!(
+                    content.len() == PUBLIC_KEY_LENGTH,
+                    "invalid peer present frame length"
+                );
+                let peer = PublicKey::try_from(&content[..32])?;
+                Self::PeerPresent { peer }
+            }
+            FrameType::WatchConns => {
+                anyhow::ensure!(content.is_empty(), "invalid watch conns frame length");
+                Self::WatchConns
+            }
             FrameType::Ping => {
                 anyhow::ensure!(content.len() == 8, "invalid ping frame length");
                 let mut data = [0u8; 8];
@@ -440,6 +500,27 @@ impl Frame {
                     try_for,
                 }
             }
+            FrameType::ForwardPacket => {
+                ensure!(
+                    content.len() >= PUBLIC_KEY_LENGTH * 2,
+                    "invalid forward packet frame length: {}",
+                    content.len()
+                );
+                let packet_len = content.len() - PUBLIC_KEY_LENGTH * 2;
+                ensure!(
+                    packet_len <= MAX_PACKET_SIZE,
+                    "data packet longer ({packet_len}) than max of {MAX_PACKET_SIZE}"
+                );
+                let src_key = PublicKey::try_from(&content[..PUBLIC_KEY_LENGTH])?;
+                let dst_key =
+                    PublicKey::try_from(&content[PUBLIC_KEY_LENGTH..PUBLIC_KEY_LENGTH * 2])?;
+                let packet = content.slice(PUBLIC_KEY_LENGTH * 2..);
+                Self::ForwardPacket {
+                    src_key,
+                    dst_key,
+                    packet,
+                }
+            }
             _ => {
                 anyhow::bail!("invalid frame type: {:?}", frame_type);
             }
@@ -579,6 +660,7 @@ mod tests {
             can_ack_pings: true,
             is_prober: true,
             mesh_key: None,
+            auth_token: None,
         };
         println!("client_key pub {:?}", client_key.public());
         let shared_secret = client_key.shared(&server_key.public());
@@ -594,6 +676,26 @@ mod tests {
         assert_eq!(client_info, got_client_info);
         Ok(())
     }
+
+    #[test]
+    fn test_client_info_compat() -> anyhow::Result<()> {
+        let client_info = ClientInfo {
+            version: PROTOCOL_VERSION,
+            can_ack_pings: true,
+            is_prober: false,
+            mesh_key: None,
+            auth_token: Some("secret".to_string()),
+        };
+        let bytes = postcard::to_stdvec(&client_info)?;
+        assert_eq!(ClientInfo::from_bytes(&bytes)?, client_info);
+
+        // clients without auth token support send only the first fields
+        let bytes = postcard::to_stdvec(&(PROTOCOL_VERSION, None::<[u8; 32]>, true, false))?;
+        let got = ClientInfo::from_bytes(&bytes)?;
+        assert_eq!(got.auth_token, None);
+        assert!(got.can_ack_pings);
+        Ok(())
+    }
 }
 
 /// these test are slow in debug mode, so only run them in release mode
@@ -635,6 +737,8 @@ mod proptests {
         let keep_alive = Just(Frame::KeepAlive);
         let note_preferred = any::<bool>().prop_map(|preferred| Frame::NotePreferred { preferred });
         let peer_gone = key().prop_map(|peer| Frame::PeerGone { peer });
+        let peer_present = key().prop_map(|peer| Frame::PeerPresent { peer });
+        let watch_conns = Just(Frame::WatchConns);
         let ping = prop::array::uniform8(any::<u8>()).prop_map(|data| Frame::Ping { data });
         let pong = prop::array::uniform8(any::<u8>()).prop_map(|data| Frame::Pong { data });
         let health = data(0).prop_map(|problem| Frame::Health { problem });
@@ -643,6 +747,12 @@ mod proptests {
                 reconnect_in,
                 try_for,
             });
+        let forward_packet =
+            (key(), key(), data(64)).prop_map(|(src_key, dst_key, packet)| Frame::ForwardPacket {
+                src_key,
+                dst_key,
+                packet,
+            });
         prop_oneof![
             server_key,
             client_info,
@@ -652,10 +762,13 @@ mod proptests {
             keep_alive,
             note_preferred,
             peer_gone,
+            peer_present,
+            watch_conns,
             ping,
             pong,
             health,
             restarting,
+            forward_packet,
         ]
     }
 
@@ -668,12 +781,15 @@ mod proptests {
                 | FrameType::Ping
                 | FrameType::Pong
                 | FrameType::Restarting
-                | FrameType::PeerGone => true,
+                | FrameType::PeerGone
+                | FrameType::PeerPresent
+                | FrameType::WatchConns => true,
                 FrameType::ClientInfo
                 | FrameType::ServerInfo
                 | FrameType::Health
                 | FrameType::SendPacket
                 | FrameType::RecvPacket
+                | FrameType::ForwardPacket
                 | FrameType::Unknown => false,
             }
         }
@@ -0,0 +1,270 @@
+//! Fair queuing of the packets sent to a relay client.
+//!
+//! Packets are queued per sender and dequeued with deficit round robin, so every sender gets
+//! an equal share of the bandwidth to the client, no matter how many packets it queues.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::types::Packet;
+use crate::key::PublicKey;
+
+/// Number of bytes a sender may send per round.
+const QUANTUM: usize = 1500;
+
+/// Creates a fair queue with the given total capacity and capacity per sender, in packets.
+pub(crate) fn channel(capacity: usize, capacity_per_sender: usize) -> (FairSender, FairReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            capacity: capacity.max(1),
+            capacity_per_sender: capacity_per_sender.max(1),
+            ..Default::default()
+        }),
+        notify: Notify::new(),
+    });
+    (
+        FairSender {
+            shared: shared.clone(),
+        },
+        FairReceiver { shared },
+    )
+}
+
+/// Error returned by [`FairSender::try_send`].
+#[derive(Debug)]
+pub(crate) enum TrySendError {
+    /// The queue of the sender, or the whole queue, is full.
+    Full,
+    /// The [`FairReceiver`] was dropped.
+    Closed,
+}
+
+/// The sending half of a fair queue.
+#[derive(Debug)]
+pub(crate) struct FairSender {
+    shared: Arc<Shared>,
+}
+
+impl FairSender {
+    /// Queues a packet, if there is capacity left for its sender.
+    pub(crate) fn try_send(&self, packet: Packet) -> Result<(), TrySendError> {
+        let mut state = self.shared.state.lock();
+        if state.receiver_closed {
+            return Err(TrySendError::Closed);
+        }
+        state.push(packet)?;
+        drop(state);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+impl Drop for FairSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().sender_closed = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+/// The receiving half of a fair queue.
+#[derive(Debug)]
+pub(crate) struct FairReceiver {
+    shared: Arc<Shared>,
+}
+
+impl FairReceiver {
+    /// Receives the next packet.
+    ///
+    /// Returns `None` once the queue is empty and the [`FairSender`] was dropped.
+    ///
+    /// Cancel safe.
+    pub(crate) async fn recv(&mut self) -> Option<Packet> {
+        loop {
+            {
+                let mut state = self.shared.state.lock();
+                if let Some(packet) = state.pop() {
+                    return Some(packet);
+                }
+                if state.sender_closed {
+                    return None;
+                }
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for FairReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().receiver_closed = true;
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    capacity: usize,
+    capacity_per_sender: usize,
+    /// The queued packets of each sender.
+    queues: HashMap<PublicKey, SenderQueue>,
+    /// The senders with queued packets, in round robin order.
+    active: VecDeque<PublicKey>,
+    /// The total number of queued packets.
+    len: usize,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct SenderQueue {
+    packets: VecDeque<Packet>,
+    /// Number of bytes the sender may still send in the current round.
+    deficit: usize,
+}
+
+impl State {
+    fn push(&mut self, packet: Packet) -> Result<(), TrySendError> {
+        if self.len >= self.capacity {
+            return Err(TrySendError::Full);
+        }
+        if !self.queues.contains_key(&packet.src) {
+            self.active.push_back(packet.src);
+        }
+        let queue = self.queues.entry(packet.src).or_default();
+        if queue.packets.len() >= self.capacity_per_sender {
+            return Err(TrySendError::Full);
+        }
+        queue.packets.push_back(packet);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Packet> {
+        loop {
+            let src = *self.active.front()?;
+            let queue = self
+                .queues
+                .get_mut(&src)
+                .expect("active senders have a queue");
+            let size = queue
+                .packets
+                .front()
+                .expect("active senders have queued packets")
+                .bytes
+                .len();
+            if queue.deficit < size {
+                // the sender used up its share of this round, move on to the next one
+                queue.deficit += QUANTUM;
+                self.active.rotate_left(1);
+                continue;
+            }
+            queue.deficit -= size;
+            let packet = queue.packets.pop_front();
+            self.len -= 1;
+            if queue.packets.is_empty() {
+                self.queues.remove(&src);
+                self.active.pop_front();
+            }
+            return packet;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::key::SecretKey;
+
+    use super::*;
+
+    fn packet(src: PublicKey, size: usize) -> Packet {
+        Packet {
+            src,
+            bytes: Bytes::from(vec![0u8; size]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_round_robin() {
+        let a = SecretKey::generate().public();
+        let b = SecretKey::generate().public();
+        let (sender, mut receiver) = channel(100, 100);
+        for _ in 0..10 {
+            sender.try_send(packet(a, QUANTUM)).unwrap();
+        }
+        for _ in 0..2 {
+            sender.try_send(packet(b, QUANTUM)).unwrap();
+        }
+        let mut order = vec![];
+        for _ in 0..12 {
+            order.push(receiver.recv().await.unwrap().src);
+        }
+        // b is served as often as a, even though a queued more packets
+        assert_eq!(&order[..4], &[a, b, a, b]);
+        assert!(order[4..].iter().all(|src| *src == a));
+
+        // small packets are served by bytes, not by count
+        for _ in 0..3 {
+            sender.try_send(packet(a, QUANTUM)).unwrap();
+        }
+        for _ in 0..6 {
+            sender.try_send(packet(b, QUANTUM / 2)).unwrap();
+        }
+        let mut order = vec![];
+        for _ in 0..9 {
+            order.push(receiver.recv().await.unwrap().src);
+        }
+        assert_eq!(&order[..6], &[a, b, b, a, b, b]);
+    }
+
+    #[tokio::test]
+    async fn test_fair_queue_capacity() {
+        let a = SecretKey::generate().public();
+        let b = SecretKey::generate().public();
+        let c = SecretKey::generate().public();
+        let (sender, mut receiver) = channel(4, 2);
+        sender.try_send(packet(a, 10)).unwrap();
+        sender.try_send(packet(a, 10)).unwrap();
+        assert!(matches!(
+            sender.try_send(packet(a, 10)),
+            Err(TrySendError::Full)
+        ));
+        sender.try_send(packet(b, 10)).unwrap();
+        sender.try_send(packet(b, 10)).unwrap();
+        assert!(matches!(
+            sender.try_send(packet(c, 10)),
+            Err(TrySendError::Full)
+        ));
+
+        receiver.recv().await.unwrap();
+        sender.try_send(packet(c, 10)).unwrap();
+
+        drop(sender);
+        for _ in 0..4 {
+            assert!(receiver.recv().await.is_some());
+        }
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_fair_queue_closed() {
+        let a = SecretKey::generate().public();
+        let (sender, receiver) = channel(4, 2);
+        drop(receiver);
+        assert!(matches!(
+            sender.try_send(packet(a, 10)),
+            Err(TrySendError::Closed)
+        ));
+    }
+}
@@ -1,11 +1,13 @@
 //! An http specific relay Client and relay Server. Allows for using tls or non tls connection
-//! upgrades.
+//! upgrades, to either the relay protocol or a WebSocket carrying it.
 //!
 mod client;
 mod server;
+mod websocket;
 
-pub use self::client::{Client, ClientBuilder, ClientError, ClientReceiver};
+pub use self::client::{Client, ClientBuilder, ClientError, ClientReceiver, Protocol};
 pub use self::server::{Server, ServerBuilder, TlsAcceptor, TlsConfig};
+pub(crate) use self::websocket::WsStream;
 
 pub(crate) const HTTP_UPGRADE_PROTOCOL: &str = "iroh derp http";
 
@@ -35,6 +37,8 @@ pub(crate) fn make_tls_config() -> TlsConfig {
 mod tests {
     use super::*;
 
+    use std::time::Duration;
+
     use anyhow::Result;
     use bytes::Bytes;
     use reqwest::Url;
@@ -44,7 +48,7 @@ mod tests {
     use tracing_subscriber::{prelude::*, EnvFilter};
 
     use crate::key::{PublicKey, SecretKey};
-    use crate::relay::ReceivedMessage;
+    use crate::relay::{AccessConfig, AccessControl, MeshConfig, MeshKey, ReceivedMessage};
 
     #[tokio::test]
     async fn test_http_clients_and_server() -> Result<()> {
@@ -119,6 +123,165 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_http_access_control() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let a_key = SecretKey::generate();
+        let b_key = SecretKey::generate();
+        let token = "secret".to_string();
+        let access = AccessControl::new(AccessConfig {
+            allowlist: Some([a_key.public()].into()),
+            tokens: [token.clone()].into(),
+            ..Default::default()
+        });
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .access_control(access.clone())
+            .spawn()
+            .await?;
+        let relay_url: Url = format!("http://{}", server.addr()).parse().unwrap();
+        let dns_resolver = crate::dns::default_resolver();
+
+        // a is allowed, but must present the token
+        let (client, _recv) =
+            ClientBuilder::new(relay_url.clone()).build(a_key.clone(), dns_resolver.clone());
+        let err = client.connect().await.unwrap_err();
+        assert!(err.to_string().contains("auth token"), "{err}");
+        let (client_a, _recv_a) = ClientBuilder::new(relay_url.clone())
+            .auth_token(Some(token.clone()))
+            .build(a_key, dns_resolver.clone());
+        client_a.connect().await?;
+
+        // b is not allowed, until the configuration is updated
+        let (client_b, _recv_b) = ClientBuilder::new(relay_url)
+            .auth_token(Some(token.clone()))
+            .build(b_key.clone(), dns_resolver.clone());
+        let err = client_b.connect().await.unwrap_err();
+        assert!(err.to_string().contains("not allowed"), "{err}");
+        access.update(AccessConfig {
+            tokens: [token].into(),
+            ..Default::default()
+        });
+        client_b.connect().await?;
+
+        client_a.close().await?;
+        client_b.close().await?;
+        server.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_mesh() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let mesh_key = MeshKey::generate();
+        let server_a = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .mesh(MeshConfig {
+                mesh_key,
+                peers: vec![],
+            })
+            .spawn()
+            .await?;
+        let url_a: Url = format!("http://{}", server_a.addr()).parse().unwrap();
+        // server b forwards to the clients of server a
+        let server_b = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .mesh(MeshConfig {
+                mesh_key,
+                peers: vec![url_a.clone().into()],
+            })
+            .spawn()
+            .await?;
+        let url_b: Url = format!("http://{}", server_b.addr()).parse().unwrap();
+
+        let (a_key, mut a_recv, client_a_task, client_a) =
+            create_test_client(SecretKey::generate(), url_a.clone());
+        let (_b_key, _b_recv, client_b_task, client_b) =
+            create_test_client(SecretKey::generate(), url_b);
+        client_a.connect().await?;
+
+        // wait for server b to learn about client a
+        let msg = Bytes::from_static(b"hello from the other side");
+        let (got_key, got_msg) = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                client_b.send(a_key, msg.clone()).await?;
+                match tokio::time::timeout(Duration::from_millis(100), a_recv.recv()).await {
+                    Ok(Some(received)) => return anyhow::Ok(received),
+                    Ok(None) => anyhow::bail!("client a is gone"),
+                    Err(_) => {}
+                }
+            }
+        })
+        .await??;
+        assert_eq!(got_key, client_b.public_key());
+        assert_eq!(got_msg, msg);
+
+        // a wrong mesh key is rejected
+        let (client, _recv) = ClientBuilder::new(url_a)
+            .mesh_key(Some(MeshKey::generate()))
+            .build(
+                SecretKey::generate(),
+                crate::dns::default_resolver().clone(),
+            );
+        let err = client.connect().await.unwrap_err();
+        assert!(err.to_string().contains("invalid mesh key"), "{err}");
+
+        client_a.close().await?;
+        client_a_task.abort();
+        client_b.close().await?;
+        client_b_task.abort();
+        server_b.shutdown().await;
+        server_a.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_websocket() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .spawn()
+            .await?;
+        let relay_url: Url = format!("http://{}", server.addr()).parse().unwrap();
+
+        // a connects over a websocket, b over the relay protocol
+        let (a_key, mut a_recv, client_a_task, client_a) = spawn_test_client(
+            SecretKey::generate(),
+            ClientBuilder::new(relay_url.clone()).protocol(Protocol::WebSocket),
+        );
+        let (b_key, mut b_recv, client_b_task, client_b) = spawn_test_client(
+            SecretKey::generate(),
+            ClientBuilder::new(relay_url).protocol(Protocol::RelayWithWebSocketFallback),
+        );
+
+        client_a.ping().await?;
+        client_b.ping().await?;
+
+        info!("sending message from a to b");
+        let msg = Bytes::from_static(b"hi there, client b!");
+        client_a.send(b_key, msg.clone()).await?;
+        let (got_key, got_msg) = b_recv.recv().await.expect("expected message from client_a");
+        assert_eq!(a_key, got_key);
+        assert_eq!(msg, got_msg);
+
+        info!("sending message from b to a");
+        let msg = Bytes::from_static(b"right back at ya, client b!");
+        client_b.send(a_key, msg.clone()).await?;
+        let (got_key, got_msg) = a_recv.recv().await.expect("expected message from client_b");
+        assert_eq!(b_key, got_key);
+        assert_eq!(msg, got_msg);
+
+        client_a.close().await?;
+        client_a_task.abort();
+        client_b.close().await?;
+        client_b_task.abort();
+        server.shutdown().await;
+        Ok(())
+    }
+
     fn create_test_client(
         key: SecretKey,
         server_url: Url,
@@ -128,7 +291,18 @@ mod tests {
         JoinHandle<()>,
         Client,
     ) {
-        let client = ClientBuilder::new(server_url);
+        spawn_test_client(key, ClientBuilder::new(server_url))
+    }
+
+    fn spawn_test_client(
+        key: SecretKey,
+        client: ClientBuilder,
+    ) -> (
+        PublicKey,
+        mpsc::Receiver<(PublicKey, Bytes)>,
+        JoinHandle<()>,
+        Client,
+    ) {
         let dns_resolver = crate::dns::default_resolver();
         let (client, mut client_reader) = client.build(key.clone(), dns_resolver.clone());
         let public_key = key.public();
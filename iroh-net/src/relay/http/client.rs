@@ -24,11 +24,11 @@ use url::Url;
 
 use crate::dns::{lookup_ipv4_ipv6, DnsResolver};
 use crate::key::{PublicKey, SecretKey};
-use crate::relay::RelayUrl;
 use crate::relay::{
     client::Client as RelayClient, client::ClientBuilder as RelayClientBuilder,
     client::ClientReceiver as RelayClientReceiver, ReceivedMessage,
 };
+use crate::relay::{http::WsStream, mesh::MeshKey, RelayUrl};
 use crate::util::AbortingJoinHandle;
 
 const DIAL_NODE_TIMEOUT: Duration = Duration::from_millis(1500);
@@ -36,6 +36,12 @@ const PING_TIMEOUT: Duration = Duration::from_secs(5);
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 const DNS_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// The path of the relay endpoint on the server.
+const RELAY_PATH: &str = "/derp";
+
+type ConnReader = Box<dyn AsyncRead + Unpin + Send + Sync + 'static>;
+type ConnWriter = Box<dyn AsyncWrite + Unpin + Send + Sync + 'static>;
+
 /// Possible connection errors on the [`Client`]
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
@@ -111,6 +117,24 @@ pub enum ClientError {
     /// The inner actor is gone, likely means things are shutdown.
     #[error("actor gone")]
     ActorGone,
+    /// The server announced a restart, reconnecting is delayed until it is back.
+    #[error("server is restarting")]
+    ServerRestarting,
+}
+
+/// The protocol a [`Client`] uses to connect to the relay server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Upgrade the HTTP connection to the relay protocol.
+    #[default]
+    Relay,
+    /// Upgrade the HTTP connection to a WebSocket, which carries the relay protocol.
+    ///
+    /// WebSocket connections pass through proxies which do not support the relay upgrade.
+    WebSocket,
+    /// Try [`Protocol::Relay`] first, and fall back to [`Protocol::WebSocket`] if the upgrade
+    /// fails.
+    RelayWithWebSocketFallback,
 }
 
 /// An HTTP Relay client.
@@ -132,6 +156,12 @@ enum ActorMessage {
     Ping(oneshot::Sender<Result<Duration, ClientError>>),
     Pong([u8; 8], oneshot::Sender<Result<(), ClientError>>),
     Send(PublicKey, Bytes, oneshot::Sender<Result<(), ClientError>>),
+    ForwardPacket(
+        PublicKey,
+        PublicKey,
+        Bytes,
+        oneshot::Sender<Result<(), ClientError>>,
+    ),
     Close(oneshot::Sender<Result<(), ClientError>>),
     CloseForReconnect(oneshot::Sender<Result<(), ClientError>>),
     IsConnected(oneshot::Sender<Result<bool, ClientError>>),
@@ -156,12 +186,18 @@ struct Actor {
     conn_gen: usize,
     is_prober: bool,
     server_public_key: Option<PublicKey>,
+    #[debug("{}", auth_token.as_ref().map_or("None", |_| "Some(..)"))]
+    auth_token: Option<String>,
+    mesh_key: Option<MeshKey>,
+    protocol: Protocol,
     url: RelayUrl,
     #[debug("TlsConnector")]
     tls_connector: tokio_rustls::TlsConnector,
     pings: PingTracker,
     ping_tasks: JoinSet<()>,
     dns_resolver: DnsResolver,
+    /// Set when the server announced a restart, no reconnects are attempted before this time
+    reconnect_after: Option<Instant>,
 }
 
 #[derive(Default, Debug)]
@@ -199,6 +235,12 @@ pub struct ClientBuilder {
     is_prober: bool,
     /// Expected PublicKey of the server
     server_public_key: Option<PublicKey>,
+    /// Token to authenticate with the server
+    auth_token: Option<String>,
+    /// Key to authenticate as a mesh peer of the server
+    mesh_key: Option<MeshKey>,
+    /// Default is [`Protocol::Relay`]
+    protocol: Protocol,
     /// Server url.
     url: RelayUrl,
 }
@@ -222,6 +264,9 @@ impl ClientBuilder {
             address_family_selector: None,
             is_prober: false,
             server_public_key: None,
+            auth_token: None,
+            mesh_key: None,
+            protocol: Protocol::default(),
             url: url.into(),
         }
     }
@@ -265,6 +310,27 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the token to authenticate with, for servers which require one.
+    pub fn auth_token(mut self, token: Option<String>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// Set the key to authenticate as a mesh peer of the server.
+    ///
+    /// Mesh peers subscribe to the connection changes of the server on every connect, see
+    /// [`crate::relay::RelayClient::watch_connection_changes`].
+    pub fn mesh_key(mut self, mesh_key: Option<MeshKey>) -> Self {
+        self.mesh_key = mesh_key;
+        self
+    }
+
+    /// Set the [`Protocol`] used to connect to the server.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
     /// Build the [`Client`]
     pub fn build(self, key: SecretKey, dns_resolver: DnsResolver) -> (Client, ClientReceiver) {
         // TODO: review TLS config
@@ -302,9 +368,13 @@ impl ClientBuilder {
             ping_tasks: Default::default(),
             is_prober: self.is_prober,
             server_public_key: self.server_public_key,
+            auth_token: self.auth_token,
+            mesh_key: self.mesh_key,
+            protocol: self.protocol,
             url: self.url,
             tls_connector,
             dns_resolver,
+            reconnect_after: None,
         };
 
         let (msg_sender, inbox) = mpsc::channel(64);
@@ -415,6 +485,23 @@ impl Client {
         self.send_actor(|s| ActorMessage::Send(dst_key, b, s)).await
     }
 
+    /// Forward a packet from `src_key` to the client `dst_key` of the server.
+    ///
+    /// Only allowed for mesh peers, see [`ClientBuilder::mesh_key`]. If there is no underlying
+    /// active relay connection, it creates one before attempting to send the message.
+    ///
+    /// If there is an error sending the packet, it closes the underlying relay connection before
+    /// returning.
+    pub async fn forward_packet(
+        &self,
+        src_key: PublicKey,
+        dst_key: PublicKey,
+        b: Bytes,
+    ) -> Result<(), ClientError> {
+        self.send_actor(|s| ActorMessage::ForwardPacket(src_key, dst_key, b, s))
+            .await
+    }
+
     /// Close the http relay connection.
     pub async fn close(self) -> Result<(), ClientError> {
         self.send_actor(ActorMessage::Close).await
@@ -466,6 +553,10 @@ impl Actor {
                             let res = self.send(key, data).await;
                             s.send(res).ok();
                         },
+                        ActorMessage::ForwardPacket(src_key, dst_key, data, s) => {
+                            let res = self.forward_packet(src_key, dst_key, data).await;
+                            s.send(res).ok();
+                        },
                         ActorMessage::Close(s) => {
                             let res = self.close().await;
                             s.send(Ok(res)).ok();
@@ -499,6 +590,12 @@ impl Actor {
         }
         async move {
             if self.relay_client.is_none() {
+                if let Some(reconnect_after) = self.reconnect_after {
+                    if Instant::now() < reconnect_after {
+                        return Err(ClientError::ServerRestarting);
+                    }
+                    self.reconnect_after = None;
+                }
                 trace!("no connection, trying to connect");
                 let (relay_client, receiver) =
                     tokio::time::timeout(CONNECT_TIMEOUT, self.connect_0())
@@ -523,6 +620,50 @@ impl Actor {
     }
 
     async fn connect_0(&self) -> Result<(RelayClient, RelayClientReceiver), ClientError> {
+        let (reader, writer, local_addr) = match self.protocol {
+            Protocol::Relay => self.connect_relay().await?,
+            Protocol::WebSocket => self.connect_websocket().await?,
+            Protocol::RelayWithWebSocketFallback => match self.connect_relay().await {
+                Ok(conn) => conn,
+                Err(
+                    err @ (ClientError::UnexpectedStatusCode(..)
+                    | ClientError::Hyper(_)
+                    | ClientError::Upgrade(_)),
+                ) => {
+                    warn!("relay upgrade failed, falling back to websocket: {err}");
+                    self.connect_websocket().await?
+                }
+                Err(err) => return Err(err),
+            },
+        };
+
+        let (relay_client, receiver) =
+            RelayClientBuilder::new(self.secret_key.clone(), local_addr, reader, writer)
+                .can_ack_pings(self.can_ack_pings)
+                .prober(self.is_prober)
+                .server_public_key(self.server_public_key)
+                .auth_token(self.auth_token.clone())
+                .mesh_key(self.mesh_key)
+                .build()
+                .await
+                .map_err(|e| ClientError::Build(e.to_string()))?;
+
+        if self.is_preferred && relay_client.note_preferred(true).await.is_err() {
+            relay_client.close().await;
+            return Err(ClientError::Send);
+        }
+
+        if self.mesh_key.is_some() && relay_client.watch_connection_changes().await.is_err() {
+            relay_client.close().await;
+            return Err(ClientError::Send);
+        }
+
+        trace!("connect_0 done");
+        Ok((relay_client, receiver))
+    }
+
+    /// Connects to the relay server with an HTTP upgrade to the relay protocol.
+    async fn connect_relay(&self) -> Result<(ConnReader, ConnWriter, SocketAddr), ClientError> {
         let tcp_stream = self.dial_url().await?;
 
         let local_addr = tcp_stream
@@ -567,23 +708,46 @@ impl Actor {
         debug!("connection upgraded");
         let (reader, writer) =
             downcast_upgrade(upgraded).map_err(|e| ClientError::Upgrade(e.to_string()))?;
+        Ok((reader, writer, local_addr))
+    }
 
-        let (relay_client, receiver) =
-            RelayClientBuilder::new(self.secret_key.clone(), local_addr, reader, writer)
-                .can_ack_pings(self.can_ack_pings)
-                .prober(self.is_prober)
-                .server_public_key(self.server_public_key)
-                .build()
-                .await
-                .map_err(|e| ClientError::Build(e.to_string()))?;
+    /// Connects to the relay server with a WebSocket upgrade.
+    async fn connect_websocket(&self) -> Result<(ConnReader, ConnWriter, SocketAddr), ClientError> {
+        let url = self.websocket_url()?;
+        let tcp_stream = self.dial_url().await?;
 
-        if self.is_preferred && relay_client.note_preferred(true).await.is_err() {
-            relay_client.close().await;
-            return Err(ClientError::Send);
-        }
+        let local_addr = tcp_stream
+            .local_addr()
+            .map_err(|e| ClientError::NoLocalAddr(e.to_string()))?;
 
-        trace!("connect_0 done");
-        Ok((relay_client, receiver))
+        debug!(server_addr = ?tcp_stream.peer_addr(), %local_addr, "TCP stream connected");
+
+        let (reader, writer) = if self.use_https() {
+            debug!("Starting TLS handshake");
+            let hostname = self
+                .tls_servername()
+                .ok_or_else(|| ClientError::InvalidUrl("No tls servername".into()))?;
+            let tls_stream = self.tls_connector.connect(hostname, tcp_stream).await?;
+            debug!("tls_connector connect success");
+            Self::start_websocket(&url, tls_stream).await?
+        } else {
+            Self::start_websocket(&url, tcp_stream).await?
+        };
+        Ok((reader, writer, local_addr))
+    }
+
+    /// Performs the WebSocket handshake with the relay server.
+    async fn start_websocket<T>(url: &Url, io: T) -> Result<(ConnReader, ConnWriter), ClientError>
+    where
+        T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        debug!(%url, "Sending websocket upgrade request");
+        let (ws, _response) = tokio_tungstenite::client_async(url.as_str(), io)
+            .await
+            .map_err(|e| ClientError::Upgrade(e.to_string()))?;
+        debug!("websocket connected");
+        let (reader, writer) = tokio::io::split(WsStream::new(ws));
+        Ok((Box::new(reader), Box::new(writer)))
     }
 
     /// Sends the HTTP upgrade request to the relay server.
@@ -608,7 +772,7 @@ impl Actor {
         );
         debug!("Sending upgrade request");
         let req = Request::builder()
-            .uri("/derp")
+            .uri(RELAY_PATH)
             .header(UPGRADE, super::HTTP_UPGRADE_PROTOCOL)
             .body(http_body_util::Empty::<hyper::body::Bytes>::new())?;
         request_sender.send_request(req).await.map_err(From::from)
@@ -685,6 +849,21 @@ impl Actor {
         Ok(())
     }
 
+    async fn forward_packet(
+        &mut self,
+        src_key: PublicKey,
+        dst_key: PublicKey,
+        b: Bytes,
+    ) -> Result<(), ClientError> {
+        trace!(src = %src_key.fmt_short(), dst = %dst_key.fmt_short(), len = b.len(), "forward packet");
+        let (client, _, _) = self.connect().await?;
+        if client.forward_packet(src_key, dst_key, b).await.is_err() {
+            self.close_for_reconnect().await;
+            return Err(ClientError::Send);
+        }
+        Ok(())
+    }
+
     async fn send_pong(&mut self, data: [u8; 8]) -> Result<(), ClientError> {
         debug!("send_pong");
         if self.can_ack_pings {
@@ -740,6 +919,16 @@ impl Actor {
         }
     }
 
+    /// The url of the relay endpoint, with the matching WebSocket scheme.
+    fn websocket_url(&self) -> Result<Url, ClientError> {
+        let mut url: Url = (*self.url).clone();
+        let scheme = if self.use_https() { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|_| ClientError::InvalidUrl(format!("no websocket url for {}", self.url)))?;
+        url.set_path(RELAY_PATH);
+        Ok(url)
+    }
+
     fn use_https(&self) -> bool {
         // only disable https if we are explicitly dialing a http url
         if self.url.scheme() == "http" {
@@ -787,9 +976,20 @@ impl Actor {
     async fn recv_detail(&mut self) -> Result<(ReceivedMessage, usize), ClientError> {
         loop {
             trace!("recv_detail tick");
+            if let (None, Some(reconnect_after)) = (&self.relay_client, self.reconnect_after) {
+                // wait for the server to be back
+                tokio::time::sleep_until(reconnect_after).await;
+            }
             let (_client, client_receiver, conn_gen) = self.connect().await?;
             match client_receiver.recv().await {
                 Ok(msg) => {
+                    if let ReceivedMessage::ServerRestarting {
+                        reconnect_in,
+                        try_for,
+                    } = msg
+                    {
+                        self.note_restarting(reconnect_in, try_for);
+                    }
                     if let ReceivedMessage::Pong(ping) = msg {
                         match self.pings.unregister(ping, "pong") {
                             Some(chan) => {
@@ -817,6 +1017,18 @@ impl Actor {
         }
     }
 
+    /// Delays reconnecting until the server is back, to a random point of the window the server
+    /// advertised, so that the clients of the server do not all reconnect at once.
+    fn note_restarting(&mut self, reconnect_in: Duration, try_for: Duration) {
+        let delay = reconnect_in + rand::thread_rng().gen_range(Duration::ZERO..=try_for);
+        debug!(
+            ?reconnect_in,
+            ?try_for,
+            "server restarting, reconnecting in {delay:?}"
+        );
+        self.reconnect_after = Some(Instant::now() + delay);
+    }
+
     /// Close the underlying relay connection. The next time the client takes some action that
     /// requires a connection, it will call `connect`.
     async fn close_for_reconnect(&mut self) {
@@ -857,12 +1069,7 @@ async fn resolve_host(
     }
 }
 
-fn downcast_upgrade(
-    upgraded: Upgraded,
-) -> anyhow::Result<(
-    Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
-    Box<dyn AsyncWrite + Unpin + Send + Sync + 'static>,
-)> {
+fn downcast_upgrade(upgraded: Upgraded) -> anyhow::Result<(ConnReader, ConnWriter)> {
     match upgraded.downcast::<hyper_util::rt::TokioIo<tokio::net::TcpStream>>() {
         Ok(Parts { read_buf, io, .. }) => {
             let (reader, writer) = tokio::io::split(io.into_inner());
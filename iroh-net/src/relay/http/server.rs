@@ -9,20 +9,25 @@ use derive_more::Debug;
 use futures::future::{Future, FutureExt};
 use http::response::Builder as ResponseBuilder;
 use hyper::body::Incoming;
-use hyper::header::{HeaderValue, UPGRADE};
+use hyper::header::{HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, UPGRADE};
 use hyper::service::Service;
 use hyper::upgrade::Upgraded;
 use hyper::{HeaderMap, Method, Request, Response, StatusCode};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::task::JoinHandle;
 use tokio_rustls_acme::AcmeAcceptor;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::key::SecretKey;
-use crate::relay::http::HTTP_UPGRADE_PROTOCOL;
+use crate::relay::http::websocket::{is_websocket_upgrade, WEBSOCKET_UPGRADE_PROTOCOL};
+use crate::relay::http::{WsStream, HTTP_UPGRADE_PROTOCOL};
 use crate::relay::server::{ClientConnHandler, MaybeTlsStream};
 use crate::relay::MaybeTlsStreamServer;
+use crate::relay::{AccessControl, ClientLimits, MeshConfig, RestartConfig};
 
 type BytesBody = http_body_util::Full<hyper::body::Bytes>;
 type HyperError = Box<dyn std::error::Error + Send + Sync>;
@@ -57,8 +62,9 @@ fn downcast_upgrade(upgraded: Upgraded) -> Result<(MaybeTlsStream, Bytes)> {
 async fn relay_connection_handler(
     conn_handler: &ClientConnHandler,
     upgraded: Upgraded,
+    websocket: bool,
 ) -> Result<()> {
-    debug!("relay_connection upgraded");
+    debug!(websocket, "relay_connection upgraded");
     let (io, read_buf) = downcast_upgrade(upgraded)?;
     ensure!(
         read_buf.is_empty(),
@@ -66,7 +72,14 @@ async fn relay_connection_handler(
         read_buf
     );
 
-    conn_handler.accept(io).await
+    if websocket {
+        let ws = WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+        conn_handler
+            .accept(MaybeTlsStream::WebSocket(Box::new(WsStream::new(ws))))
+            .await
+    } else {
+        conn_handler.accept(io).await
+    }
 }
 
 /// A Relay Server handler. Created using [`ServerBuilder::spawn`], it starts a relay server
@@ -81,6 +94,8 @@ pub struct Server {
 
 impl Server {
     /// Close the underlying relay server and the HTTP(S) server task
+    ///
+    /// The relay server announces the restart to its clients first, see [`RestartConfig`].
     pub async fn shutdown(self) {
         if let Some(server) = self.server {
             server.close().await;
@@ -143,6 +158,22 @@ pub struct ServerBuilder {
     /// When `None`, a default is provided.
     #[debug("{}", not_found_fn.as_ref().map_or("None", |_| "Some(Box<Fn(ResponseBuilder) -> Result<Response<Body>> + Send + Sync + 'static>)"))]
     not_found_fn: Option<HyperHandler>,
+    /// Which clients are admitted to the relay server.
+    ///
+    /// When `None`, all clients are admitted.
+    access_control: Option<AccessControl>,
+    /// Limits applied to each client connection of the relay server.
+    ///
+    /// When `None`, [`ClientLimits::default`] is used.
+    client_limits: Option<ClientLimits>,
+    /// The mesh the relay server is part of.
+    ///
+    /// When `None`, the relay server is not part of a mesh.
+    mesh: Option<MeshConfig>,
+    /// How the relay server announces a restart to its clients when shut down.
+    ///
+    /// When `None`, [`RestartConfig::default`] is used.
+    restart_config: Option<RestartConfig>,
 }
 
 impl ServerBuilder {
@@ -157,6 +188,10 @@ impl ServerBuilder {
             relay_override: None,
             headers: HeaderMap::new(),
             not_found_fn: None,
+            access_control: None,
+            client_limits: None,
+            mesh: None,
+            restart_config: None,
         }
     }
 
@@ -203,6 +238,33 @@ impl ServerBuilder {
         self
     }
 
+    /// Set the [`AccessControl`] which decides which clients are admitted to the relay server.
+    ///
+    /// Keep a clone to update the configuration of the running server.
+    pub fn access_control(mut self, access: AccessControl) -> Self {
+        self.access_control = Some(access);
+        self
+    }
+
+    /// Set the [`ClientLimits`] applied to each client connection of the relay server.
+    pub fn client_limits(mut self, limits: ClientLimits) -> Self {
+        self.client_limits = Some(limits);
+        self
+    }
+
+    /// Make the relay server part of a mesh, see [`MeshConfig`].
+    pub fn mesh(mut self, config: MeshConfig) -> Self {
+        self.mesh = Some(config);
+        self
+    }
+
+    /// Set how the relay server announces a restart to its clients when shut down, see
+    /// [`RestartConfig`].
+    pub fn restart_config(mut self, config: RestartConfig) -> Self {
+        self.restart_config = Some(config);
+        self
+    }
+
     /// Add http headers.
     pub fn headers(mut self, headers: HeaderMap) -> Self {
         for (k, v) in headers.iter() {
@@ -215,7 +277,19 @@ impl ServerBuilder {
     pub async fn spawn(self) -> Result<Server> {
         ensure!(self.secret_key.is_some() || self.relay_override.is_some(), "Must provide a `SecretKey` for the relay server OR pass in an override function for the 'relay' endpoint");
         let (relay_handler, relay_server) = if let Some(secret_key) = self.secret_key {
-            let server = crate::relay::server::Server::new(secret_key.clone());
+            let mut server = crate::relay::server::Server::new(secret_key.clone());
+            if let Some(access) = self.access_control {
+                server.set_access_control(access);
+            }
+            if let Some(limits) = self.client_limits {
+                server.set_client_limits(limits);
+            }
+            if let Some(config) = self.mesh {
+                server.enable_mesh(config);
+            }
+            if let Some(config) = self.restart_config {
+                server.set_restart_config(config);
+            }
             (
                 RelayHandler::ConnHandler(server.client_conn_handler(self.headers.clone())),
                 Some(server),
@@ -347,6 +421,14 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                     *res.status_mut() = StatusCode::BAD_REQUEST;
                     return Ok(res);
                 }
+                let websocket_key = is_websocket_upgrade(req.headers())
+                    .then(|| req.headers()[SEC_WEBSOCKET_KEY].clone());
+                let websocket = websocket_key.is_some();
+                let protocol = if websocket {
+                    WEBSOCKET_UPGRADE_PROTOCOL
+                } else {
+                    HTTP_UPGRADE_PROTOCOL
+                };
 
                 // Setup a future that will eventually receive the upgraded
                 // connection and talk a new protocol, and spawn the future
@@ -359,17 +441,16 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                     async move {
                         match hyper::upgrade::on(&mut req).await {
                             Ok(upgraded) => {
-                                if let Err(e) =
-                                    relay_connection_handler(&closure_conn_handler, upgraded).await
+                                if let Err(e) = relay_connection_handler(
+                                    &closure_conn_handler,
+                                    upgraded,
+                                    websocket,
+                                )
+                                .await
                                 {
-                                    tracing::warn!(
-                                        "upgrade to \"{HTTP_UPGRADE_PROTOCOL}\": io error: {:?}",
-                                        e
-                                    );
+                                    tracing::warn!("upgrade to \"{protocol}\": io error: {:?}", e);
                                 } else {
-                                    tracing::debug!(
-                                        "upgrade to \"{HTTP_UPGRADE_PROTOCOL}\" success"
-                                    );
+                                    tracing::debug!("upgrade to \"{protocol}\" success");
                                 };
                             }
                             Err(e) => tracing::warn!("upgrade error: {:?}", e),
@@ -379,10 +460,20 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                 );
 
                 // Now return a 101 Response saying we agree to the upgrade to the
-                // HTTP_UPGRADE_PROTOCOL
+                // HTTP_UPGRADE_PROTOCOL, or to the WebSocket
                 *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
                 res.headers_mut()
-                    .insert(UPGRADE, HeaderValue::from_static(HTTP_UPGRADE_PROTOCOL));
+                    .insert(UPGRADE, HeaderValue::from_static(protocol));
+                if let Some(key) = websocket_key {
+                    res.headers_mut()
+                        .insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+                    res.headers_mut().insert(
+                        SEC_WEBSOCKET_ACCEPT,
+                        derive_accept_key(key.as_bytes())
+                            .parse()
+                            .expect("valid header value"),
+                    );
+                }
                 Ok(res)
             }
         }
@@ -0,0 +1,158 @@
+//! Carries the relay protocol over WebSocket connections.
+//!
+//! Proxies which do not understand the custom relay upgrade often still pass WebSocket traffic.
+//! The relay frames are sent unchanged in the binary messages of the WebSocket, so both ends use
+//! the same codec regardless of the transport.
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures::{ready, Sink, Stream};
+use hyper::HeaderMap;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// The `Upgrade` header value of WebSocket connections.
+pub(crate) const WEBSOCKET_UPGRADE_PROTOCOL: &str = "websocket";
+
+/// Whether the request headers ask for an upgrade to a WebSocket connection.
+pub(crate) fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    let upgrade = headers
+        .get(hyper::header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case(WEBSOCKET_UPGRADE_PROTOCOL));
+    upgrade && headers.contains_key(hyper::header::SEC_WEBSOCKET_KEY)
+}
+
+/// A byte stream over a WebSocket connection.
+///
+/// Every write is sent as a single binary message, the payloads of received binary messages are
+/// read back to back. Pings and pongs are handled by the WebSocket, text messages are an error.
+pub struct WsStream<S> {
+    inner: WebSocketStream<S>,
+    /// The unread part of the last received message.
+    read_buf: Bytes,
+}
+
+impl<S> WsStream<S> {
+    /// Wraps an established WebSocket connection.
+    pub(crate) fn new(inner: WebSocketStream<S>) -> Self {
+        Self {
+            inner,
+            read_buf: Bytes::new(),
+        }
+    }
+}
+
+impl<S> fmt::Debug for WsStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsStream")
+            .field("read_buf", &self.read_buf.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.read_buf.is_empty() {
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => self.read_buf = data.into(),
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                // a closed connection is the end of the stream
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Ok(Message::Text(_) | Message::Frame(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected websocket message",
+                    )));
+                }
+                Some(Err(err)) => return Poll::Ready(Err(into_io_error(err))),
+            }
+        }
+        let len = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf[..len]);
+        self.read_buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(into_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+fn into_io_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::ErrorKind::BrokenPipe.into()
+        }
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::SinkExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    #[tokio::test]
+    async fn test_ws_stream() -> anyhow::Result<()> {
+        let (a, b) = tokio::io::duplex(1024);
+        let mut a = WsStream::new(WebSocketStream::from_raw_socket(a, Role::Client, None).await);
+        let mut b = WsStream::new(WebSocketStream::from_raw_socket(b, Role::Server, None).await);
+
+        // writes are read back to back, across message boundaries
+        a.write_all(b"hello ").await?;
+        a.write_all(b"world").await?;
+        a.flush().await?;
+        let mut buf = [0u8; 11];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello world");
+
+        // text messages are rejected
+        a.inner.send(Message::Text("hello".into())).await?;
+        assert!(b.read(&mut buf).await.is_err());
+
+        // closing the connection ends the stream
+        a.shutdown().await?;
+        drop(a);
+        assert_eq!(b.read(&mut buf).await?, 0);
+        Ok(())
+    }
+}
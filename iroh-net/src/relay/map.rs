@@ -77,6 +77,7 @@ impl RelayMap {
                 url,
                 stun_only: false,
                 stun_port,
+                auth_token: None,
             }
             .into(),
         );
@@ -114,7 +115,7 @@ impl fmt::Display for RelayMap {
 /// Information on a specific relay server.
 ///
 /// Includes the Url where it can be dialed.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
+#[derive(derive_more::Debug, Clone, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct RelayNode {
     /// The [`RelayUrl`] where this relay server can be dialed.
     pub url: RelayUrl,
@@ -127,6 +128,10 @@ pub struct RelayNode {
     ///
     /// Setting this to `0` means the default STUN port is used.
     pub stun_port: u16,
+    /// Token to authenticate with this relay server, if it requires one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[debug("{}", auth_token.as_ref().map_or("None", |_| "Some(..)"))]
+    pub auth_token: Option<String>,
 }
 
 impl fmt::Display for RelayNode {
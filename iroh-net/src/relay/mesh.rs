@@ -0,0 +1,316 @@
+//! Meshing of relay servers.
+//!
+//! Relay servers which share a [`MeshKey`] can form a mesh, so that clients connected to
+//! different servers of the mesh can reach each other. This allows running several relay servers
+//! behind a single hostname.
+//!
+//! Each server connects to every other server of the mesh as a client which authenticates with the
+//! mesh key, and subscribes to the connects and disconnects of the clients of that server.
+//! Packets for clients which are not connected locally, but to a mesh peer, are forwarded to that
+//! peer, which delivers them to its client. Forwarded packets are never forwarded again.
+//!
+//! Mesh peers bypass the [`super::AccessControl`] and the [`super::ClientLimits`] of the server:
+//! they are admitted by the mesh key alone, and their connections are not rate limited, because
+//! they carry the packets of many clients. These clients are subject to the access control and
+//! limits of the server they are connected to, so all servers of a mesh should use the same
+//! configuration.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use super::{
+    http::ClientBuilder,
+    types::{Packet, ServerMessage},
+    HttpClient, ReceivedMessage, RelayUrl,
+};
+use crate::key::{PublicKey, SecretKey};
+
+/// Number of packets buffered for forwarding to a mesh peer.
+const MESH_SEND_QUEUE_DEPTH: usize = 1024;
+
+/// Delay before reconnecting to a mesh peer after the connection failed.
+const MESH_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+static FORWARDER_ID: AtomicUsize = AtomicUsize::new(1);
+fn new_forwarder_id() -> usize {
+    FORWARDER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A key shared by the relay servers of a mesh, which authenticates them to each other.
+///
+/// Parsed from and displayed as 64 hex characters.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MeshKey([u8; 32]);
+
+impl MeshKey {
+    /// Generate a new random [`MeshKey`].
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// The bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Compares the key with `other` in constant time.
+    pub(crate) fn matches(&self, other: &[u8; 32]) -> bool {
+        self.0
+            .iter()
+            .zip(other.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+impl From<[u8; 32]> for MeshKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl fmt::Debug for MeshKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MeshKey(..)")
+    }
+}
+
+impl fmt::Display for MeshKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for MeshKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = hex::decode(s.trim()).context("mesh key is not hex encoded")?;
+        ensure!(bytes.len() == 32, "mesh key must be 32 bytes");
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes);
+        Ok(Self(key))
+    }
+}
+
+impl TryFrom<String> for MeshKey {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<MeshKey> for String {
+    fn from(key: MeshKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// Configuration of the mesh a relay server is part of.
+///
+/// Anyone with the mesh key can connect as a mesh peer, without access control or rate limits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MeshConfig {
+    /// The key shared by all relay servers of the mesh.
+    pub mesh_key: MeshKey,
+    /// The urls of the other relay servers of the mesh.
+    ///
+    /// Servers must be reachable by these urls directly, not through a load balancer. The url
+    /// of the server itself may be included, it is skipped. Every server of the mesh must have its
+    /// own secret key.
+    pub peers: Vec<RelayUrl>,
+}
+
+/// A change of the clients connected to a server, sent to the mesh peers watching it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PeerChange {
+    /// The client connected.
+    Present(PublicKey),
+    /// The client disconnected.
+    Gone(PublicKey),
+}
+
+/// Forwards packets to the clients of a mesh peer.
+#[derive(Debug, Clone)]
+pub(crate) struct PacketForwarder {
+    /// Identifies the mesh peer connection, so that a route is only removed by the connection
+    /// which added it.
+    pub(crate) id: usize,
+    sender: mpsc::Sender<(PublicKey, Packet)>,
+}
+
+impl PacketForwarder {
+    /// Queues a packet for forwarding to `dst`, returns `false` if it was dropped.
+    pub(crate) fn forward(&self, dst: PublicKey, packet: Packet) -> bool {
+        self.sender.try_send((dst, packet)).is_ok()
+    }
+}
+
+/// Maintains the connection to the mesh peer at `url`, until the server shuts down.
+///
+/// Routes to the clients of the peer are registered with the server through `server_channel`.
+pub(crate) async fn run_mesh_client(
+    secret_key: SecretKey,
+    mesh_key: MeshKey,
+    url: RelayUrl,
+    server_channel: mpsc::Sender<ServerMessage>,
+) {
+    let (sender, mut packets) = mpsc::channel(MESH_SEND_QUEUE_DEPTH);
+    let mut peer = MeshPeer {
+        forwarder: PacketForwarder {
+            id: new_forwarder_id(),
+            sender,
+        },
+        server_channel,
+        present: HashSet::new(),
+        conn_gen: None,
+    };
+    let (client, mut receiver) = ClientBuilder::new(url.clone())
+        .mesh_key(Some(mesh_key))
+        .build(secret_key.clone(), crate::dns::default_resolver().clone());
+
+    // after a failed connection, stop receiving for a while, which delays the reconnect, while
+    // still draining the packets to forward
+    let backoff = tokio::time::sleep(Duration::ZERO);
+    tokio::pin!(backoff);
+    let mut backing_off = false;
+    loop {
+        tokio::select! {
+            _ = &mut backoff, if backing_off => {
+                backing_off = false;
+            }
+            msg = receiver.recv(), if !backing_off => {
+                let Some(msg) = msg else {
+                    break;
+                };
+                let res = match msg {
+                    Ok((msg, conn_gen)) => {
+                        if peer.conn_gen != Some(conn_gen) && is_self(&client, &secret_key).await {
+                            info!(%url, "mesh peer is this server, skipping");
+                            peer.clear().await.ok();
+                            break;
+                        }
+                        peer.handle_message(msg, conn_gen).await
+                    }
+                    Err(err) => {
+                        warn!(%url, "mesh peer connection failed: {err:#}");
+                        backoff
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + MESH_RECONNECT_DELAY);
+                        backing_off = true;
+                        peer.clear().await
+                    }
+                };
+                if res.is_err() {
+                    // the server is shut down
+                    break;
+                }
+            }
+            Some((dst, packet)) = packets.recv() => {
+                if let Err(err) = client.forward_packet(packet.src, dst, packet.bytes).await {
+                    debug!(%url, "failed to forward packet: {err:#}");
+                }
+            }
+        }
+    }
+    client.close().await.ok();
+}
+
+/// Whether the client is connected to the server with the key `secret_key`.
+async fn is_self(client: &HttpClient, secret_key: &SecretKey) -> bool {
+    match client.connect().await {
+        Ok((relay_client, _)) => relay_client.server_public_key() == secret_key.public(),
+        Err(_) => false,
+    }
+}
+
+/// The routes to the clients of a mesh peer.
+#[derive(Debug)]
+struct MeshPeer {
+    forwarder: PacketForwarder,
+    server_channel: mpsc::Sender<ServerMessage>,
+    /// The clients currently connected to the peer.
+    present: HashSet<PublicKey>,
+    /// The generation of the connection to the peer, `None` while disconnected.
+    conn_gen: Option<usize>,
+}
+
+impl MeshPeer {
+    async fn handle_message(&mut self, msg: ReceivedMessage, conn_gen: usize) -> Result<()> {
+        if self.conn_gen != Some(conn_gen) {
+            // on a new connection, the peer announces all of its clients again
+            self.clear().await?;
+            self.conn_gen = Some(conn_gen);
+        }
+        match msg {
+            ReceivedMessage::PeerPresent(key) => {
+                if self.present.insert(key) {
+                    self.send_server(ServerMessage::AddPacketForwarder((
+                        key,
+                        self.forwarder.clone(),
+                    )))
+                    .await?;
+                }
+            }
+            ReceivedMessage::PeerGone(key) => {
+                if self.present.remove(&key) {
+                    self.send_server(ServerMessage::RemovePacketForwarder((
+                        key,
+                        self.forwarder.id,
+                    )))
+                    .await?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Removes all routes through this peer.
+    async fn clear(&mut self) -> Result<()> {
+        self.conn_gen = None;
+        for key in std::mem::take(&mut self.present) {
+            self.send_server(ServerMessage::RemovePacketForwarder((
+                key,
+                self.forwarder.id,
+            )))
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn send_server(&self, msg: ServerMessage) -> Result<()> {
+        self.server_channel
+            .send(msg)
+            .await
+            .map_err(|_| anyhow::anyhow!("server gone"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mesh_key() -> Result<()> {
+        let key = MeshKey::generate();
+        let parsed: MeshKey = key.to_string().parse()?;
+        assert!(parsed.matches(key.as_bytes()));
+        assert!(!MeshKey::generate().matches(key.as_bytes()));
+        assert!("abcd".parse::<MeshKey>().is_err());
+        assert_eq!(format!("{key:?}"), "MeshKey(..)");
+        Ok(())
+    }
+}
@@ -36,6 +36,13 @@ pub struct Metrics {
     /// Packets of other `FrameType`s dropped
     pub other_packets_dropped: Counter,
 
+    /// Packets a client sent above its rate limit, which were dropped
+    pub packets_throttled_recv: Counter,
+    /// Packets to a client above its rate limit, which were dropped
+    pub packets_throttled_send: Counter,
+    /// Packets to a client dropped because its send queue was full
+    pub packets_dropped_queue_full: Counter,
+
     /// Number of packets we have forwarded out to another packet forwarder
     pub packets_forwarded_out: Counter,
     /// Number of packets we have been asked to forward
@@ -60,6 +67,8 @@ pub struct Metrics {
     pub accepts: Counter,
     /// Number of connections we have removed because of an error
     pub disconnects: Counter,
+    /// Number of clients rejected by the access control
+    pub rejected_clients: Counter,
     // TODO: enable when we can have multiple connections for one node id
     // pub duplicate_client_keys: Counter,
     // pub duplicate_client_conns: Counter,
@@ -92,6 +101,16 @@ impl Default for Metrics {
                 "Number of times a non-disco, non-'send; packet was dropped.",
             ),
 
+            packets_throttled_recv: Counter::new(
+                "Number of packets received from clients above their rate limit, and dropped.",
+            ),
+            packets_throttled_send: Counter::new(
+                "Number of packets to clients above their rate limit, and dropped.",
+            ),
+            packets_dropped_queue_full: Counter::new(
+                "Number of packets dropped because the send queue of the client was full.",
+            ),
+
             packets_forwarded_out: Counter::new(
                 "Number of times the server has sent a forwarded packet",
             ),
@@ -115,6 +134,9 @@ impl Default for Metrics {
 
             accepts: Counter::new("Number of times this server has accepted a connection."),
             disconnects: Counter::new("Number of clients that have then disconnected."),
+            rejected_clients: Counter::new(
+                "Number of clients that were rejected by the access control.",
+            ),
             // TODO: enable when we can have multiple connections for one node id
             // pub duplicate_client_keys: Counter::new("Number of duplicate client keys."),
             // pub duplicate_client_conns: Counter::new("Number of duplicate client connections."),
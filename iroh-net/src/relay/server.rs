@@ -1,11 +1,13 @@
 //! based on tailscale/derp/derp_server.go
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::{Context as _, Result};
+use anyhow::{bail, Context as _, Result};
+use bytes::Bytes;
 use futures::SinkExt;
 use hyper::HeaderMap;
 use iroh_metrics::core::UsageStatsReport;
@@ -17,18 +19,23 @@ use tokio_util::codec::Framed;
 use tokio_util::sync::CancellationToken;
 use tracing::{info_span, trace, Instrument};
 
+use crate::disco::looks_like_disco_wrapper;
 use crate::key::{PublicKey, SecretKey, SharedSecret};
+use crate::util::AbortingJoinHandle;
 
 use super::{
-    client_conn::ClientConnBuilder,
+    access::AccessControl,
+    client_conn::{ClientConnBuilder, ClientLimits},
     clients::Clients,
     codec::{
         recv_client_key, write_frame, DerpCodec, Frame, PER_CLIENT_SEND_QUEUE_DEPTH,
         PROTOCOL_VERSION, SERVER_CHANNEL_SIZE,
     },
+    http::WsStream,
+    mesh::{run_mesh_client, MeshConfig, MeshKey, PacketForwarder},
     metrics::Metrics,
     types::ServerInfo,
-    types::ServerMessage,
+    types::{ClientInfo, Packet, Restarting, ServerMessage},
 };
 
 // TODO: skipping `verboseDropKeys` for now
@@ -40,6 +47,33 @@ fn new_conn_num() -> usize {
 
 pub(crate) const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// How the [`Server`] announces a restart to its clients when it is closed.
+///
+/// On [`Server::close`] the server tells its clients that it is restarting, keeps serving them
+/// for the grace period, and then shuts down. The clients wait until the end of the grace period
+/// before reconnecting, and spread their reconnects over the reconnect window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartConfig {
+    /// How long the server keeps serving its clients after announcing the restart.
+    ///
+    /// Defaults to zero, the server shuts down right after the announcement.
+    pub grace_period: Duration,
+    /// How long after the grace period the clients should try to reconnect.
+    ///
+    /// Clients pick a random point in this window for their first reconnect. Defaults to 5
+    /// seconds.
+    pub reconnect_window: Duration,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::ZERO,
+            reconnect_window: Duration::from_secs(5),
+        }
+    }
+}
+
 /// A relay server.
 ///
 /// Responsible for managing connections to relay [`super::client::Client`]s, sending packets from one client to another.
@@ -63,6 +97,18 @@ pub struct Server {
     loop_handler: JoinHandle<Result<()>>,
     /// Done token, forces a hard shutdown. To gracefully shutdown, use [`Server::close`]
     cancel: CancellationToken,
+    /// Which clients are admitted
+    access: AccessControl,
+    /// Tells the server loop about updates of the access control
+    access_task: AbortingJoinHandle<()>,
+    /// Limits applied to each client connection
+    limits: ClientLimits,
+    /// The key mesh peers authenticate with, if the server is part of a mesh
+    mesh_key: Option<MeshKey>,
+    /// The connections to the mesh peers
+    mesh_tasks: Vec<AbortingJoinHandle<()>>,
+    /// How the restart is announced when the server is closed
+    restart: RestartConfig,
     // TODO: stats collection
 }
 
@@ -78,6 +124,8 @@ impl Server {
                 .instrument(info_span!("relay.server", me = %key.public().fmt_short())),
         );
         let meta_cert = init_meta_cert(&key.public());
+        let access = AccessControl::default();
+        let access_task = watch_access(&access, server_channel_s.clone());
         Self {
             write_timeout: Some(WRITE_TIMEOUT),
             secret_key: key,
@@ -88,6 +136,80 @@ impl Server {
             server_info: ServerInfo::no_rate_limit(),
             loop_handler: server_task,
             cancel: cancel_token,
+            access,
+            access_task,
+            limits: ClientLimits::default(),
+            mesh_key: None,
+            mesh_tasks: Vec::new(),
+            restart: RestartConfig::default(),
+        }
+    }
+
+    /// Returns the [`AccessControl`] which decides which clients are admitted.
+    ///
+    /// By default, all clients are admitted.
+    pub fn access_control(&self) -> &AccessControl {
+        &self.access
+    }
+
+    /// Sets the [`AccessControl`] which decides which clients are admitted.
+    ///
+    /// Only applies to [`ClientConnHandler`]s created afterwards. When the access control is
+    /// updated, connected clients which are no longer admitted are disconnected.
+    pub fn set_access_control(&mut self, access: AccessControl) {
+        self.access_task = watch_access(&access, self.server_channel.clone());
+        self.access = access;
+    }
+
+    /// Returns the [`ClientLimits`] applied to each client connection.
+    pub fn client_limits(&self) -> &ClientLimits {
+        &self.limits
+    }
+
+    /// Sets the [`ClientLimits`] applied to each client connection.
+    ///
+    /// The receive rate limit is announced to clients in the server info. Only applies to
+    /// [`ClientConnHandler`]s created afterwards.
+    pub fn set_client_limits(&mut self, limits: ClientLimits) {
+        self.server_info = match limits.recv_rate {
+            Some(rate) => ServerInfo {
+                version: PROTOCOL_VERSION,
+                token_bucket_bytes_per_second: rate.bytes_per_second,
+                token_bucket_bytes_burst: rate.burst(),
+            },
+            None => ServerInfo::no_rate_limit(),
+        };
+        self.limits = limits;
+    }
+
+    /// Returns the [`RestartConfig`] used when the server is closed.
+    pub fn restart_config(&self) -> &RestartConfig {
+        &self.restart
+    }
+
+    /// Sets the [`RestartConfig`] used when the server is closed.
+    pub fn set_restart_config(&mut self, restart: RestartConfig) {
+        self.restart = restart;
+    }
+
+    /// Makes the server part of a mesh, see [`MeshConfig`].
+    ///
+    /// Connects to the mesh peers, to forward packets to their clients. Mesh peers are admitted
+    /// by [`ClientConnHandler`]s created afterwards.
+    pub fn enable_mesh(&mut self, config: MeshConfig) {
+        self.mesh_key = Some(config.mesh_key);
+        for url in config.peers {
+            let span = info_span!("relay.mesh", peer = %url);
+            let task = tokio::task::spawn(
+                run_mesh_client(
+                    self.secret_key.clone(),
+                    config.mesh_key,
+                    url,
+                    self.server_channel.clone(),
+                )
+                .instrument(span),
+            );
+            self.mesh_tasks.push(task.into());
         }
     }
 
@@ -102,8 +224,27 @@ impl Server {
     }
 
     /// Closes the server and waits for the connections to disconnect.
+    ///
+    /// Announces the restart to the clients first and keeps serving them for the grace period,
+    /// see [`RestartConfig`].
     pub async fn close(mut self) {
         if !self.closed {
+            let restarting = Restarting {
+                reconnect_in: self.restart.grace_period,
+                try_for: self.restart.reconnect_window,
+            };
+            if self
+                .server_channel
+                .send(ServerMessage::Restart(restarting))
+                .await
+                .is_ok()
+            {
+                tracing::info!(
+                    "announced restart, shutting down in {:?}",
+                    self.restart.grace_period
+                );
+                tokio::time::sleep(self.restart.grace_period).await;
+            }
             if let Err(err) = self.server_channel.send(ServerMessage::Shutdown).await {
                 tracing::warn!(
                     "could not shutdown the server gracefully, doing a forced shutdown: {:?}",
@@ -133,6 +274,9 @@ impl Server {
             secret_key: self.secret_key.clone(),
             write_timeout: self.write_timeout,
             server_info: self.server_info.clone(),
+            access: self.access.clone(),
+            limits: self.limits,
+            mesh_key: self.mesh_key,
             default_headers: Arc::new(default_headers),
         }
     }
@@ -155,6 +299,9 @@ pub struct ClientConnHandler {
     secret_key: SecretKey,
     write_timeout: Option<Duration>,
     server_info: ServerInfo,
+    access: AccessControl,
+    limits: ClientLimits,
+    mesh_key: Option<MeshKey>,
     pub(super) default_headers: Arc<HeaderMap>,
 }
 
@@ -165,6 +312,9 @@ impl Clone for ClientConnHandler {
             secret_key: self.secret_key.clone(),
             write_timeout: self.write_timeout,
             server_info: self.server_info.clone(),
+            access: self.access.clone(),
+            limits: self.limits,
+            mesh_key: self.mesh_key,
             default_headers: Arc::clone(&self.default_headers),
         }
     }
@@ -177,6 +327,10 @@ impl ClientConnHandler {
     /// some read or write error to the connection,  if the server is meant to verify clients,
     /// and is unable to verify this one, or if there is some issue communicating with the server.
     ///
+    /// Clients which are not admitted by the [`AccessControl`] of the server receive a health
+    /// frame with the reason instead of the server info, and are disconnected. Mesh peers are
+    /// admitted if they present the mesh key of the server.
+    ///
     /// The provided [`AsyncRead`] and [`AsyncWrite`] must be already connected to the connection.
     pub async fn accept(&self, io: MaybeTlsStream) -> Result<()> {
         let mut io = Framed::new(io, DerpCodec);
@@ -185,11 +339,22 @@ impl ClientConnHandler {
             .await
             .context("unable to send server key to client")?;
         trace!("accept: recv client key");
-        let (client_key, _, shared_secret) = recv_client_key(self.secret_key.clone(), &mut io)
-            .await
-            .context("unable to receive client information")?;
+        let (client_key, client_info, shared_secret) =
+            recv_client_key(self.secret_key.clone(), &mut io)
+                .await
+                .context("unable to receive client information")?;
+        let mesh_peer = match self.admit(&client_key, &client_info) {
+            Ok(mesh_peer) => mesh_peer,
+            Err(reason) => {
+                inc!(Metrics, rejected_clients);
+                self.send_health(&mut io, reason)
+                    .await
+                    .context("unable to send rejection to client")?;
+                bail!("rejected client {}: {reason}", client_key.fmt_short());
+            }
+        };
         trace!("accept: send server info");
-        self.send_server_info(&mut io, &shared_secret)
+        self.send_server_info(&mut io, &shared_secret, mesh_peer)
             .await
             .context("unable to sent server info to client {client_key}")?;
         trace!("accept: build client conn");
@@ -199,6 +364,9 @@ impl ClientConnHandler {
             io,
             write_timeout: self.write_timeout,
             channel_capacity: PER_CLIENT_SEND_QUEUE_DEPTH,
+            limits: self.limits,
+            mesh_peer,
+            auth_token: client_info.auth_token,
             server_channel: self.server_channel.clone(),
         };
         trace!("accept: create client");
@@ -211,6 +379,20 @@ impl ClientConnHandler {
         Ok(())
     }
 
+    /// Checks whether the client is admitted, and whether it is a mesh peer.
+    ///
+    /// Returns the reason for the rejection if it is not admitted.
+    fn admit(&self, key: &PublicKey, info: &ClientInfo) -> Result<bool, &'static str> {
+        match (&info.mesh_key, &self.mesh_key) {
+            (None, _) => self
+                .access
+                .check(key, info.auth_token.as_deref())
+                .map(|()| false),
+            (Some(peer_key), Some(mesh_key)) if mesh_key.matches(peer_key) => Ok(true),
+            (Some(_), _) => Err("invalid mesh key"),
+        }
+    }
+
     async fn send_server_key<T>(&self, mut writer: &mut Framed<T, DerpCodec>) -> Result<()>
     where
         T: AsyncWrite + Unpin,
@@ -227,15 +409,41 @@ impl ClientConnHandler {
         Ok(())
     }
 
+    async fn send_health<T>(
+        &self,
+        mut writer: &mut Framed<T, DerpCodec>,
+        problem: &str,
+    ) -> Result<()>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        write_frame(
+            &mut writer,
+            Frame::Health {
+                problem: Bytes::copy_from_slice(problem.as_bytes()),
+            },
+            Some(Duration::from_secs(10)),
+        )
+        .await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
     async fn send_server_info<T>(
         &self,
         mut writer: &mut Framed<T, DerpCodec>,
         shared_secret: &SharedSecret,
+        mesh_peer: bool,
     ) -> Result<()>
     where
         T: AsyncWrite + Unpin,
     {
-        let mut msg = postcard::to_stdvec(&self.server_info)?;
+        // mesh peers are not rate limited
+        let mut msg = if mesh_peer {
+            postcard::to_stdvec(&ServerInfo::no_rate_limit())?
+        } else {
+            postcard::to_stdvec(&self.server_info)?
+        };
         shared_secret.seal(&mut msg);
         write_frame(
             &mut writer,
@@ -255,6 +463,11 @@ pub(crate) struct ServerActor {
     receiver: mpsc::Receiver<ServerMessage>,
     /// All clients connected to this server
     clients: Clients,
+    /// Routes to the clients connected to mesh peers of this server
+    packet_forwarders: HashMap<PublicKey, PacketForwarder>,
+    /// When the server shuts down and how long clients should try to reconnect, once the restart
+    /// has been announced
+    restart: Option<(Instant, Duration)>,
 }
 
 impl ServerActor {
@@ -263,6 +476,29 @@ impl ServerActor {
             key,
             receiver,
             clients: Clients::new(),
+            packet_forwarders: HashMap::new(),
+            restart: None,
+        }
+    }
+
+    /// The restart announcement for a client connecting while the server is restarting.
+    fn restarting(&self) -> Option<Restarting> {
+        self.restart.map(|(shutdown_at, try_for)| Restarting {
+            reconnect_in: shutdown_at.saturating_duration_since(Instant::now()),
+            try_for,
+        })
+    }
+
+    /// Forwards a packet to the mesh peer which `key` is connected to, if any.
+    ///
+    /// Returns `false` if there is no route to `key`, or the packet was dropped.
+    fn forward_packet(&self, key: PublicKey, packet: Packet) -> bool {
+        match self.packet_forwarders.get(&key) {
+            Some(forwarder) if forwarder.forward(key, packet) => {
+                inc!(Metrics, packets_forwarded_out);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -296,7 +532,7 @@ impl ServerActor {
                                 if self.clients.send_packet(&key, packet).is_ok() {
                                     self.clients.record_send(&src, key);
                                 }
-                            } else {
+                            } else if !self.forward_packet(key, packet) {
                                 tracing::warn!("send packet: no way to reach client {key:?}, dropped packet");
                                 inc!(Metrics, send_packets_dropped);
                             }
@@ -311,11 +547,39 @@ impl ServerActor {
 
                                     self.clients.record_send(&src, key);
                                 }
-                            } else {
+                            } else if !self.forward_packet(key, packet) {
                                 tracing::warn!("send disco packet: no way to reach client {key:?}, dropped packet");
                                 inc!(Metrics, disco_packets_dropped);
                             }
                        }
+                       ServerMessage::RecvForwardedPacket((key, packet)) => {
+                           tracing::trace!("recv forwarded packet from: {:?} to: {:?} ({}b)", packet.src, key, packet.bytes.len());
+                           // forwarded packets are only delivered locally, never forwarded again
+                           if !self.clients.contains_key(&key) {
+                               tracing::debug!("recv forwarded packet: client {key:?} is gone, dropped packet");
+                               inc!(Metrics, send_packets_dropped);
+                           } else if looks_like_disco_wrapper(&packet.bytes) {
+                               self.clients.send_disco_packet(&key, packet).ok();
+                           } else {
+                               self.clients.send_packet(&key, packet).ok();
+                           }
+                       }
+                       ServerMessage::AddWatcher(key) => {
+                           self.clients.add_watcher(key);
+                       }
+                       ServerMessage::AddPacketForwarder((key, forwarder)) => {
+                           tracing::trace!("add packet forwarder for: {:?}", key);
+                           inc!(Metrics, added_pkt_fwder);
+                           self.packet_forwarders.insert(key, forwarder);
+                       }
+                       ServerMessage::RemovePacketForwarder((key, id)) => {
+                           // the client might have moved to another mesh peer in the meantime
+                           if self.packet_forwarders.get(&key).is_some_and(|forwarder| forwarder.id == id) {
+                               tracing::trace!("remove packet forwarder for: {:?}", key);
+                               inc!(Metrics, removed_pkt_fwder);
+                               self.packet_forwarders.remove(&key);
+                           }
+                       }
                        ServerMessage::CreateClient(client_builder) => {
                            inc!(Metrics, accepts);
 
@@ -333,6 +597,9 @@ impl ServerActor {
                            // build and register client, starting up read & write loops for the
                            // client connection
                            self.clients.register(client_builder);
+                           if let Some(restarting) = self.restarting() {
+                               self.clients.send_restarting(&key, restarting);
+                           }
 
                        }
                        ServerMessage::RemoveClient((key, conn_num)) => {
@@ -345,6 +612,15 @@ impl ServerActor {
                                self.clients.unregister(&key);
                             }
                        }
+                       ServerMessage::UpdateAccess(access) => {
+                           tracing::debug!("access control updated, checking clients");
+                           self.clients.retain_admitted(&access);
+                       }
+                       ServerMessage::Restart(restarting) => {
+                           tracing::info!("server restarting, announcing to clients...");
+                           self.restart = Some((Instant::now() + restarting.reconnect_in, restarting.try_for));
+                           self.clients.broadcast_restarting(restarting);
+                       }
                        ServerMessage::Shutdown => {
                         tracing::info!("server gracefully shutting down...");
                         // close all client connections and client read/write loops
@@ -358,6 +634,27 @@ impl ServerActor {
     }
 }
 
+/// Forwards the updates of the [`AccessControl`] to the server loop.
+fn watch_access(
+    access: &AccessControl,
+    server_channel: mpsc::Sender<ServerMessage>,
+) -> AbortingJoinHandle<()> {
+    let mut updates = access.subscribe();
+    tokio::task::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let access = updates.borrow_and_update().clone();
+            if server_channel
+                .send(ServerMessage::UpdateAccess(access))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    })
+    .into()
+}
+
 /// Initializes the [`Server`] with a self-signed x509 cert
 /// encoding this server's public key and protocol version. "cmd/relay_server
 /// then sends this after the Let's Encrypt leaf + intermediate certs after
@@ -395,6 +692,8 @@ pub enum MaybeTlsStream {
     Plain(tokio::net::TcpStream),
     /// A Tls wrapped [`tokio::net::TcpStream`]
     Tls(tokio_rustls::server::TlsStream<tokio::net::TcpStream>),
+    /// A WebSocket connection carrying the relay protocol
+    WebSocket(Box<WsStream<MaybeTlsStream>>),
     #[cfg(test)]
     Test(tokio::io::DuplexStream),
 }
@@ -408,6 +707,7 @@ impl AsyncRead for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_read(cx, buf),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_read(cx, buf),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_read(cx, buf),
         }
@@ -422,6 +722,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_flush(cx),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_flush(cx),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_flush(cx),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_flush(cx),
         }
@@ -434,6 +735,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_shutdown(cx),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_shutdown(cx),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_shutdown(cx),
         }
@@ -447,6 +749,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_write(cx, buf),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_write(cx, buf),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_write(cx, buf),
         }
@@ -460,6 +763,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
         }
@@ -472,7 +776,7 @@ mod tests {
 
     use crate::relay::{
         client::ClientBuilder,
-        client_conn::ClientConnBuilder,
+        client_conn::{ClientConnBuilder, ClientLimits},
         codec::{recv_frame, DerpCodec, FrameType},
         types::ClientInfo,
         ReceivedMessage,
@@ -497,6 +801,9 @@ mod tests {
                 io: Framed::new(MaybeTlsStream::Test(io), DerpCodec),
                 write_timeout: None,
                 channel_capacity: 10,
+                limits: ClientLimits::default(),
+                mesh_peer: false,
+                auth_token: None,
                 server_channel,
             },
             Framed::new(test_io, DerpCodec),
@@ -579,6 +886,9 @@ mod tests {
             secret_key: client_key.clone(),
             write_timeout: None,
             server_info: ServerInfo::no_rate_limit(),
+            access: AccessControl::default(),
+            limits: ClientLimits::default(),
+            mesh_key: None,
             server_channel: server_channel_s,
             default_headers: Default::default(),
         };
@@ -603,6 +913,7 @@ mod tests {
                 can_ack_pings: true,
                 is_prober: true,
                 mesh_key: None,
+                auth_token: None,
             };
             let shared_secret = client_key.shared(&got_server_key);
             crate::relay::codec::send_client_key(
@@ -710,11 +1021,73 @@ mod tests {
         // close the server and clients
         server.close().await;
 
-        // client connections have been shutdown
+        // client connections have been told about the restart, and shutdown
         let res = client_a
             .send(public_key_b, Bytes::from_static(b"try to send"))
             .await;
         assert!(res.is_err());
+        assert!(matches!(
+            client_receiver_b.recv().await?,
+            ReceivedMessage::ServerRestarting { .. }
+        ));
+        assert!(client_receiver_b.recv().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_restart() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let mut server = Server::new(SecretKey::generate());
+        let restart = RestartConfig {
+            grace_period: Duration::from_millis(500),
+            reconnect_window: Duration::from_secs(2),
+        };
+        server.set_restart_config(restart);
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let key = SecretKey::generate();
+            let public_key = key.public();
+            let (rw, client_builder) = make_test_client(key);
+            let handler = server.client_conn_handler(Default::default());
+            let handler_task =
+                tokio::spawn(async move { handler.accept(MaybeTlsStream::Test(rw)).await });
+            let (client, receiver) = client_builder.build().await?;
+            handler_task.await??;
+            clients.push((public_key, client, receiver));
+        }
+        let (public_key_a, client_a, mut client_receiver_a) = clients.remove(0);
+        let (public_key_b, _client_b, mut client_receiver_b) = clients.remove(0);
+
+        let close_task = tokio::spawn(server.close());
+
+        // both clients are told to reconnect after the grace period
+        for receiver in [&mut client_receiver_a, &mut client_receiver_b] {
+            match receiver.recv().await? {
+                ReceivedMessage::ServerRestarting {
+                    reconnect_in,
+                    try_for,
+                } => {
+                    assert!(reconnect_in <= restart.grace_period);
+                    assert_eq!(try_for, restart.reconnect_window);
+                }
+                msg => anyhow::bail!("expected ServerRestarting msg, got {msg:?}"),
+            }
+        }
+
+        // the clients are still served during the grace period
+        let msg = Bytes::from_static(b"still there?");
+        client_a.send(public_key_b, msg.clone()).await?;
+        match client_receiver_b.recv().await? {
+            ReceivedMessage::ReceivedPacket { source, data } => {
+                assert_eq!(public_key_a, source);
+                assert_eq!(&msg[..], data);
+            }
+            msg => anyhow::bail!("expected ReceivedPacket msg, got {msg:?}"),
+        }
+
+        close_task.await?;
         assert!(client_receiver_b.recv().await.is_err());
         Ok(())
     }
@@ -816,11 +1189,15 @@ mod tests {
         // close the server and clients
         server.close().await;
 
-        // client connections have been shutdown
+        // client connections have been told about the restart, and shutdown
         let res = client_a
             .send(public_key_b, Bytes::from_static(b"try to send"))
             .await;
         assert!(res.is_err());
+        assert!(matches!(
+            new_client_receiver_b.recv().await?,
+            ReceivedMessage::ServerRestarting { .. }
+        ));
         assert!(new_client_receiver_b.recv().await.is_err());
         Ok(())
     }
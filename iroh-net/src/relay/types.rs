@@ -1,11 +1,15 @@
 use std::num::NonZeroU32;
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use bytes::Bytes;
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-use super::{client_conn::ClientConnBuilder, codec::PROTOCOL_VERSION};
+use super::{
+    access::AccessConfig, client_conn::ClientConnBuilder, codec::PROTOCOL_VERSION,
+    mesh::PacketForwarder,
+};
 use crate::key::PublicKey;
 
 pub(crate) struct RateLimiter {
@@ -51,17 +55,42 @@ pub(crate) struct Packet {
     pub(crate) bytes: Bytes,
 }
 
-#[derive(Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+#[derive(derive_more::Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct ClientInfo {
     /// The DERP protocol version that the client was built with.
     /// See [`PROTOCOL_VERSION`].
     pub(crate) version: usize,
-    /// Unused field, ignored by the relay server.
+    /// The key shared by the servers of a mesh, sent by mesh peers.
+    ///
+    /// See [`super::mesh`].
+    #[debug("{}", mesh_key.as_ref().map_or("None", |_| "Some(..)"))]
     pub(crate) mesh_key: Option<[u8; 32]>,
     /// Whether the client declares it's able to ack pings
     pub(crate) can_ack_pings: bool,
     /// Whether this client is a prober.
     pub(crate) is_prober: bool,
+    /// Token to authenticate with relay servers which require one.
+    #[debug("{}", auth_token.as_ref().map_or("None", |_| "Some(..)"))]
+    pub(crate) auth_token: Option<String>,
+}
+
+impl ClientInfo {
+    /// Decodes a [`ClientInfo`].
+    ///
+    /// Clients which predate `auth_token` do not send it, their encoding is accepted as well.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if let Ok(info) = postcard::from_bytes(bytes) {
+            return Ok(info);
+        }
+        let (version, mesh_key, can_ack_pings, is_prober) = postcard::from_bytes(bytes)?;
+        Ok(Self {
+            version,
+            mesh_key,
+            can_ack_pings,
+            is_prober,
+            auth_token: None,
+        })
+    }
 }
 
 /// The information we send to the [`super::client::Client`] about the [`super::server::Server`]'s
@@ -87,6 +116,15 @@ impl ServerInfo {
     }
 }
 
+/// Announces to a client that the server is restarting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Restarting {
+    /// How long the client should wait before reconnecting.
+    pub(crate) reconnect_in: Duration,
+    /// How long the client should try to reconnect.
+    pub(crate) try_for: Duration,
+}
+
 #[derive(derive_more::Debug)]
 pub(crate) enum ServerMessage {
     SendPacket((PublicKey, Packet)),
@@ -94,5 +132,15 @@ pub(crate) enum ServerMessage {
     #[debug("CreateClient")]
     CreateClient(ClientConnBuilder),
     RemoveClient((PublicKey, usize)),
+    /// A packet forwarded by a mesh peer, only delivered to local clients.
+    RecvForwardedPacket((PublicKey, Packet)),
+    /// A mesh peer subscribes to the connects and disconnects of the clients.
+    AddWatcher(PublicKey),
+    AddPacketForwarder((PublicKey, PacketForwarder)),
+    RemovePacketForwarder((PublicKey, usize)),
+    /// Announce to all clients that the server is restarting.
+    Restart(Restarting),
+    /// The access control changed, disconnect the clients which are no longer admitted.
+    UpdateAccess(AccessConfig),
     Shutdown,
 }
@@ -193,6 +193,7 @@ pub mod test {
             RelayNode {
                 url,
                 stun_port: port,
+                auth_token: None,
                 stun_only,
             }
         });
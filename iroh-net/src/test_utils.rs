@@ -45,6 +45,7 @@ pub(crate) async fn run_relay_server() -> Result<(RelayMap, RelayUrl, CleanupDro
         url: url.clone(),
         stun_only: false,
         stun_port: stun_addr.port(),
+        auth_token: None,
     }])
     .expect("hardcoded");
 
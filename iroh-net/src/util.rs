@@ -2,11 +2,14 @@
 
 use std::{
     future::Future,
+    path::Path,
     pin::Pin,
     task::{Context, Poll},
 };
 
+use anyhow::{ensure, Context as _};
 use futures::FutureExt;
+use tokio::io::AsyncWriteExt;
 
 /// A join handle that owns the task it is running, and aborts it when dropped.
 #[derive(Debug, derive_more::Deref)]
@@ -81,6 +84,40 @@ impl<T: Future + Unpin> Future for MaybeFuture<T> {
     }
 }
 
+/// Write `contents` to the file at `path`, replacing the file atomically.
+///
+/// The contents are written to a temporary file next to `path` first, which is then renamed to
+/// `path`. Missing parent directories are created.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    ensure!(!path.is_dir(), "{} must be a file", path.display());
+
+    let mut ext = path.extension().map(|s| s.to_owned()).unwrap_or_default();
+    ext.push(".tmp");
+    let tmp_path = path.with_extension(ext);
+
+    if tokio::fs::try_exists(&tmp_path).await.unwrap_or(false) {
+        tokio::fs::remove_file(&tmp_path)
+            .await
+            .context("failed deleting existing tmp file")?;
+    }
+    if let Some(parent) = tmp_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut tmp = tokio::fs::File::create(&tmp_path)
+        .await
+        .context("failed creating tmp file")?;
+    tmp.write_all(contents)
+        .await
+        .context("failed writing tmp file")?;
+    tmp.flush().await.context("failed flushing tmp file")?;
+    drop(tmp);
+
+    tokio::fs::rename(tmp_path, path)
+        .await
+        .context("failed renaming tmp file")?;
+    Ok(())
+}
+
 /// Check if we are running in "relay only" mode, as informed
 /// by the compile time env var `DEV_RELAY_ONLY`.
 ///
@@ -5,21 +5,25 @@ use std::{
     num::NonZeroU64,
     sync::Arc,
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use bytes::Bytes;
-use iroh_base::hash::Hash;
+use iroh_base::{hash::Hash, key::PublicKey};
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 use tracing::{debug, error, error_span, trace, warn};
 
 use crate::{
-    ranger::Message,
-    store::{fs::StoreInstance, DownloadPolicy, ImportNamespaceOutcome, Query, Store},
+    encryption::DocEncryptionKey,
+    ranger::{Message, Range},
+    store::{
+        fs::StoreInstance, AccessPolicy, DownloadPolicy, ImportNamespaceOutcome, Query, Store,
+    },
     Author, AuthorHeads, AuthorId, Capability, CapabilityKind, ContentStatus,
-    ContentStatusCallback, Event, NamespaceId, NamespaceSecret, PeerIdBytes, Replica, SignedEntry,
-    SyncOutcome,
+    ContentStatusCallback, Event, NamespaceId, NamespaceSecret, PeerIdBytes, RecordIdentifier,
+    Replica, SignedEntry, SyncOutcome,
 };
 
 #[derive(derive_more::Debug, derive_more::Display)]
@@ -105,6 +109,12 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<()>>,
     },
+    InsertLocalBatch {
+        author: AuthorId,
+        entries: Vec<(Bytes, Hash, u64)>,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
     DeletePrefix {
         author: AuthorId,
         key: Bytes,
@@ -119,6 +129,7 @@ enum ReplicaAction {
         reply: oneshot::Sender<Result<()>>,
     },
     SyncInitialMessage {
+        peer: PeerIdBytes,
         #[debug("reply")]
         reply: oneshot::Sender<Result<Message<SignedEntry>>>,
     },
@@ -129,6 +140,11 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<(Option<Message<SignedEntry>>, SyncOutcome)>>,
     },
+    ClearSyncCheckpoint {
+        peer: PeerIdBytes,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
     GetSyncPeers {
         #[debug("reply")]
         reply: oneshot::Sender<Result<Option<Vec<PeerIdBytes>>>>,
@@ -168,6 +184,24 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<DownloadPolicy>>,
     },
+    SetAccessPolicy {
+        policy: AccessPolicy,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetAccessPolicy {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<AccessPolicy>>,
+    },
+    SetEncryptionKey {
+        key: DocEncryptionKey,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetEncryptionKey {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Option<DocEncryptionKey>>>,
+    },
 }
 
 /// The state for an open replica.
@@ -242,6 +276,7 @@ impl SyncHandle {
             states: Default::default(),
             action_rx,
             content_status_callback,
+            checkpoints: SyncCheckpoints::new(),
         };
         let join_handle = std::thread::Builder::new()
             .name("sync-actor".to_string())
@@ -324,6 +359,22 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn insert_local_batch(
+        &self,
+        namespace: NamespaceId,
+        author: AuthorId,
+        entries: Vec<(Bytes, Hash, u64)>,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::InsertLocalBatch {
+            author,
+            entries,
+            reply,
+        };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn delete_prefix(
         &self,
         namespace: NamespaceId,
@@ -354,12 +405,17 @@ impl SyncHandle {
         rx.await?
     }
 
+    /// Create the initial message to sync with `peer`.
+    ///
+    /// If the last sync with `peer` was interrupted, this resumes from the ranges that were
+    /// still open.
     pub async fn sync_initial_message(
         &self,
         namespace: NamespaceId,
+        peer: PeerIdBytes,
     ) -> Result<Message<SignedEntry>> {
         let (reply, rx) = oneshot::channel();
-        let action = ReplicaAction::SyncInitialMessage { reply };
+        let action = ReplicaAction::SyncInitialMessage { peer, reply };
         self.send_replica(namespace, action).await?;
         rx.await?
     }
@@ -382,6 +438,18 @@ impl SyncHandle {
         rx.await?
     }
 
+    /// Mark the sync with `peer` as complete, so that the next sync does not resume from it.
+    pub async fn clear_sync_checkpoint(
+        &self,
+        namespace: NamespaceId,
+        peer: PeerIdBytes,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::ClearSyncCheckpoint { peer, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn get_sync_peers(&self, namespace: NamespaceId) -> Result<Option<Vec<PeerIdBytes>>> {
         let (reply, rx) = oneshot::channel();
         let action = ReplicaAction::GetSyncPeers { reply };
@@ -522,6 +590,45 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn get_access_policy(&self, namespace: NamespaceId) -> Result<AccessPolicy> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetAccessPolicy { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_access_policy(
+        &self,
+        namespace: NamespaceId,
+        policy: AccessPolicy,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetAccessPolicy { reply, policy };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn get_encryption_key(
+        &self,
+        namespace: NamespaceId,
+    ) -> Result<Option<DocEncryptionKey>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetEncryptionKey { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_encryption_key(
+        &self,
+        namespace: NamespaceId,
+        key: DocEncryptionKey,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetEncryptionKey { reply, key };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     async fn send(&self, action: Action) -> Result<()> {
         self.tx
             .send_async(action)
@@ -553,11 +660,20 @@ struct Actor {
     states: OpenReplicas,
     action_rx: flume::Receiver<Action>,
     content_status_callback: Option<ContentStatusCallback>,
+    checkpoints: SyncCheckpoints,
 }
 
 impl Actor {
     fn run(&mut self) -> Result<()> {
-        while let Ok(action) = self.action_rx.recv() {
+        loop {
+            let action = match self.action_rx.recv_timeout(CHECKPOINT_FLUSH_INTERVAL) {
+                Ok(action) => action,
+                Err(flume::RecvTimeoutError::Timeout) => {
+                    self.flush_checkpoints();
+                    continue;
+                }
+                Err(flume::RecvTimeoutError::Disconnected) => break,
+            };
             trace!(%action, "tick");
             let is_shutdown = matches!(action, Action::Shutdown { .. });
             if self.on_action(action).is_err() {
@@ -574,6 +690,7 @@ impl Actor {
     fn on_action(&mut self, action: Action) -> Result<(), SendReplyError> {
         match action {
             Action::Shutdown { reply } => {
+                self.flush_checkpoints();
                 self.close_all();
                 if let Some(reply) = reply {
                     send_reply(reply, ())
@@ -656,6 +773,16 @@ impl Actor {
                 replica.insert(&key, &author, hash, len)?;
                 Ok(())
             }),
+            ReplicaAction::InsertLocalBatch {
+                author,
+                entries,
+                reply,
+            } => send_reply_with(reply, self, |this| {
+                let author = get_author(&this.store, &author)?;
+                let replica = this.states.replica(&namespace)?;
+                replica.insert_batch(&author, entries)?;
+                Ok(())
+            }),
             ReplicaAction::DeletePrefix { author, key, reply } => {
                 send_reply_with(reply, self, |this| {
                     let author = get_author(&this.store, &author)?;
@@ -670,15 +797,30 @@ impl Actor {
                 content_status,
                 reply,
             } => send_reply_with(reply, self, move |this| {
+                // Entries delivered by a gossip neighbour our access policy denies are dropped.
+                // `from` is the neighbour, which is not necessarily the node that inserted them.
+                let peer = PublicKey::from_bytes(&from)?;
+                if !this.store.get_access_policy(&namespace)?.is_allowed(&peer) {
+                    return Err(anyhow!("peer is not allowed by the access policy"));
+                }
                 let replica = this.states.replica_if_syncing(&namespace)?;
                 replica.insert_remote_entry(entry, from, content_status)?;
                 Ok(())
             }),
 
-            ReplicaAction::SyncInitialMessage { reply } => {
+            ReplicaAction::SyncInitialMessage { peer, reply } => {
                 send_reply_with(reply, self, move |this| {
                     let replica = this.states.replica_if_syncing(&namespace)?;
-                    let res = replica.sync_initial_message()?;
+                    let res = match this.checkpoints.get(&this.store, &namespace, &peer)? {
+                        Some(ranges) => {
+                            debug!(ranges = ranges.len(), "resume interrupted sync");
+                            replica.sync_resume_message(ranges)?
+                        }
+                        None => replica.sync_initial_message()?,
+                    };
+                    let ranges = res.ranges().cloned().collect();
+                    this.checkpoints
+                        .set(&this.store, namespace, peer, Some(ranges))?;
                     Ok(res)
                 })
             }
@@ -690,8 +832,17 @@ impl Actor {
             } => send_reply_with(reply, self, move |this| {
                 let replica = this.states.replica_if_syncing(&namespace)?;
                 let res = replica.sync_process_message(message, from, &mut state)?;
+                let ranges = res.as_ref().map(|reply| reply.ranges().cloned().collect());
+                this.checkpoints.set(&this.store, namespace, from, ranges)?;
                 Ok((res, state))
             }),
+            ReplicaAction::ClearSyncCheckpoint { peer, reply } => {
+                send_reply_with(reply, self, move |this| {
+                    // the sync is complete, write its final state right away
+                    this.checkpoints.set(&this.store, namespace, peer, None)?;
+                    this.checkpoints.flush(&this.store)
+                })
+            }
             ReplicaAction::GetSyncPeers { reply } => send_reply_with(reply, self, move |this| {
                 this.states.ensure_open(&namespace)?;
                 let peers = this.store.get_sync_peers(&namespace)?;
@@ -719,6 +870,7 @@ impl Actor {
             }
             ReplicaAction::DropReplica { reply } => send_reply_with(reply, self, |this| {
                 this.close(namespace);
+                this.checkpoints.remove_replica(&namespace);
                 this.store.remove_replica(&namespace)
             }),
             ReplicaAction::ExportSecretKey { reply } => {
@@ -746,6 +898,18 @@ impl Actor {
             ReplicaAction::GetDownloadPolicy { reply } => {
                 send_reply(reply, self.store.get_download_policy(&namespace))
             }
+            ReplicaAction::SetAccessPolicy { policy, reply } => {
+                send_reply(reply, self.store.set_access_policy(&namespace, policy))
+            }
+            ReplicaAction::GetAccessPolicy { reply } => {
+                send_reply(reply, self.store.get_access_policy(&namespace))
+            }
+            ReplicaAction::SetEncryptionKey { key, reply } => {
+                send_reply(reply, self.store.set_encryption_key(&namespace, key))
+            }
+            ReplicaAction::GetEncryptionKey { reply } => {
+                send_reply(reply, self.store.get_encryption_key(&namespace))
+            }
         }
     }
 
@@ -759,6 +923,12 @@ impl Actor {
         self.states.close_all_with(on_close_cb);
     }
 
+    fn flush_checkpoints(&mut self) {
+        if let Err(err) = self.checkpoints.flush(&self.store) {
+            warn!(?err, "failed to write sync checkpoints");
+        }
+    }
+
     fn open(&mut self, namespace: NamespaceId, opts: OpenOpts) -> Result<()> {
         let open_cb = || {
             let mut replica = self.store.open_replica(&namespace)?;
@@ -771,6 +941,77 @@ impl Actor {
     }
 }
 
+/// How often the checkpoints of running syncs are written to the store.
+const CHECKPOINT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Checkpoints of running syncs which are not yet written to the store.
+///
+/// A sync updates its checkpoint in every round. Instead of a write transaction for each round,
+/// the checkpoints are written together at most once per [`CHECKPOINT_FLUSH_INTERVAL`]. If the
+/// latest checkpoints are lost, an interrupted sync resumes from an earlier round.
+#[derive(Debug)]
+struct SyncCheckpoints {
+    pending: HashMap<(NamespaceId, PeerIdBytes), Option<Vec<Range<RecordIdentifier>>>>,
+    last_flush: Instant,
+}
+
+impl SyncCheckpoints {
+    fn new() -> Self {
+        Self {
+            pending: Default::default(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn get(
+        &self,
+        store: &Store,
+        namespace: &NamespaceId,
+        peer: &PeerIdBytes,
+    ) -> Result<Option<Vec<Range<RecordIdentifier>>>> {
+        match self.pending.get(&(*namespace, *peer)) {
+            Some(ranges) => Ok(ranges.clone()),
+            None => store.get_sync_checkpoint(namespace, peer),
+        }
+    }
+
+    /// Set the checkpoint of a sync, or clear it if `ranges` is `None`.
+    ///
+    /// Writes all pending checkpoints if the last write is older than the flush interval.
+    fn set(
+        &mut self,
+        store: &Store,
+        namespace: NamespaceId,
+        peer: PeerIdBytes,
+        ranges: Option<Vec<Range<RecordIdentifier>>>,
+    ) -> Result<()> {
+        self.pending.insert((namespace, peer), ranges);
+        if self.last_flush.elapsed() >= CHECKPOINT_FLUSH_INTERVAL {
+            self.flush(store)?;
+        }
+        Ok(())
+    }
+
+    /// Write all pending checkpoints to the store.
+    fn flush(&mut self, store: &Store) -> Result<()> {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        store.set_sync_checkpoints(
+            pending
+                .into_iter()
+                .map(|((namespace, peer), ranges)| (namespace, peer, ranges)),
+        )
+    }
+
+    /// Drop the pending checkpoints of a replica which is removed.
+    fn remove_replica(&mut self, namespace: &NamespaceId) {
+        self.pending.retain(|(id, _peer), _| id != namespace);
+    }
+}
+
 #[derive(Default)]
 struct OpenReplicas(HashMap<NamespaceId, OpenReplica>);
 
@@ -925,4 +1166,81 @@ mod tests {
         assert!(rx.recv_async().await.is_err());
         Ok(())
     }
+
+    #[test]
+    fn sync_checkpoints() -> anyhow::Result<()> {
+        let mut rng = rand::rngs::OsRng {};
+        let store = store::Store::memory();
+        let namespace = NamespaceSecret::new(&mut rng).id();
+        let author = Author::new(&mut rng).id();
+        let peer = [1u8; 32];
+        let range = Range::new(
+            RecordIdentifier::new(namespace, author, "a"),
+            RecordIdentifier::new(namespace, author, "b"),
+        );
+
+        // checkpoints are only written to the store when flushed
+        let mut checkpoints = SyncCheckpoints::new();
+        checkpoints.set(&store, namespace, peer, Some(vec![range.clone()]))?;
+        assert_eq!(
+            checkpoints.get(&store, &namespace, &peer)?,
+            Some(vec![range.clone()])
+        );
+        assert_eq!(store.get_sync_checkpoint(&namespace, &peer)?, None);
+        checkpoints.flush(&store)?;
+        assert_eq!(
+            store.get_sync_checkpoint(&namespace, &peer)?,
+            Some(vec![range])
+        );
+
+        // a cleared checkpoint hides the one in the store until it is flushed
+        checkpoints.set(&store, namespace, peer, None)?;
+        assert_eq!(checkpoints.get(&store, &namespace, &peer)?, None);
+        assert!(store.get_sync_checkpoint(&namespace, &peer)?.is_some());
+        checkpoints.flush(&store)?;
+        assert_eq!(store.get_sync_checkpoint(&namespace, &peer)?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn insert_remote_access_policy() -> anyhow::Result<()> {
+        let mut rng = rand::rngs::OsRng {};
+        let store = store::Store::memory();
+        let sync = SyncHandle::spawn(store, None, "foo".into());
+        let namespace = NamespaceSecret::new(&mut rng);
+        let id = namespace.id();
+        let author = Author::new(&mut rng);
+        sync.import_namespace(namespace.clone().into()).await?;
+        sync.open(id, OpenOpts::default().sync()).await?;
+
+        let allowed = iroh_base::key::SecretKey::generate().public();
+        let denied = iroh_base::key::SecretKey::generate().public();
+        sync.set_access_policy(id, AccessPolicy::EveryoneExcept(vec![denied]))
+            .await?;
+
+        let entry = |key: &str| {
+            let id = crate::RecordIdentifier::new(id, author.id(), key);
+            let record = crate::Record::new_current(Hash::new(key), 1);
+            crate::Entry::new(id, record).sign(&namespace, &author)
+        };
+
+        // entries from a denied peer are rejected
+        let res = sync
+            .insert_remote(id, entry("a"), *denied.as_bytes(), ContentStatus::Missing)
+            .await;
+        assert!(res.is_err());
+        let res = sync
+            .get_exact(id, author.id(), Bytes::from_static(b"a"), false)
+            .await?;
+        assert!(res.is_none());
+
+        // entries from other peers are inserted
+        sync.insert_remote(id, entry("b"), *allowed.as_bytes(), ContentStatus::Missing)
+            .await?;
+        let res = sync
+            .get_exact(id, author.id(), Bytes::from_static(b"b"), false)
+            .await?;
+        assert!(res.is_some());
+        Ok(())
+    }
 }
@@ -0,0 +1,222 @@
+//! Encryption of entry keys and content for encrypted documents
+//!
+//! In an encrypted document, the keys of all entries and the content blobs they point to are
+//! encrypted with a symmetric [`DocEncryptionKey`]. The key is shared alongside the namespace
+//! capability, but is not needed to sync the document: nodes without the key replicate the
+//! encrypted entries and content like any other.
+//!
+//! Entry keys are encrypted deterministically, so that the same plaintext key always maps to the
+//! same encrypted key and entries for a key can still be looked up and replaced. Content is
+//! encrypted with a random nonce.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, ensure, Result};
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use iroh_base::base32;
+use rand_core::{CryptoRngCore, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// Length of the nonce prepended to each ciphertext.
+const NONCE_LEN: usize = 24;
+/// Length of the authentication tag appended to each ciphertext.
+const TAG_LEN: usize = 16;
+
+/// Number of bytes a ciphertext is longer than its plaintext.
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_LEN + TAG_LEN;
+
+/// Context for deriving the key that encrypts entry keys and content.
+const CIPHER_KEY_CONTEXT: &str = "iroh-sync 2024-03 document encryption cipher key";
+/// Context for deriving the key that derives the nonces of encrypted entry keys.
+const NONCE_KEY_CONTEXT: &str = "iroh-sync 2024-03 document encryption nonce key";
+
+/// Associated data for encrypted entry keys.
+const KEY_AAD: &[u8] = b"key";
+/// Associated data for encrypted content.
+const CONTENT_AAD: &[u8] = b"content";
+
+/// Symmetric key to encrypt the entry keys and content of a document.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DocEncryptionKey([u8; 32]);
+
+impl DocEncryptionKey {
+    /// Create a new [`DocEncryptionKey`] with a random key.
+    pub fn new<R: CryptoRngCore + ?Sized>(rng: &mut R) -> Self {
+        let mut bytes = [0u8; 32];
+        rng.fill_bytes(&mut bytes);
+        Self(bytes)
+    }
+
+    /// Create a [`DocEncryptionKey`] from a byte array.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+        Self(*bytes)
+    }
+
+    /// Returns the [`DocEncryptionKey`] byte representation.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Returns the [`DocEncryptionKey`] as a byte array.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        let key = blake3::derive_key(CIPHER_KEY_CONTEXT, &self.0);
+        XChaCha20Poly1305::new(&key.into())
+    }
+
+    /// The nonce for an entry key, derived from the key itself.
+    fn key_nonce(&self, key: &[u8]) -> [u8; NONCE_LEN] {
+        let nonce_key = blake3::derive_key(NONCE_KEY_CONTEXT, &self.0);
+        let hash = blake3::keyed_hash(&nonce_key, key);
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&hash.as_bytes()[..NONCE_LEN]);
+        nonce
+    }
+
+    /// Encrypt an entry key.
+    ///
+    /// The encryption is deterministic: encrypting the same key twice gives the same result.
+    pub fn encrypt_key(&self, key: &[u8]) -> Bytes {
+        let nonce = self.key_nonce(key);
+        seal(&self.cipher(), nonce, key, KEY_AAD)
+    }
+
+    /// Decrypt an entry key that was encrypted with [`Self::encrypt_key`].
+    pub fn decrypt_key(&self, encrypted: &[u8]) -> Result<Bytes> {
+        let (nonce, key) = open(&self.cipher(), encrypted, KEY_AAD)?;
+        ensure!(
+            nonce == self.key_nonce(&key),
+            "entry key was not deterministically encrypted"
+        );
+        Ok(key)
+    }
+
+    /// Encrypt the content of an entry.
+    pub fn encrypt_content(&self, content: &[u8]) -> Bytes {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        seal(&self.cipher(), nonce, content, CONTENT_AAD)
+    }
+
+    /// Decrypt the content of an entry that was encrypted with [`Self::encrypt_content`].
+    pub fn decrypt_content(&self, encrypted: &[u8]) -> Result<Bytes> {
+        let (_nonce, content) = open(&self.cipher(), encrypted, CONTENT_AAD)?;
+        Ok(content)
+    }
+}
+
+/// Encrypt `plaintext`, returning the nonce followed by the ciphertext.
+fn seal(cipher: &XChaCha20Poly1305, nonce: [u8; NONCE_LEN], plaintext: &[u8], aad: &[u8]) -> Bytes {
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), payload)
+        .expect("encryption failed");
+    let mut res = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    res.extend_from_slice(&nonce);
+    res.extend_from_slice(&ciphertext);
+    res.into()
+}
+
+/// Decrypt the output of [`seal`], returning the nonce and the plaintext.
+fn open(
+    cipher: &XChaCha20Poly1305,
+    encrypted: &[u8],
+    aad: &[u8],
+) -> Result<([u8; NONCE_LEN], Bytes)> {
+    ensure!(
+        encrypted.len() >= ENCRYPTION_OVERHEAD,
+        "ciphertext too short"
+    );
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("decryption failed"))?;
+    let nonce = nonce.try_into().expect("nonce has the right length");
+    Ok((nonce, plaintext.into()))
+}
+
+impl fmt::Display for DocEncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", base32::fmt(self.0))
+    }
+}
+
+impl fmt::Debug for DocEncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DocEncryptionKey(..)")
+    }
+}
+
+impl FromStr for DocEncryptionKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_bytes(&base32::parse_array(s)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn test_key_encryption() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let doc_key = DocEncryptionKey::new(&mut rng);
+
+        let a = doc_key.encrypt_key(b"hello");
+        assert_eq!(a.len(), b"hello".len() + ENCRYPTION_OVERHEAD);
+        // deterministic, but not the plaintext
+        assert_eq!(a, doc_key.encrypt_key(b"hello"));
+        assert_ne!(a, doc_key.encrypt_key(b"world"));
+        assert!(!a.windows(5).any(|w| w == b"hello"));
+        assert_eq!(&doc_key.decrypt_key(&a)?[..], b"hello");
+
+        // other keys can not decrypt, and produce different ciphertexts
+        let other = DocEncryptionKey::new(&mut rng);
+        assert_ne!(a, other.encrypt_key(b"hello"));
+        assert!(other.decrypt_key(&a).is_err());
+
+        // content can not be passed off as an entry key
+        let content = doc_key.encrypt_content(b"hello");
+        assert!(doc_key.decrypt_key(&content).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_encryption() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let doc_key = DocEncryptionKey::new(&mut rng);
+
+        let a = doc_key.encrypt_content(b"some content");
+        let b = doc_key.encrypt_content(b"some content");
+        assert_ne!(a, b);
+        assert_eq!(&doc_key.decrypt_content(&a)?[..], b"some content");
+        assert_eq!(&doc_key.decrypt_content(&b)?[..], b"some content");
+
+        let mut tampered = a.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(doc_key.decrypt_content(&tampered).is_err());
+        assert!(doc_key.decrypt_content(&a[..10]).is_err());
+
+        let encoded = doc_key.to_string();
+        assert_eq!(encoded.parse::<DocEncryptionKey>()?, doc_key);
+        Ok(())
+    }
+}
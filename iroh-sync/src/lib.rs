@@ -34,6 +34,7 @@
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
 pub mod actor;
+pub mod encryption;
 mod heads;
 mod keys;
 #[cfg(feature = "metrics")]
@@ -284,6 +284,8 @@ pub enum AbortReason {
     AlreadySyncing,
     /// We experienced an error while trying to provide the requested resource
     InternalServerError,
+    /// The access policy of the namespace does not allow the peer to sync.
+    AccessDenied,
 }
 
 impl AcceptError {
@@ -19,7 +19,11 @@ use crate::{
 #[derive(Debug, Default)]
 struct SyncCodec;
 
-const MAX_MESSAGE_SIZE: usize = 1024 * 1024 * 1024; // This is likely too large, but lets have some restrictions
+/// Maximum size of a sync message.
+///
+/// The values of a message are bounded by [`crate::ranger::DEFAULT_MAX_MESSAGE_BYTES`], which
+/// leaves plenty of room for the fingerprints of the ranges.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
 
 impl Decoder for SyncCodec {
     type Item = Message;
@@ -102,10 +106,22 @@ pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
 
     let mut progress = Some(SyncOutcome::default());
 
-    // Init message
+    // Do not share the namespace with peers our access policy does not allow
+    let policy = handle
+        .get_access_policy(namespace)
+        .await
+        .map_err(ConnectError::sync)?;
+    if !policy.is_allowed(&peer) {
+        return Err(ConnectError::sync(anyhow!(
+            "peer is not allowed by the access policy"
+        )));
+    }
+
+    // Init message, which resumes from the open ranges if our last sync with the peer was
+    // interrupted
 
     let message = handle
-        .sync_initial_message(namespace)
+        .sync_initial_message(namespace, peer_bytes)
         .await
         .map_err(ConnectError::sync)?;
     let init_message = Message::Init { namespace, message };
@@ -146,6 +162,12 @@ pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
         }
     }
 
+    // All ranges are reconciled, the next sync starts from scratch
+    handle
+        .clear_sync_checkpoint(namespace, peer_bytes)
+        .await
+        .map_err(ConnectError::sync)?;
+
     trace!("done");
     Ok(progress.unwrap())
 }
@@ -214,7 +236,16 @@ impl BobState {
                     Span::current()
                         .record("namespace", tracing::field::display(&namespace.fmt_short()));
                     trace!("recv init message");
-                    let accept = accept_cb(namespace, self.peer).await;
+                    let accept = match sync.get_access_policy(namespace).await {
+                        Ok(policy) if !policy.is_allowed(&self.peer) => {
+                            AcceptOutcome::Reject(AbortReason::AccessDenied)
+                        }
+                        Ok(_) => accept_cb(namespace, self.peer).await,
+                        Err(err) => {
+                            debug!(?err, "failed to get access policy");
+                            AcceptOutcome::Reject(AbortReason::InternalServerError)
+                        }
+                    };
                     match accept {
                         AcceptOutcome::Allow => {
                             trace!("allow request");
@@ -274,10 +305,17 @@ impl BobState {
             }
         }
 
-        trace!("done");
+        let namespace = self
+            .namespace()
+            .ok_or_else(|| self.fail(anyhow!("Stream closed before init message")))?;
 
-        self.namespace()
-            .ok_or_else(|| self.fail(anyhow!("Stream closed before init message")))
+        // All ranges are reconciled, the next sync starts from scratch
+        sync.clear_sync_checkpoint(namespace, *self.peer.as_bytes())
+            .await
+            .map_err(|e| self.fail(e))?;
+
+        trace!("done");
+        Ok(namespace)
     }
 
     /// Get the namespace that is synced, if available.
@@ -295,8 +333,9 @@ impl BobState {
 mod tests {
     use crate::{
         actor::OpenOpts,
+        ranger::{Range, RangeEntry, DEFAULT_MAX_MESSAGE_BYTES},
         store::{self, fs::StoreInstance, Query, Store},
-        AuthorId, NamespaceSecret,
+        AuthorId, Entry, NamespaceSecret, Record, RecordIdentifier,
     };
     use anyhow::Result;
     use iroh_base::hash::Hash;
@@ -416,6 +455,97 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_sync_access_denied() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let alice_peer_id = SecretKey::from_bytes(&[1u8; 32]).public();
+        let bob_peer_id = SecretKey::from_bytes(&[2u8; 32]).public();
+        let namespace = NamespaceSecret::new(&mut rng);
+
+        let alice_store = store::Store::memory();
+        let author = alice_store.new_author(&mut rng).unwrap();
+        let mut alice_replica = alice_store.new_replica(namespace.clone()).unwrap();
+        alice_replica
+            .hash_and_insert("hello bob", &author, "from alice")
+            .unwrap();
+        alice_store.close_replica(alice_replica);
+
+        let bob_store = store::Store::memory();
+        let bob_replica = bob_store.new_replica(namespace.clone()).unwrap();
+        bob_store.close_replica(bob_replica);
+        bob_store.set_access_policy(
+            &namespace.id(),
+            store::AccessPolicy::NobodyExcept(vec![bob_peer_id]),
+        )?;
+
+        let (alice, bob) = tokio::io::duplex(64);
+
+        let (mut alice_reader, mut alice_writer) = tokio::io::split(alice);
+        let alice_handle = SyncHandle::spawn(alice_store.clone(), None, "alice".to_string());
+        alice_handle
+            .open(namespace.id(), OpenOpts::default().sync())
+            .await?;
+        let namespace_id = namespace.id();
+        let alice_handle2 = alice_handle.clone();
+        let alice_task = tokio::task::spawn(async move {
+            run_alice(
+                &mut alice_writer,
+                &mut alice_reader,
+                &alice_handle2,
+                namespace_id,
+                bob_peer_id,
+            )
+            .await
+        });
+
+        let (mut bob_reader, mut bob_writer) = tokio::io::split(bob);
+        let bob_handle = SyncHandle::spawn(bob_store.clone(), None, "bob".to_string());
+        bob_handle
+            .open(namespace.id(), OpenOpts::default().sync())
+            .await?;
+        let bob_handle2 = bob_handle.clone();
+        let bob_task = tokio::task::spawn(async move {
+            run_bob(
+                &mut bob_writer,
+                &mut bob_reader,
+                bob_handle2,
+                |_namespace, _peer| futures::future::ready(AcceptOutcome::Allow),
+                alice_peer_id,
+            )
+            .await
+        });
+
+        let alice_res = alice_task.await?;
+        assert!(matches!(
+            alice_res,
+            Err(ConnectError::RemoteAbort(AbortReason::AccessDenied))
+        ));
+        let bob_res = bob_task.await?;
+        assert!(matches!(
+            bob_res,
+            Err(AcceptError::Abort {
+                reason: AbortReason::AccessDenied,
+                ..
+            })
+        ));
+
+        alice_handle.shutdown().await;
+        bob_handle.shutdown().await;
+
+        // nothing was synced to bob
+        assert_eq!(
+            bob_store
+                .get_many(namespace.id(), Query::all())
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+                .len(),
+            0
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_sync_many_authors_memory() -> Result<()> {
         let _guard = iroh_test::logging::setup();
@@ -693,4 +823,124 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_sync_large() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(99);
+        let alice_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let bob_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice_store = store::Store::memory();
+        let bob_store = store::Store::memory();
+
+        // more entries than fit into two messages
+        let author = alice_store.new_author(&mut rng)?;
+        let id = RecordIdentifier::new(namespace.id(), author.id(), "00000");
+        let entry = Entry::new(id, Record::new_current(Hash::new("00000"), 5));
+        let count =
+            DEFAULT_MAX_MESSAGE_BYTES * 5 / 2 / entry.sign(&namespace, &author).encoded_len();
+        let mut alice_replica = alice_store.new_replica(namespace.clone())?;
+        let entries = (0..count).map(|i| {
+            let key = format!("{i:05}");
+            let hash = Hash::new(&key);
+            (key, hash, 5)
+        });
+        alice_replica.insert_batch(&author, entries)?;
+        alice_store.close_replica(alice_replica);
+        let bob_replica = bob_store.new_replica(namespace.clone())?;
+        bob_store.close_replica(bob_replica);
+
+        let alice_handle = SyncHandle::spawn(alice_store.clone(), None, "alice".to_string());
+        let bob_handle = SyncHandle::spawn(bob_store.clone(), None, "bob".to_string());
+        run_sync(
+            alice_handle.clone(),
+            alice_node_pubkey,
+            bob_handle.clone(),
+            bob_node_pubkey,
+            namespace.id(),
+        )
+        .await?;
+
+        assert_eq!(get_messages(&bob_store, namespace.id()).len(), count);
+        assert_eq!(
+            get_messages(&alice_store, namespace.id()),
+            get_messages(&bob_store, namespace.id())
+        );
+
+        alice_handle.shutdown().await;
+        bob_handle.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_resume() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(99);
+        let alice_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let bob_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice_store = store::Store::memory();
+        let bob_store = store::Store::memory();
+
+        let author = alice_store.new_author(&mut rng)?;
+        bob_store.import_author(author.clone())?;
+        let mut alice_replica = alice_store.new_replica(namespace.clone())?;
+        let mut bob_replica = bob_store.new_replica(namespace.clone())?;
+        for i in 0..10 {
+            alice_replica.hash_and_insert(format!("a{i}"), &author, "from alice")?;
+            bob_replica.hash_and_insert(format!("b{i}"), &author, "from bob")?;
+        }
+        alice_store.close_replica(alice_replica);
+        bob_store.close_replica(bob_replica);
+
+        // a previous sync was interrupted with only this range left open
+        let open = Range::new(
+            RecordIdentifier::new(namespace.id(), author.id(), "a5"),
+            RecordIdentifier::new(namespace.id(), author.id(), "b5"),
+        );
+        let alice_peer = *alice_node_pubkey.as_bytes();
+        let bob_peer = *bob_node_pubkey.as_bytes();
+        alice_store.set_sync_checkpoint(&namespace.id(), &bob_peer, [&open])?;
+
+        let alice_handle = SyncHandle::spawn(alice_store.clone(), None, "alice".to_string());
+        let bob_handle = SyncHandle::spawn(bob_store.clone(), None, "bob".to_string());
+        run_sync(
+            alice_handle.clone(),
+            alice_node_pubkey,
+            bob_handle.clone(),
+            bob_node_pubkey,
+            namespace.id(),
+        )
+        .await?;
+
+        // only the open range was reconciled
+        assert_eq!(get_messages(&alice_store, namespace.id()).len(), 15);
+        assert_eq!(get_messages(&bob_store, namespace.id()).len(), 15);
+        assert!(alice_store
+            .get_sync_checkpoint(&namespace.id(), &bob_peer)?
+            .is_none());
+        assert!(bob_store
+            .get_sync_checkpoint(&namespace.id(), &alice_peer)?
+            .is_none());
+
+        // the next sync starts from scratch
+        run_sync(
+            alice_handle.clone(),
+            alice_node_pubkey,
+            bob_handle.clone(),
+            bob_node_pubkey,
+            namespace.id(),
+        )
+        .await?;
+        assert_eq!(get_messages(&alice_store, namespace.id()).len(), 20);
+        assert_eq!(
+            get_messages(&alice_store, namespace.id()),
+            get_messages(&bob_store, namespace.id())
+        );
+
+        alice_handle.shutdown().await;
+        bob_handle.shutdown().await;
+        Ok(())
+    }
 }
@@ -36,6 +36,9 @@ pub trait RangeEntry: Debug + Clone {
 
     /// Get the fingerprint for this entry.
     fn as_fingerprint(&self) -> Fingerprint;
+
+    /// Get the approximate size of this entry in bytes when sent in a message.
+    fn encoded_len(&self) -> usize;
 }
 
 /// A trait constraining types that are valid entry keys.
@@ -190,6 +193,13 @@ impl<E: RangeEntry> MessagePart<E> {
             MessagePart::RangeItem(RangeItem { values, .. }) => Some(values),
         }
     }
+
+    pub fn range(&self) -> &Range<E::Key> {
+        match self {
+            MessagePart::RangeFingerprint(RangeFingerprint { range, .. }) => range,
+            MessagePart::RangeItem(RangeItem { range, .. }) => range,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -211,6 +221,27 @@ impl<E: RangeEntry> Message<E> {
         Ok(Message { parts: vec![part] })
     }
 
+    /// Construct a message that continues the reconciliation of the given ranges.
+    fn resume<S: Store<E>>(
+        store: &S,
+        ranges: impl IntoIterator<Item = Range<E::Key>>,
+    ) -> Result<Self, S::Error> {
+        let parts = ranges
+            .into_iter()
+            .map(|range| {
+                let fingerprint = store.get_fingerprint(&range)?;
+                Ok(MessagePart::RangeFingerprint(RangeFingerprint {
+                    range,
+                    fingerprint,
+                }))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if parts.is_empty() {
+            return Self::init(store);
+        }
+        Ok(Message { parts })
+    }
+
     pub fn parts(&self) -> &[MessagePart<E>] {
         &self.parts
     }
@@ -222,6 +253,15 @@ impl<E: RangeEntry> Message<E> {
     pub fn value_count(&self) -> usize {
         self.values().count()
     }
+
+    /// The ranges that are still open after this message was sent.
+    ///
+    /// Reconciliation of all other ranges is complete once this message is received, so these
+    /// ranges are sufficient to resume an interrupted reconciliation with
+    /// [`Peer::resume_message`].
+    pub fn ranges(&self) -> impl Iterator<Item = &Range<E::Key>> {
+        self.parts().iter().map(|p| p.range())
+    }
 }
 
 pub trait Store<E: RangeEntry>: Sized {
@@ -281,6 +321,56 @@ pub trait Store<E: RangeEntry>: Sized {
         prefix: &E::Key,
         predicate: impl Fn(&E::Value) -> bool,
     ) -> Result<usize, Self::Error>;
+
+    /// Start a batch of writes.
+    ///
+    /// All writes until [`Self::commit_batch`] or [`Self::abort_batch`] are applied together.
+    /// Stores without transactions apply writes immediately, which is what the default
+    /// implementation does.
+    fn start_batch(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Apply all writes since [`Self::start_batch`].
+    fn commit_batch(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Discard all writes since [`Self::start_batch`].
+    fn abort_batch(&mut self) {}
+}
+
+/// Default for the maximum size in bytes of the values sent in a single message.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 512 * 1024;
+
+/// The space left for values in an outgoing message.
+#[derive(Debug)]
+struct Budget {
+    bytes: usize,
+    empty: bool,
+}
+
+impl Budget {
+    fn new(bytes: usize) -> Self {
+        Self { bytes, empty: true }
+    }
+
+    /// Whether `values` still fit into the message.
+    ///
+    /// A single value always fits into a message without values, so that every message makes
+    /// progress.
+    fn fits<E: RangeEntry>(&self, values: &[E]) -> bool {
+        (self.empty && values.len() == 1) || Self::size(values) <= self.bytes
+    }
+
+    fn take<E: RangeEntry>(&mut self, values: &[E]) {
+        self.bytes = self.bytes.saturating_sub(Self::size(values));
+        self.empty &= values.is_empty();
+    }
+
+    fn size<E: RangeEntry>(values: &[E]) -> usize {
+        values.iter().map(|entry| entry.encoded_len()).sum()
+    }
 }
 
 #[derive(Debug)]
@@ -290,6 +380,12 @@ pub struct Peer<E: RangeEntry, S: Store<E>> {
     max_set_size: usize,
     /// `k` in the protocol, how many splits to generate. at least 2
     split_factor: usize,
+    /// Up to how many bytes of values to send in a single message.
+    ///
+    /// Values that do not fit are replaced by the fingerprint of their range, which continues
+    /// the reconciliation of that range in the next round. The first value of a message is
+    /// always sent, even if it is larger.
+    max_message_bytes: usize,
 
     /// This is needed because the `E: RangeEntry` would be unused otherwise.
     /// Only having it referenced in the `S: Store` generic doesn't satisfy rustc.
@@ -306,6 +402,7 @@ where
             store: S::default(),
             max_set_size: 1,
             split_factor: 2,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
             _phantom: Default::default(),
         }
     }
@@ -321,15 +418,38 @@ where
             store,
             max_set_size: 1,
             split_factor: 2,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
             _phantom: Default::default(),
         }
     }
 
+    /// Set the maximum size in bytes of the values sent in a single message.
+    ///
+    /// Must be at least 1.
+    pub fn set_max_message_bytes(&mut self, max_message_bytes: usize) {
+        assert!(
+            max_message_bytes > 0,
+            "max_message_bytes must be at least 1"
+        );
+        self.max_message_bytes = max_message_bytes;
+    }
+
     /// Generates the initial message.
     pub fn initial_message(&self) -> Result<Message<E>, S::Error> {
         Message::init(&self.store)
     }
 
+    /// Generates a message to resume an interrupted reconciliation.
+    ///
+    /// `ranges` are the ranges of the last message sent before the interruption, see
+    /// [`Message::ranges`]. If `ranges` is empty, this is the same as [`Self::initial_message`].
+    pub fn resume_message(
+        &self,
+        ranges: impl IntoIterator<Item = Range<E::Key>>,
+    ) -> Result<Message<E>, S::Error> {
+        Message::resume(&self.store, ranges)
+    }
+
     /// Processes an incoming message and produces a response.
     /// If terminated, returns `None`
     ///
@@ -356,6 +476,8 @@ where
         F3: Fn(&S, &E) -> ContentStatus,
     {
         let mut out = Vec::new();
+        // Space left for values in the outgoing message.
+        let mut budget = Budget::new(self.max_message_bytes);
 
         // TODO: can these allocs be avoided?
         let mut items = Vec::new();
@@ -400,12 +522,6 @@ where
                             }
                             Err(err) => Some(Err(err)),
                         })
-                        .map(|entry| {
-                            entry.map(|entry| {
-                                let content_status = content_status_cb(&self.store, &entry);
-                                (entry, content_status)
-                            })
-                        })
                         .collect::<Result<_, _>>()?,
                 )
             };
@@ -423,11 +539,7 @@ where
 
             if let Some(diff) = diff {
                 if !diff.is_empty() {
-                    out.push(MessagePart::RangeItem(RangeItem {
-                        range,
-                        values: diff,
-                        have_local: true,
-                    }));
+                    self.push_values(&mut out, range, diff, true, &mut budget, &content_status_cb)?;
                 }
             }
         }
@@ -449,18 +561,14 @@ where
                 .get_range(range.clone())?
                 .collect::<Result<_, _>>()?;
             if local_values.len() <= 1 || fingerprint == Fingerprint::empty() {
-                let values = local_values
-                    .into_iter()
-                    .map(|entry| {
-                        let content_status = content_status_cb(&self.store, &entry);
-                        (entry, content_status)
-                    })
-                    .collect::<Vec<_>>();
-                out.push(MessagePart::RangeItem(RangeItem {
+                self.push_values(
+                    &mut out,
                     range,
-                    values,
-                    have_local: false,
-                }));
+                    local_values,
+                    false,
+                    &mut budget,
+                    &content_status_cb,
+                )?;
             } else {
                 // Case3 Recurse
                 // Create partition
@@ -538,21 +646,21 @@ where
                     }
                     // Add either the fingerprint or the item set
                     let fingerprint = self.store.get_fingerprint(&range)?;
-                    if chunk.len() > self.max_set_size {
+                    let chunk = chunk.into_iter().collect::<Result<Vec<_>, _>>()?;
+                    if chunk.len() > self.max_set_size || !budget.fits(&chunk) {
                         out.push(MessagePart::RangeFingerprint(RangeFingerprint {
                             range: range.clone(),
                             fingerprint,
                         }));
                     } else {
+                        budget.take(&chunk);
                         let values = chunk
                             .into_iter()
                             .map(|entry| {
-                                entry.map(|entry| {
-                                    let content_status = content_status_cb(&self.store, &entry);
-                                    (entry, content_status)
-                                })
+                                let content_status = content_status_cb(&self.store, &entry);
+                                (entry, content_status)
                             })
-                            .collect::<Result<_, _>>()?;
+                            .collect();
                         out.push(MessagePart::RangeItem(RangeItem {
                             range,
                             values,
@@ -572,6 +680,72 @@ where
         }
     }
 
+    /// Add a [`RangeItem`] with the `values` of `range` to `out`, within the `budget` of bytes.
+    ///
+    /// If not all values fit into the budget, the item only covers the start of the range up to
+    /// the first value that does not fit. For the rest of the range, we send our fingerprint, so
+    /// that the other peer continues to reconcile it in the next round.
+    fn push_values<F>(
+        &self,
+        out: &mut Vec<MessagePart<E>>,
+        range: Range<E::Key>,
+        mut values: Vec<E>,
+        have_local: bool,
+        budget: &mut Budget,
+        content_status_cb: &F,
+    ) -> Result<(), S::Error>
+    where
+        F: Fn(&S, &E) -> ContentStatus,
+    {
+        let (range, rest) = if budget.fits(&values) {
+            budget.take(&values);
+            (range, None)
+        } else {
+            // Order the values as they appear in the range, which for wrapping ranges is not
+            // the order of the keys: values at or after `x` come first.
+            let Range { x, y } = range;
+            values.sort_by(|a, b| (a.key() < &x, a.key()).cmp(&(b.key() < &x, b.key())));
+            // not all values fit, so at least the last one is left for the next round
+            let mut count = 0;
+            while count + 1 < values.len() && budget.fits(&values[count..=count]) {
+                budget.take(&values[count..=count]);
+                count += 1;
+            }
+            if count == 0 {
+                let range = Range::new(x, y);
+                let fingerprint = self.store.get_fingerprint(&range)?;
+                out.push(MessagePart::RangeFingerprint(RangeFingerprint {
+                    range,
+                    fingerprint,
+                }));
+                return Ok(());
+            }
+            let split = values[count].key().clone();
+            values.truncate(count);
+            (Range::new(x, split.clone()), Some(Range::new(split, y)))
+        };
+        let values = values
+            .into_iter()
+            .map(|entry| {
+                let content_status = content_status_cb(&self.store, &entry);
+                (entry, content_status)
+            })
+            .collect();
+        out.push(MessagePart::RangeItem(RangeItem {
+            range,
+            values,
+            have_local,
+        }));
+        if let Some(range) = rest {
+            let fingerprint = self.store.get_fingerprint(&range)?;
+            out.push(MessagePart::RangeFingerprint(RangeFingerprint {
+                range,
+                fingerprint,
+            }));
+        }
+        Ok(())
+    }
+
     /// Insert a key value pair.
     ///
     /// Entries are inserted if they compare strictly greater than all entries in the set of
@@ -679,6 +853,10 @@ mod tests {
             hasher.update(format!("{:?}", self.1).as_bytes());
             Fingerprint(hasher.finalize().into())
         }
+
+        fn encoded_len(&self) -> usize {
+            format!("{:?}{:?}", self.0, self.1).len()
+        }
     }
 
     impl RangeKey for &'static str {
@@ -1330,8 +1508,33 @@ mod tests {
     }
 
     fn sync_exchange_messages<K, V, F1, F2>(
+        alice: Peer<(K, V), SimpleStore<K, V>>,
+        bob: Peer<(K, V), SimpleStore<K, V>>,
+        alice_validate_cb: F1,
+        bob_validate_cb: F2,
+        max_rounds: usize,
+    ) -> SyncResult<K, V>
+    where
+        K: RangeKey + Default,
+        V: RangeValue,
+        F1: Fn(&SimpleStore<K, V>, &(K, V), ContentStatus) -> bool,
+        F2: Fn(&SimpleStore<K, V>, &(K, V), ContentStatus) -> bool,
+    {
+        let initial_message = alice.initial_message().unwrap();
+        sync_exchange_messages_from(
+            alice,
+            bob,
+            initial_message,
+            alice_validate_cb,
+            bob_validate_cb,
+            max_rounds,
+        )
+    }
+
+    fn sync_exchange_messages_from<K, V, F1, F2>(
         mut alice: Peer<(K, V), SimpleStore<K, V>>,
         mut bob: Peer<(K, V), SimpleStore<K, V>>,
+        initial_message: Message<(K, V)>,
         alice_validate_cb: F1,
         bob_validate_cb: F2,
         max_rounds: usize,
@@ -1344,7 +1547,6 @@ mod tests {
     {
         let mut alice_to_bob = Vec::new();
         let mut bob_to_alice = Vec::new();
-        let initial_message = alice.initial_message().unwrap();
 
         let mut next_to_bob = Some(initial_message);
         let mut rounds = 0;
@@ -1536,6 +1738,130 @@ mod tests {
         let _res = sync(&alice, &bob);
     }
 
+    #[proptest]
+    fn simple_store_sync_bounded(
+        #[strategy(test_vec_string_u8())] alice: Vec<(String, u8)>,
+        #[strategy(test_vec_string_u8())] bob: Vec<(String, u8)>,
+        #[strategy(1usize..64)] max_message_bytes: usize,
+    ) {
+        let _res = sync_bounded(&alice, &bob, max_message_bytes);
+    }
+
+    #[test]
+    fn test_max_message_bytes() {
+        // each entry, e.g. `("a00", ())`, is 7 bytes long
+        let alice_set = mk_test_vec((0..20).map(|i| format!("a{i:02}")));
+        let bob_set = mk_test_vec((0..15).map(|i| format!("b{i:02}")));
+        let res = sync_bounded(&alice_set, &bob_set, 3 * 7);
+        assert!(res.alice_to_bob.iter().any(|msg| msg.value_count() == 3));
+
+        // an empty peer receives all values in bounded messages
+        let res = sync_bounded(&alice_set, &[], 4 * 7);
+        assert_eq!(
+            res.alice_to_bob
+                .iter()
+                .map(|m| m.value_count())
+                .sum::<usize>(),
+            20
+        );
+        assert!(res.alice_to_bob.len() >= 5);
+    }
+
+    #[test]
+    fn test_resume() {
+        let alice_set = mk_test_vec((0..20).map(|i| format!("a{i:02}")));
+        let bob_set = mk_test_vec((0..20).map(|i| format!("b{i:02}")));
+        let mut expected = alice_set.clone();
+        expected.extend(bob_set.iter().cloned());
+        expected.sort();
+
+        let mut alice = Peer::<(String, ()), SimpleStore<String, ()>>::default();
+        let mut bob = Peer::<(String, ()), SimpleStore<String, ()>>::default();
+        alice.set_max_message_bytes(4 * 7);
+        bob.set_max_message_bytes(4 * 7);
+        for e in alice_set {
+            alice.put(e).unwrap();
+        }
+        for e in bob_set {
+            bob.put(e).unwrap();
+        }
+
+        // run until alice received the first values, then lose the next message of alice
+        let mut msg = alice.initial_message().unwrap();
+        while alice.store.len().unwrap() == 20 {
+            let reply = bob
+                .process_message(
+                    msg,
+                    |_, _, _| true,
+                    |_, _, _| (),
+                    |_, _| ContentStatus::Complete,
+                )
+                .unwrap()
+                .unwrap();
+            msg = alice
+                .process_message(
+                    reply,
+                    |_, _, _| true,
+                    |_, _, _| (),
+                    |_, _| ContentStatus::Complete,
+                )
+                .unwrap()
+                .unwrap();
+        }
+        assert!(alice.store.len().unwrap() <= 24);
+
+        // resume from the ranges of the lost message
+        let ranges = msg.ranges().cloned().collect::<Vec<_>>();
+        assert!(!ranges.iter().any(|range| range.is_all()));
+        let resumed = alice.resume_message(ranges).unwrap();
+        let res =
+            sync_exchange_messages_from(alice, bob, resumed, |_, _, _| true, |_, _, _| true, 100);
+        let alice_now: Vec<_> = res.alice.all().unwrap().collect::<Result<_, _>>().unwrap();
+        let bob_now: Vec<_> = res.bob.all().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(alice_now, expected);
+        assert_eq!(bob_now, expected);
+    }
+
+    /// Sync with a limit on the size of the values per message.
+    fn sync_bounded<K, V>(
+        alice_set: &[(K, V)],
+        bob_set: &[(K, V)],
+        max_message_bytes: usize,
+    ) -> SyncResult<K, V>
+    where
+        K: RangeKey + Default,
+        V: RangeValue,
+    {
+        let mut alice = Peer::<(K, V), SimpleStore<K, V>>::default();
+        let mut bob = Peer::<(K, V), SimpleStore<K, V>>::default();
+        alice.set_max_message_bytes(max_message_bytes);
+        bob.set_max_message_bytes(max_message_bytes);
+
+        let mut expected_set = BTreeMap::new();
+        for e in alice_set {
+            alice.put(e.clone()).unwrap();
+            insert_if_larger(&mut expected_set, e.0.clone(), e.1.clone());
+        }
+        for e in bob_set {
+            bob.put(e.clone()).unwrap();
+            insert_if_larger(&mut expected_set, e.0.clone(), e.1.clone());
+        }
+        let expected_set = expected_set.into_iter().collect::<Vec<_>>();
+
+        let res = sync_exchange_messages(alice, bob, |_, _, _| true, |_, _, _| true, 1000);
+
+        for msg in res.alice_to_bob.iter().chain(res.bob_to_alice.iter()) {
+            let bytes = msg.values().map(|(e, _)| e.encoded_len()).sum::<usize>();
+            // a single value is sent even if it is larger than the limit
+            assert!(bytes <= max_message_bytes || msg.value_count() == 1);
+        }
+        let alice_now: Vec<_> = res.alice.all().unwrap().collect::<Result<_, _>>().unwrap();
+        let bob_now: Vec<_> = res.bob.all().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(alice_now, expected_set, "alice_now does not match expected");
+        assert_eq!(bob_now, expected_set, "bob_now does not match expected");
+        res
+    }
+
     /// A generic fn to make a test for the get_range fn of a store.
     #[allow(clippy::type_complexity)]
     fn store_get_ranges_test<S, E>(
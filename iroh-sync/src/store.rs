@@ -3,6 +3,7 @@ use std::num::NonZeroUsize;
 
 use anyhow::Result;
 use bytes::Bytes;
+use iroh_base::key::PublicKey;
 use serde::{Deserialize, Serialize};
 
 use crate::{AuthorId, Entry, NamespaceId};
@@ -159,6 +160,36 @@ impl DownloadPolicy {
     }
 }
 
+/// Access policy to decide which nodes may sync a document with us.
+///
+/// The policy is checked before reconciliation, both for incoming and outgoing sync requests,
+/// and for entries delivered live by a gossip neighbour. The live check only looks at the
+/// neighbour which delivered an entry, not at the node which inserted it: entries relayed by an
+/// allowed neighbour are accepted, and entries relayed by a denied neighbour are dropped.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AccessPolicy {
+    /// Allow every node to sync, unless it is in the list.
+    EveryoneExcept(Vec<PublicKey>),
+    /// Deny every node to sync, unless it is in the list.
+    NobodyExcept(Vec<PublicKey>),
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        AccessPolicy::EveryoneExcept(Vec::default())
+    }
+}
+
+impl AccessPolicy {
+    /// Check if a node is allowed to sync according to this policy.
+    pub fn is_allowed(&self, node: &PublicKey) -> bool {
+        match self {
+            AccessPolicy::EveryoneExcept(denied) => !denied.contains(node),
+            AccessPolicy::NobodyExcept(allowed) => allowed.contains(node),
+        }
+    }
+}
+
 /// A query builder for document queries.
 #[derive(Debug, Default)]
 pub struct QueryBuilder<K> {
@@ -322,6 +353,16 @@ impl Query {
     pub fn offset(&self) -> u64 {
         self.offset
     }
+
+    /// Get the key filter for this query.
+    pub fn key_filter(&self) -> &KeyFilter {
+        &self.filter_key
+    }
+
+    /// Replace the key filter of this query.
+    pub fn set_key_filter(&mut self, filter: KeyFilter) {
+        self.filter_key = filter;
+    }
 }
 
 /// Sort direction
@@ -15,14 +15,16 @@ use bytes::Bytes;
 use derive_more::From;
 use ed25519_dalek::{SignatureError, VerifyingKey};
 use iroh_base::hash::Hash;
+use ouroboros::self_referencing;
 use parking_lot::RwLock;
 use rand_core::CryptoRngCore;
 use redb::{
-    Database, MultimapTableDefinition, ReadOnlyTable, ReadableMultimapTable, ReadableTable,
-    TableDefinition,
+    Database, MultimapTableDefinition, ReadableMultimapTable, ReadableTable, TableDefinition,
+    WriteTransaction,
 };
 
 use crate::{
+    encryption::DocEncryptionKey,
     keys::Author,
     ranger::{Fingerprint, Range, RangeEntry},
     sync::{Entry, EntrySignature, Record, RecordIdentifier, Replica, SignedEntry},
@@ -30,16 +32,18 @@ use crate::{
 };
 
 use super::{
-    pubkeys::MemPublicKeyStore, DownloadPolicy, ImportNamespaceOutcome, OpenError, PublicKeyStore,
-    Query,
+    pubkeys::MemPublicKeyStore, AccessPolicy, DownloadPolicy, ImportNamespaceOutcome, OpenError,
+    PublicKeyStore, Query,
 };
 
 mod bounds;
+mod fingerprints;
 mod migrations;
 mod query;
 mod ranges;
 
 use self::bounds::{ByKeyBounds, RecordsBounds};
+use self::fingerprints::{fingerprint_of, FingerprintIndex};
 use self::query::QueryIterator;
 use self::ranges::{TableRange, TableReader};
 
@@ -73,7 +77,6 @@ const RECORDS_TABLE: TableDefinition<RecordsId, RecordsValue> = TableDefinition:
 type RecordsId<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8]);
 type RecordsIdOwned = ([u8; 32], [u8; 32], Bytes);
 type RecordsValue<'a> = (u64, &'a [u8; 64], &'a [u8; 64], u64, &'a [u8; 32]);
-type RecordsTable<'a> = ReadOnlyTable<'a, RecordsId<'static>, RecordsValue<'static>>;
 
 /// Table: Latest per author
 /// Key:   `([u8; 32], [u8; 32])`    # (NamespaceId, AuthorId)
@@ -108,6 +111,41 @@ type Nanos = u64;
 const DOWNLOAD_POLICY_TABLE: TableDefinition<&[u8; 32], &[u8]> =
     TableDefinition::new("download-policy-1");
 
+/// Table: Access policy
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `Vec<u8>`         # Postcard encoded access policy
+const ACCESS_POLICY_TABLE: TableDefinition<&[u8; 32], &[u8]> =
+    TableDefinition::new("access-policy-1");
+
+/// Table: Encryption keys
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `[u8; 32]`        # DocEncryptionKey
+const ENCRYPTION_KEY_TABLE: TableDefinition<&[u8; 32], &[u8; 32]> =
+    TableDefinition::new("encryption-key-1");
+
+/// Table: Fingerprint index nodes
+/// Key:   `(u8, [u8; 32], [u8; 32], &[u8])`
+///      # (Level, NamespaceId, AuthorId, Key)
+/// Value: `[u8; 32]`
+///      # XOR of the fingerprints of the records up to the next node on the same level
+const FINGERPRINTS_TABLE: TableDefinition<FingerprintsId, &[u8; 32]> =
+    TableDefinition::new("fingerprints-1");
+type FingerprintsId<'a> = (u8, &'a [u8; 32], &'a [u8; 32], &'a [u8]);
+
+/// Table: Fingerprint index heads
+/// Key:   `([u8; 32], u8)` # (NamespaceId, Level)
+/// Value: `[u8; 32]`
+///      # XOR of the fingerprints of the records before the first node on the level
+const FINGERPRINT_HEADS_TABLE: TableDefinition<FingerprintHeadsId, &[u8; 32]> =
+    TableDefinition::new("fingerprint-heads-1");
+type FingerprintHeadsId<'a> = (&'a [u8; 32], u8);
+
+/// Table: Sync checkpoints
+/// Key:   `([u8; 32], [u8; 32])` # (NamespaceId, PeerIdBytes)
+/// Value: `Vec<u8>`              # Postcard encoded ranges that were still open
+const SYNC_CHECKPOINTS_TABLE: TableDefinition<(&[u8; 32], &PeerIdBytes), &[u8]> =
+    TableDefinition::new("sync-checkpoints-1");
+
 /// Manages the replicas and authors for an instance.
 #[derive(Debug, Clone)]
 pub struct Store {
@@ -144,7 +182,12 @@ impl Store {
             let _table = write_tx.open_table(LATEST_PER_AUTHOR_TABLE)?;
             let _table = write_tx.open_multimap_table(NAMESPACE_PEERS_TABLE)?;
             let _table = write_tx.open_table(DOWNLOAD_POLICY_TABLE)?;
+            let _table = write_tx.open_table(ACCESS_POLICY_TABLE)?;
+            let _table = write_tx.open_table(ENCRYPTION_KEY_TABLE)?;
             let _table = write_tx.open_table(AUTHORS_TABLE)?;
+            let _table = write_tx.open_table(FINGERPRINTS_TABLE)?;
+            let _table = write_tx.open_table(FINGERPRINT_HEADS_TABLE)?;
+            let _table = write_tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
         }
         write_tx.commit()?;
 
@@ -353,6 +396,7 @@ impl Store {
             let bounds = ByKeyBounds::namespace(*namespace);
             let _ = table.drain(bounds.as_ref());
         }
+        fingerprints::remove_index(&write_tx, namespace)?;
         {
             let mut namespace_table = write_tx.open_table(NAMESPACES_TABLE)?;
             namespace_table.remove(namespace.as_bytes())?;
@@ -362,6 +406,13 @@ impl Store {
             peers_table.remove_all(namespace.as_bytes())?;
             let mut dl_policies_table = write_tx.open_table(DOWNLOAD_POLICY_TABLE)?;
             dl_policies_table.remove(namespace.as_bytes())?;
+            let mut access_policies_table = write_tx.open_table(ACCESS_POLICY_TABLE)?;
+            access_policies_table.remove(namespace.as_bytes())?;
+            let mut encryption_keys_table = write_tx.open_table(ENCRYPTION_KEY_TABLE)?;
+            encryption_keys_table.remove(namespace.as_bytes())?;
+            let mut checkpoints_table = write_tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
+            let namespace = namespace.as_bytes();
+            checkpoints_table.drain((namespace, &[0u8; 32])..=(namespace, &[255u8; 32]))?;
         }
         write_tx.commit()?;
         Ok(())
@@ -494,6 +545,86 @@ impl Store {
         }
     }
 
+    /// Save the ranges that are still open in an interrupted sync with a peer.
+    ///
+    /// The next sync with the peer resumes from these ranges, see
+    /// [`Replica::sync_resume_message`].
+    pub fn set_sync_checkpoint<'a>(
+        &self,
+        namespace: &NamespaceId,
+        peer: &PeerIdBytes,
+        ranges: impl IntoIterator<Item = &'a Range<RecordIdentifier>>,
+    ) -> Result<()> {
+        let ranges = ranges.into_iter().collect::<Vec<_>>();
+        let value = postcard::to_stdvec(&ranges)?;
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
+            table.insert((namespace.as_bytes(), peer), value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the ranges that were still open when the last sync with a peer was interrupted.
+    pub fn get_sync_checkpoint(
+        &self,
+        namespace: &NamespaceId,
+        peer: &PeerIdBytes,
+    ) -> Result<Option<Vec<Range<RecordIdentifier>>>> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
+        let value = table.get((namespace.as_bytes(), peer))?;
+        Ok(match value {
+            None => None,
+            Some(value) => Some(postcard::from_bytes(value.value())?),
+        })
+    }
+
+    /// Remove the checkpoint for a peer, after a sync with the peer completed.
+    pub fn clear_sync_checkpoint(&self, namespace: &NamespaceId, peer: &PeerIdBytes) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
+            table.remove((namespace.as_bytes(), peer))?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Save or remove the checkpoints of many syncs in a single write transaction.
+    ///
+    /// Checkpoints without ranges are removed, as with [`Self::clear_sync_checkpoint`].
+    pub fn set_sync_checkpoints(
+        &self,
+        checkpoints: impl IntoIterator<
+            Item = (
+                NamespaceId,
+                PeerIdBytes,
+                Option<Vec<Range<RecordIdentifier>>>,
+            ),
+        >,
+    ) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let mut table = tx.open_table(SYNC_CHECKPOINTS_TABLE)?;
+            for (namespace, peer, ranges) in checkpoints {
+                let key = (namespace.as_bytes(), &peer);
+                match ranges {
+                    Some(ranges) => {
+                        let value = postcard::to_stdvec(&ranges)?;
+                        table.insert(key, value.as_slice())?;
+                    }
+                    None => {
+                        table.remove(key)?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
     /// Set the download policy for a namespace.
     pub fn set_download_policy(
         &self,
@@ -529,6 +660,76 @@ impl Store {
             Some(value) => postcard::from_bytes(value.value())?,
         })
     }
+
+    /// Set the access policy for a namespace.
+    pub fn set_access_policy(&self, namespace: &NamespaceId, policy: AccessPolicy) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(ACCESS_POLICY_TABLE)?;
+            let value = postcard::to_stdvec(&policy)?;
+            table.insert(namespace, value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the access policy for a namespace.
+    pub fn get_access_policy(&self, namespace: &NamespaceId) -> Result<AccessPolicy> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(ACCESS_POLICY_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(match value {
+            None => AccessPolicy::default(),
+            Some(value) => postcard::from_bytes(value.value())?,
+        })
+    }
+
+    /// Set the encryption key for a namespace, making it an encrypted document.
+    ///
+    /// The key can only be set once, because entries encrypted with different keys could not
+    /// be read together.
+    pub fn set_encryption_key(&self, namespace: &NamespaceId, key: DocEncryptionKey) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(ENCRYPTION_KEY_TABLE)?;
+            if let Some(existing) = table.get(namespace)? {
+                anyhow::ensure!(
+                    existing.value() == key.as_bytes(),
+                    "document already has a different encryption key"
+                );
+            }
+            table.insert(namespace, key.as_bytes())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the encryption key for a namespace, if it is an encrypted document we hold the key
+    /// for.
+    pub fn get_encryption_key(&self, namespace: &NamespaceId) -> Result<Option<DocEncryptionKey>> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(ENCRYPTION_KEY_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(value.map(|value| DocEncryptionKey::from_bytes(value.value())))
+    }
 }
 
 fn parse_capability((raw_kind, raw_bytes): (u8, &[u8; 32])) -> Result<Capability> {
@@ -536,7 +737,7 @@ fn parse_capability((raw_kind, raw_bytes): (u8, &[u8; 32])) -> Result<Capability
 }
 
 fn get_exact(
-    record_table: &RecordsTable,
+    record_table: &impl ReadableTable<RecordsId<'static>, RecordsValue<'static>>,
     namespace: NamespaceId,
     author: AuthorId,
     key: impl AsRef<[u8]>,
@@ -550,15 +751,59 @@ fn get_exact(
 }
 
 /// A wrapper around [`Store`] for a specific [`NamespaceId`]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct StoreInstance {
     namespace: NamespaceId,
     store: Store,
+    batch: Option<WriteBatch>,
 }
 
 impl StoreInstance {
     fn new(namespace: NamespaceId, store: Store) -> Self {
-        StoreInstance { namespace, store }
+        StoreInstance {
+            namespace,
+            store,
+            batch: None,
+        }
+    }
+
+    /// Run `f` in the write transaction of the running batch, or in a new write transaction that
+    /// is committed right away if no batch is running.
+    fn with_write_tx<T>(&self, f: impl FnOnce(&WriteTransaction) -> Result<T>) -> Result<T> {
+        match &self.batch {
+            Some(batch) => batch.with_tx(|tx| match tx {
+                Some(tx) => f(tx),
+                None => Err(anyhow!("batch is already committed")),
+            }),
+            None => {
+                let write_tx = self.store.db.begin_write()?;
+                let res = f(&write_tx)?;
+                write_tx.commit()?;
+                Ok(res)
+            }
+        }
+    }
+}
+
+impl Clone for StoreInstance {
+    /// Clone the instance. The clone does not take part in a running batch.
+    fn clone(&self) -> Self {
+        Self::new(self.namespace, self.store.clone())
+    }
+}
+
+/// A write transaction spanning the writes of a batch on a [`StoreInstance`].
+#[self_referencing]
+struct WriteBatch {
+    db: Arc<Database>,
+    #[borrows(db)]
+    #[not_covariant]
+    tx: Option<WriteTransaction<'this>>,
+}
+
+impl std::fmt::Debug for WriteBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WriteBatch")
     }
 }
 
@@ -619,22 +864,20 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
     }
 
     fn get_fingerprint(&self, range: &Range<RecordIdentifier>) -> Result<Fingerprint> {
-        // TODO: optimize
-        let elements = self.get_range(range.clone())?;
-
-        let mut fp = Fingerprint::empty();
-        for el in elements {
-            let el = el?;
-            fp ^= el.as_fingerprint();
-        }
-
-        Ok(fp)
+        let read_tx = self.store.db.begin_read()?;
+        let record_table = read_tx.open_table(RECORDS_TABLE)?;
+        let index = FingerprintIndex::new(
+            self.namespace,
+            &record_table,
+            read_tx.open_table(FINGERPRINTS_TABLE)?,
+            read_tx.open_table(FINGERPRINT_HEADS_TABLE)?,
+        );
+        index.fingerprint(range)
     }
 
     fn put(&mut self, e: SignedEntry) -> Result<()> {
         let id = e.id();
-        let write_tx = self.store.db.begin_write()?;
-        {
+        self.with_write_tx(|write_tx| {
             // insert into record table
             let mut record_table = write_tx.open_table(RECORDS_TABLE)?;
             let key = (
@@ -650,7 +893,18 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
                 e.content_len(),
                 hash.as_bytes(),
             );
-            record_table.insert(key, value)?;
+            let old = record_table
+                .insert(key, value)?
+                .map(|old| fingerprint_of(key, old.value()));
+
+            // update fingerprint index
+            let fingerprint = e.as_fingerprint().0;
+            let mut index = FingerprintIndex::open(write_tx, id.namespace(), &record_table)?;
+            match old {
+                None => index.insert(key, &fingerprint)?,
+                Some(old) if old != fingerprint => index.update(key, &old, &fingerprint)?,
+                Some(_) => {}
+            }
 
             // insert into by key index table
             let mut idx_by_key = write_tx.open_table(RECORDS_BY_KEY_TABLE)?;
@@ -666,9 +920,8 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
             let key = (&e.id().namespace().to_bytes(), &e.id().author().to_bytes());
             let value = (e.timestamp(), e.id().key());
             latest_table.insert(key, value)?;
-        }
-        write_tx.commit()?;
-        Ok(())
+            Ok(())
+        })
     }
 
     fn get_range(&self, range: Range<RecordIdentifier>) -> Result<Self::RangeIterator<'_>> {
@@ -708,21 +961,23 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
     }
 
     fn remove(&mut self, id: &RecordIdentifier) -> Result<Option<SignedEntry>> {
-        let write_tx = self.store.db.begin_write()?;
         let (namespace, author, key) = id.as_byte_tuple();
-        {
-            let mut table = write_tx.open_table(RECORDS_BY_KEY_TABLE)?;
-            let id = (namespace, key, author);
-            table.remove(id)?;
-        }
-        let entry = {
+        self.with_write_tx(|write_tx| {
+            {
+                let mut table = write_tx.open_table(RECORDS_BY_KEY_TABLE)?;
+                let id = (namespace, key, author);
+                table.remove(id)?;
+            }
             let mut table = write_tx.open_table(RECORDS_TABLE)?;
             let id = (namespace, author, key);
             let value = table.remove(id)?;
-            value.map(|value| into_entry(id, value.value()))
-        };
-        write_tx.commit()?;
-        Ok(entry)
+            let entry = value.map(|value| into_entry(id, value.value()));
+            if let Some(entry) = &entry {
+                let mut index = FingerprintIndex::open(write_tx, entry.namespace(), &table)?;
+                index.remove(id, &entry.as_fingerprint().0)?;
+            }
+            Ok(entry)
+        })
     }
 
     fn all(&self) -> Result<Self::RangeIterator<'_>> {
@@ -732,12 +987,28 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
     }
 
     fn prefixes_of(&self, id: &RecordIdentifier) -> Result<Self::ParentIterator<'_>, Self::Error> {
-        ParentIterator::new(
-            &self.store.db,
-            id.namespace(),
-            id.author(),
-            id.key().to_vec(),
-        )
+        if self.batch.is_none() {
+            return ParentIterator::new(
+                &self.store.db,
+                id.namespace(),
+                id.author(),
+                id.key().to_vec(),
+            );
+        }
+        // read from the write transaction to see the writes of the batch
+        let entries = self.with_write_tx(|write_tx| {
+            let table = write_tx.open_table(RECORDS_TABLE)?;
+            let mut key = id.key().to_vec();
+            let mut entries = Vec::new();
+            while !key.is_empty() {
+                if let Some(entry) = get_exact(&table, id.namespace(), id.author(), &key, false)? {
+                    entries.push(entry);
+                }
+                key.pop();
+            }
+            Ok(entries)
+        })?;
+        Ok(ParentIterator::from_entries(entries))
     }
 
     fn prefixed_by(&self, id: &RecordIdentifier) -> Result<Self::RangeIterator<'_>> {
@@ -752,8 +1023,7 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
         predicate: impl Fn(&Record) -> bool,
     ) -> Result<usize> {
         let bounds = RecordsBounds::author_prefix(id.namespace(), id.author(), id.key_bytes());
-        let write_tx = self.store.db.begin_write()?;
-        let count = {
+        self.with_write_tx(|write_tx| {
             let mut table = write_tx.open_table(RECORDS_TABLE)?;
             let cb = |_k: RecordsId, v: RecordsValue| {
                 let (timestamp, _namespace_sig, _author_sig, len, hash) = v;
@@ -761,11 +1031,41 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
 
                 predicate(&record)
             };
-            let iter = table.drain_filter(bounds.as_ref(), cb)?;
-            iter.count()
-        };
-        write_tx.commit()?;
-        Ok(count)
+            let removed = table
+                .drain_filter(bounds.as_ref(), cb)?
+                .map(|item| {
+                    let (id, value) = item?;
+                    let (namespace, author, key) = id.value();
+                    let fingerprint = fingerprint_of((namespace, author, key), value.value());
+                    Ok(((*namespace, *author, key.to_vec()), fingerprint))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut index = FingerprintIndex::open(write_tx, id.namespace(), &table)?;
+            for ((namespace, author, key), fingerprint) in &removed {
+                index.remove((namespace, author, &key[..]), fingerprint)?;
+            }
+            Ok(removed.len())
+        })
+    }
+
+    fn start_batch(&mut self) -> Result<()> {
+        if self.batch.is_some() {
+            return Err(anyhow!("a batch is already running"));
+        }
+        let batch = WriteBatch::try_new(self.store.db.clone(), |db| db.begin_write().map(Some))?;
+        self.batch = Some(batch);
+        Ok(())
+    }
+
+    fn commit_batch(&mut self) -> Result<()> {
+        if let Some(mut batch) = self.batch.take() {
+            batch.with_tx_mut(|tx| tx.take().map(|tx| tx.commit()).transpose())?;
+        }
+        Ok(())
+    }
+
+    fn abort_batch(&mut self) {
+        self.batch = None;
     }
 }
 
@@ -778,11 +1078,17 @@ fn chain_none<'a, I: Iterator<Item = T> + 'a, T>(
 /// Iterator over parent entries, i.e. entries with the same namespace and author, and a key which
 /// is a prefix of the key passed to the iterator.
 #[derive(Debug)]
-pub struct ParentIterator<'a> {
-    reader: TableReader<'a, RecordsId<'static>, RecordsValue<'static>>,
-    namespace: NamespaceId,
-    author: AuthorId,
-    key: Vec<u8>,
+pub struct ParentIterator<'a>(ParentIteratorInner<'a>);
+
+#[derive(Debug)]
+enum ParentIteratorInner<'a> {
+    Reader {
+        reader: TableReader<'a, RecordsId<'static>, RecordsValue<'static>>,
+        namespace: NamespaceId,
+        author: AuthorId,
+        key: Vec<u8>,
+    },
+    Entries(std::vec::IntoIter<SignedEntry>),
 }
 
 impl<'a> ParentIterator<'a> {
@@ -793,12 +1099,16 @@ impl<'a> ParentIterator<'a> {
         key: Vec<u8>,
     ) -> anyhow::Result<Self> {
         let reader = TableReader::new(db, |tx| tx.open_table(RECORDS_TABLE))?;
-        Ok(Self {
+        Ok(Self(ParentIteratorInner::Reader {
             reader,
             namespace,
             author,
             key,
-        })
+        }))
+    }
+
+    fn from_entries(entries: Vec<SignedEntry>) -> Self {
+        Self(ParentIteratorInner::Entries(entries.into_iter()))
     }
 }
 
@@ -806,17 +1116,27 @@ impl Iterator for ParentIterator<'_> {
     type Item = Result<SignedEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let records_table = self.reader.table();
-        while !self.key.is_empty() {
-            let entry = get_exact(records_table, self.namespace, self.author, &self.key, false);
-            self.key.pop();
-            match entry {
-                Err(err) => return Some(Err(err)),
-                Ok(Some(entry)) => return Some(Ok(entry)),
-                Ok(None) => continue,
+        match &mut self.0 {
+            ParentIteratorInner::Reader {
+                reader,
+                namespace,
+                author,
+                key,
+            } => {
+                let records_table = reader.table();
+                while !key.is_empty() {
+                    let entry = get_exact(records_table, *namespace, *author, &*key, false);
+                    key.pop();
+                    match entry {
+                        Err(err) => return Some(Err(err)),
+                        Ok(Some(entry)) => return Some(Ok(entry)),
+                        Ok(None) => continue,
+                    }
+                }
+                None
             }
+            ParentIteratorInner::Entries(entries) => entries.next().map(Ok),
         }
-        None
     }
 }
 
@@ -886,6 +1206,9 @@ fn into_entry(key: RecordsId, value: RecordsValue) -> SignedEntry {
 
 #[cfg(test)]
 mod tests {
+    use rand::Rng;
+    use rand_core::SeedableRng;
+
     use crate::ranger::Store as _;
     use crate::NamespaceSecret;
 
@@ -992,6 +1315,117 @@ mod tests {
         Ok(())
     }
 
+    /// Compute the fingerprint of a range by iterating over all entries in the range.
+    fn naive_fingerprint(
+        instance: &StoreInstance,
+        range: &Range<RecordIdentifier>,
+    ) -> Result<Fingerprint> {
+        let mut fp = Fingerprint::empty();
+        for entry in instance.get_range(range.clone())? {
+            fp ^= entry?.as_fingerprint();
+        }
+        Ok(fp)
+    }
+
+    fn assert_fingerprints(instance: &StoreInstance, ids: &[RecordIdentifier]) -> Result<()> {
+        for x in ids.iter().step_by(7) {
+            for y in ids.iter().step_by(5) {
+                let range = Range::new(x.clone(), y.clone());
+                assert_eq!(
+                    instance.get_fingerprint(&range)?,
+                    naive_fingerprint(instance, &range)?,
+                    "fingerprint mismatch for {range:?}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_index() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let store = Store::memory();
+        let author1 = store.new_author(&mut rng)?;
+        let author2 = store.new_author(&mut rng)?;
+        let namespace = NamespaceSecret::new(&mut rng);
+        let other_namespace = NamespaceSecret::new(&mut rng);
+        let mut instance = StoreInstance::new(namespace.id(), store.clone());
+        let mut other_instance = StoreInstance::new(other_namespace.id(), store.clone());
+
+        let put = |instance: &mut StoreInstance,
+                   namespace: &NamespaceSecret,
+                   author: &Author,
+                   key: String,
+                   value: String|
+         -> Result<RecordIdentifier> {
+            let id = RecordIdentifier::new(namespace.id(), author.id(), key);
+            let entry = Entry::new(id.clone(), Record::current_from_data(value));
+            instance.put(SignedEntry::from_entry(entry, namespace, author))?;
+            Ok(id)
+        };
+
+        // empty namespace
+        let empty = RecordIdentifier::default();
+        assert_eq!(
+            instance.get_fingerprint(&Range::new(empty.clone(), empty))?,
+            Fingerprint::empty()
+        );
+
+        let mut ids = Vec::new();
+        for i in 0..300 {
+            let author = if i % 3 == 0 { &author1 } else { &author2 };
+            let key = format!("key-{}", rng.gen_range(0..1000));
+            ids.push(put(&mut instance, &namespace, author, key, format!("{i}"))?);
+            put(
+                &mut other_instance,
+                &other_namespace,
+                author,
+                format!("other-{i}"),
+                format!("{i}"),
+            )?;
+        }
+        ids.push(RecordIdentifier::new(
+            namespace.id(),
+            author1.id(),
+            "unused",
+        ));
+        ids.sort();
+        ids.dedup();
+        assert_fingerprints(&instance, &ids)?;
+
+        // update existing entries
+        for id in ids.iter().step_by(3) {
+            let author = if id.author() == author1.id() {
+                &author1
+            } else {
+                &author2
+            };
+            let key = String::from_utf8(id.key().to_vec())?;
+            put(&mut instance, &namespace, author, key, "updated".into())?;
+        }
+        assert_fingerprints(&instance, &ids)?;
+
+        // remove entries
+        for id in ids.iter().step_by(4) {
+            instance.remove(id)?;
+        }
+        let prefix = RecordIdentifier::new(namespace.id(), author2.id(), "key-1");
+        instance.remove_prefix_filtered(&prefix, |_| true)?;
+        assert_fingerprints(&instance, &ids)?;
+
+        // removing the other namespace does not affect this one
+        let fingerprint = instance.get_fingerprint(&Range::new(ids[0].clone(), ids[0].clone()))?;
+        drop(other_instance);
+        store.remove_replica(&other_namespace.id())?;
+        assert_eq!(
+            instance.get_fingerprint(&Range::new(ids[0].clone(), ids[0].clone()))?,
+            fingerprint
+        );
+        assert_fingerprints(&instance, &ids)?;
+
+        Ok(())
+    }
+
     fn copy_and_modify(
         source: &Path,
         modify: impl Fn(&redb::WriteTransaction) -> Result<()>,
@@ -1065,4 +1499,53 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_migration_005_populate_fingerprint_index() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let namespace = NamespaceSecret::new(&mut rand::thread_rng());
+
+        // create a store and add some data
+        let (ids, expected) = {
+            let store = Store::persistent(dbfile.path())?;
+            let author = store.new_author(&mut rand::thread_rng())?;
+            let mut replica = store.new_replica(namespace.clone())?;
+            let mut ids = Vec::new();
+            for i in 0..100 {
+                let key = format!("k{i}");
+                replica.hash_and_insert(&key, &author, b"v1")?;
+                ids.push(RecordIdentifier::new(namespace.id(), author.id(), key));
+            }
+            ids.sort();
+            let instance = StoreInstance::new(namespace.id(), store.clone());
+            let expected = ids
+                .iter()
+                .map(|id| instance.get_fingerprint(&Range::new(id.clone(), ids[10].clone())))
+                .collect::<Result<Vec<_>>>()?;
+            // drop everything to clear file locks.
+            store.close_replica(replica);
+            drop(instance);
+            drop(store);
+            (ids, expected)
+        };
+
+        // create a copy of our db file with the fingerprint index deleted.
+        let dbfile_before_migration = copy_and_modify(dbfile.path(), |tx| {
+            tx.delete_table(FINGERPRINTS_TABLE)?;
+            tx.delete_table(FINGERPRINT_HEADS_TABLE)?;
+            Ok(())
+        })?;
+
+        // open the copied db file, which will run the migration.
+        let store = Store::persistent(dbfile_before_migration.path())?;
+        let instance = StoreInstance::new(namespace.id(), store.clone());
+        let actual = ids
+            .iter()
+            .map(|id| instance.get_fingerprint(&Range::new(id.clone(), ids[10].clone())))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected, actual);
+        assert_fingerprints(&instance, &ids)?;
+
+        Ok(())
+    }
 }
@@ -161,7 +161,7 @@ impl RangeBounds<RecordsByKeyIdOwned> for ByKeyBounds {
 /// Increment a byte string by one, by incrementing the last byte that is not 255 by one.
 ///
 /// Returns false if all bytes are 255.
-fn increment_by_one(value: &mut [u8]) -> bool {
+pub fn increment_by_one(value: &mut [u8]) -> bool {
     for char in value.iter_mut().rev() {
         if *char != 255 {
             *char += 1;
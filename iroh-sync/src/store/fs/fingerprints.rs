@@ -0,0 +1,426 @@
+//! Index to compute the fingerprint of a range of records in logarithmic time.
+//!
+//! The fingerprint of a range is the XOR of [`Fingerprint::empty`] and the fingerprints of all
+//! entries in the range. The index is a deterministic skip list over the records of each
+//! namespace. The records table itself is level 0, and each record is also a node on the levels
+//! `1..=level_of(id)`, where the level is derived from the hash of the record identifier so that
+//! on average every fourth node of a level is also a node on the level above. Each node stores
+//! the XOR of the fingerprints of all records from the node up to (excluding) the next node on the
+//! same level. Each namespace also has a head per level, which covers the records before the first
+//! node of that level. No record is a node on [`MAX_LEVEL`], so its head covers the whole
+//! namespace.
+//!
+//! The XOR of all records before an identifier is found by walking down from the highest level,
+//! which takes a logarithmic number of steps in expectation. Because XOR is its own inverse, the
+//! fingerprint of any range follows from two of these prefix fingerprints.
+
+use std::{cmp::Ordering, ops::Bound};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use redb::{ReadableTable, Table, WriteTransaction};
+
+use crate::{
+    ranger::{Fingerprint, Range, RangeEntry},
+    sync::RecordIdentifier,
+    NamespaceId,
+};
+
+use super::{
+    bounds::{increment_by_one, RecordsBounds},
+    into_entry, FingerprintHeadsId, FingerprintsId, RecordsId, RecordsIdOwned, RecordsValue,
+    FINGERPRINTS_TABLE, FINGERPRINT_HEADS_TABLE, RECORDS_TABLE,
+};
+
+/// The level of the heads which cover all records of a namespace.
+///
+/// Records are nodes on at most `MAX_LEVEL - 1` levels, which keeps lookups logarithmic for up to
+/// `4^(MAX_LEVEL - 1)` records per namespace.
+const MAX_LEVEL: u8 = 16;
+
+/// The XOR of a set of fingerprints, without the empty fingerprint mixed in.
+type Xor = [u8; 32];
+
+type NodesTable<'db, 'txn> = Table<'db, 'txn, FingerprintsId<'static>, &'static [u8; 32]>;
+type HeadsTable<'db, 'txn> = Table<'db, 'txn, FingerprintHeadsId<'static>, &'static [u8; 32]>;
+
+/// The fingerprint index of a namespace.
+pub struct FingerprintIndex<'a, R, N, H> {
+    namespace: [u8; 32],
+    namespace_end: Option<[u8; 32]>,
+    records: &'a R,
+    nodes: N,
+    heads: H,
+}
+
+impl<'a, R, N, H> FingerprintIndex<'a, R, N, H>
+where
+    R: ReadableTable<RecordsId<'static>, RecordsValue<'static>>,
+    N: ReadableTable<FingerprintsId<'static>, &'static [u8; 32]>,
+    H: ReadableTable<FingerprintHeadsId<'static>, &'static [u8; 32]>,
+{
+    /// Create the index for `namespace` from the records, nodes and heads tables.
+    pub fn new(namespace: NamespaceId, records: &'a R, nodes: N, heads: H) -> Self {
+        let namespace = namespace.to_bytes();
+        let mut namespace_end = namespace;
+        let namespace_end = increment_by_one(&mut namespace_end).then_some(namespace_end);
+        Self {
+            namespace,
+            namespace_end,
+            records,
+            nodes,
+            heads,
+        }
+    }
+
+    /// Get the fingerprint of the records in `range`.
+    ///
+    /// This equals the XOR of the fingerprints of all entries returned by
+    /// [`crate::ranger::Store::get_range`].
+    pub fn fingerprint(&self, range: &Range<RecordIdentifier>) -> Result<Fingerprint> {
+        let mut acc = match range.x().cmp(range.y()) {
+            // identity range: all records
+            Ordering::Equal => self.total()?,
+            // regular range: x <= t < y
+            Ordering::Less => self.prefix(range.y())?,
+            // split range: start <= t < y and x <= t <= end
+            Ordering::Greater => {
+                let mut acc = self.total()?;
+                xor(&mut acc, &self.prefix(range.y())?);
+                acc
+            }
+        };
+        if range.x() != range.y() {
+            xor(&mut acc, &self.prefix(range.x())?);
+        }
+        let mut fp = Fingerprint::empty();
+        fp ^= Fingerprint(acc);
+        Ok(fp)
+    }
+
+    /// XOR of the fingerprints of all records in the namespace.
+    fn total(&self) -> Result<Xor> {
+        self.get(MAX_LEVEL, None)
+    }
+
+    /// XOR of the fingerprints of all records in the namespace which are smaller than `bound`.
+    fn prefix(&self, bound: &RecordIdentifier) -> Result<Xor> {
+        let bound = bound.as_byte_tuple();
+        match bound.0.cmp(&self.namespace) {
+            Ordering::Less => return Ok([0u8; 32]),
+            Ordering::Greater => return self.total(),
+            Ordering::Equal => {}
+        }
+        let mut acc = [0u8; 32];
+        let mut current = None;
+        for level in (0..MAX_LEVEL).rev() {
+            while let Some(next) = self.next(level, current.as_ref())? {
+                if as_id(&next) > bound {
+                    break;
+                }
+                xor(&mut acc, &self.get(level, current.as_ref())?);
+                current = Some(next);
+            }
+        }
+        // `current` is the last record not greater than `bound`, and not yet included
+        if let Some(current) = current {
+            if as_id(&current) < bound {
+                xor(&mut acc, &self.get(0, Some(&current))?);
+            }
+        }
+        Ok(acc)
+    }
+
+    /// Get the value of `node` on `level`, or of the head of `level` if `node` is `None`.
+    fn get(&self, level: u8, node: Option<&RecordsIdOwned>) -> Result<Xor> {
+        match node {
+            None => {
+                let value = self.heads.get((&self.namespace, level))?;
+                Ok(value.map(|value| *value.value()).unwrap_or_default())
+            }
+            Some(id) if level == 0 => {
+                let id = as_id(id);
+                let value = self
+                    .records
+                    .get(id)?
+                    .ok_or_else(|| anyhow!("fingerprint index refers to missing record"))?;
+                Ok(fingerprint_of(id, value.value()))
+            }
+            Some(id) => {
+                let value = self
+                    .nodes
+                    .get((level, &id.0, &id.1, &id.2[..]))?
+                    .ok_or_else(|| anyhow!("fingerprint index is missing a node"))?;
+                Ok(*value.value())
+            }
+        }
+    }
+
+    /// Get the first node on `level` after `node`, or the first node if `node` is `None`.
+    fn next(&self, level: u8, node: Option<&RecordsIdOwned>) -> Result<Option<RecordsIdOwned>> {
+        if level == 0 {
+            let start = match node {
+                Some(id) => Bound::Excluded(id.clone()),
+                None => Bound::Included((self.namespace, [0u8; 32], Bytes::new())),
+            };
+            let bounds = RecordsBounds::to_end(&(&self.namespace).into(), start);
+            let next = self.records.range(bounds.as_ref())?.next().transpose()?;
+            return Ok(next.map(|(id, _value)| to_owned(id.value())));
+        }
+        let start = match node {
+            Some(id) => Bound::Excluded((level, &id.0, &id.1, &id.2[..])),
+            None => Bound::Included((level, &self.namespace, &[0u8; 32], &[][..])),
+        };
+        let end = match self.namespace_end {
+            Some(ref namespace_end) => Bound::Excluded((level, namespace_end, &[0u8; 32], &[][..])),
+            None => Bound::Excluded((level + 1, &[0u8; 32], &[0u8; 32], &[][..])),
+        };
+        let next = self.nodes.range((start, end))?.next().transpose()?;
+        Ok(next.map(|(node, _value)| {
+            let (_level, namespace, author, key) = node.value();
+            to_owned((namespace, author, key))
+        }))
+    }
+
+    /// Get the last node on `level` before `id`, or `None` if there is no such node.
+    fn prev(&self, level: u8, id: RecordsId) -> Result<Option<RecordsIdOwned>> {
+        let (namespace, author, key) = id;
+        let start = Bound::Included((level, &self.namespace, &[0u8; 32], &[][..]));
+        let end = Bound::Excluded((level, namespace, author, key));
+        let prev = self.nodes.range((start, end))?.next_back().transpose()?;
+        Ok(prev.map(|(node, _value)| {
+            let (_level, namespace, author, key) = node.value();
+            to_owned((namespace, author, key))
+        }))
+    }
+}
+
+impl<'a, 'db, 'txn, R> FingerprintIndex<'a, R, NodesTable<'db, 'txn>, HeadsTable<'db, 'txn>>
+where
+    R: ReadableTable<RecordsId<'static>, RecordsValue<'static>>,
+{
+    /// Open the index for `namespace` in a write transaction.
+    pub fn open(
+        tx: &'txn WriteTransaction<'db>,
+        namespace: NamespaceId,
+        records: &'a R,
+    ) -> Result<Self> {
+        let nodes = tx.open_table(FINGERPRINTS_TABLE)?;
+        let heads = tx.open_table(FINGERPRINT_HEADS_TABLE)?;
+        Ok(Self::new(namespace, records, nodes, heads))
+    }
+
+    /// Add a record with `fingerprint` to the index.
+    ///
+    /// The record must already be in the records table.
+    pub fn insert(&mut self, id: RecordsId, fingerprint: &Xor) -> Result<()> {
+        let height = level_of(id);
+        let id = to_owned(id);
+        for level in 1..=MAX_LEVEL {
+            let prev = self.prev(level, as_id(&id))?;
+            let mut prev_value = self.get(level, prev.as_ref())?;
+            xor(&mut prev_value, fingerprint);
+            if level <= height {
+                // the new node covers the nodes of the level below, up to the next node of this level
+                let end = self.next(level, prev.as_ref())?;
+                let mut value = [0u8; 32];
+                let mut current = Some(id.clone());
+                while let Some(node) = current {
+                    if end.as_ref().is_some_and(|end| &node >= end) {
+                        break;
+                    }
+                    xor(&mut value, &self.get(level - 1, Some(&node))?);
+                    current = self.next(level - 1, Some(&node))?;
+                }
+                xor(&mut prev_value, &value);
+                self.set(level, Some(&id), &value)?;
+            }
+            self.set(level, prev.as_ref(), &prev_value)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the fingerprint of a record that is already in the index.
+    pub fn update(&mut self, id: RecordsId, old: &Xor, new: &Xor) -> Result<()> {
+        let height = level_of(id);
+        let id = to_owned(id);
+        let mut delta = *old;
+        xor(&mut delta, new);
+        for level in 1..=MAX_LEVEL {
+            let node = match level <= height {
+                true => Some(id.clone()),
+                false => self.prev(level, as_id(&id))?,
+            };
+            let mut value = self.get(level, node.as_ref())?;
+            xor(&mut value, &delta);
+            self.set(level, node.as_ref(), &value)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a record with `fingerprint` from the index.
+    pub fn remove(&mut self, id: RecordsId, fingerprint: &Xor) -> Result<()> {
+        let height = level_of(id);
+        for level in 1..=MAX_LEVEL {
+            let prev = self.prev(level, id)?;
+            let mut prev_value = self.get(level, prev.as_ref())?;
+            xor(&mut prev_value, fingerprint);
+            if level <= height {
+                let (namespace, author, key) = id;
+                if let Some(value) = self.nodes.remove((level, namespace, author, key))? {
+                    xor(&mut prev_value, value.value());
+                }
+            }
+            self.set(level, prev.as_ref(), &prev_value)?;
+        }
+        Ok(())
+    }
+
+    /// Set the value of `node` on `level`, or of the head of `level` if `node` is `None`.
+    fn set(&mut self, level: u8, node: Option<&RecordsIdOwned>, value: &Xor) -> Result<()> {
+        set(
+            &mut self.nodes,
+            &mut self.heads,
+            &self.namespace,
+            level,
+            node,
+            value,
+        )
+    }
+}
+
+/// Build the fingerprint index for all records in the store.
+///
+/// Returns the number of indexed records.
+pub fn build_index(tx: &WriteTransaction) -> Result<usize> {
+    let records = tx.open_table(RECORDS_TABLE)?;
+    let mut nodes = tx.open_table(FINGERPRINTS_TABLE)?;
+    let mut heads = tx.open_table(FINGERPRINT_HEADS_TABLE)?;
+
+    // the currently open node and its value for each level, starting at level 1
+    let mut open: Vec<(Option<RecordsIdOwned>, Xor)> = Vec::new();
+    let mut namespace = None;
+    let mut len = 0;
+    for next in records.iter()? {
+        let next = next?;
+        let id = next.0.value();
+        if namespace != Some(*id.0) {
+            if let Some(namespace) = namespace {
+                for (level, (node, value)) in (1..).zip(open.iter()) {
+                    set(
+                        &mut nodes,
+                        &mut heads,
+                        &namespace,
+                        level,
+                        node.as_ref(),
+                        value,
+                    )?;
+                }
+            }
+            namespace = Some(*id.0);
+            open = vec![(None, [0u8; 32]); MAX_LEVEL as usize];
+        }
+        let namespace = namespace.as_ref().expect("just set");
+        let height = level_of(id);
+        for (level, (node, value)) in (1..=height).zip(open.iter_mut()) {
+            set(
+                &mut nodes,
+                &mut heads,
+                namespace,
+                level,
+                node.as_ref(),
+                value,
+            )?;
+            *node = Some(to_owned(id));
+            *value = [0u8; 32];
+        }
+        let fingerprint = fingerprint_of(id, next.1.value());
+        for (_node, value) in open.iter_mut() {
+            xor(value, &fingerprint);
+        }
+        len += 1;
+    }
+    if let Some(namespace) = namespace {
+        for (level, (node, value)) in (1..).zip(open.iter()) {
+            set(
+                &mut nodes,
+                &mut heads,
+                &namespace,
+                level,
+                node.as_ref(),
+                value,
+            )?;
+        }
+    }
+    Ok(len)
+}
+
+/// Remove the fingerprint index of a namespace.
+pub fn remove_index(tx: &WriteTransaction, namespace: &NamespaceId) -> Result<()> {
+    let namespace = namespace.as_bytes();
+    let mut namespace_end = *namespace;
+    let has_end = increment_by_one(&mut namespace_end);
+    let mut nodes = tx.open_table(FINGERPRINTS_TABLE)?;
+    for level in 1..MAX_LEVEL {
+        let start = Bound::Included((level, namespace, &[0u8; 32], &[][..]));
+        let end = match has_end {
+            true => Bound::Excluded((level, &namespace_end, &[0u8; 32], &[][..])),
+            false => Bound::Excluded((level + 1, &[0u8; 32], &[0u8; 32], &[][..])),
+        };
+        nodes.drain((start, end))?;
+    }
+    let mut heads = tx.open_table(FINGERPRINT_HEADS_TABLE)?;
+    heads.drain((namespace, 0)..=(namespace, MAX_LEVEL))?;
+    Ok(())
+}
+
+fn set(
+    nodes: &mut NodesTable,
+    heads: &mut HeadsTable,
+    namespace: &[u8; 32],
+    level: u8,
+    node: Option<&RecordsIdOwned>,
+    value: &Xor,
+) -> Result<()> {
+    match node {
+        None => {
+            heads.insert((namespace, level), value)?;
+        }
+        Some(id) => {
+            nodes.insert((level, &id.0, &id.1, &id.2[..]), value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the fingerprint of a record in the records table.
+pub fn fingerprint_of(id: RecordsId, value: RecordsValue) -> Xor {
+    into_entry(id, value).as_fingerprint().0
+}
+
+/// Get the highest level on which a record is a node.
+fn level_of(id: RecordsId) -> u8 {
+    let (namespace, author, key) = id;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(namespace);
+    hasher.update(author);
+    hasher.update(key);
+    let hash = hasher.finalize();
+    let bits = u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("slice of 8 bytes"));
+    ((bits.trailing_zeros() / 2) as u8).min(MAX_LEVEL - 1)
+}
+
+fn xor(acc: &mut Xor, other: &Xor) {
+    for (a, b) in acc.iter_mut().zip(other.iter()) {
+        *a ^= b;
+    }
+}
+
+fn as_id(id: &RecordsIdOwned) -> RecordsId {
+    (&id.0, &id.1, &id.2[..])
+}
+
+fn to_owned(id: RecordsId) -> RecordsIdOwned {
+    let (namespace, author, key) = id;
+    (*namespace, *author, Bytes::copy_from_slice(key))
+}
@@ -7,8 +7,8 @@ use tracing::{debug, info};
 use crate::{Capability, NamespaceSecret};
 
 use super::{
-    LATEST_PER_AUTHOR_TABLE, NAMESPACES_TABLE, NAMESPACES_TABLE_V1, RECORDS_BY_KEY_TABLE,
-    RECORDS_TABLE,
+    fingerprints, FINGERPRINT_HEADS_TABLE, LATEST_PER_AUTHOR_TABLE, NAMESPACES_TABLE,
+    NAMESPACES_TABLE_V1, RECORDS_BY_KEY_TABLE, RECORDS_TABLE,
 };
 
 /// Run all database migrations, if needed.
@@ -17,6 +17,7 @@ pub fn run_migrations(db: &Database) -> Result<()> {
     run_migration(db, migration_002_namespaces_populate_v2)?;
     run_migration(db, migration_003_namespaces_delete_v1)?;
     run_migration(db, migration_004_populate_by_key_index)?;
+    run_migration(db, migration_005_populate_fingerprint_index)?;
     Ok(())
 }
 
@@ -137,3 +138,16 @@ fn migration_004_populate_by_key_index(tx: &WriteTransaction) -> Result<MigrateO
     }
     Ok(MigrateOutcome::Execute(len))
 }
+
+/// migration 005: populate the fingerprint index tables (which did not exist before)
+fn migration_005_populate_fingerprint_index(tx: &WriteTransaction) -> Result<MigrateOutcome> {
+    {
+        let heads_table = tx.open_table(FINGERPRINT_HEADS_TABLE)?;
+        let records_table = tx.open_table(RECORDS_TABLE)?;
+        if !heads_table.is_empty()? || records_table.is_empty()? {
+            return Ok(MigrateOutcome::Skip);
+        }
+    }
+    let len = fingerprints::build_index(tx)?;
+    Ok(MigrateOutcome::Execute(len))
+}
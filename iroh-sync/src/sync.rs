@@ -27,7 +27,7 @@ pub use crate::heads::AuthorHeads;
 use crate::metrics::Metrics;
 use crate::{
     keys::{Author, AuthorId, AuthorPublicKey, NamespaceId, NamespacePublicKey, NamespaceSecret},
-    ranger::{self, Fingerprint, InsertOutcome, Peer, RangeEntry, RangeKey, RangeValue},
+    ranger::{self, Fingerprint, InsertOutcome, Peer, Range, RangeEntry, RangeKey, RangeValue},
     store::{self, PublicKeyStore},
 };
 
@@ -56,6 +56,20 @@ pub enum Event {
         namespace: NamespaceId,
         /// Inserted entry.
         entry: SignedEntry,
+        /// Whether the entry was inserted as part of a batch.
+        ///
+        /// The events for the entries of a batch are followed by a single
+        /// [`Event::LocalInsertBatch`].
+        batched: bool,
+    },
+    /// A batch of local entries has been added with [`Replica::insert_batch`].
+    LocalInsertBatch {
+        /// Document in which the entries were inserted.
+        namespace: NamespaceId,
+        /// Timestamp of the latest inserted entry for each author.
+        heads: AuthorHeads,
+        /// Number of inserted entries.
+        len: usize,
     },
     /// A remote entry has been added.
     RemoteInsert {
@@ -365,6 +379,73 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
         self.insert_entry(signed_entry, InsertOrigin::Local)
     }
 
+    /// Insert many new records in a single batch.
+    ///
+    /// The entries will be signed by the provided `author`. For each entry, the `len` must be the
+    /// byte length of the data identified by `hash`.
+    ///
+    /// All entries are written to the store together: if any entry fails to insert, none of
+    /// them are inserted. Once all entries are inserted, an [`Event::LocalInsert`] is emitted for
+    /// each entry, followed by a single [`Event::LocalInsertBatch`].
+    ///
+    /// Returns the number of entries removed as a consequence of the insertions.
+    pub fn insert_batch<K: AsRef<[u8]>>(
+        &mut self,
+        author: &Author,
+        entries: impl IntoIterator<Item = (K, Hash, u64)>,
+    ) -> Result<usize, InsertError<S>> {
+        self.ensure_open()?;
+        let namespace = self.id();
+        let secret = self.secret_key()?;
+        let entries = entries
+            .into_iter()
+            .map(|(key, hash, len)| {
+                if len == 0 || hash == Hash::EMPTY {
+                    return Err(InsertError::EntryIsEmpty);
+                }
+                let id = RecordIdentifier::new(namespace, author.id(), key);
+                let record = Record::new_current(hash, len);
+                Ok(Entry::new(id, record).sign(secret, author))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut heads = AuthorHeads::default();
+        for entry in &entries {
+            heads.insert(entry.author(), entry.timestamp());
+        }
+        let len = entries.len();
+
+        self.peer.store.start_batch().map_err(InsertError::Store)?;
+        let mut removed = 0;
+        let mut events = Vec::with_capacity(len);
+        for entry in entries {
+            match self.put_entry(entry, InsertOrigin::Local, true) {
+                Ok((count, event)) => {
+                    removed += count;
+                    events.push(event);
+                }
+                Err(err) => {
+                    self.peer.store.abort_batch();
+                    return Err(err);
+                }
+            }
+        }
+        self.peer.store.commit_batch().map_err(InsertError::Store)?;
+
+        for event in events {
+            self.subscribers.send(event);
+        }
+        self.subscribers.send(Event::LocalInsertBatch {
+            namespace,
+            heads,
+            len,
+        });
+        Ok(removed)
+    }
+
     /// Delete entries that match the given `author` and key `prefix`.
     ///
     /// This inserts an empty entry with the key set to `prefix`, effectively clearing all other
@@ -413,6 +494,21 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
         entry: SignedEntry,
         origin: InsertOrigin,
     ) -> Result<usize, InsertError<S>> {
+        let (removed_count, insert_event) = self.put_entry(entry, origin, false)?;
+        self.subscribers.send(insert_event);
+        Ok(removed_count)
+    }
+
+    /// Validate and insert a signed entry into the database, without emitting the event.
+    ///
+    /// Returns the number of entries removed as a consequence of this insertion and the event to
+    /// emit.
+    fn put_entry(
+        &mut self,
+        entry: SignedEntry,
+        origin: InsertOrigin,
+        batched: bool,
+    ) -> Result<(usize, Event), InsertError<S>> {
         let namespace = self.id();
 
         #[cfg(feature = "metrics")]
@@ -435,7 +531,11 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
                     inc!(Metrics, new_entries_local);
                     inc_by!(Metrics, new_entries_local_size, len);
                 }
-                Event::LocalInsert { namespace, entry }
+                Event::LocalInsert {
+                    namespace,
+                    entry,
+                    batched,
+                }
             }
             InsertOrigin::Sync {
                 from,
@@ -463,9 +563,7 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
             }
         };
 
-        self.subscribers.send(insert_event);
-
-        Ok(removed_count)
+        Ok((removed_count, insert_event))
     }
 
     /// Hashes the given data and inserts it.
@@ -496,6 +594,18 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
         self.peer.initial_message().map_err(Into::into)
     }
 
+    /// Create the message to resume an interrupted set reconciliation flow with a remote peer.
+    ///
+    /// `ranges` are the ranges that were still open when the flow was interrupted, as returned
+    /// from `ProtocolMessage::ranges` for the last message sent to the peer.
+    pub fn sync_resume_message(
+        &self,
+        ranges: Vec<Range<RecordIdentifier>>,
+    ) -> anyhow::Result<crate::ranger::Message<SignedEntry>> {
+        self.ensure_open()?;
+        self.peer.resume_message(ranges).map_err(Into::into)
+    }
+
     /// Process a set reconciliation message from a remote peer.
     ///
     /// Returns the next message to be sent to the peer, if any.
@@ -793,6 +903,12 @@ impl RangeEntry for SignedEntry {
         hasher.update(self.content_hash().as_bytes());
         Fingerprint(hasher.finalize().into())
     }
+
+    fn encoded_len(&self) -> usize {
+        // the two signatures, the record identifier, and hash, length and timestamp of the
+        // record, which are encoded as varints of at most 10 bytes
+        2 * 64 + self.entry.id.0.len() + 32 + 2 * 10
+    }
 }
 
 /// Signature over an entry.
@@ -1824,6 +1940,74 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_batch_memory() -> Result<()> {
+        let store = store::Store::memory();
+        test_insert_batch(store)
+    }
+
+    #[test]
+    fn test_insert_batch_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::persistent(dbfile.path())?;
+        test_insert_batch(store)
+    }
+
+    fn test_insert_batch(store: Store) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+        let mut replica = store.new_replica(namespace.clone())?;
+        let (events_sender, events) = flume::bounded(32);
+        replica.subscribe(events_sender);
+
+        let batch = ["a", "b", "c"].map(|key| (key, Hash::new(key), 1));
+        replica.insert_batch(&author, batch)?;
+        let res = store
+            .get_many(namespace.id(), Query::all())?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(res.len(), 3);
+
+        let received = events.drain().collect::<Vec<_>>();
+        assert_eq!(received.len(), 4);
+        for event in &received[..3] {
+            assert!(matches!(event, Event::LocalInsert { batched: true, .. }));
+        }
+        let Event::LocalInsertBatch { heads, len, .. } = &received[3] else {
+            panic!("expected batch event, got {:?}", received[3]);
+        };
+        assert_eq!(*len, 3);
+        assert_eq!(heads.len(), 1);
+        assert!(heads.get(&author.id()).is_some());
+
+        // a newer entry for one key fails the whole batch
+        let id = replica.record_id("e", &author);
+        let timestamp = system_time_now() + 400_000;
+        let record = Record::new(Hash::new("e"), 1, timestamp);
+        let entry = Entry::new(id, record).sign(&namespace, &author);
+        replica.insert_remote_entry(entry, [1u8; 32], ContentStatus::Missing)?;
+        let batch = ["d", "e"].map(|key| (key, Hash::new(key), 1));
+        let res = replica.insert_batch(&author, batch);
+        assert!(matches!(res, Err(InsertError::NewerEntryExists)));
+        let entry = store.get_exact(namespace.id(), author.id(), "d", false)?;
+        assert!(entry.is_none());
+
+        // empty entries are rejected before inserting anything
+        let batch = [("f", Hash::new("f"), 1), ("g", Hash::EMPTY, 0)];
+        let res = replica.insert_batch(&author, batch);
+        assert!(matches!(res, Err(InsertError::EntryIsEmpty)));
+        let entry = store.get_exact(namespace.id(), author.id(), "f", false)?;
+        assert!(entry.is_none());
+
+        // the replica is usable after a failed batch
+        replica.insert_batch(&author, [("d", Hash::new("d"), 1)])?;
+        let res = store
+            .get_many(namespace.id(), Query::all())?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(res.len(), 5);
+        Ok(())
+    }
+
     #[test]
     fn test_replica_remove_memory() -> Result<()> {
         let alice_store = store::Store::memory();
@@ -2384,6 +2568,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_access_policies_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::persistent(dbfile.path())?;
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let id = namespace.id();
+        let allowed = iroh_base::key::SecretKey::generate_with_rng(&mut rng).public();
+        let other = iroh_base::key::SecretKey::generate_with_rng(&mut rng).public();
+
+        let policy = store::AccessPolicy::NobodyExcept(vec![allowed]);
+        store
+            .set_access_policy(&id, policy.clone())
+            .expect_err("document does not exist");
+
+        // now create the document
+        let replica = store.new_replica(namespace)?;
+        store.close_replica(replica);
+        assert_eq!(
+            store.get_access_policy(&id)?,
+            store::AccessPolicy::default()
+        );
+
+        store.set_access_policy(&id, policy.clone())?;
+        let retrieved_policy = store.get_access_policy(&id)?;
+        assert_eq!(retrieved_policy, policy);
+        assert!(retrieved_policy.is_allowed(&allowed));
+        assert!(!retrieved_policy.is_allowed(&other));
+
+        // the policy is removed with the document
+        store.remove_replica(&id)?;
+        assert_eq!(
+            store.get_access_policy(&id)?,
+            store::AccessPolicy::default()
+        );
+        Ok(())
+    }
+
     fn assert_keys(store: &Store, namespace: NamespaceId, mut expected: Vec<Vec<u8>>) {
         expected.sort();
         assert_eq!(expected, get_keys_sorted(store, namespace));
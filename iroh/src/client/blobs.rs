@@ -681,6 +681,13 @@ impl BlobReader {
         }
     }
 
+    /// Create a reader for data that is already in memory.
+    pub(crate) fn from_bytes(data: Bytes) -> Self {
+        let size = data.len() as u64;
+        let stream = futures::stream::iter([Ok(data)]);
+        Self::new(size, size, true, Box::pin(stream))
+    }
+
     pub(crate) async fn from_rpc_read<C: ServiceConnection<ProviderService>>(
         rpc: &RpcClient<ProviderService, C>,
         hash: Hash,
@@ -5,7 +5,7 @@ use std::{
     task::{Context, Poll},
 };
 
-use anyhow::{anyhow, Context as _, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 use bytes::Bytes;
 use futures::{Stream, StreamExt, TryStreamExt};
 use iroh_base::key::PublicKey;
@@ -13,7 +13,8 @@ use iroh_bytes::{export::ExportProgress, store::ExportMode, Hash};
 use iroh_net::NodeAddr;
 use iroh_sync::{
     actor::OpenState,
-    store::{DownloadPolicy, Query},
+    encryption::{DocEncryptionKey, ENCRYPTION_OVERHEAD},
+    store::{AccessPolicy, DownloadPolicy, KeyFilter, Query},
     AuthorId, CapabilityKind, ContentStatus, NamespaceId, PeerIdBytes, RecordIdentifier,
 };
 use portable_atomic::{AtomicBool, Ordering};
@@ -23,11 +24,13 @@ use serde::{Deserialize, Serialize};
 use crate::{
     rpc_protocol::{
         DocCloseRequest, DocCreateRequest, DocDelRequest, DocDelResponse, DocDropRequest,
-        DocExportFileRequest, DocGetDownloadPolicyRequest, DocGetExactRequest, DocGetManyRequest,
-        DocGetSyncPeersRequest, DocImportFileRequest, DocImportProgress, DocImportRequest,
-        DocLeaveRequest, DocListRequest, DocOpenRequest, DocSetDownloadPolicyRequest,
-        DocSetHashRequest, DocSetRequest, DocShareRequest, DocStartSyncRequest, DocStatusRequest,
-        DocSubscribeRequest, ProviderService, ShareMode,
+        DocExportFileRequest, DocGetAccessPolicyRequest, DocGetDownloadPolicyRequest,
+        DocGetEncryptionKeyRequest, DocGetExactRequest, DocGetManyRequest, DocGetSyncPeersRequest,
+        DocImportFileRequest, DocImportProgress, DocImportRequest, DocLeaveRequest, DocListRequest,
+        DocOpenRequest, DocSetAccessPolicyRequest, DocSetDownloadPolicyRequest,
+        DocSetEncryptionKeyRequest, DocSetHashBatchRequest, DocSetHashRequest, DocSetRequest,
+        DocShareRequest, DocStartSyncRequest, DocStatusRequest, DocSubscribeRequest,
+        ProviderService, ShareMode,
     },
     sync_engine::SyncEvent,
     ticket::DocTicket,
@@ -48,7 +51,26 @@ where
     /// Create a new document.
     pub async fn create(&self) -> Result<Doc<C>> {
         let res = self.rpc.rpc(DocCreateRequest {}).await??;
-        let doc = Doc::new(self.rpc.clone(), res.id);
+        let doc = Doc::new(self.rpc.clone(), res.id, None);
+        Ok(doc)
+    }
+
+    /// Create a new encrypted document.
+    ///
+    /// The keys and content of all entries are encrypted with a new random
+    /// [`DocEncryptionKey`]. The key is included in tickets shared with [`ShareMode::Read`] or
+    /// [`ShareMode::Write`], while nodes that join with a [`ShareMode::Relay`] ticket replicate
+    /// the document without being able to read it.
+    pub async fn create_encrypted(&self) -> Result<Doc<C>> {
+        let res = self.rpc.rpc(DocCreateRequest {}).await??;
+        let key = DocEncryptionKey::new(&mut rand::rngs::OsRng);
+        self.rpc
+            .rpc(DocSetEncryptionKeyRequest {
+                doc_id: res.id,
+                key: key.clone(),
+            })
+            .await??;
+        let doc = Doc::new(self.rpc.clone(), res.id, Some(key));
         Ok(doc)
     }
 
@@ -65,7 +87,7 @@ where
     /// Import a document from a ticket and join all peers in the ticket.
     pub async fn import(&self, ticket: DocTicket) -> Result<Doc<C>> {
         let res = self.rpc.rpc(DocImportRequest(ticket)).await??;
-        let doc = Doc::new(self.rpc.clone(), res.doc_id);
+        let doc = Doc::load(self.rpc.clone(), res.doc_id).await?;
         Ok(doc)
     }
 
@@ -78,7 +100,7 @@ where
     /// Get a [`Doc`] client for a single document. Return None if the document cannot be found.
     pub async fn open(&self, id: NamespaceId) -> Result<Option<Doc<C>>> {
         self.rpc.rpc(DocOpenRequest { doc_id: id }).await??;
-        let doc = Doc::new(self.rpc.clone(), id);
+        let doc = Doc::load(self.rpc.clone(), id).await?;
         Ok(Some(doc))
     }
 }
@@ -101,6 +123,7 @@ struct DocInner<C: ServiceConnection<ProviderService>> {
     rpc: RpcClient<ProviderService, C>,
     closed: AtomicBool,
     rt: tokio::runtime::Handle,
+    encryption_key: Option<DocEncryptionKey>,
 }
 
 impl<C> Drop for DocInner<C>
@@ -120,15 +143,26 @@ impl<C> Doc<C>
 where
     C: ServiceConnection<ProviderService>,
 {
-    fn new(rpc: RpcClient<ProviderService, C>, id: NamespaceId) -> Self {
+    fn new(
+        rpc: RpcClient<ProviderService, C>,
+        id: NamespaceId,
+        encryption_key: Option<DocEncryptionKey>,
+    ) -> Self {
         Self(Arc::new(DocInner {
             rpc,
             id,
             closed: AtomicBool::new(false),
             rt: tokio::runtime::Handle::current(),
+            encryption_key,
         }))
     }
 
+    /// Create a handle for an open document, fetching its encryption key from the node.
+    async fn load(rpc: RpcClient<ProviderService, C>, id: NamespaceId) -> Result<Self> {
+        let res = rpc.rpc(DocGetEncryptionKeyRequest { doc_id: id }).await??;
+        Ok(Self::new(rpc, id, res.key))
+    }
+
     async fn rpc<M>(&self, msg: M) -> Result<M::Response>
     where
         M: RpcMsg<ProviderService>,
@@ -142,6 +176,22 @@ where
         self.0.id
     }
 
+    /// Get the key used to encrypt this document.
+    ///
+    /// Returns `None` if the document is not encrypted, or if the node only relays the
+    /// encrypted document without holding its key.
+    pub fn encryption_key(&self) -> Option<&DocEncryptionKey> {
+        self.0.encryption_key.as_ref()
+    }
+
+    fn ensure_not_encrypted(&self) -> Result<()> {
+        if self.0.encryption_key.is_some() {
+            Err(anyhow!("operation not supported for encrypted documents"))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Close the document.
     pub async fn close(&self) -> Result<()> {
         self.0.closed.store(true, Ordering::Release);
@@ -158,6 +208,9 @@ where
     }
 
     /// Set the content of a key to a byte array.
+    ///
+    /// For encrypted documents the key and value are encrypted, and the returned hash is the
+    /// hash of the encrypted value.
     pub async fn set_bytes(
         &self,
         author_id: AuthorId,
@@ -165,18 +218,25 @@ where
         value: impl Into<Bytes>,
     ) -> Result<Hash> {
         self.ensure_open()?;
+        let (key, value) = (key.into(), value.into());
+        let (key, value) = match self.encryption_key() {
+            Some(doc_key) => (doc_key.encrypt_key(&key), doc_key.encrypt_content(&value)),
+            None => (key, value),
+        };
         let res = self
             .rpc(DocSetRequest {
                 doc_id: self.id(),
                 author_id,
-                key: key.into(),
-                value: value.into(),
+                key,
+                value,
             })
             .await??;
         Ok(res.entry.content_hash())
     }
 
     /// Set an entries on the doc via its key, hash, and size.
+    ///
+    /// Not supported for encrypted documents, use [`Self::set_bytes`] instead.
     pub async fn set_hash(
         &self,
         author_id: AuthorId,
@@ -185,6 +245,7 @@ where
         size: u64,
     ) -> Result<()> {
         self.ensure_open()?;
+        self.ensure_not_encrypted()?;
         self.rpc(DocSetHashRequest {
             doc_id: self.id(),
             author_id,
@@ -196,7 +257,35 @@ where
         Ok(())
     }
 
+    /// Set many entries on the doc via their key, hash, and size, in a single batch.
+    ///
+    /// The entries are inserted together, and announced to peers with a single gossip message
+    /// instead of one per entry. If any entry fails to insert, none of them are inserted.
+    ///
+    /// Not supported for encrypted documents, use [`Self::set_bytes`] instead.
+    pub async fn set_hash_batch<K: Into<Bytes>>(
+        &self,
+        author_id: AuthorId,
+        entries: impl IntoIterator<Item = (K, Hash, u64)>,
+    ) -> Result<()> {
+        self.ensure_open()?;
+        self.ensure_not_encrypted()?;
+        let entries = entries
+            .into_iter()
+            .map(|(key, hash, size)| (key.into(), hash, size))
+            .collect();
+        self.rpc(DocSetHashBatchRequest {
+            doc_id: self.id(),
+            author_id,
+            entries,
+        })
+        .await??;
+        Ok(())
+    }
+
     /// Add an entry from an absolute file path
+    ///
+    /// Not supported for encrypted documents.
     pub async fn import_file(
         &self,
         author: AuthorId,
@@ -205,6 +294,7 @@ where
         in_place: bool,
     ) -> Result<DocImportFileProgress> {
         self.ensure_open()?;
+        self.ensure_not_encrypted()?;
         let stream = self
             .0
             .rpc
@@ -220,6 +310,8 @@ where
     }
 
     /// Export an entry as a file to a given absolute path.
+    ///
+    /// Not supported for encrypted documents.
     pub async fn export_file(
         &self,
         entry: Entry,
@@ -227,6 +319,7 @@ where
         mode: ExportMode,
     ) -> Result<DocExportFileProgress> {
         self.ensure_open()?;
+        self.ensure_not_encrypted()?;
         let stream = self
             .0
             .rpc
@@ -245,13 +338,21 @@ where
     /// entries whose key starts with or is equal to the given `prefix`.
     ///
     /// Returns the number of entries deleted.
+    ///
+    /// Encrypted keys do not share prefixes, so for encrypted documents only the entry with
+    /// exactly the key `prefix` is deleted.
     pub async fn del(&self, author_id: AuthorId, prefix: impl Into<Bytes>) -> Result<usize> {
         self.ensure_open()?;
+        let prefix = prefix.into();
+        let prefix = match self.encryption_key() {
+            Some(doc_key) => doc_key.encrypt_key(&prefix),
+            None => prefix,
+        };
         let res = self
             .rpc(DocDelRequest {
                 doc_id: self.id(),
                 author_id,
-                prefix: prefix.into(),
+                prefix,
             })
             .await??;
         let DocDelResponse { removed } = res;
@@ -268,32 +369,65 @@ where
         include_empty: bool,
     ) -> Result<Option<Entry>> {
         self.ensure_open()?;
+        let key = match self.encryption_key() {
+            Some(doc_key) => doc_key.encrypt_key(key.as_ref()),
+            None => key.as_ref().to_vec().into(),
+        };
         let res = self
             .rpc(DocGetExactRequest {
                 author,
-                key: key.as_ref().to_vec().into(),
+                key,
                 doc_id: self.id(),
                 include_empty,
             })
             .await??;
-        Ok(res.entry.map(|entry| entry.into()))
+        let Some(entry) = res.entry else {
+            return Ok(None);
+        };
+        let entry: Entry = entry.into();
+        match self.encryption_key() {
+            Some(doc_key) => entry.decrypt(doc_key).map(Some),
+            None => Ok(Some(entry)),
+        }
     }
 
     /// Get entries.
+    ///
+    /// For encrypted documents, queries by key prefix are not supported, and sorting by key
+    /// sorts by the encrypted keys.
     pub async fn get_many(
         &self,
         query: impl Into<Query>,
     ) -> Result<impl Stream<Item = Result<Entry>>> {
         self.ensure_open()?;
+        let mut query = query.into();
+        if let Some(doc_key) = self.encryption_key() {
+            let filter = match query.key_filter() {
+                KeyFilter::Any => KeyFilter::Any,
+                KeyFilter::Exact(key) => KeyFilter::Exact(doc_key.encrypt_key(key)),
+                KeyFilter::Prefix(prefix) if prefix.is_empty() => KeyFilter::Any,
+                KeyFilter::Prefix(_) => {
+                    bail!("queries by key prefix are not supported for encrypted documents")
+                }
+            };
+            query.set_key_filter(filter);
+        }
         let stream = self
             .0
             .rpc
             .server_streaming(DocGetManyRequest {
                 doc_id: self.id(),
-                query: query.into(),
+                query,
             })
             .await?;
-        Ok(flatten(stream).map_ok(|res| res.entry.into()))
+        let doc_key = self.0.encryption_key.clone();
+        Ok(flatten(stream).map(move |res| {
+            let entry: Entry = res?.entry.into();
+            match &doc_key {
+                Some(doc_key) => entry.decrypt(doc_key),
+                None => Ok(entry),
+            }
+        }))
     }
 
     /// Get a single entry.
@@ -340,9 +474,14 @@ where
             .rpc
             .server_streaming(DocSubscribeRequest { doc_id: self.id() })
             .await?;
-        Ok(flatten(stream)
-            .map_ok(|res| res.event.into())
-            .map_err(Into::into))
+        let doc_key = self.0.encryption_key.clone();
+        Ok(flatten(stream).map(move |res| {
+            let event: LiveEvent = res?.event.into();
+            match &doc_key {
+                Some(doc_key) => event.decrypt(doc_key),
+                None => Ok(event),
+            }
+        }))
     }
 
     /// Get status info for this document
@@ -370,6 +509,26 @@ where
         Ok(res.policy)
     }
 
+    /// Set the access policy for this document
+    ///
+    /// The access policy decides which nodes may sync this document with us.
+    pub async fn set_access_policy(&self, policy: AccessPolicy) -> Result<()> {
+        self.rpc(DocSetAccessPolicyRequest {
+            doc_id: self.id(),
+            policy,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the access policy for this document
+    pub async fn get_access_policy(&self) -> Result<AccessPolicy> {
+        let res = self
+            .rpc(DocGetAccessPolicyRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.policy)
+    }
+
     /// Get sync peers for this document
     pub async fn get_sync_peers(&self) -> Result<Option<Vec<PeerIdBytes>>> {
         let res = self
@@ -388,22 +547,45 @@ impl<'a, C: ServiceConnection<ProviderService>> From<&'a Doc<C>>
 }
 
 /// A single entry in a [`Doc`].
+///
+/// Entries of encrypted documents are decrypted transparently: [`Entry::key`],
+/// [`Entry::content_len`] and the content refer to the plaintext, while [`Entry::id`] and
+/// [`Entry::content_hash`] refer to the encrypted data.
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-pub struct Entry(iroh_sync::Entry);
+pub struct Entry(iroh_sync::Entry, #[serde(skip)] Option<Decrypted>);
+
+/// The decrypted parts of an entry of an encrypted document.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Decrypted {
+    /// The plaintext key.
+    key: Bytes,
+    /// The key to decrypt the content with.
+    doc_key: DocEncryptionKey,
+}
 
 impl From<iroh_sync::Entry> for Entry {
     fn from(value: iroh_sync::Entry) -> Self {
-        Self(value)
+        Self(value, None)
     }
 }
 
 impl From<iroh_sync::SignedEntry> for Entry {
     fn from(value: iroh_sync::SignedEntry) -> Self {
-        Self(value.into())
+        Self(value.into(), None)
     }
 }
 
 impl Entry {
+    /// Decrypt the key of an entry of an encrypted document.
+    fn decrypt(self, doc_key: &DocEncryptionKey) -> Result<Self> {
+        let key = doc_key.decrypt_key(self.0.key())?;
+        let decrypted = Decrypted {
+            key,
+            doc_key: doc_key.clone(),
+        };
+        Ok(Self(self.0, Some(decrypted)))
+    }
+
     /// Get the [`RecordIdentifier`] for this entry.
     pub fn id(&self) -> &RecordIdentifier {
         self.0.id()
@@ -421,12 +603,21 @@ impl Entry {
 
     /// Get the length of the data addressed by this record's content hash.
     pub fn content_len(&self) -> u64 {
-        self.0.content_len()
+        match &self.1 {
+            Some(_) => self
+                .0
+                .content_len()
+                .saturating_sub(ENCRYPTION_OVERHEAD as u64),
+            None => self.0.content_len(),
+        }
     }
 
     /// Get the key of this entry.
     pub fn key(&self) -> &[u8] {
-        self.0.key()
+        match &self.1 {
+            Some(decrypted) => &decrypted.key,
+            None => self.0.key(),
+        }
     }
 
     /// Get the timestamp of this entry.
@@ -444,7 +635,14 @@ impl Entry {
     where
         C: ServiceConnection<ProviderService>,
     {
-        BlobReader::from_rpc_read(client.into(), self.content_hash()).await
+        match &self.1 {
+            None => BlobReader::from_rpc_read(client.into(), self.content_hash()).await,
+            Some(_) => {
+                // encrypted content can only be decrypted as a whole
+                let content = self.content_bytes(client).await?;
+                Ok(BlobReader::from_bytes(content))
+            }
+        }
     }
 
     /// Read all content of an [`Entry`] into a buffer.
@@ -457,10 +655,15 @@ impl Entry {
     where
         C: ServiceConnection<ProviderService>,
     {
-        BlobReader::from_rpc_read(client.into(), self.content_hash())
+        let content = BlobReader::from_rpc_read(client.into(), self.content_hash())
             .await?
             .read_to_bytes()
-            .await
+            .await?;
+        match &self.1 {
+            // deletion markers have no content
+            Some(decrypted) if !content.is_empty() => decrypted.doc_key.decrypt_content(&content),
+            _ => Ok(content),
+        }
     }
 }
 
@@ -517,6 +720,28 @@ impl From<crate::sync_engine::LiveEvent> for LiveEvent {
     }
 }
 
+impl LiveEvent {
+    /// Decrypt the entries of events of an encrypted document.
+    fn decrypt(self, doc_key: &DocEncryptionKey) -> Result<Self> {
+        let event = match self {
+            Self::InsertLocal { entry } => Self::InsertLocal {
+                entry: entry.decrypt(doc_key)?,
+            },
+            Self::InsertRemote {
+                from,
+                entry,
+                content_status,
+            } => Self::InsertRemote {
+                from,
+                entry: entry.decrypt(doc_key)?,
+                content_status,
+            },
+            event => event,
+        };
+        Ok(event)
+    }
+}
+
 /// Progress stream for doc import operations.
 #[derive(derive_more::Debug)]
 #[must_use = "streams do nothing unless polled"]
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use iroh_gossip::{net::TopicInfo, proto::TopicId};
+use quic_rpc::{RpcClient, ServiceConnection};
+
+use crate::rpc_protocol::{GossipTopicInfoRequest, ProviderService};
+
+/// Iroh gossip client.
+#[derive(Debug, Clone)]
+pub struct Client<C> {
+    pub(super) rpc: RpcClient<ProviderService, C>,
+}
+
+impl<C> Client<C>
+where
+    C: ServiceConnection<ProviderService>,
+{
+    /// Get a snapshot of the membership and broadcast state of a topic.
+    ///
+    /// Returns `None` if the node did not join the topic.
+    pub async fn topic_info(&self, topic: TopicId) -> Result<Option<TopicInfo>> {
+        let res = self.rpc.rpc(GossipTopicInfoRequest { topic }).await??;
+        Ok(res.info)
+    }
+}
@@ -0,0 +1,262 @@
+//! Gossip with support for payloads larger than a single gossip message.
+//!
+//! [`iroh_gossip`] limits each message to [`MAX_MESSAGE_SIZE`]. [`BlobGossip`] lifts this limit
+//! by storing larger payloads in the blob store of the node and broadcasting only a reference to
+//! the blob. Receivers fetch the blob from the neighbor that delivered the message or from its
+//! publisher before emitting the [`Event::Received`].
+//!
+//! All peers of a topic must use [`BlobGossip`] for broadcasting and subscribing, since the
+//! payloads are wrapped in an envelope that plain [`Gossip`] does not understand.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use genawaiter::sync::Gen;
+use iroh_bytes::{
+    downloader::{DownloadKind, Downloader, Role},
+    store::{MapEntry, Store as BaoStore},
+    BlobFormat, Hash, HashAndFormat, TempTag,
+};
+use iroh_gossip::net::{Event, Gossip, JoinTopicFut, MAX_MESSAGE_SIZE};
+use iroh_io::AsyncSliceReaderExt;
+use iroh_net::key::PublicKey;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_util::time::DelayQueue;
+use tracing::{debug, warn};
+
+pub use iroh_gossip::{net::TopicInfo, proto::TopicId};
+
+/// Payloads up to this size are sent inline in the gossip message.
+///
+/// Larger payloads are sent as a blob reference. This leaves room below [`MAX_MESSAGE_SIZE`]
+/// for the framing of the gossip protocol and for message signatures.
+pub const MAX_INLINE_SIZE: usize = MAX_MESSAGE_SIZE / 2;
+
+/// Maximum size of a payload that is fetched as a blob.
+///
+/// Blob references to larger payloads are dropped without fetching them.
+pub const MAX_BLOB_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Duration for which broadcast and fetched blobs are protected from garbage collection, so
+/// that peers further down the broadcast tree can fetch them from us.
+const BLOB_RETENTION: Duration = Duration::from_secs(300);
+
+/// The content of a gossip message sent with [`BlobGossip`].
+#[derive(Debug, Serialize, Deserialize)]
+enum Payload {
+    /// The payload is included in the message.
+    Inline(Bytes),
+    /// The payload is stored as a raw blob.
+    Blob {
+        /// Hash of the blob.
+        hash: Hash,
+        /// Size of the blob in bytes.
+        size: u64,
+        /// The node that published the payload, which can always provide the blob.
+        provider: PublicKey,
+    },
+}
+
+/// Gossip on topics with payloads of any size up to [`MAX_BLOB_SIZE`].
+///
+/// Payloads larger than [`MAX_INLINE_SIZE`] are imported into the blob store and broadcast as a
+/// blob reference. Received blob references are resolved through the bytes protocol before the
+/// message is emitted.
+///
+/// Joining and leaving topics works as with [`Gossip`].
+#[derive(derive_more::Debug, Clone)]
+pub struct BlobGossip<D> {
+    gossip: Gossip,
+    #[debug("BaoStore")]
+    store: D,
+    downloader: Downloader,
+    me: PublicKey,
+    /// Sends blobs to protect from garbage collection to [`retain_blobs`].
+    retain_tx: mpsc::Sender<TempTag>,
+}
+
+impl<D: BaoStore> BlobGossip<D> {
+    /// Create a [`BlobGossip`] on top of a [`Gossip`] instance.
+    pub fn new(gossip: Gossip, store: D, downloader: Downloader, me: PublicKey) -> Self {
+        let (retain_tx, retain_rx) = mpsc::channel(64);
+        tokio::spawn(retain_blobs(retain_rx));
+        Self {
+            gossip,
+            store,
+            downloader,
+            me,
+            retain_tx,
+        }
+    }
+
+    /// Get the underlying [`Gossip`].
+    pub fn gossip(&self) -> &Gossip {
+        &self.gossip
+    }
+
+    /// Join a topic and connect to peers.
+    ///
+    /// See [`Gossip::join`].
+    pub async fn join(&self, topic: TopicId, peers: Vec<PublicKey>) -> Result<JoinTopicFut> {
+        self.gossip.join(topic, peers).await
+    }
+
+    /// Quit a topic.
+    ///
+    /// See [`Gossip::quit`].
+    pub async fn quit(&self, topic: TopicId) -> Result<()> {
+        self.gossip.quit(topic).await
+    }
+
+    /// Broadcast a payload on a topic to all peers in the swarm.
+    pub async fn broadcast(&self, topic: TopicId, content: Bytes) -> Result<()> {
+        let message = self.encode(content).await?;
+        self.gossip.broadcast(topic, message).await
+    }
+
+    /// Broadcast a payload on a topic to the immediate neighbors.
+    pub async fn broadcast_neighbors(&self, topic: TopicId, content: Bytes) -> Result<()> {
+        let message = self.encode(content).await?;
+        self.gossip.broadcast_neighbors(topic, message).await
+    }
+
+    /// Subscribe to messages and event notifications for a topic.
+    ///
+    /// The content of each [`Event::Received`] is the full payload. Payloads sent as blob
+    /// references are fetched before the event is emitted, which delays all following events of
+    /// the topic. Messages whose payload can not be decoded or fetched are skipped.
+    pub async fn subscribe(&self, topic: TopicId) -> Result<impl Stream<Item = Event>> {
+        let mut events = self.gossip.subscribe(topic).await?;
+        let this = self.clone();
+        Ok(Gen::new(|co| async move {
+            loop {
+                let mut event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        warn!(%topic, "subscriber lagged by {n} - dropping gossip events");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if let Event::Received(message) = &mut event {
+                    let res = this
+                        .decode(
+                            &message.content,
+                            message.delivered_from,
+                            message.scope.is_direct(),
+                        )
+                        .await;
+                    match res {
+                        Ok(content) => message.content = content,
+                        Err(err) => {
+                            let from = message.delivered_from.fmt_short();
+                            warn!(%topic, %from, "dropping gossip message: {err:#}");
+                            continue;
+                        }
+                    }
+                }
+                co.yield_(event).await;
+            }
+        }))
+    }
+
+    /// Encode a payload into a gossip message, storing it as a blob if it is too large.
+    async fn encode(&self, content: Bytes) -> Result<Bytes> {
+        let payload = if content.len() <= MAX_INLINE_SIZE {
+            Payload::Inline(content)
+        } else {
+            let size = content.len() as u64;
+            ensure!(size <= MAX_BLOB_SIZE, "payload too large ({size} bytes)");
+            let tag = self.store.import_bytes(content, BlobFormat::Raw).await?;
+            let hash = *tag.hash();
+            self.retain(tag).await;
+            Payload::Blob {
+                hash,
+                size,
+                provider: self.me,
+            }
+        };
+        Ok(postcard::to_stdvec(&payload)?.into())
+    }
+
+    /// Decode a received gossip message, fetching the payload if it was sent as a blob.
+    ///
+    /// `direct` is whether the message was delivered by its publisher.
+    async fn decode(&self, message: &[u8], from: PublicKey, direct: bool) -> Result<Bytes> {
+        let payload: Payload = postcard::from_bytes(message).context("invalid payload")?;
+        let (hash, size, provider) = match payload {
+            Payload::Inline(content) => return Ok(content),
+            Payload::Blob {
+                hash,
+                size,
+                provider,
+            } => (hash, size, provider),
+        };
+        ensure!(size <= MAX_BLOB_SIZE, "blob too large ({size} bytes)");
+
+        let complete = matches!(
+            self.store.get(&hash).await?,
+            Some(entry) if entry.is_complete()
+        );
+        if !complete {
+            debug!(%hash, %size, from = %from.fmt_short(), "fetching gossip payload");
+            // the neighbor has the blob if it is the publisher, otherwise it might still be
+            // fetching it
+            let role = match direct {
+                true => Role::Provider,
+                false => Role::Candidate,
+            };
+            let mut nodes = vec![(from, role).into()];
+            if provider != from {
+                nodes.push((provider, Role::Provider).into());
+            }
+            // only request the announced size, so that we never fetch more than that
+            let kind = DownloadKind::blob_bytes(hash, 0..size);
+            let handle = self.downloader.clone().queue(kind, nodes).await;
+            handle.await?;
+        }
+        // keep the blob around for peers that fetch it from us
+        self.retain(self.store.temp_tag(HashAndFormat::raw(hash)))
+            .await;
+
+        let entry = self
+            .store
+            .get(&hash)
+            .await?
+            .ok_or_else(|| anyhow!("blob not found after download"))?;
+        ensure!(
+            entry.is_complete() && entry.size().value() == size,
+            "blob does not match the announced size"
+        );
+        let mut reader = entry.data_reader().await?;
+        let content = reader.read_to_end().await?;
+        Ok(content)
+    }
+
+    /// Protect a blob from garbage collection for [`BLOB_RETENTION`].
+    async fn retain(&self, tag: TempTag) {
+        // only fails if the node is shutting down
+        self.retain_tx.send(tag).await.ok();
+    }
+}
+
+/// Hold the tags of retained blobs until their [`BLOB_RETENTION`] expired.
+///
+/// Runs until all clones of the [`BlobGossip`] are dropped.
+async fn retain_blobs(mut tags: mpsc::Receiver<TempTag>) {
+    let mut expiry = DelayQueue::new();
+    loop {
+        tokio::select! {
+            tag = tags.recv() => match tag {
+                Some(tag) => {
+                    expiry.insert(tag, BLOB_RETENTION);
+                }
+                None => break,
+            },
+            Some(expired) = expiry.next(), if !expiry.is_empty() => drop(expired.into_inner()),
+        }
+    }
+}
@@ -34,15 +34,18 @@ use tokio_util::sync::CancellationToken;
 use tokio_util::task::LocalPoolHandle;
 use tracing::debug;
 
+use crate::gossip::BlobGossip;
 use crate::rpc_protocol::{ProviderRequest, ProviderResponse};
 use crate::sync_engine::SyncEngine;
 use crate::ticket::BlobTicket;
 
 mod builder;
+mod protocol;
 mod rpc;
 mod rpc_status;
 
 pub use builder::{Builder, GcPolicy, StorageConfig};
+pub use protocol::{IntoArcAny, ProtocolHandler};
 pub use rpc_status::RpcStatus;
 
 type EventCallback = Box<dyn Fn(Event) -> BoxFuture<'static, ()> + 'static + Sync + Send>;
@@ -108,6 +111,9 @@ struct NodeInner<D> {
     #[debug("rt")]
     rt: LocalPoolHandle,
     pub(crate) sync: SyncEngine,
+    gossip: BlobGossip<D>,
+    protocols: protocol::ProtocolMap,
+    provider_config: iroh_bytes::provider::ProviderConfig,
 }
 
 /// Events emitted by the [`Node`] informing about the current status.
@@ -207,6 +213,22 @@ impl<D: BaoStore> Node<D> {
         &self.client
     }
 
+    /// Returns the gossip protocol of the node.
+    ///
+    /// Unlike the plain [`iroh_gossip::net::Gossip`], this supports payloads of any size by
+    /// transferring large payloads as blobs.
+    pub fn gossip(&self) -> &BlobGossip<D> {
+        &self.inner.gossip
+    }
+
+    /// Returns the [`ProtocolHandler`] registered for `alpn` with [`Builder::accept`].
+    ///
+    /// Returns `None` if no handler is registered for `alpn`, or if the handler is not of
+    /// type `P`.
+    pub fn get_protocol<P: ProtocolHandler>(&self, alpn: &[u8]) -> Option<Arc<P>> {
+        self.inner.protocols.get_typed(alpn)
+    }
+
     /// Returns a referenc to the used `LocalPoolHandle`.
     pub fn local_pool_handle(&self) -> &LocalPoolHandle {
         &self.inner.rt
@@ -388,6 +410,80 @@ mod tests {
         Ok(())
     }
 
+    const ECHO_ALPN: &[u8] = b"/iroh/test/echo/0";
+
+    #[derive(Debug, Default)]
+    struct Echo {
+        shutdown: std::sync::atomic::AtomicBool,
+    }
+
+    impl ProtocolHandler for Echo {
+        fn accept(self: Arc<Self>, conn: quinn::Connecting) -> BoxFuture<'static, Result<()>> {
+            async move {
+                let conn = conn.await?;
+                let (mut send, mut recv) = conn.accept_bi().await?;
+                tokio::io::copy(&mut recv, &mut send).await?;
+                send.finish().await?;
+                Ok(())
+            }
+            .boxed()
+        }
+
+        fn shutdown(self: Arc<Self>) -> BoxFuture<'static, ()> {
+            async move {
+                self.shutdown
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_protocol() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let node1 = Node::memory()
+            .bind_port(0)
+            .relay_mode(iroh_net::relay::RelayMode::Disabled)
+            .accept(ECHO_ALPN, Arc::new(Echo::default()))
+            .spawn()
+            .await?;
+        let node2 = Node::memory()
+            .bind_port(0)
+            .relay_mode(iroh_net::relay::RelayMode::Disabled)
+            .spawn()
+            .await?;
+
+        let conn = node2
+            .magic_endpoint()
+            .connect(node1.my_addr().await?, ECHO_ALPN)
+            .await?;
+        let (mut send, mut recv) = conn.open_bi().await?;
+        send.write_all(b"hello echo").await?;
+        send.finish().await?;
+        let res = recv.read_to_end(1024).await?;
+        assert_eq!(res, b"hello echo");
+
+        let echo = node1
+            .get_protocol::<Echo>(ECHO_ALPN)
+            .expect("echo is registered");
+        node1.shutdown();
+        node1.await?;
+        assert!(echo.shutdown.load(std::sync::atomic::Ordering::SeqCst));
+        node2.shutdown();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_custom_protocol_reserved_alpn() {
+        let res = Node::memory()
+            .bind_port(0)
+            .accept(iroh_gossip::net::GOSSIP_ALPN, Arc::new(Echo::default()))
+            .spawn()
+            .await;
+        assert!(res.is_err());
+    }
+
     #[cfg(feature = "fs-store")]
     #[tokio::test]
     async fn test_shutdown() -> Result<()> {
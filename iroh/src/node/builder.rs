@@ -12,11 +12,18 @@ use iroh_base::key::SecretKey;
 use iroh_bytes::{
     downloader::Downloader,
     protocol::Closed,
-    store::{GcMarkEvent, GcSweepEvent, Map, Store as BaoStore},
+    provider::{
+        AllowAll, AuthorizationHandler, ProviderConfig, ProviderLimits, PushPolicy, RejectAll,
+        Throttle,
+    },
+    store::{CacheLimits, GcMarkEvent, GcSweepEvent, Map, Store as BaoStore},
 };
 use iroh_gossip::net::{Gossip, GOSSIP_ALPN};
 use iroh_net::{
-    magic_endpoint::get_alpn, relay::RelayMode, util::AbortingJoinHandle, MagicEndpoint,
+    magic_endpoint::{get_alpn, get_remote_node_id},
+    relay::RelayMode,
+    util::AbortingJoinHandle,
+    MagicEndpoint,
 };
 use iroh_sync::net::SYNC_ALPN;
 use quic_rpc::{
@@ -30,13 +37,17 @@ use tracing::{debug, error, error_span, info, trace, warn, Instrument};
 
 use crate::{
     client::quic::RPC_ALPN,
+    gossip::BlobGossip,
     node::{Event, NodeInner},
     rpc_protocol::{ProviderRequest, ProviderResponse, ProviderService},
     sync_engine::SyncEngine,
     util::{fs::load_secret_key, path::IrohPaths},
 };
 
-use super::{rpc, Callbacks, EventCallback, Node, RpcStatus};
+use super::{
+    protocol::{ProtocolHandler, ProtocolMap},
+    rpc, Callbacks, EventCallback, Node, RpcStatus,
+};
 
 pub const PROTOCOLS: [&[u8]; 3] = [&iroh_bytes::protocol::ALPN, GOSSIP_ALPN, SYNC_ALPN];
 
@@ -81,6 +92,10 @@ where
     relay_mode: RelayMode,
     gc_policy: GcPolicy,
     docs_store: iroh_sync::store::fs::Store,
+    protocols: ProtocolMap,
+    authorization: Arc<dyn AuthorizationHandler>,
+    push_policy: Arc<dyn PushPolicy>,
+    provider_limits: ProviderLimits,
 }
 
 /// Configuration for storage.
@@ -104,6 +119,10 @@ impl Default for Builder<iroh_bytes::store::mem::Store> {
             rpc_endpoint: Default::default(),
             gc_policy: GcPolicy::Disabled,
             docs_store: iroh_sync::store::Store::memory(),
+            protocols: Default::default(),
+            authorization: Arc::new(AllowAll),
+            push_policy: Arc::new(RejectAll),
+            provider_limits: Default::default(),
         }
     }
 }
@@ -125,6 +144,10 @@ impl<D: Map> Builder<D> {
             rpc_endpoint: Default::default(),
             gc_policy: GcPolicy::Disabled,
             docs_store,
+            protocols: Default::default(),
+            authorization: Arc::new(AllowAll),
+            push_policy: Arc::new(RejectAll),
+            provider_limits: Default::default(),
         }
     }
 }
@@ -183,6 +206,10 @@ where
             relay_mode: self.relay_mode,
             gc_policy: self.gc_policy,
             docs_store,
+            protocols: self.protocols,
+            authorization: self.authorization,
+            push_policy: self.push_policy,
+            provider_limits: self.provider_limits,
         })
     }
 
@@ -199,6 +226,10 @@ where
             relay_mode: self.relay_mode,
             gc_policy: self.gc_policy,
             docs_store: self.docs_store,
+            protocols: self.protocols,
+            authorization: self.authorization,
+            push_policy: self.push_policy,
+            provider_limits: self.provider_limits,
         }
     }
 
@@ -222,6 +253,10 @@ where
             relay_mode: self.relay_mode,
             gc_policy: self.gc_policy,
             docs_store: self.docs_store,
+            protocols: self.protocols,
+            authorization: self.authorization,
+            push_policy: self.push_policy,
+            provider_limits: self.provider_limits,
         })
     }
 
@@ -233,6 +268,44 @@ where
         self
     }
 
+    /// Accepts incoming connections for a custom ALPN protocol.
+    ///
+    /// The ALPN is advertised by the node's endpoint in addition to the iroh internal ones
+    /// and every incoming connection negotiating it is passed to `handler`.  Registering a
+    /// handler for an ALPN a second time replaces the previous handler.
+    ///
+    /// The ALPNs of the iroh internal protocols can not be used, [`Self::spawn`] will fail
+    /// in this case.
+    pub fn accept(mut self, alpn: &'static [u8], handler: Arc<dyn ProtocolHandler>) -> Self {
+        self.protocols.insert(alpn, handler);
+        self
+    }
+
+    /// Sets the handler deciding which blobs remote nodes may fetch.
+    ///
+    /// By default all requests for blobs in the store are served.
+    pub fn authorization_handler(mut self, handler: Arc<dyn AuthorizationHandler>) -> Self {
+        self.authorization = handler;
+        self
+    }
+
+    /// Sets the policy deciding which blobs pushed by remote nodes are stored.
+    ///
+    /// Accepted blobs and collections are tagged with a new tag.  By default all pushes
+    /// are rejected.
+    pub fn push_policy(mut self, policy: Arc<dyn PushPolicy>) -> Self {
+        self.push_policy = policy;
+        self
+    }
+
+    /// Sets the limits for serving blobs to remote nodes.
+    ///
+    /// By default no limits are enforced.
+    pub fn provider_limits(mut self, limits: ProviderLimits) -> Self {
+        self.provider_limits = limits;
+        self
+    }
+
     /// Sets the relay servers to assist in establishing connectivity.
     ///
     /// Relay servers are used to discover other nodes by `PublicKey` and also help
@@ -278,6 +351,22 @@ where
     /// get information about it.
     pub async fn spawn(self) -> Result<Node<D>> {
         trace!("spawning node");
+        if let Some(alpn) = self
+            .protocols
+            .alpns()
+            .find(|alpn| PROTOCOLS.contains(*alpn))
+        {
+            bail!(
+                "ALPN {} is reserved for an iroh internal protocol",
+                String::from_utf8_lossy(alpn)
+            );
+        }
+        let alpns = PROTOCOLS
+            .iter()
+            .chain(self.protocols.alpns())
+            .map(|p| p.to_vec())
+            .collect();
+
         let lp = LocalPoolHandle::new(num_cpus::get());
 
         let mut transport_config = quinn::TransportConfig::default();
@@ -287,7 +376,7 @@ where
 
         let endpoint = MagicEndpoint::builder()
             .secret_key(self.secret_key.clone())
-            .alpns(PROTOCOLS.iter().map(|p| p.to_vec()).collect())
+            .alpns(alpns)
             .keylog(self.keylog)
             .transport_config(transport_config)
             .concurrent_connections(MAX_CONNECTIONS)
@@ -311,10 +400,32 @@ where
         let addr = endpoint.my_addr().await?;
 
         // initialize the gossip protocol
-        let gossip = Gossip::from_endpoint(endpoint.clone(), Default::default(), &addr.info);
+        let gossip = match self.storage {
+            StorageConfig::Persistent(ref root) => {
+                let peers_path = IrohPaths::GossipPeers.with_root(root);
+                // topics are not rejoined on start: the sync engine joins the topic of a document
+                // once it syncs it again, through the peers remembered for the topic
+                Gossip::from_endpoint_persistent(
+                    endpoint.clone(),
+                    Default::default(),
+                    &addr.info,
+                    peers_path,
+                    |_topic| false,
+                )
+            }
+            StorageConfig::Mem => {
+                Gossip::from_endpoint(endpoint.clone(), Default::default(), &addr.info)
+            }
+        };
 
         // spawn the sync engine
         let downloader = Downloader::new(self.blobs_store.clone(), endpoint.clone(), lp.clone());
+        let blob_gossip = BlobGossip::new(
+            gossip.clone(),
+            self.blobs_store.clone(),
+            downloader.clone(),
+            endpoint.node_id(),
+        );
         let ds = self.docs_store.clone();
         let sync = SyncEngine::spawn(
             endpoint.clone(),
@@ -325,11 +436,13 @@ where
         );
 
         let callbacks = Callbacks::default();
-        let gc_task = if let GcPolicy::Interval(gc_period) = self.gc_policy {
+        let gc_task = if let Some(gc_period) = self.gc_policy.interval() {
             tracing::info!("Starting GC task with interval {:?}", gc_period);
             let db = self.blobs_store.clone();
             let callbacks = callbacks.clone();
-            let task = lp.spawn_pinned(move || Self::gc_loop(db, ds, gc_period, callbacks));
+            let cache_limits = self.gc_policy.cache_limits();
+            let task =
+                lp.spawn_pinned(move || Self::gc_loop(db, ds, gc_period, cache_limits, callbacks));
             Some(AbortingJoinHandle(task))
         } else {
             None
@@ -348,6 +461,13 @@ where
             gc_task,
             rt: lp.clone(),
             sync,
+            gossip: blob_gossip,
+            protocols: self.protocols,
+            provider_config: ProviderConfig {
+                authorization: self.authorization,
+                push_policy: self.push_policy,
+                throttle: Arc::new(Throttle::new(self.provider_limits)),
+            },
         });
         let task = {
             let gossip = gossip.clone();
@@ -435,9 +555,15 @@ where
                 _ = cancel_token.cancelled() => {
                     // clean shutdown of the blobs db to close the write transaction
                     handler.inner.db.shutdown().await;
+                    // save the gossip topics before the sync engine quits them
+                    if let Err(err) = gossip.shutdown().await {
+                        warn!("gossip shutdown error: {:?}", err);
+                    }
                     if let Err(err) = handler.inner.sync.shutdown().await {
                         warn!("sync shutdown error: {:?}", err);
                     }
+                    // give custom protocols the chance to finish their connections
+                    handler.inner.protocols.shutdown().await;
                     break
                 },
                 // handle rpc requests. This will do nothing if rpc is not configured, since
@@ -505,6 +631,7 @@ where
         db: D,
         ds: iroh_sync::store::fs::Store,
         gc_period: Duration,
+        cache_limits: Option<CacheLimits>,
         callbacks: Callbacks,
     ) {
         let mut live = BTreeSet::new();
@@ -569,7 +696,10 @@ where
             drop(stream);
 
             tracing::debug!("Starting GC sweep phase");
-            let mut stream = db.gc_sweep(&live);
+            let mut stream = match cache_limits {
+                Some(limits) => db.gc_evict(&live, limits).left_stream(),
+                None => db.gc_sweep(&live).right_stream(),
+            };
             while let Some(item) = stream.next().await {
                 match item {
                     GcSweepEvent::CustomDebug(text) => {
@@ -598,6 +728,29 @@ pub enum GcPolicy {
     Disabled,
     /// Garbage collection is run at the given interval.
     Interval(Duration),
+    /// Blobs that are not reachable from tags are kept as a cache, and only removed once
+    /// they exceed the [`CacheLimits`], least recently accessed first.
+    ///
+    /// The limits are enforced at the given interval.
+    Cache(Duration, CacheLimits),
+}
+
+impl GcPolicy {
+    /// The interval at which garbage collection runs, if it is enabled.
+    pub fn interval(&self) -> Option<Duration> {
+        match self {
+            Self::Disabled => None,
+            Self::Interval(interval) | Self::Cache(interval, _) => Some(*interval),
+        }
+    }
+
+    /// The limits for keeping unreachable blobs, if blobs are kept as a cache.
+    pub fn cache_limits(&self) -> Option<CacheLimits> {
+        match self {
+            Self::Cache(_, limits) => Some(*limits),
+            _ => None,
+        }
+    }
 }
 
 impl Default for GcPolicy {
@@ -619,15 +772,22 @@ async fn handle_connection<D: BaoStore>(
         GOSSIP_ALPN => gossip.handle_connection(connecting.await?).await?,
         SYNC_ALPN => sync.handle_connection(connecting).await?,
         alpn if alpn == iroh_bytes::protocol::ALPN => {
+            let connection = connecting.await?;
+            let node_id = get_remote_node_id(&connection).ok();
             iroh_bytes::provider::handle_connection(
-                connecting,
+                connection,
+                node_id,
                 node.db.clone(),
                 node.callbacks.clone(),
+                node.provider_config.clone(),
                 node.rt.clone(),
             )
             .await
         }
-        _ => bail!("ignoring connection: unsupported ALPN protocol"),
+        alpn => match node.protocols.get(alpn) {
+            Some(handler) => handler.accept(connecting).await?,
+            None => bail!("ignoring connection: unsupported ALPN protocol"),
+        },
     }
     Ok(())
 }
@@ -0,0 +1,85 @@
+//! Custom protocols that can be served by the [`Node`](super::Node).
+//!
+//! Besides the iroh internal protocols (blobs, gossip and docs) a node can accept
+//! connections for any number of additional ALPNs.  Implement [`ProtocolHandler`] for your
+//! protocol and register it with [`Builder::accept`](super::Builder::accept).  The ALPN will
+//! then be advertised by the node's [`MagicEndpoint`](iroh_net::MagicEndpoint) and all
+//! incoming connections for it are passed to the handler.
+use std::{any::Any, collections::BTreeMap, fmt, sync::Arc};
+
+use anyhow::Result;
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+
+/// Handler for incoming connections of a custom ALPN protocol.
+///
+/// A handler is registered per ALPN on the node [`Builder`](super::Builder).  The node's
+/// accept loop calls [`ProtocolHandler::accept`] for each connection that negotiated this
+/// ALPN, in its own task.
+pub trait ProtocolHandler: Send + Sync + IntoArcAny + fmt::Debug + 'static {
+    /// Handle an incoming connection.
+    ///
+    /// The returned future runs on a freshly spawned tokio task, so it may run as long as the
+    /// connection is alive.  An error is logged and otherwise ignored.
+    fn accept(self: Arc<Self>, conn: quinn::Connecting) -> BoxFuture<'static, Result<()>>;
+
+    /// Called when the node shuts down.
+    ///
+    /// This is called before the endpoint is closed, so the handler still has the chance to
+    /// gracefully finish its connections.  The default implementation does nothing.
+    fn shutdown(self: Arc<Self>) -> BoxFuture<'static, ()> {
+        async move {}.boxed()
+    }
+}
+
+/// Helper trait to convert an `Arc<dyn ProtocolHandler>` back into its concrete type.
+///
+/// This is implemented for all `Send + Sync + 'static` types and does not need to be
+/// implemented manually.
+pub trait IntoArcAny {
+    /// Casts `Arc<Self>` into `Arc<dyn Any + Send + Sync>`.
+    fn into_arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: Send + Sync + 'static> IntoArcAny for T {
+    fn into_arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+/// Map of custom ALPNs to their [`ProtocolHandler`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProtocolMap(BTreeMap<&'static [u8], Arc<dyn ProtocolHandler>>);
+
+impl ProtocolMap {
+    /// Returns the registered handler for `alpn`, downcast to `P`.
+    ///
+    /// Returns `None` if no handler is registered or the handler is not of type `P`.
+    pub(crate) fn get_typed<P: ProtocolHandler>(&self, alpn: &[u8]) -> Option<Arc<P>> {
+        let protocol: Arc<dyn ProtocolHandler> = self.0.get(alpn)?.clone();
+        let protocol_any: Arc<dyn Any + Send + Sync> = protocol.into_arc_any();
+        let protocol_ref = Arc::downcast(protocol_any).ok()?;
+        Some(protocol_ref)
+    }
+
+    /// Returns the registered handler for `alpn`.
+    pub(crate) fn get(&self, alpn: &[u8]) -> Option<Arc<dyn ProtocolHandler>> {
+        self.0.get(alpn).cloned()
+    }
+
+    /// Registers a handler for `alpn`, replacing any previously registered handler.
+    pub(crate) fn insert(&mut self, alpn: &'static [u8], handler: Arc<dyn ProtocolHandler>) {
+        self.0.insert(alpn, handler);
+    }
+
+    /// Returns an iterator over all registered ALPNs.
+    pub(crate) fn alpns(&self) -> impl Iterator<Item = &&'static [u8]> {
+        self.0.keys()
+    }
+
+    /// Calls [`ProtocolHandler::shutdown`] on all handlers and waits for them to complete.
+    pub(crate) async fn shutdown(&self) {
+        let handlers = self.0.values().map(|handler| handler.clone().shutdown());
+        join_all(handlers).await;
+    }
+}
@@ -37,10 +37,11 @@ use crate::rpc_protocol::{
     BlobReadAtRequest, BlobReadAtResponse, BlobValidateRequest, CreateCollectionRequest,
     CreateCollectionResponse, DeleteTagRequest, DocExportFileRequest, DocExportFileResponse,
     DocImportFileRequest, DocImportFileResponse, DocImportProgress, DocSetHashRequest,
-    ListTagsRequest, ListTagsResponse, NodeConnectionInfoRequest, NodeConnectionInfoResponse,
-    NodeConnectionsRequest, NodeConnectionsResponse, NodeShutdownRequest, NodeStatsRequest,
-    NodeStatsResponse, NodeStatusRequest, NodeStatusResponse, NodeWatchRequest, NodeWatchResponse,
-    ProviderRequest, ProviderService, SetTagOption,
+    GossipTopicInfoRequest, GossipTopicInfoResponse, ListTagsRequest, ListTagsResponse,
+    NodeConnectionInfoRequest, NodeConnectionInfoResponse, NodeConnectionsRequest,
+    NodeConnectionsResponse, NodeShutdownRequest, NodeStatsRequest, NodeStatsResponse,
+    NodeStatusRequest, NodeStatusResponse, NodeWatchRequest, NodeWatchResponse, ProviderRequest,
+    ProviderService, SetTagOption,
 };
 
 use super::{Event, NodeInner};
@@ -76,6 +77,7 @@ impl<D: BaoStore> Handler<D> {
                         .await
                 }
                 NodeConnectionInfo(msg) => chan.rpc(msg, handler, Self::node_connection_info).await,
+                GossipTopicInfo(msg) => chan.rpc(msg, handler, Self::gossip_topic_info).await,
                 BlobList(msg) => chan.server_streaming(msg, handler, Self::blob_list).await,
                 BlobListIncomplete(msg) => {
                     chan.server_streaming(msg, handler, Self::blob_list_incomplete)
@@ -218,6 +220,12 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
+                DocSetHashBatch(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_hash_batch(req).await
+                    })
+                    .await
+                }
                 DocGet(msg) => {
                     chan.server_streaming(msg, handler, |handler, req| {
                         handler.inner.sync.doc_get_many(req)
@@ -266,6 +274,30 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
+                DocSetAccessPolicy(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_access_policy(req).await
+                    })
+                    .await
+                }
+                DocGetAccessPolicy(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_access_policy(req).await
+                    })
+                    .await
+                }
+                DocSetEncryptionKey(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_encryption_key(req).await
+                    })
+                    .await
+                }
+                DocGetEncryptionKey(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_encryption_key(req).await
+                    })
+                    .await
+                }
                 DocGetSyncPeers(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
                         handler.inner.sync.doc_get_sync_peers(req).await
@@ -797,6 +829,14 @@ impl<D: BaoStore> Handler<D> {
         res
     }
 
+    async fn gossip_topic_info(
+        self,
+        req: GossipTopicInfoRequest,
+    ) -> RpcResult<GossipTopicInfoResponse> {
+        let info = self.inner.gossip.gossip().topic_info(req.topic).await?;
+        Ok(GossipTopicInfoResponse { info })
+    }
+
     async fn node_status(self, _: NodeStatusRequest) -> RpcResult<NodeStatusResponse> {
         Ok(NodeStatusResponse {
             addr: self.inner.endpoint.my_addr().await?,
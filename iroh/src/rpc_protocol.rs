@@ -17,6 +17,7 @@ use iroh_bytes::{
     store::{BaoBlobSize, ConsistencyCheckProgress},
     util::Tag,
 };
+use iroh_gossip::{net::TopicInfo, proto::TopicId};
 use iroh_net::{
     key::PublicKey,
     magic_endpoint::{ConnectionInfo, NodeAddr},
@@ -24,7 +25,8 @@ use iroh_net::{
 
 use iroh_sync::{
     actor::OpenState,
-    store::{DownloadPolicy, Query},
+    encryption::DocEncryptionKey,
+    store::{AccessPolicy, DownloadPolicy, Query},
     Author, PeerIdBytes, {AuthorId, CapabilityKind, Entry, NamespaceId, SignedEntry},
 };
 use quic_rpc::{
@@ -542,6 +544,11 @@ pub enum ShareMode {
     Read,
     /// Write access
     Write,
+    /// Read-only access to the entries, without the key to decrypt an encrypted document
+    ///
+    /// This allows nodes to replicate an encrypted document without being able to read it.
+    /// For documents that are not encrypted this is the same as [`ShareMode::Read`].
+    Relay,
 }
 
 /// Subscribe to events for a document.
@@ -908,6 +915,25 @@ impl RpcMsg<ProviderService> for DocSetHashRequest {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DocSetHashResponse {}
 
+/// Set many entries on a document via their key, hash, and size, in a single batch.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetHashBatchRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Author of the entries.
+    pub author_id: AuthorId,
+    /// Key, hash and size of each entry.
+    pub entries: Vec<(Bytes, Hash, u64)>,
+}
+
+impl RpcMsg<ProviderService> for DocSetHashBatchRequest {
+    type Response = RpcResult<DocSetHashBatchResponse>;
+}
+
+/// Response to [`DocSetHashBatchRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetHashBatchResponse {}
+
 /// Get entries from a document
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DocGetManyRequest {
@@ -991,6 +1017,76 @@ pub struct DocGetDownloadPolicyResponse {
     pub policy: DownloadPolicy,
 }
 
+/// Set an access policy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetAccessPolicyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Access policy
+    pub policy: AccessPolicy,
+}
+
+impl RpcMsg<ProviderService> for DocSetAccessPolicyRequest {
+    type Response = RpcResult<DocSetAccessPolicyResponse>;
+}
+
+/// Response to [`DocSetAccessPolicyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetAccessPolicyResponse {}
+
+/// Get an access policy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetAccessPolicyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetAccessPolicyRequest {
+    type Response = RpcResult<DocGetAccessPolicyResponse>;
+}
+
+/// Response to [`DocGetAccessPolicyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetAccessPolicyResponse {
+    /// The access policy
+    pub policy: AccessPolicy,
+}
+
+/// Set the encryption key of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetEncryptionKeyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Encryption key
+    pub key: DocEncryptionKey,
+}
+
+impl RpcMsg<ProviderService> for DocSetEncryptionKeyRequest {
+    type Response = RpcResult<DocSetEncryptionKeyResponse>;
+}
+
+/// Response to [`DocSetEncryptionKeyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetEncryptionKeyResponse {}
+
+/// Get the encryption key of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetEncryptionKeyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetEncryptionKeyRequest {
+    type Response = RpcResult<DocGetEncryptionKeyResponse>;
+}
+
+/// Response to [`DocGetEncryptionKeyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetEncryptionKeyResponse {
+    /// The encryption key, if the document is encrypted and we hold the key
+    pub key: Option<DocEncryptionKey>,
+}
+
 /// Get peers for document
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DocGetSyncPeersRequest {
@@ -1098,6 +1194,24 @@ pub struct NodeStatsResponse {
     pub stats: BTreeMap<String, CounterStats>,
 }
 
+/// Get a snapshot of the gossip state of a topic
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GossipTopicInfoRequest {
+    /// The topic to get the info for
+    pub topic: TopicId,
+}
+
+impl RpcMsg<ProviderService> for GossipTopicInfoRequest {
+    type Response = RpcResult<GossipTopicInfoResponse>;
+}
+
+/// Response to [`GossipTopicInfoRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GossipTopicInfoResponse {
+    /// The topic info, or `None` if the node did not join the topic
+    pub info: Option<TopicInfo>,
+}
+
 /// The RPC service for the iroh provider process.
 #[derive(Debug, Clone)]
 pub struct ProviderService;
@@ -1113,6 +1227,8 @@ pub enum ProviderRequest {
     NodeConnectionInfo(NodeConnectionInfoRequest),
     NodeWatch(NodeWatchRequest),
 
+    GossipTopicInfo(GossipTopicInfoRequest),
+
     BlobReadAt(BlobReadAtRequest),
     BlobAddStream(BlobAddStreamRequest),
     BlobAddStreamUpdate(BlobAddStreamUpdate),
@@ -1140,6 +1256,7 @@ pub enum ProviderRequest {
     DocImport(DocImportRequest),
     DocSet(DocSetRequest),
     DocSetHash(DocSetHashRequest),
+    DocSetHashBatch(DocSetHashBatchRequest),
     DocGet(DocGetManyRequest),
     DocGetExact(DocGetExactRequest),
     DocImportFile(DocImportFileRequest),
@@ -1151,6 +1268,10 @@ pub enum ProviderRequest {
     DocSubscribe(DocSubscribeRequest),
     DocGetDownloadPolicy(DocGetDownloadPolicyRequest),
     DocSetDownloadPolicy(DocSetDownloadPolicyRequest),
+    DocGetAccessPolicy(DocGetAccessPolicyRequest),
+    DocSetAccessPolicy(DocSetAccessPolicyRequest),
+    DocGetEncryptionKey(DocGetEncryptionKeyRequest),
+    DocSetEncryptionKey(DocSetEncryptionKeyRequest),
     DocGetSyncPeers(DocGetSyncPeersRequest),
 
     AuthorList(AuthorListRequest),
@@ -1171,6 +1292,8 @@ pub enum ProviderResponse {
     NodeShutdown(()),
     NodeWatch(NodeWatchResponse),
 
+    GossipTopicInfo(RpcResult<GossipTopicInfoResponse>),
+
     BlobReadAt(RpcResult<BlobReadAtResponse>),
     BlobAddStream(BlobAddStreamResponse),
     BlobAddPath(BlobAddPathResponse),
@@ -1196,6 +1319,7 @@ pub enum ProviderResponse {
     DocImport(RpcResult<DocImportResponse>),
     DocSet(RpcResult<DocSetResponse>),
     DocSetHash(RpcResult<DocSetHashResponse>),
+    DocSetHashBatch(RpcResult<DocSetHashBatchResponse>),
     DocGet(RpcResult<DocGetManyResponse>),
     DocGetExact(RpcResult<DocGetExactResponse>),
     DocImportFile(DocImportFileResponse),
@@ -1207,6 +1331,10 @@ pub enum ProviderResponse {
     DocSubscribe(RpcResult<DocSubscribeResponse>),
     DocGetDownloadPolicy(RpcResult<DocGetDownloadPolicyResponse>),
     DocSetDownloadPolicy(RpcResult<DocSetDownloadPolicyResponse>),
+    DocGetAccessPolicy(RpcResult<DocGetAccessPolicyResponse>),
+    DocSetAccessPolicy(RpcResult<DocSetAccessPolicyResponse>),
+    DocGetEncryptionKey(RpcResult<DocGetEncryptionKeyResponse>),
+    DocSetEncryptionKey(RpcResult<DocSetEncryptionKeyResponse>),
     DocGetSyncPeers(RpcResult<DocGetSyncPeersResponse>),
 
     AuthorList(RpcResult<AuthorListResponse>),
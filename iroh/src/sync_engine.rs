@@ -196,8 +196,9 @@ impl SyncEngine {
             let replica_events = {
                 let (s, r) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
                 this.sync.subscribe(namespace, s).await?;
-                r.into_stream()
-                    .map(move |ev| LiveEvent::from_replica_event(ev, &content_status_cb))
+                r.into_stream().filter_map(move |ev| {
+                    LiveEvent::from_replica_event(ev, &content_status_cb).transpose()
+                })
             };
 
             // Subscribe to events from the [`live::Actor`].
@@ -297,19 +298,22 @@ impl From<live::Event> for LiveEvent {
 }
 
 impl LiveEvent {
+    /// Convert a replica event into a [`LiveEvent`], or `None` if it has no equivalent.
     fn from_replica_event(
         ev: iroh_sync::Event,
         content_status_cb: &ContentStatusCallback,
-    ) -> Result<Self> {
+    ) -> Result<Option<Self>> {
         Ok(match ev {
-            iroh_sync::Event::LocalInsert { entry, .. } => Self::InsertLocal {
+            iroh_sync::Event::LocalInsert { entry, .. } => Some(Self::InsertLocal {
                 entry: entry.into(),
-            },
-            iroh_sync::Event::RemoteInsert { entry, from, .. } => Self::InsertRemote {
+            }),
+            iroh_sync::Event::RemoteInsert { entry, from, .. } => Some(Self::InsertRemote {
                 content_status: content_status_cb(entry.content_hash()),
                 entry: entry.into(),
                 from: PublicKey::from_bytes(&from)?,
-            },
+            }),
+            // the entries of a batch are already reported individually
+            iroh_sync::Event::LocalInsertBatch { .. } => None,
         })
     }
 }
@@ -611,15 +611,30 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
 
     async fn on_replica_event(&mut self, event: iroh_sync::Event) -> Result<()> {
         match event {
-            iroh_sync::Event::LocalInsert { namespace, entry } => {
+            iroh_sync::Event::LocalInsert {
+                namespace,
+                entry,
+                batched,
+            } => {
                 let topic = TopicId::from_bytes(*namespace.as_bytes());
                 // A new entry was inserted locally. Broadcast a gossip message.
-                if self.state.is_syncing(&namespace) {
+                // Entries inserted in a batch are announced together once the batch is done.
+                if self.state.is_syncing(&namespace) && !batched {
                     let op = Op::Put(entry.clone());
                     let message = postcard::to_stdvec(&op)?.into();
                     self.gossip.broadcast(topic, message).await?;
                 }
             }
+            iroh_sync::Event::LocalInsertBatch {
+                namespace, heads, ..
+            } => {
+                // A batch of entries was inserted locally. Instead of broadcasting each entry,
+                // send a sync report to our neighbors, which will then sync with us.
+                let heads = heads.encode(Some(iroh_gossip::net::MAX_MESSAGE_SIZE))?;
+                let report = SyncReport { namespace, heads };
+                self.broadcast_neighbors(namespace, &Op::SyncReport(report))
+                    .await;
+            }
             iroh_sync::Event::RemoteInsert {
                 namespace,
                 entry,
@@ -14,14 +14,18 @@ use crate::{
     rpc_protocol::{
         AuthorCreateRequest, AuthorCreateResponse, AuthorListRequest, AuthorListResponse,
         DocCloseRequest, DocCloseResponse, DocCreateRequest, DocCreateResponse, DocDelRequest,
-        DocDelResponse, DocDropRequest, DocDropResponse, DocGetDownloadPolicyRequest,
-        DocGetDownloadPolicyResponse, DocGetExactRequest, DocGetExactResponse, DocGetManyRequest,
-        DocGetManyResponse, DocImportRequest, DocImportResponse, DocLeaveRequest, DocLeaveResponse,
-        DocListRequest, DocListResponse, DocOpenRequest, DocOpenResponse,
-        DocSetDownloadPolicyRequest, DocSetDownloadPolicyResponse, DocSetHashRequest,
-        DocSetHashResponse, DocSetRequest, DocSetResponse, DocShareRequest, DocShareResponse,
-        DocStartSyncRequest, DocStartSyncResponse, DocStatusRequest, DocStatusResponse,
-        DocSubscribeRequest, DocSubscribeResponse, DocTicket, RpcResult, ShareMode,
+        DocDelResponse, DocDropRequest, DocDropResponse, DocGetAccessPolicyRequest,
+        DocGetAccessPolicyResponse, DocGetDownloadPolicyRequest, DocGetDownloadPolicyResponse,
+        DocGetEncryptionKeyRequest, DocGetEncryptionKeyResponse, DocGetExactRequest,
+        DocGetExactResponse, DocGetManyRequest, DocGetManyResponse, DocImportRequest,
+        DocImportResponse, DocLeaveRequest, DocLeaveResponse, DocListRequest, DocListResponse,
+        DocOpenRequest, DocOpenResponse, DocSetAccessPolicyRequest, DocSetAccessPolicyResponse,
+        DocSetDownloadPolicyRequest, DocSetDownloadPolicyResponse, DocSetEncryptionKeyRequest,
+        DocSetEncryptionKeyResponse, DocSetHashBatchRequest, DocSetHashBatchResponse,
+        DocSetHashRequest, DocSetHashResponse, DocSetRequest, DocSetResponse, DocShareRequest,
+        DocShareResponse, DocStartSyncRequest, DocStartSyncResponse, DocStatusRequest,
+        DocStatusResponse, DocSubscribeRequest, DocSubscribeResponse, DocTicket, RpcResult,
+        ShareMode,
     },
     sync_engine::SyncEngine,
 };
@@ -129,16 +133,21 @@ impl SyncEngine {
     pub async fn doc_share(&self, req: DocShareRequest) -> RpcResult<DocShareResponse> {
         let me = self.endpoint.my_addr().await?;
         let capability = match req.mode {
-            ShareMode::Read => iroh_sync::Capability::Read(req.doc_id),
+            ShareMode::Read | ShareMode::Relay => iroh_sync::Capability::Read(req.doc_id),
             ShareMode::Write => {
                 let secret = self.sync.export_secret_key(req.doc_id).await?;
                 iroh_sync::Capability::Write(secret)
             }
         };
+        let encryption_key = match req.mode {
+            ShareMode::Relay => None,
+            ShareMode::Read | ShareMode::Write => self.sync.get_encryption_key(req.doc_id).await?,
+        };
         self.start_sync(req.doc_id, vec![]).await?;
         Ok(DocShareResponse(DocTicket {
             capability,
             nodes: vec![me],
+            encryption_key,
         }))
     }
 
@@ -157,8 +166,12 @@ impl SyncEngine {
         let DocImportRequest(DocTicket {
             capability,
             nodes: peers,
+            encryption_key,
         }) = req;
         let doc_id = self.sync.import_namespace(capability).await?;
+        if let Some(key) = encryption_key {
+            self.sync.set_encryption_key(doc_id, key).await?;
+        }
         self.sync.open(doc_id, Default::default()).await?;
         self.start_sync(doc_id, peers).await?;
         Ok(DocImportResponse { doc_id })
@@ -227,6 +240,21 @@ impl SyncEngine {
         Ok(DocSetHashResponse {})
     }
 
+    pub async fn doc_set_hash_batch(
+        &self,
+        req: DocSetHashBatchRequest,
+    ) -> RpcResult<DocSetHashBatchResponse> {
+        let DocSetHashBatchRequest {
+            doc_id,
+            author_id,
+            entries,
+        } = req;
+        self.sync
+            .insert_local_batch(doc_id, author_id, entries)
+            .await?;
+        Ok(DocSetHashBatchResponse {})
+    }
+
     pub fn doc_get_many(
         &self,
         req: DocGetManyRequest,
@@ -279,6 +307,36 @@ impl SyncEngine {
         Ok(DocGetDownloadPolicyResponse { policy })
     }
 
+    pub async fn doc_set_access_policy(
+        &self,
+        req: DocSetAccessPolicyRequest,
+    ) -> RpcResult<DocSetAccessPolicyResponse> {
+        self.sync.set_access_policy(req.doc_id, req.policy).await?;
+        Ok(DocSetAccessPolicyResponse {})
+    }
+    pub async fn doc_get_access_policy(
+        &self,
+        req: DocGetAccessPolicyRequest,
+    ) -> RpcResult<DocGetAccessPolicyResponse> {
+        let policy = self.sync.get_access_policy(req.doc_id).await?;
+        Ok(DocGetAccessPolicyResponse { policy })
+    }
+
+    pub async fn doc_set_encryption_key(
+        &self,
+        req: DocSetEncryptionKeyRequest,
+    ) -> RpcResult<DocSetEncryptionKeyResponse> {
+        self.sync.set_encryption_key(req.doc_id, req.key).await?;
+        Ok(DocSetEncryptionKeyResponse {})
+    }
+    pub async fn doc_get_encryption_key(
+        &self,
+        req: DocGetEncryptionKeyRequest,
+    ) -> RpcResult<DocGetEncryptionKeyResponse> {
+        let key = self.sync.get_encryption_key(req.doc_id).await?;
+        Ok(DocGetEncryptionKeyResponse { key })
+    }
+
     pub async fn doc_get_sync_peers(
         &self,
         req: DocGetSyncPeersRequest,
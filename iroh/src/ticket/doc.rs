@@ -2,7 +2,7 @@
 
 use iroh_base::ticket;
 use iroh_net::NodeAddr;
-use iroh_sync::Capability;
+use iroh_sync::{encryption::DocEncryptionKey, Capability};
 use serde::{Deserialize, Serialize};
 
 /// Contains both a key (either secret or public) to a document, and a list of peers to join.
@@ -13,29 +13,53 @@ pub struct DocTicket {
     pub capability: Capability,
     /// A list of nodes to contact.
     pub nodes: Vec<NodeAddr>,
+    /// The key to read an encrypted document.
+    ///
+    /// Only set for encrypted documents, and not included in tickets for relay nodes.
+    pub encryption_key: Option<DocEncryptionKey>,
 }
 
 /// Wire format for [`DocTicket`].
 ///
-/// In the future we might have multiple variants (not versions, since they
-/// might be both equally valid), so this is a single variant enum to force
-/// postcard to add a discriminator.
+/// Tickets for documents that are not encrypted use [`TicketWireFormat::Variant0`], so that
+/// they can be read by nodes that do not know about encrypted documents.
 #[derive(Serialize, Deserialize)]
 enum TicketWireFormat {
-    Variant0(DocTicket),
+    Variant0(Variant0DocTicket),
+    Variant1(DocTicket),
+}
+
+/// A [`DocTicket`] without encryption key.
+#[derive(Serialize, Deserialize)]
+struct Variant0DocTicket {
+    capability: Capability,
+    nodes: Vec<NodeAddr>,
 }
 
 impl ticket::Ticket for DocTicket {
     const KIND: &'static str = "doc";
 
     fn to_bytes(&self) -> Vec<u8> {
-        let data = TicketWireFormat::Variant0(self.clone());
+        let data = match self.encryption_key {
+            None => TicketWireFormat::Variant0(Variant0DocTicket {
+                capability: self.capability.clone(),
+                nodes: self.nodes.clone(),
+            }),
+            Some(_) => TicketWireFormat::Variant1(self.clone()),
+        };
         postcard::to_stdvec(&data).expect("postcard serialization failed")
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Self, ticket::Error> {
         let res: TicketWireFormat = postcard::from_bytes(bytes).map_err(ticket::Error::Postcard)?;
-        let TicketWireFormat::Variant0(res) = res;
+        let res = match res {
+            TicketWireFormat::Variant0(Variant0DocTicket { capability, nodes }) => DocTicket {
+                capability,
+                nodes,
+                encryption_key: None,
+            },
+            TicketWireFormat::Variant1(res) => res,
+        };
         if res.nodes.is_empty() {
             return Err(ticket::Error::Verify("addressing info cannot be empty"));
         }
@@ -49,6 +73,7 @@ impl DocTicket {
         Self {
             capability,
             nodes: peers,
+            encryption_key: None,
         }
     }
 }
@@ -86,6 +111,7 @@ mod tests {
         let ticket = DocTicket {
             capability: Capability::Read(namespace_id),
             nodes: vec![NodeAddr::from_parts(node_id, None, vec![])],
+            encryption_key: None,
         };
         let base32 = base32::parse_vec(ticket.to_string().strip_prefix("doc").unwrap()).unwrap();
         let expected = parse_hexdump("
@@ -99,4 +125,25 @@ mod tests {
         ").unwrap();
         assert_eq_hex!(base32, expected);
     }
+
+    #[test]
+    fn test_ticket_encryption_key_roundtrip() {
+        let node_id =
+            PublicKey::from_str("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                .unwrap();
+        let namespace_id = NamespaceId::from(&[1u8; 32]);
+        let ticket = DocTicket {
+            capability: Capability::Read(namespace_id),
+            nodes: vec![NodeAddr::from_parts(node_id, None, vec![])],
+            encryption_key: Some(DocEncryptionKey::from_bytes(&[2u8; 32])),
+        };
+        let parsed = DocTicket::from_str(&ticket.to_string()).unwrap();
+        assert_eq!(parsed.encryption_key, ticket.encryption_key);
+        assert_eq!(parsed.capability.id(), namespace_id);
+
+        // tickets without key keep the old format
+        let ticket = DocTicket::new(Capability::Read(namespace_id), ticket.nodes);
+        let parsed = DocTicket::from_str(&ticket.to_string()).unwrap();
+        assert_eq!(parsed.encryption_key, None);
+    }
 }
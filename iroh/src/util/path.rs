@@ -21,6 +21,9 @@ pub enum IrohPaths {
     #[strum(serialize = "peers.postcard")]
     /// Path to store known peer data.
     PeerData,
+    #[strum(serialize = "gossip-peers.postcard")]
+    /// Path to store the joined gossip topics and their known peers.
+    GossipPeers,
     #[strum(serialize = "rpc.lock")]
     /// Path to RPC lock file, containing the RPC port if running.
     RpcLock,
@@ -5,11 +5,12 @@ use bao_tree::{blake3, io::sync::Outboard, ChunkRanges};
 use bytes::Bytes;
 use futures::FutureExt;
 use iroh::node::{self, Node};
+use iroh_io::AsyncSliceReaderExt;
 use rand::RngCore;
 
 use iroh_bytes::{
     hashseq::HashSeq,
-    store::{EntryStatus, MapMut, Store},
+    store::{CacheLimits, EntryStatus, Map, MapEntry, MapMut, Store},
     util::Tag,
     BlobFormat, HashAndFormat, IROH_BLOCK_SIZE,
 };
@@ -126,6 +127,58 @@ async fn gc_basics() -> Result<()> {
     Ok(())
 }
 
+/// Test that unreachable blobs are kept as a cache, and evicted least recently used first.
+#[tokio::test]
+async fn gc_cache_lru() -> Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let bao_store = iroh_bytes::store::mem::Store::new();
+    let doc_store = iroh_sync::store::Store::memory();
+    let limits = CacheLimits {
+        max_size: Some(4500),
+        max_idle: None,
+    };
+    let node = node::Builder::with_db_and_store(
+        bao_store.clone(),
+        doc_store,
+        iroh::node::StorageConfig::Mem,
+    )
+    .gc_policy(iroh::node::GcPolicy::Cache(
+        Duration::from_millis(500),
+        limits,
+    ))
+    .spawn()
+    .await?;
+    let evs = attach_db_events(&node).await;
+    let tt1 = bao_store
+        .import_bytes(create_test_data(1000), BlobFormat::Raw)
+        .await?;
+    let tt2 = bao_store
+        .import_bytes(create_test_data(2000), BlobFormat::Raw)
+        .await?;
+    let tt3 = bao_store
+        .import_bytes(create_test_data(3000), BlobFormat::Raw)
+        .await?;
+    let (h1, h2, h3) = (*tt1.hash(), *tt2.hash(), *tt3.hash());
+
+    // read the blobs so that h3 is the least recently used one
+    for hash in [h3, h2, h1] {
+        let entry = bao_store.get(&hash).await?.unwrap();
+        entry.data_reader().await?.read_to_end().await?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    drop((tt1, tt2, tt3));
+
+    // evicting h3 is enough to get within the budget
+    step(&evs).await;
+    assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::Complete);
+    assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::Complete);
+    assert_eq!(bao_store.entry_status(&h3).await?, EntryStatus::NotFound);
+
+    node.shutdown();
+    node.await?;
+    Ok(())
+}
+
 /// Test gc for sequences of hashes that protect their children from deletion.
 #[tokio::test]
 async fn gc_hashseq_impl() -> Result<()> {
@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::StreamExt;
+use iroh::{gossip::MAX_INLINE_SIZE, node::Node};
+use iroh_gossip::{net::Event, proto::TopicId};
+use iroh_net::{key::SecretKey, relay::RelayMode};
+use rand::{RngCore, SeedableRng};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn spawn_node(
+    rng: &mut rand_chacha::ChaCha12Rng,
+) -> Result<Node<iroh_bytes::store::mem::Store>> {
+    Node::memory()
+        .secret_key(SecretKey::generate_with_rng(rng))
+        .relay_mode(RelayMode::Disabled)
+        .spawn()
+        .await
+}
+
+/// Broadcast payloads above and below the inline limit, and receive them in full.
+#[tokio::test]
+async fn gossip_large_payload() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+    let alice = spawn_node(&mut rng).await?;
+    let bob = spawn_node(&mut rng).await?;
+    let topic = TopicId::from_bytes([1u8; 32]);
+
+    bob.magic_endpoint().add_node_addr(alice.my_addr().await?)?;
+    let alice_events = alice.gossip().subscribe(topic).await?;
+    tokio::pin!(alice_events);
+    let events = bob.gossip().subscribe(topic).await?;
+    tokio::pin!(events);
+    alice.gossip().join(topic, vec![]).await?;
+    let joined = bob.gossip().join(topic, vec![alice.node_id()]).await?;
+    tokio::time::timeout(TIMEOUT, joined)
+        .await
+        .context("timeout joining topic")??;
+    // once bob is a neighbor of alice, her broadcasts reach him
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(event) = alice_events.next().await {
+            if matches!(event, Event::NeighborUp(peer) if peer == bob.node_id()) {
+                return;
+            }
+        }
+    })
+    .await
+    .context("timeout waiting for neighbor")?;
+
+    let mut large = vec![0u8; MAX_INLINE_SIZE * 100];
+    rng.fill_bytes(&mut large);
+    let payloads = [Bytes::from(large), Bytes::from_static(b"small")];
+    for payload in payloads.iter() {
+        alice.gossip().broadcast(topic, payload.clone()).await?;
+        let received = tokio::time::timeout(TIMEOUT, async {
+            while let Some(event) = events.next().await {
+                if let Event::Received(message) = event {
+                    return Some(message);
+                }
+            }
+            None
+        })
+        .await
+        .context("timeout receiving message")?
+        .context("event stream closed")?;
+        assert_eq!(received.content, *payload);
+        assert_eq!(received.delivered_from, alice.node_id());
+    }
+
+    // the large payload was transferred as a blob
+    let blobs = bob.blobs.list().await?.collect::<Vec<_>>().await;
+    assert_eq!(blobs.len(), 1);
+    Ok(())
+}
+
+/// Join two nodes on a topic, and check their topic info over RPC.
+#[tokio::test]
+async fn gossip_topic_info() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+    let alice = spawn_node(&mut rng).await?;
+    let bob = spawn_node(&mut rng).await?;
+    let topic = TopicId::from_bytes([1u8; 32]);
+
+    // topics which were not joined have no info
+    assert_eq!(alice.client().gossip.topic_info(topic).await?, None);
+
+    bob.magic_endpoint().add_node_addr(alice.my_addr().await?)?;
+    let alice_events = alice.gossip().subscribe(topic).await?;
+    tokio::pin!(alice_events);
+    let events = bob.gossip().subscribe(topic).await?;
+    tokio::pin!(events);
+    alice.gossip().join(topic, vec![]).await?;
+    let joined = bob.gossip().join(topic, vec![alice.node_id()]).await?;
+    tokio::time::timeout(TIMEOUT, joined)
+        .await
+        .context("timeout joining topic")??;
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(event) = alice_events.next().await {
+            if matches!(event, Event::NeighborUp(peer) if peer == bob.node_id()) {
+                return;
+            }
+        }
+    })
+    .await
+    .context("timeout waiting for neighbor")?;
+
+    // both nodes are in each other's active view, and nobody is left for the passive view
+    let alice_info = alice
+        .client()
+        .gossip
+        .topic_info(topic)
+        .await?
+        .context("alice joined the topic")?;
+    let bob_info = bob
+        .client()
+        .gossip
+        .topic_info(topic)
+        .await?
+        .context("bob joined the topic")?;
+    assert_eq!(alice_info.active_view, vec![bob.node_id()]);
+    assert_eq!(bob_info.active_view, vec![alice.node_id()]);
+    assert!(alice_info.passive_view.is_empty());
+    assert!(bob_info.passive_view.is_empty());
+
+    alice
+        .gossip()
+        .broadcast(topic, Bytes::from_static(b"hello"))
+        .await?;
+    tokio::time::timeout(TIMEOUT, async {
+        while let Some(event) = events.next().await {
+            if matches!(event, Event::Received(_)) {
+                return;
+            }
+        }
+    })
+    .await
+    .context("timeout receiving message")?;
+
+    // the broadcast is counted on both sides
+    let alice_after = alice
+        .client()
+        .gossip
+        .topic_info(topic)
+        .await?
+        .context("alice joined the topic")?;
+    let bob_after = bob
+        .client()
+        .gossip
+        .topic_info(topic)
+        .await?
+        .context("bob joined the topic")?;
+    assert!(alice_after.messages_sent > alice_info.messages_sent);
+    assert_eq!(
+        alice_after.message_cache_size,
+        alice_info.message_cache_size + 1
+    );
+    assert!(bob_after.messages_received > bob_info.messages_received);
+    assert_eq!(
+        bob_after.payload_messages_received,
+        bob_info.payload_messages_received + 1
+    );
+    Ok(())
+}
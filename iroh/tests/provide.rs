@@ -2,12 +2,13 @@ use std::{
     collections::BTreeMap,
     net::SocketAddr,
     ops::Range,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use bytes::Bytes;
-use futures::FutureExt;
+use futures::{future::BoxFuture, FutureExt, TryStreamExt};
 use iroh::{
     dial::Options,
     node::{Builder, Event},
@@ -21,14 +22,16 @@ use bao_tree::{blake3, ChunkNum, ChunkRanges};
 use iroh_bytes::{
     format::collection::Collection,
     get::{
+        error::GetError,
         fsm::ConnectedNext,
         fsm::{self, DecodeError},
         Stats,
     },
     protocol::{GetRequest, RangeSpecSeq},
     provider,
+    push::{push, PushError},
     store::{MapMut, Store},
-    BlobFormat, Hash,
+    BlobFormat, Hash, HashAndFormat,
 };
 
 fn test_node<D: Store>(db: D) -> Builder<D, DummyServerEndpoint> {
@@ -437,6 +440,204 @@ async fn test_not_found() {
     .expect("get failed");
 }
 
+/// Authorization handler that denies requests for a single hash.
+#[derive(Debug)]
+struct DenyHash {
+    hash: Hash,
+    requests: std::sync::Mutex<Vec<(Option<NodeId>, HashAndFormat)>>,
+}
+
+impl provider::AuthorizationHandler for DenyHash {
+    fn authorize(
+        &self,
+        node_id: Option<NodeId>,
+        content: HashAndFormat,
+        _ranges: &RangeSpecSeq,
+    ) -> BoxFuture<'static, bool> {
+        self.requests.lock().unwrap().push((node_id, content));
+        let allowed = content.hash != self.hash;
+        async move { allowed }.boxed()
+    }
+}
+
+/// Requests rejected by the authorization handler fail with a permission denied error
+#[tokio::test]
+async fn test_permission_denied() {
+    let _ = iroh_test::logging::setup();
+
+    let (db, hashes) = iroh_bytes::store::readonly_mem::Store::new([
+        ("public", b"hello".to_vec()),
+        ("private", b"world".to_vec()),
+    ]);
+    let public = Hash::from(hashes["public"]);
+    let private = Hash::from(hashes["private"]);
+    let handler = Arc::new(DenyHash {
+        hash: private,
+        requests: Default::default(),
+    });
+    let node = test_node(db)
+        .authorization_handler(handler.clone())
+        .spawn()
+        .await
+        .unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let peer_id = node.node_id();
+    let opts = get_options(peer_id, addrs);
+    let getter_id = opts.secret_key.public();
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let connection = iroh::dial::dial(opts).await?;
+
+        let data = get_blob(connection.clone(), public).await?;
+        anyhow::ensure!(data == b"hello".as_slice());
+
+        let Err(err) = get_blob(connection, private).await else {
+            anyhow::bail!("expected the request to be rejected");
+        };
+        match err.downcast::<GetError>()? {
+            GetError::PermissionDenied(_) => {}
+            err => anyhow::bail!("expected GetError::PermissionDenied, got {err:?}"),
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("get failed");
+
+    let requests = handler.requests.lock().unwrap();
+    assert_eq!(
+        *requests,
+        vec![
+            (Some(getter_id), HashAndFormat::raw(public)),
+            (Some(getter_id), HashAndFormat::raw(private)),
+        ]
+    );
+}
+
+/// Push policy that accepts everything.
+#[derive(Debug)]
+struct AcceptAll;
+
+impl provider::PushPolicy for AcceptAll {
+    fn allow_push(
+        &self,
+        _node_id: Option<NodeId>,
+        _content: HashAndFormat,
+    ) -> BoxFuture<'static, bool> {
+        async { true }.boxed()
+    }
+}
+
+/// Pushed blobs and collections are stored and tagged once by the receiving node
+#[tokio::test]
+async fn test_push() -> Result<()> {
+    let _ = iroh_test::logging::setup();
+
+    let db = iroh_bytes::store::mem::Store::new();
+    let blob = make_test_data(1024 * 64 + 17);
+    let blob_tag = db
+        .import_bytes(blob.clone().into(), BlobFormat::Raw)
+        .await?;
+    let children = [b"hello".to_vec(), b"world".to_vec()];
+    let mut collection = Collection::default();
+    for (i, child) in children.iter().enumerate() {
+        let tag = db
+            .import_bytes(child.clone().into(), BlobFormat::Raw)
+            .await?;
+        collection.push(i.to_string(), *tag.hash());
+    }
+    let collection_tag = collection.clone().store(&db).await?;
+
+    let node = test_node(iroh_bytes::store::mem::Store::new())
+        .push_policy(Arc::new(AcceptAll))
+        .spawn()
+        .await?;
+    let opts = get_options(node.node_id(), node.local_endpoint_addresses().await?);
+    let connection = iroh::dial::dial(opts).await?;
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        push(&connection, &db, HashAndFormat::raw(*blob_tag.hash())),
+    )
+    .await??;
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        push(
+            &connection,
+            &db,
+            HashAndFormat::hash_seq(*collection_tag.hash()),
+        ),
+    )
+    .await??;
+    // pushing the same content again reuses its tag
+    tokio::time::timeout(
+        Duration::from_secs(10),
+        push(&connection, &db, HashAndFormat::raw(*blob_tag.hash())),
+    )
+    .await??;
+
+    let client = node.client();
+    assert_eq!(client.blobs.read_to_bytes(*blob_tag.hash()).await?, blob);
+    for (name, hash) in collection {
+        let data = client.blobs.read_to_bytes(hash).await?;
+        assert_eq!(data, children[name.parse::<usize>()?]);
+    }
+    let tags = client
+        .tags
+        .list()
+        .await?
+        .map_ok(|tag| HashAndFormat {
+            hash: tag.hash,
+            format: tag.format,
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(tags.len(), 2);
+    assert!(tags.contains(&HashAndFormat::raw(*blob_tag.hash())));
+    assert!(tags.contains(&HashAndFormat::hash_seq(*collection_tag.hash())));
+    Ok(())
+}
+
+/// Pushes are rejected by default
+#[tokio::test]
+async fn test_push_rejected() -> Result<()> {
+    let _ = iroh_test::logging::setup();
+
+    let db = iroh_bytes::store::mem::Store::new();
+    let tag = db
+        .import_bytes(make_test_data(1024 * 1024).into(), BlobFormat::Raw)
+        .await?;
+
+    let node = test_node(iroh_bytes::store::mem::Store::new())
+        .spawn()
+        .await?;
+    let opts = get_options(node.node_id(), node.local_endpoint_addresses().await?);
+    let connection = iroh::dial::dial(opts).await?;
+    let res = tokio::time::timeout(
+        Duration::from_secs(10),
+        push(&connection, &db, HashAndFormat::raw(*tag.hash())),
+    )
+    .await?;
+    assert!(matches!(res, Err(PushError::PermissionDenied)), "{res:?}");
+    assert!(node
+        .client()
+        .blobs
+        .read_to_bytes(*tag.hash())
+        .await
+        .is_err());
+    Ok(())
+}
+
+/// Fetches a single blob, mapping errors to [`GetError`].
+async fn get_blob(connection: quinn::Connection, hash: Hash) -> anyhow::Result<Bytes> {
+    let initial = fsm::start(connection, GetRequest::single(hash));
+    let connected = initial.next().await?;
+    let ConnectedNext::StartRoot(start) = connected.next().await? else {
+        anyhow::bail!("expected StartRoot");
+    };
+    let (content, _size) = start.next().next().await.map_err(GetError::from)?;
+    let (_end, data) = content.concatenate_into_vec().await?;
+    Ok(data.into())
+}
+
 /// Simulate a node that has just begun downloading a blob, but does not yet have any data
 #[tokio::test]
 #[ignore = "flaky"]
@@ -22,7 +22,7 @@ use tracing_subscriber::{prelude::*, EnvFilter};
 use iroh_bytes::Hash;
 use iroh_net::relay::RelayMode;
 use iroh_sync::{
-    store::{DownloadPolicy, FilterKind, Query},
+    store::{AccessPolicy, DownloadPolicy, FilterKind, Query},
     AuthorId, ContentStatus,
 };
 
@@ -120,6 +120,98 @@ async fn sync_simple() -> Result<()> {
     Ok(())
 }
 
+/// A node that is not allowed by the access policy of a document can not sync it.
+#[tokio::test]
+async fn sync_access_policy() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_access_policy");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    // create doc on node0, only allowing node0 itself
+    let peer0 = nodes[0].node_id();
+    let author0 = clients[0].authors.create().await?;
+    let doc0 = clients[0].docs.create().await?;
+    doc0.set_bytes(author0, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    let policy = AccessPolicy::NobodyExcept(vec![peer0]);
+    doc0.set_access_policy(policy.clone()).await?;
+    assert_eq!(doc0.get_access_policy().await?, policy);
+    let ticket = doc0.share(ShareMode::Read).await?;
+
+    info!("node1: join");
+    let doc1 = clients[1].docs.import(ticket).await?;
+    let mut events1 = doc1.subscribe().await?;
+    let denied = async {
+        while let Some(event) = events1.next().await {
+            if let LiveEvent::SyncFinished(e) = event? {
+                if e.peer == peer0 {
+                    return anyhow::Ok(e.result);
+                }
+            }
+        }
+        bail!("event stream ended")
+    };
+    let res = tokio::time::timeout(TIMEOUT, denied).await??;
+    assert!(res.is_err());
+    assert!(get_latest(&doc1, b"k1").await.is_err());
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+/// An encrypted document can be replicated by a relay node that can not read it, and read by
+/// nodes that sync it from the relay.
+#[tokio::test]
+async fn sync_encrypted_relay() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_encrypted_relay");
+    let nodes = spawn_nodes(3, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let author0 = clients[0].authors.create().await?;
+    let doc0 = clients[0].docs.create_encrypted().await?;
+    assert!(doc0.encryption_key().is_some());
+    doc0.set_bytes(author0, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    assert_latest(&doc0, b"k1", b"v1").await;
+    assert!(doc0.get_many(Query::key_prefix("k")).await.is_err());
+
+    // the relay node replicates the document, without being able to read it
+    let relay_ticket = doc0.share(ShareMode::Relay).await?;
+    assert!(relay_ticket.encryption_key.is_none());
+    let doc1 = clients[1].docs.import(relay_ticket).await?;
+    assert!(doc1.encryption_key().is_none());
+    let entry = wait_for(|| async {
+        let entry = doc1.get_one(Query::all()).await?;
+        let entry = entry.context("entry not synced yet")?;
+        entry.content_bytes(&doc1).await?;
+        Ok(entry)
+    })
+    .await?;
+    assert_ne!(entry.key(), b"k1");
+    assert_ne!(&entry.content_bytes(&doc1).await?[..], b"v1");
+
+    // a reader syncing only with the relay node can read the document
+    let mut ticket = doc0.share(ShareMode::Read).await?;
+    assert!(ticket.encryption_key.is_some());
+    nodes[0].shutdown();
+    ticket.nodes = vec![nodes[1].my_addr().await?];
+    let doc2 = clients[2].docs.import(ticket).await?;
+    wait_for(|| get_latest(&doc2, b"k1")).await?;
+    assert_latest(&doc2, b"k1", b"v1").await;
+    let entry = doc2.get_exact(author0, b"k1", false).await?.unwrap();
+    assert_eq!(entry.key(), b"k1");
+    assert_eq!(entry.content_len(), 2);
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
 /// Test subscribing to replica events (without sync)
 #[tokio::test]
 async fn sync_subscribe_no_sync() -> Result<()> {
@@ -913,6 +1005,22 @@ async fn sync_drop_doc() -> Result<()> {
     Ok(())
 }
 
+/// Retry `f` until it succeeds, failing after [`TIMEOUT`].
+async fn wait_for<T, F, Fut>(f: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let start = Instant::now();
+    loop {
+        match f().await {
+            Ok(res) => return Ok(res),
+            Err(err) if start.elapsed() > TIMEOUT => return Err(err),
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+}
+
 async fn assert_latest(doc: &Doc, key: &[u8], value: &[u8]) {
     let content = get_latest(doc, key).await.unwrap();
     assert_eq!(content, value.to_vec());